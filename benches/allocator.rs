@@ -0,0 +1,75 @@
+// Allocation pattern of one worker handling requests, timed under whichever
+// global allocator the build selects. Compare the two allocators with
+//
+//   cargo bench --bench allocator
+//   cargo bench --bench allocator --features small-allocator
+//
+// Each request copies a header snapshot, picks headers for the FilterRequest
+// and serializes it; a window of requests stays in flight while the authz
+// call is pending, so allocations are freed out of order as in the filter.
+
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::time::Instant;
+
+#[cfg(feature = "small-allocator")]
+#[global_allocator]
+static GLOBAL: rlsf::SmallGlobalTlsf = rlsf::SmallGlobalTlsf::new();
+
+const REQUESTS: usize = 200_000;
+// Requests waiting on the authz call at any time
+const IN_FLIGHT: usize = 64;
+
+struct Request {
+    headers: Vec<(String, String)>,
+    message: Vec<u8>,
+}
+
+fn request(n: usize) -> Request {
+    let mut headers: Vec<(String, String)> = (0..20)
+        .map(|i| (format!("x-header-{}", i), "v".repeat(16 + (n + i) % 48)))
+        .collect();
+    headers.push((":path".into(), format!("/api/items/{}?page={}", n, n % 7)));
+    headers.push((
+        "authorization".into(),
+        format!("Bearer {}", "t".repeat(600)),
+    ));
+
+    let mut message = Vec::with_capacity(256);
+    for (key, value) in headers
+        .iter()
+        .filter(|(key, _)| !key.starts_with("x-header-1"))
+    {
+        message.extend_from_slice(key.as_bytes());
+        message.extend_from_slice(value.as_bytes());
+    }
+    Request { headers, message }
+}
+
+fn main() {
+    let allocator = if cfg!(feature = "small-allocator") {
+        "rlsf (small-allocator)"
+    } else {
+        "default"
+    };
+
+    let mut in_flight = VecDeque::with_capacity(IN_FLIGHT);
+    let start = Instant::now();
+    for n in 0..REQUESTS {
+        in_flight.push_back(request(n));
+        // Completions arrive out of order
+        if in_flight.len() == IN_FLIGHT {
+            let done = in_flight.swap_remove_back(n % IN_FLIGHT).unwrap();
+            black_box((done.headers.len(), done.message.len()));
+        }
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "allocator={} requests={} total_ms={} ns_per_request={}",
+        allocator,
+        REQUESTS,
+        elapsed.as_millis(),
+        elapsed.as_nanos() / REQUESTS as u128
+    );
+}
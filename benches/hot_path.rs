@@ -0,0 +1,327 @@
+// Per-request cost of the filter's hot path, driven through its proxy-wasm
+// entry points on the simulated host (src/harness.rs), as Criterion
+// benchmarks:
+//
+//   cargo bench --bench hot_path --features simulated-host -- --save-baseline release
+//   cargo bench --bench hot_path --features simulated-host -- --baseline release
+//
+// For each header set:
+//   request_headers  on_http_request_headers up to the authz dispatch: the
+//                    header map read, header selection and FilterRequest
+//                    serialization
+//   decision_allow   the authz reply to the resumed request with its
+//                    identity header
+//   decision_deny    the authz reply to the local 401
+//
+// Every phase is measured three times, as Criterion groups of their own:
+// wall time (`time/`), heap allocations (`allocations/`) and host calls
+// (`host_calls/`) per request. Against a saved baseline Criterion reports each
+// change and whether it is significant. Allocations include the simulated
+// host's own copies, so compare runs of the same build setup rather than
+// reading them as the Wasm module's. memory-tracking and small-allocator
+// install their own global allocator; with either, the allocation groups are
+// left out.
+
+use std::hint::black_box;
+
+use criterion::measurement::{Measurement, ValueFormatter, WallTime};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use grpc_call_envoy::harness::{self, FilterResponse, Simulation};
+
+#[cfg(not(any(feature = "memory-tracking", feature = "small-allocator")))]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use criterion::measurement::{Measurement, ValueFormatter};
+
+    use super::CountFormatter;
+
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    // Heap allocations (and reallocations) made by the measured code
+    pub struct Allocations;
+
+    impl Measurement for Allocations {
+        type Intermediate = u64;
+        type Value = u64;
+
+        fn start(&self) -> u64 {
+            ALLOCATIONS.load(Ordering::Relaxed)
+        }
+
+        fn end(&self, start: u64) -> u64 {
+            ALLOCATIONS.load(Ordering::Relaxed) - start
+        }
+
+        fn add(&self, v1: &u64, v2: &u64) -> u64 {
+            v1 + v2
+        }
+
+        fn zero(&self) -> u64 {
+            0
+        }
+
+        fn to_f64(&self, value: &u64) -> f64 {
+            *value as f64
+        }
+
+        fn formatter(&self) -> &dyn ValueFormatter {
+            &CountFormatter("allocs")
+        }
+    }
+}
+
+#[cfg(not(any(feature = "memory-tracking", feature = "small-allocator")))]
+use counting::Allocations;
+
+// Counts are reported as they are, in `unit`
+struct CountFormatter(&'static str);
+
+impl ValueFormatter for CountFormatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        self.0
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        _throughput: &Throughput,
+        _values: &mut [f64],
+    ) -> &'static str {
+        self.0
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        self.0
+    }
+}
+
+// `proxy_*` imports called by the measured code
+struct HostCalls;
+
+impl Measurement for HostCalls {
+    type Intermediate = u64;
+    type Value = u64;
+
+    fn start(&self) -> u64 {
+        harness::host_calls()
+    }
+
+    fn end(&self, start: u64) -> u64 {
+        harness::host_calls() - start
+    }
+
+    fn add(&self, v1: &u64, v2: &u64) -> u64 {
+        v1 + v2
+    }
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn to_f64(&self, value: &u64) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &CountFormatter("host calls")
+    }
+}
+
+// Pseudo-headers and a bearer token
+fn minimal() -> Vec<(String, String)> {
+    vec![
+        (":method".into(), "GET".into()),
+        (":scheme".into(), "https".into()),
+        (":authority".into(), "api.example.com".into()),
+        (":path".into(), "/api/items/42".into()),
+        (
+            "authorization".into(),
+            format!("Bearer {}", "t".repeat(600)),
+        ),
+    ]
+}
+
+// What a browser sends through an ingress
+fn browser() -> Vec<(String, String)> {
+    let mut headers = minimal();
+    headers[3].1 = "/api/items?page=3&sort=name&filter=active".into();
+    for (name, value) in [
+        (
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64) Gecko/20100101 Firefox/128.0",
+        ),
+        ("accept", "application/json, text/plain, */*"),
+        ("accept-language", "en-US,en;q=0.9,de;q=0.8"),
+        ("accept-encoding", "gzip, deflate, br, zstd"),
+        ("referer", "https://app.example.com/items"),
+        ("origin", "https://app.example.com"),
+        ("sec-fetch-dest", "empty"),
+        ("sec-fetch-mode", "cors"),
+        ("sec-fetch-site", "same-site"),
+        ("x-forwarded-for", "203.0.113.7, 10.0.0.2"),
+        ("x-forwarded-proto", "https"),
+        ("x-request-id", "6b1e1f4e-4c3a-4b8f-9a51-2f0d6c1d7e42"),
+        (
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ),
+    ] {
+        headers.push((name.into(), value.into()));
+    }
+    headers.push((
+        "cookie".into(),
+        format!("session={}; theme=dark", "s".repeat(200)),
+    ));
+    headers
+}
+
+// Many headers and a large cookie
+fn large() -> Vec<(String, String)> {
+    let mut headers = browser();
+    for i in 0..40 {
+        headers.push((format!("x-custom-{}", i), "v".repeat(16 + i % 48)));
+    }
+    headers.push(("cookie".into(), format!("tracking={}", "c".repeat(4096))));
+    headers
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    RequestHeaders,
+    DecisionAllow,
+    DecisionDeny,
+}
+
+// Run `f`, adding what it cost to `total` when `measured`
+fn measure<M: Measurement, T>(
+    measurement: &M,
+    total: &mut M::Value,
+    measured: bool,
+    f: impl FnOnce() -> T,
+) -> T {
+    if !measured {
+        return f();
+    }
+    let start = measurement.start();
+    let result = f();
+    *total = measurement.add(total, &measurement.end(start));
+    result
+}
+
+// Take `iterations` requests through headers and a decision, measuring only
+// `phase` of each
+fn run<M: Measurement>(
+    measurement: &M,
+    iterations: u64,
+    headers: &[(&str, &str)],
+    phase: Phase,
+) -> M::Value {
+    let reply = match phase {
+        Phase::DecisionDeny => FilterResponse {
+            message: "Bearer error=\"invalid_token\"".into(),
+            ..Default::default()
+        },
+        _ => FilterResponse {
+            allow: true,
+            user: "alice".into(),
+            ..Default::default()
+        },
+    };
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    let mut total = measurement.zero();
+    for _ in 0..iterations {
+        let (context_id, _) = measure(
+            measurement,
+            &mut total,
+            phase == Phase::RequestHeaders,
+            || black_box(simulation.request(headers)),
+        );
+        let callout = simulation.take_callouts().pop().expect("no authz call");
+        measure(
+            measurement,
+            &mut total,
+            phase != Phase::RequestHeaders,
+            || simulation.grpc_reply(&callout, &reply),
+        );
+        simulation.end(context_id);
+    }
+    total
+}
+
+fn phases<M: Measurement>(c: &mut Criterion<M>, measurement: &M, name: &str) {
+    let header_sets = [
+        ("minimal", minimal()),
+        ("browser", browser()),
+        ("large", large()),
+    ];
+    for (phase_name, phase) in [
+        ("request_headers", Phase::RequestHeaders),
+        ("decision_allow", Phase::DecisionAllow),
+        ("decision_deny", Phase::DecisionDeny),
+    ] {
+        let mut group = c.benchmark_group(format!("{}/{}", name, phase_name));
+        for (headers_name, headers) in &header_sets {
+            let headers: Vec<(&str, &str)> = headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            group.bench_function(*headers_name, |b| {
+                b.iter_custom(|iterations| run(measurement, iterations, &headers, phase))
+            });
+        }
+        group.finish();
+    }
+}
+
+fn time(c: &mut Criterion) {
+    phases(c, &WallTime, "time");
+}
+
+#[cfg(not(any(feature = "memory-tracking", feature = "small-allocator")))]
+fn allocations(c: &mut Criterion<Allocations>) {
+    phases(c, &Allocations, "allocations");
+}
+
+fn host_calls(c: &mut Criterion<HostCalls>) {
+    phases(c, &HostCalls, "host_calls");
+}
+
+criterion_group!(time_benches, time);
+#[cfg(not(any(feature = "memory-tracking", feature = "small-allocator")))]
+criterion_group! {
+    name = allocation_benches;
+    config = Criterion::default().with_measurement(Allocations);
+    targets = allocations
+}
+criterion_group! {
+    name = host_call_benches;
+    config = Criterion::default().with_measurement(HostCalls);
+    targets = host_calls
+}
+#[cfg(not(any(feature = "memory-tracking", feature = "small-allocator")))]
+criterion_main!(time_benches, allocation_benches, host_call_benches);
+#[cfg(any(feature = "memory-tracking", feature = "small-allocator"))]
+criterion_main!(time_benches, host_call_benches);
@@ -1,9 +1,34 @@
+use std::path::PathBuf;
+
 fn main() {
-    let proto_files = vec!["./protos/uipbdiauthz.proto"];
+    let proto_files = ["./protos/uipbdiauthz.proto"];
+    // Declaring any rerun-if condition drops cargo's default of rerunning
+    // on every package change, so the protos are listed explicitly
+    println!("cargo:rerun-if-changed=protos");
 
-    protoc_rust::Codegen::new()
-        .out_dir("./src")
-        .inputs(proto_files)
-        .run()
+    prost_build::Config::new()
+        .protoc_executable(protoc())
+        // Only exists as the recordDenial response, which the filter ignores
+        .type_attribute("authengine.DenialAck", "#[allow(dead_code)]")
+        // Encoded by hand around the serialized FilterRequest (see stream.rs)
+        .type_attribute("authengine.StreamRequest", "#[allow(dead_code)]")
+        .compile_protos(&proto_files, &["./protos"])
         .expect("running protoc failed");
 }
+
+// $PROTOC when set, else the protoc shipped with protoc-bin-vendored, so no
+// system protoc is needed
+fn protoc() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=PROTOC");
+    if let Some(protoc) = std::env::var_os("PROTOC") {
+        return protoc.into();
+    }
+    protoc_bin_vendored::protoc_bin_path().unwrap_or_else(|e| {
+        panic!(
+            "no vendored protoc for this platform ({}); install protoc \
+             (https://github.com/protocolbuffers/protobuf/releases) and set \
+             PROTOC to its path",
+            e
+        )
+    })
+}
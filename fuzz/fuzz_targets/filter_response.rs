@@ -0,0 +1,40 @@
+// Arbitrary bytes as the authz service's reply, delivered through
+// proxy_on_grpc_receive to a filter running on the simulated host: the reply
+// is parsed (FilterResponse, legacy bodies), folded from v2, its extensions
+// applied and the verdict acted on. Any panic aborts the VM, which libFuzzer
+// reports as a crash.
+//
+// The first byte picks the setup: bit 0 enables filter_request_v2, bit 1
+// sends a gRPC client request (verdicts become grpc-status), bit 2 adds the
+// authn call, which gets the same reply.
+
+#![no_main]
+
+use grpc_call_envoy::harness::Simulation;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&setup, reply)) = data.split_first() else {
+        return;
+    };
+    let mut config = serde_json::json!({ "filter_request_v2": setup & 1 != 0 });
+    if setup & 4 != 0 {
+        config["authn"] = serde_json::json!({ "cluster": "authn" });
+    }
+    let mut headers = vec![
+        (":method", "POST"),
+        (":scheme", "https"),
+        (":authority", "api.example.com"),
+        (":path", "/orders.v1.OrderService/GetOrder?limit=5"),
+        ("authorization", "Basic YWxpY2U6czNjcmV0"),
+    ];
+    if setup & 2 != 0 {
+        headers.push(("content-type", "application/grpc"));
+    }
+
+    let mut simulation = Simulation::start(config);
+    simulation.request(&headers);
+    for callout in simulation.callouts() {
+        simulation.grpc_reply_bytes(&callout, reply);
+    }
+});
@@ -0,0 +1,59 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::query::query_param;
+
+// Local API-key authentication. Keys are stored as hex SHA-256 digests so the
+// plugin config never carries usable credentials; a valid key resolves to a
+// principal and the request skips the remote authz call.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ApiKeyConfig {
+    pub header: String,
+    pub query_param: Option<String>,
+    // sha256(key) hex digest -> principal
+    pub keys: HashMap<String, String>,
+    // Shared-data key holding an additional JSON digest -> principal map,
+    // kept in sync by whatever provisions keys at runtime
+    pub shared_data_key: Option<String>,
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-api-key".into(),
+            query_param: None,
+            keys: HashMap::new(),
+            shared_data_key: None,
+        }
+    }
+}
+
+impl ApiKeyConfig {
+    pub fn extract(&self, header_value: Option<String>, path: &str) -> Option<String> {
+        header_value
+            .filter(|value| !value.is_empty())
+            .or_else(|| query_param(path, self.query_param.as_deref()?))
+            .filter(|value| !value.is_empty())
+    }
+
+    // Resolve a presented key to its principal, checking the static key set
+    // before the shared-data store
+    pub fn lookup(&self, key: &str, shared_keys: Option<&[u8]>) -> Option<String> {
+        let digest = key_digest(key);
+        if let Some(principal) = self.keys.get(&digest) {
+            return Some(principal.clone());
+        }
+
+        let mut shared: HashMap<String, String> = serde_json::from_slice(shared_keys?).ok()?;
+        shared.remove(&digest)
+    }
+}
+
+pub fn key_digest(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,228 @@
+use log::warn;
+use proxy_wasm::hostcalls;
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::Metrics;
+use crate::shared_counter;
+
+// Audit events are handed off through an Envoy shared queue so the request
+// path never waits on audit delivery. Shared queues are unbounded and expose
+// no length, so the queue depth is tracked in shared data next to it and the
+// configured drop policy decides what happens once it reaches capacity.
+
+const DEPTH_KEY: &str = "uipbdiauthz.audit.depth";
+const DROPPED_KEY: &str = "uipbdiauthz.audit.dropped";
+// Dropped total already reported by a summary event (shared by all workers)
+const SUMMARIZED_KEY: &str = "uipbdiauthz.audit.summarized";
+const CAS_RETRIES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    // Evict the oldest queued event to make room for the new one
+    DropOldest,
+    // Discard the new event when the queue is full
+    DropNewest,
+    // Never drop and never block: capacity is only used to count overflow
+    BlockNever,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub queue_name: String,
+    pub capacity: u64,
+    pub drop_policy: DropPolicy,
+    pub summary_interval_secs: u64,
+    // Ship queued events to an audit cluster from this instance (usually the
+    // singleton); without it something else must consume the queue
+    pub sink: Option<AuditSinkConfig>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            queue_name: "uipbdiauthz.audit".into(),
+            capacity: 10_000,
+            drop_policy: DropPolicy::DropOldest,
+            summary_interval_secs: 60,
+            sink: None,
+        }
+    }
+}
+
+// Events are POSTed in batches as newline-delimited JSON
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditSinkConfig {
+    pub cluster: String,
+    pub authority: String,
+    pub path: String,
+    pub batch_size: usize,
+    // Partial batches are shipped at least this often
+    pub flush_interval_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for AuditSinkConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            authority: String::new(),
+            path: "/audit".into(),
+            batch_size: 100,
+            flush_interval_ms: 1000,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+// One record per request decision
+#[derive(Debug, Serialize)]
+pub struct AuditEvent<'a> {
+    pub ts: u64,
+    pub principal: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub decision: &'a str,
+    pub status: u32,
+    // Time from request headers to the decision
+    pub latency_ms: u64,
+}
+
+// Emitted when events were lost since the previous summary
+#[derive(Debug, Serialize)]
+struct DropSummary {
+    kind: &'static str,
+    ts: u64,
+    dropped: u64,
+    total_dropped: u64,
+    policy: DropPolicy,
+    capacity: u64,
+}
+
+pub fn publish(config: &AuditConfig, queue_id: u32, event: &AuditEvent, metrics: &Metrics) {
+    match serde_json::to_vec(event) {
+        Ok(payload) => enqueue(config, config.drop_policy, queue_id, &payload, metrics),
+        Err(e) => warn!("[AUDIT] Failed to serialize audit event: {}", e),
+    }
+}
+
+// Called periodically by the root context; at most one worker wins the CAS and
+// reports a given batch of drops
+pub fn publish_drop_summary(config: &AuditConfig, queue_id: u32, now: u64, metrics: &Metrics) {
+    let (total_dropped, _) = shared_counter::read(DROPPED_KEY);
+    let (summarized, cas) = shared_counter::read(SUMMARIZED_KEY);
+    if total_dropped <= summarized || !shared_counter::write(SUMMARIZED_KEY, total_dropped, cas) {
+        return;
+    }
+
+    let summary = DropSummary {
+        kind: "audit_drop_summary",
+        ts: now,
+        dropped: total_dropped - summarized,
+        total_dropped,
+        policy: config.drop_policy,
+        capacity: config.capacity,
+    };
+    warn!(
+        "[AUDIT] {} audit events dropped in the last interval ({} total, policy {:?})",
+        summary.dropped, total_dropped, config.drop_policy
+    );
+
+    // The summary itself must not be lost to the policy it reports on
+    if let Ok(payload) = serde_json::to_vec(&summary) {
+        enqueue(config, DropPolicy::DropOldest, queue_id, &payload, metrics);
+    }
+}
+
+// Dequeue up to `batch_size` events as an NDJSON body; returns the body and
+// the number of events in it
+pub fn take_batch(queue_id: u32, batch_size: usize) -> Option<(Vec<u8>, usize)> {
+    let mut body = Vec::new();
+    let mut count = 0;
+    while count < batch_size {
+        match hostcalls::dequeue_shared_queue(queue_id) {
+            Ok(Some(event)) => {
+                body.extend_from_slice(&event);
+                body.push(b'\n');
+                count += 1;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                warn!("[AUDIT] Failed to dequeue audit event: {:?}", e);
+                break;
+            }
+        }
+    }
+
+    release(count);
+    (count > 0).then_some((body, count))
+}
+
+// Events currently queued, as tracked next to the queue
+pub fn queue_depth() -> u64 {
+    shared_counter::read(DEPTH_KEY).0
+}
+
+// Free queue slots taken by the consumer
+fn release(count: usize) {
+    if count > 0 {
+        shared_counter::add(DEPTH_KEY, -(count as i64));
+    }
+}
+
+fn enqueue(
+    config: &AuditConfig,
+    policy: DropPolicy,
+    queue_id: u32,
+    payload: &[u8],
+    metrics: &Metrics,
+) {
+    if !admit(config, policy, queue_id, metrics) {
+        record_drop(metrics);
+        return;
+    }
+
+    if let Err(e) = hostcalls::enqueue_shared_queue(queue_id, Some(payload)) {
+        warn!("[AUDIT] Failed to enqueue audit event: {:?}", e);
+        shared_counter::add(DEPTH_KEY, -1);
+        record_drop(metrics);
+    }
+}
+
+// Reserve a slot for a new event according to the drop policy
+fn admit(config: &AuditConfig, policy: DropPolicy, queue_id: u32, metrics: &Metrics) -> bool {
+    for _ in 0..CAS_RETRIES {
+        let (depth, cas) = shared_counter::read(DEPTH_KEY);
+
+        if depth < config.capacity || policy == DropPolicy::BlockNever {
+            if depth >= config.capacity {
+                metrics.audit_overflow.increment(1);
+            }
+            if shared_counter::write(DEPTH_KEY, depth + 1, cas) {
+                return true;
+            }
+            continue;
+        }
+
+        return match policy {
+            DropPolicy::DropNewest => false,
+            // Evicting the head keeps the depth unchanged
+            _ => match hostcalls::dequeue_shared_queue(queue_id) {
+                Ok(Some(_)) => {
+                    record_drop(metrics);
+                    true
+                }
+                // Depth counter is stale (e.g. consumer restarted); resync it
+                _ => shared_counter::write(DEPTH_KEY, 1, cas),
+            },
+        };
+    }
+    false
+}
+
+fn record_drop(metrics: &Metrics) {
+    metrics.audit_dropped.increment(1);
+    shared_counter::add(DROPPED_KEY, 1);
+}
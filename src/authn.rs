@@ -0,0 +1,135 @@
+use serde::Deserialize;
+
+use crate::uipbdiauthz::FilterResponse;
+
+// A second call dispatched alongside the authz call, typically to a token
+// validation service, with the same FilterRequest. The request is resumed
+// once both calls answered: an authn deny or error wins, then the authz
+// verdict; when both allow, the authn identity and headers are kept and the
+// authz service may override headers.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthnConfig {
+    pub cluster: String,
+    pub service: String,
+    pub method: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for AuthnConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            service: "authengine.UIPBDIAuthZProcessor".to_string(),
+            method: "processReq".to_string(),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl AuthnConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.cluster.is_empty() {
+            return Err("authn.cluster is required".into());
+        }
+        Ok(())
+    }
+}
+
+// Answer of one call: the reply, or the (status, body) to answer with
+pub type LegResult = Result<FilterResponse, (u32, &'static [u8])>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Leg {
+    Authn,
+    Authz,
+    // A chained authz service
+    Chain,
+}
+
+impl Leg {
+    pub fn name(self) -> &'static str {
+        match self {
+            Leg::Authn => "authn",
+            Leg::Authz => "authz",
+            Leg::Chain => "chain",
+        }
+    }
+}
+
+pub fn combine(authn: LegResult, authz: LegResult) -> LegResult {
+    let authn = authn?;
+    if !authn.allow {
+        return Ok(authn);
+    }
+    let authz = authz?;
+    if !authz.allow {
+        return Ok(authz);
+    }
+    let mut headers = authn.headers;
+    headers.extend(authz.headers);
+    Ok(FilterResponse {
+        allow: true,
+        user: if authn.user.is_empty() {
+            authz.user
+        } else {
+            authn.user
+        },
+        headers,
+        message: authz.message,
+        negotiate: false,
+        negotiate_token: authn.negotiate_token,
+        rewrite_path: authz.rewrite_path,
+        extensions: [authn.extensions, authz.extensions].concat(),
+        // Both answers were normalized to v1 fields
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn authn_deny_or_error_wins_over_authz() {
+        use super::combine;
+        use crate::uipbdiauthz::FilterResponse;
+
+        let allow = |user: &str, header: (&str, &str)| FilterResponse {
+            allow: true,
+            user: user.into(),
+            headers: [(header.0.to_string(), header.1.to_string())].into(),
+            ..Default::default()
+        };
+        let deny = FilterResponse {
+            message: "token expired".into(),
+            ..Default::default()
+        };
+
+        let combined = combine(
+            Ok(allow("alice", ("x-tier", "gold"))),
+            Ok(allow("", ("x-tier", "silver"))),
+        )
+        .unwrap();
+        assert!(combined.allow);
+        assert_eq!(combined.user, "alice");
+        assert_eq!(combined.headers["x-tier"], "silver");
+
+        let combined = combine(Ok(deny.clone()), Err((503, b"unavailable"))).unwrap();
+        assert_eq!(
+            (combined.allow, combined.message.as_str()),
+            (false, "token expired")
+        );
+        assert_eq!(
+            combine(Err((504, b"timeout")), Ok(allow("bob", ("a", "b"))))
+                .unwrap_err()
+                .0,
+            504
+        );
+        assert_eq!(
+            combine(Ok(allow("bob", ("a", "b"))), Err((503, b"unavailable")))
+                .unwrap_err()
+                .0,
+            503
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Deserialize;
+
+use crate::forwarded_headers;
+
+// Named authz policies picked by `:authority`, so one filter deployment can
+// front many domains with their own authz backends. A policy replaces the
+// authz cluster, the call timeout and the headers copied into FilterRequest;
+// requests matching no policy use the defaults. Policies are tried in order.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthorityPolicy {
+    pub name: String,
+    // Hosts (port ignored): `api.example.com`, `*.example.com` for any
+    // subdomain, or `*` for everything
+    pub authorities: Vec<String>,
+    // Authz cluster; the default cluster when unset
+    pub cluster: Option<String>,
+    pub timeout_ms: u64,
+    // Headers sent in FilterRequest instead of the default set, `prefix*`
+    // for header families (pseudo-headers are always sent)
+    pub headers: Option<Vec<String>>,
+}
+
+impl Default for AuthorityPolicy {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            authorities: Vec::new(),
+            cluster: None,
+            timeout_ms: 5000,
+            headers: None,
+        }
+    }
+}
+
+impl AuthorityPolicy {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("policies need a name".into());
+        }
+        if self.timeout_ms == 0 {
+            return Err(format!(
+                "policy '{}': timeout_ms must be at least 1",
+                self.name
+            ));
+        }
+        for authority in &mut self.authorities {
+            authority.make_ascii_lowercase();
+        }
+        if let Some(headers) = self.headers.as_mut() {
+            forwarded_headers::init(headers, &format!("policy '{}' headers", self.name))?;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.authorities
+            .iter()
+            .any(|pattern| match pattern.strip_prefix('*') {
+                Some("") => true,
+                Some(suffix) if suffix.starts_with('.') => host.ends_with(suffix),
+                _ => pattern == host,
+            })
+    }
+}
+
+// Index of the first policy matching `authority`
+pub fn select(policies: &[AuthorityPolicy], authority: &str) -> Option<usize> {
+    let host = host(authority).to_ascii_lowercase();
+    policies.iter().position(|policy| policy.matches(&host))
+}
+
+// `authority` without its port; IPv6 literals keep their brackets
+pub fn host(authority: &str) -> &str {
+    if authority.starts_with('[') {
+        return authority
+            .find(']')
+            .map_or(authority, |end| &authority[..=end]);
+    }
+    authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _)| host)
+}
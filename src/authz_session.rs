@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::oidc;
+use crate::pipeline::RequestSource;
+
+// Session cookie issued when the authz service allows a request. Later
+// requests that carry a valid cookie are allowed locally with the identity it
+// holds, without an authz call, until it expires. The cookie is encrypted and
+// authenticated (AES-256-GCM) like the OIDC cookies, and bound to the
+// authority, method and route of the allowed request and to its
+// `authorization` header, so one allow cannot be taken to another host,
+// another route or another token.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthzSessionConfig {
+    pub cookie_name: String,
+    // Base64-encoded 32 byte key used to encrypt the cookie
+    pub cookie_secret: String,
+    pub ttl_secs: u64,
+
+    // Decoded by `init`
+    #[serde(skip)]
+    key: Vec<u8>,
+}
+
+impl Default for AuthzSessionConfig {
+    fn default() -> Self {
+        Self {
+            cookie_name: "uip_authz".into(),
+            cookie_secret: String::new(),
+            ttl_secs: 300,
+            key: Vec::new(),
+        }
+    }
+}
+
+// Contents of the cookie
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthzSession {
+    pub user: String,
+    // `x-uip-*` identity headers of the allow, restored on later requests
+    pub headers: Vec<(String, String)>,
+    pub authority: String,
+    pub method: String,
+    // See `route`
+    pub route: String,
+    // Digest of the `authorization` header, empty when there was none
+    pub credential: String,
+    // Exchanged `authorization` header for the upstream, if any
+    #[serde(default)]
+    pub upstream_authorization: Option<String>,
+    // Path the authz service rewrote (requested, rewritten), if it did
+    #[serde(default)]
+    pub path_rewrite: Option<(String, String)>,
+    pub exp: u64,
+}
+
+// Route the request matched: Envoy's route name, or the path without its
+// query when the route has none
+pub fn route(source: &dyn RequestSource, path: &str) -> String {
+    source
+        .property(&["xds", "route_name"])
+        .and_then(|name| String::from_utf8(name).ok())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| path.split('?').next().unwrap_or_default().to_string())
+}
+
+pub fn credential_digest(authorization: Option<&str>) -> String {
+    authorization.map_or_else(String::new, |authorization| {
+        Sha256::digest(authorization.as_bytes())[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    })
+}
+
+impl AuthzSessionConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.cookie_name.is_empty() {
+            return Err("authz_session.cookie_name is required".into());
+        }
+        if self.ttl_secs == 0 {
+            return Err("authz_session.ttl_secs must be positive".into());
+        }
+        self.key = oidc::cookie_key(&self.cookie_secret, "authz_session.cookie_secret")?;
+        Ok(())
+    }
+
+    // Set-Cookie value for an allowed request
+    pub fn issue(&self, session: &AuthzSession) -> Option<String> {
+        let sealed = oidc::seal(&self.key, &self.cookie_name, session)?;
+        Some(format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            self.cookie_name, sealed, self.ttl_secs
+        ))
+    }
+
+    // The request's session, when its cookie is valid for this authority,
+    // method, route and credential and has not expired
+    pub fn validate(
+        &self,
+        source: &dyn RequestSource,
+        route: &str,
+        now: u64,
+    ) -> Option<AuthzSession> {
+        let cookie_header = source.header("cookie")?;
+        let value = oidc::find_cookie(&cookie_header, &self.cookie_name)?;
+        let session: AuthzSession = oidc::open(&self.key, &self.cookie_name, value)?;
+        let authority = source.header(":authority").unwrap_or_default();
+        let method = source.header(":method").unwrap_or_default();
+        let authorization = source.header("authorization");
+        (session.exp > now
+            && session.authority.eq_ignore_ascii_case(&authority)
+            && session.method == method
+            && session.route == route
+            && session.credential == credential_digest(authorization.as_deref()))
+        .then_some(session)
+    }
+}
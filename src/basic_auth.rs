@@ -0,0 +1,32 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::Deserialize;
+
+// `Authorization: Basic` handling. The username is decoded and sent to the
+// authz service as its own FilterRequest field; the password is never kept
+// outside the original header value and never logged.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BasicAuthConfig {
+    // Remove the Basic credential from the request forwarded upstream once
+    // the authz service has seen it
+    pub strip_credentials: bool,
+}
+
+pub fn is_basic(authorization: &str) -> bool {
+    authorization
+        .get(..6)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("basic "))
+}
+
+// Username from a `Basic base64(user:password)` value
+pub fn username(authorization: &str) -> Option<String> {
+    if !is_basic(authorization) {
+        return None;
+    }
+    let decoded = STANDARD.decode(authorization[6..].trim()).ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (user, _password) = credentials.split_once(':')?;
+    Some(user.to_string())
+}
@@ -0,0 +1,91 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::metrics::Metric;
+
+// Requests whose path matches a bypass rule (health checks, static assets)
+// are let through without the authz call. Rules match the path without its
+// query string, after normalization when `path.normalize` is on, so dot
+// segments cannot smuggle a protected path past a prefix rule. Each rule
+// counts its requests in `uipbdiauthz.bypass.<name>`.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BypassRule {
+    pub name: String,
+    // Exactly one of the following
+    pub exact: Option<String>,
+    pub prefix: Option<String>,
+    // `*` matches within a segment, `**` across segments, `?` one character
+    pub glob: Option<String>,
+    pub regex: Option<String>,
+
+    #[serde(skip)]
+    pattern: Option<Regex>,
+    #[serde(skip)]
+    pub counter: Metric,
+}
+
+impl BypassRule {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("path_bypass rules need a name".into());
+        }
+        let label = format!("path_bypass rule '{}'", self.name);
+        let pattern = match (&self.exact, &self.prefix, &self.glob, &self.regex) {
+            (Some(_), None, None, None) | (None, Some(_), None, None) => None,
+            (None, None, Some(glob), None) => Some(glob_to_regex(glob)),
+            (None, None, None, Some(regex)) => Some(regex.clone()),
+            _ => {
+                return Err(format!(
+                    "{} needs exactly one of exact, prefix, glob, regex",
+                    label
+                ))
+            }
+        };
+        if let Some(pattern) = pattern {
+            self.pattern = Some(Regex::new(&pattern).map_err(|e| format!("{}: {}", label, e))?);
+        }
+        Ok(())
+    }
+
+    pub fn define_counter(&mut self) {
+        self.counter = Metric::counter(&format!("uipbdiauthz.bypass.{}", self.name));
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if let Some(exact) = &self.exact {
+            return path == exact;
+        }
+        if let Some(prefix) = &self.prefix {
+            return path.starts_with(prefix.as_str());
+        }
+        self.pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_match(path))
+    }
+}
+
+// Index of the first rule matching `path` (query string included or not)
+pub fn matching_rule(rules: &[BypassRule], path: &str) -> Option<usize> {
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    rules.iter().position(|rule| rule.matches(path))
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
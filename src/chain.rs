@@ -0,0 +1,90 @@
+use serde::Deserialize;
+
+use crate::uipbdiauthz::{FilterRequest, FilterResponse, Header};
+
+// Authz services called one after another once the primary authz call (and
+// the authn call, if any) allowed the request. Each step gets the
+// FilterRequest with the headers returned so far added, so it can build on
+// what earlier services resolved (e.g. the user's tenant or roles). A deny or
+// error from any step decides the request; the headers of all steps are
+// applied when the last one allows.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChainStep {
+    pub cluster: String,
+    pub service: String,
+    pub method: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for ChainStep {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            service: "authengine.UIPBDIAuthZProcessor".to_string(),
+            method: "processReq".to_string(),
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl ChainStep {
+    pub fn init(&self) -> Result<(), String> {
+        if self.cluster.is_empty() {
+            return Err("chain steps need a cluster".into());
+        }
+        Ok(())
+    }
+}
+
+// Add the headers returned so far to the request for the next step,
+// replacing client headers of the same name
+pub fn enrich(request: &mut FilterRequest, reply: &FilterResponse) {
+    request.headers.retain(|header| {
+        !reply
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(&header.key))
+    });
+    for (key, value) in &reply.headers {
+        request.headers.push(Header {
+            key: key.to_ascii_lowercase(),
+            value: value.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn chain_step_request_carries_earlier_headers() {
+        use crate::uipbdiauthz::{FilterRequest, FilterResponse, Header};
+
+        let mut request = FilterRequest {
+            headers: vec![
+                Header {
+                    key: "x-tenant".into(),
+                    value: "spoofed".into(),
+                },
+                Header {
+                    key: "accept".into(),
+                    value: "*/*".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        let reply = FilterResponse {
+            allow: true,
+            headers: [("X-Tenant".to_string(), "acme".to_string())].into(),
+            ..Default::default()
+        };
+        super::enrich(&mut request, &reply);
+        let headers: Vec<_> = request
+            .headers
+            .iter()
+            .map(|header| (header.key.as_str(), header.value.as_str()))
+            .collect();
+        assert_eq!(headers, [("accept", "*/*"), ("x-tenant", "acme")]);
+    }
+}
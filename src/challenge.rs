@@ -0,0 +1,88 @@
+use serde::Deserialize;
+
+// `WWW-Authenticate` challenge for requests the authz service denies. The
+// filter builds an RFC 6750 style challenge (`Bearer error="invalid_token"`
+// unless configured otherwise) and the service's message only goes into
+// `error_description`, quoted, so a service message cannot break the header or
+// add parameters.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ChallengeConfig {
+    pub scheme: String,
+    // Parameters left out when empty
+    pub realm: String,
+    pub scope: String,
+    pub error: String,
+    // Send the authz message as `error_description`
+    pub include_description: bool,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        Self {
+            scheme: "Bearer".into(),
+            realm: String::new(),
+            scope: String::new(),
+            error: "invalid_token".into(),
+            include_description: true,
+        }
+    }
+}
+
+// RFC 7230 quoted-string; characters a header value cannot carry are dropped
+fn quote(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => {}
+        }
+    }
+    out.push('"');
+}
+
+impl ChallengeConfig {
+    pub fn init(&self) -> Result<(), String> {
+        let token = |value: &str| {
+            value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+        };
+        if self.scheme.is_empty() || !token(&self.scheme) {
+            return Err(format!("challenge.scheme '{}' is not a token", self.scheme));
+        }
+        Ok(())
+    }
+
+    // Challenge for a denial with the authz service's message
+    pub fn header(&self, message: &str) -> String {
+        let description = if self.include_description {
+            message
+        } else {
+            ""
+        };
+        let params = [
+            ("realm", self.realm.as_str()),
+            ("scope", self.scope.as_str()),
+            ("error", self.error.as_str()),
+            ("error_description", description),
+        ];
+        let mut header = self.scheme.clone();
+        for (index, (name, value)) in params
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .enumerate()
+        {
+            header.push_str(if index == 0 { " " } else { ", " });
+            header.push_str(name);
+            header.push('=');
+            quote(value, &mut header);
+        }
+        header
+    }
+}
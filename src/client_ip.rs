@@ -0,0 +1,134 @@
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::pipeline::RequestSource;
+
+// Client IP evaluation. The client IP is taken from `x-forwarded-for`, counting
+// `xff_trusted_hops` entries from the right (the addresses appended by the
+// proxies in front of the filter) over all its headers, or from the
+// connection's source address.
+// Denied addresses are answered with 403 locally; all others reach the authz
+// service with the normalized address in `FilterRequest.client_ip`, unless
+// `allowlist_bypasses_authz` lets allowlisted addresses through directly.
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ClientIpConfig {
+    // 0 ignores x-forwarded-for and uses the source address; N takes the Nth
+    // entry from the right. Requests with fewer entries use the source address.
+    pub xff_trusted_hops: usize,
+    // CIDRs (or single addresses); when set, other clients are denied
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    // Allowlisted clients skip the authz call
+    pub allowlist_bypasses_authz: bool,
+
+    #[serde(skip)]
+    allow_ranges: Vec<Cidr>,
+    #[serde(skip)]
+    deny_ranges: Vec<Cidr>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Denied,
+    Allowlisted,
+    // Neither list decides; the authz service does
+    Undecided,
+}
+
+impl ClientIpConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        self.allow_ranges = parse_ranges("client_ip.allow", &self.allow)?;
+        self.deny_ranges = parse_ranges("client_ip.deny", &self.deny)?;
+        Ok(())
+    }
+
+    pub fn client_ip(&self, source: &dyn RequestSource) -> Option<IpAddr> {
+        let forwarded = (self.xff_trusted_hops > 0)
+            .then(|| source.header_list(FORWARDED_FOR))
+            .flatten()
+            .and_then(|header| {
+                let entries: Vec<&str> = header.split(',').map(str::trim).collect();
+                let index = entries.len().checked_sub(self.xff_trusted_hops)?;
+                parse_address(entries[index])
+            });
+        forwarded.or_else(|| source.source_address().as_deref().and_then(parse_address))
+    }
+
+    // Deny list first; an unknown client IP fails an allowlist
+    pub fn evaluate(&self, ip: Option<IpAddr>) -> Verdict {
+        let listed = |ranges: &[Cidr]| ip.is_some_and(|ip| ranges.iter().any(|r| r.contains(ip)));
+        if listed(&self.deny_ranges) {
+            return Verdict::Denied;
+        }
+        if self.allow_ranges.is_empty() {
+            return Verdict::Undecided;
+        }
+        if !listed(&self.allow_ranges) {
+            return Verdict::Denied;
+        }
+        if self.allowlist_bypasses_authz {
+            Verdict::Allowlisted
+        } else {
+            Verdict::Undecided
+        }
+    }
+}
+
+// `1.2.3.4`, `1.2.3.4:80`, `[::1]:80` or `::1`; IPv4-mapped IPv6 addresses
+// become plain IPv4
+pub fn parse_address(value: &str) -> Option<IpAddr> {
+    let ip = value
+        .parse::<IpAddr>()
+        .or_else(|_| value.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()?;
+    Some(ip.to_canonical())
+}
+
+#[derive(Debug)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl Cidr {
+    fn parse(value: &str) -> Option<Self> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, len)) => (address, Some(len.parse().ok()?)),
+            None => (value, None),
+        };
+        let network = address.parse::<IpAddr>().ok()?.to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        (prefix_len <= max_len).then_some(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+pub fn parse_ranges(label: &str, values: &[String]) -> Result<Vec<Cidr>, String> {
+    values
+        .iter()
+        .map(|value| {
+            Cidr::parse(value).ok_or_else(|| format!("{}: invalid CIDR '{}'", label, value))
+        })
+        .collect()
+}
@@ -0,0 +1,177 @@
+use prost::Message;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::uipbdiauthz::FilterRequest;
+
+// Identical concurrent authz calls share one call. A request whose key (its
+// authz cluster and encoded FilterRequest, less the headers that differ on
+// every request) matches a call already in flight on the worker does not
+// dispatch its own: it is parked on that call, and when the verdict arrives every parked request is decided
+// with it, as if it had been its own answer. The verdict is still applied per
+// request, so each gets its own headers, cookies and audit records.
+//
+// Coalescing is per worker. Envoy delivers a shared queue's wakeups to a
+// single worker, so parking requests of other workers would leave them
+// waiting on the ticks; a client's burst mostly arrives on one connection,
+// which a worker owns, anyway.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CoalesceConfig {
+    // FilterRequest headers left out of the key: ids and trace context that
+    // differ on every request but not in what the authz service decides on
+    pub ignore_headers: Vec<String>,
+    // Requests parked on one call beyond this dispatch their own
+    pub max_waiters: usize,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            ignore_headers: vec![
+                "x-request-id".into(),
+                "x-correlation-id".into(),
+                "traceparent".into(),
+                "tracestate".into(),
+            ],
+            max_waiters: 100,
+        }
+    }
+}
+
+impl CoalesceConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.max_waiters == 0 {
+            return Err("coalesce.max_waiters must be at least 1".into());
+        }
+        for name in &mut self.ignore_headers {
+            name.make_ascii_lowercase();
+        }
+        Ok(())
+    }
+
+    // Digest of everything the request's verdict depends on: the cluster and
+    // the serialized FilterRequest sent to it
+    pub fn key(&self, cluster: &str, message: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        // Length-prefixed, so the parts cannot run into each other
+        hasher.update((cluster.len() as u64).to_le_bytes());
+        hasher.update(cluster.as_bytes());
+        match FilterRequest::decode(message) {
+            Ok(mut request) => {
+                request
+                    .headers
+                    .retain(|header| !self.ignore_headers.contains(&header.key));
+                hasher.update(request.encode_to_vec());
+            }
+            Err(_) => hasher.update(message),
+        }
+        hasher.finalize()[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// A request's part in a coalesced call
+#[derive(Debug)]
+pub enum Membership {
+    // Dispatched the call for the key
+    Leader(String),
+    // Parked on another request's call
+    Follower,
+}
+
+// Request context taking part in a coalesced call
+pub struct Member<T> {
+    pub context_id: u32,
+    pub engine: T,
+}
+
+struct Call<T> {
+    leader: u32,
+    followers: Vec<Member<T>>,
+}
+
+// Authz calls in flight on the worker, by key. A call that ends unanswered
+// (its request went away) leaves its followers to the root tick, which has
+// them dispatch again.
+pub struct Coalescer<T> {
+    calls: HashMap<String, Call<T>>,
+    orphans: Vec<Member<T>>,
+}
+
+pub type SharedCoalescer<T> = Rc<RefCell<Coalescer<T>>>;
+
+impl<T> Default for Coalescer<T> {
+    fn default() -> Self {
+        Self {
+            calls: HashMap::new(),
+            orphans: Vec::new(),
+        }
+    }
+}
+
+impl<T> Coalescer<T> {
+    // Park the request on the call for `key`; gives the member back when
+    // there is no such call or it has `max_waiters` already
+    pub fn follow(
+        &mut self,
+        config: &CoalesceConfig,
+        key: &str,
+        member: Member<T>,
+    ) -> Result<(), Member<T>> {
+        match self.calls.get_mut(key) {
+            Some(call) if call.followers.len() < config.max_waiters => {
+                call.followers.push(member);
+                Ok(())
+            }
+            _ => Err(member),
+        }
+    }
+
+    // Record the call the request dispatched for `key`
+    pub fn lead(&mut self, key: &str, context_id: u32) {
+        self.calls.entry(key.to_string()).or_insert(Call {
+            leader: context_id,
+            followers: Vec::new(),
+        });
+    }
+
+    // The call for `key` was answered: its followers, to be decided with the
+    // answer
+    pub fn finish(&mut self, key: &str, context_id: u32) -> Vec<Member<T>> {
+        match self.calls.get(key) {
+            Some(call) if call.leader == context_id => self
+                .calls
+                .remove(key)
+                .map(|call| call.followers)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    // The call for `key` ended unanswered: its followers wait for the tick
+    pub fn abandon(&mut self, key: &str, context_id: u32) {
+        let followers = self.finish(key, context_id);
+        self.orphans.extend(followers);
+    }
+
+    pub fn take_orphans(&mut self) -> Vec<Member<T>> {
+        std::mem::take(&mut self.orphans)
+    }
+
+    // A parked request went away
+    pub fn leave(&mut self, context_id: u32) {
+        for call in self.calls.values_mut() {
+            call.followers
+                .retain(|member| member.context_id != context_id);
+        }
+        self.orphans
+            .retain(|member| member.context_id != context_id);
+    }
+}
@@ -0,0 +1,150 @@
+use log::warn;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::pipeline::RequestSource;
+use crate::shared_codec;
+use crate::shared_counter::Counter;
+
+// Ceiling on authz calls outstanding across all workers, protecting the wasm
+// heap (every paused request holds its context) and the authz service during
+// traffic spikes. The count lives in shared data: a request takes a slot
+// before its call is dispatched and gives it back once the call is answered
+// or the request goes away. Requests finding no free slot get the failure
+// mode right away, unless the worker queues them: queued requests stay paused
+// and the root tick dispatches their calls, oldest first, as slots free up.
+
+const KEY: &str = "uipbdiauthz.authz_in_flight";
+const CAS_RETRIES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyLimitConfig {
+    pub max_in_flight: u64,
+    // Requests each worker holds at the ceiling (none when 0); beyond this
+    // they get the failure mode
+    pub queue_size: usize,
+    // Queued requests waiting longer than this get the failure mode
+    pub queue_timeout_ms: u64,
+}
+
+impl Default for ConcurrencyLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 0,
+            queue_size: 0,
+            queue_timeout_ms: 1000,
+        }
+    }
+}
+
+impl ConcurrencyLimitConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.max_in_flight == 0 {
+            return Err("concurrency_limit.max_in_flight must be at least 1".into());
+        }
+        if self.queue_size > 0 && self.queue_timeout_ms == 0 {
+            return Err("concurrency_limit.queue_timeout_ms must be positive".into());
+        }
+        Ok(())
+    }
+
+    // Some(true) when a slot was taken, Some(false) at the ceiling, None when
+    // the count could not be updated (the call goes out uncounted)
+    pub fn acquire(&self, source: &dyn RequestSource) -> Option<bool> {
+        for _ in 0..CAS_RETRIES {
+            let (in_flight, cas) = read(source);
+            if in_flight >= self.max_in_flight {
+                return Some(false);
+            }
+            if write(source, in_flight + 1, cas) {
+                return Some(true);
+            }
+        }
+        warn!(
+            "[CONCURRENCY] Could not update '{}', dispatching uncounted",
+            KEY
+        );
+        None
+    }
+}
+
+pub fn release(source: &dyn RequestSource) {
+    for _ in 0..CAS_RETRIES {
+        let (in_flight, cas) = read(source);
+        if write(source, in_flight.saturating_sub(1), cas) {
+            return;
+        }
+    }
+    warn!("[CONCURRENCY] Could not release a slot in '{}'", KEY);
+}
+
+// Request context waiting for a slot
+pub struct Queued<T> {
+    pub context_id: u32,
+    // Gets the failure mode from the root tick after this
+    pub deadline_ms: u64,
+    pub engine: T,
+}
+
+// Requests of the worker waiting for a slot, oldest first
+pub struct CallQueue<T> {
+    entries: VecDeque<Queued<T>>,
+}
+
+pub type SharedCallQueue<T> = Rc<RefCell<CallQueue<T>>>;
+
+impl<T> Default for CallQueue<T> {
+    fn default() -> Self {
+        Self {
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> CallQueue<T> {
+    // False when the queue is full
+    pub fn push(&mut self, config: &ConcurrencyLimitConfig, entry: Queued<T>) -> bool {
+        if self.entries.len() >= config.queue_size {
+            return false;
+        }
+        self.entries.push_back(entry);
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<Queued<T>> {
+        self.entries.pop_front()
+    }
+
+    // Put back a request that found no free slot, keeping its place
+    pub fn push_front(&mut self, entry: Queued<T>) {
+        self.entries.push_front(entry);
+    }
+
+    pub fn take_expired(&mut self, now_ms: u64) -> Vec<Queued<T>> {
+        let (expired, waiting): (VecDeque<_>, VecDeque<_>) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition(|entry| entry.deadline_ms <= now_ms);
+        self.entries = waiting;
+        expired.into()
+    }
+
+    pub fn remove_context(&mut self, context_id: u32) {
+        self.entries.retain(|entry| entry.context_id != context_id);
+    }
+}
+
+fn read(source: &dyn RequestSource) -> (u64, Option<u32>) {
+    let (bytes, cas) = source.shared_data_cas(KEY);
+    let counter = bytes
+        .as_deref()
+        .and_then(shared_codec::from_bytes::<Counter>)
+        .map_or(0, |counter| counter.0);
+    (counter, cas)
+}
+
+fn write(source: &dyn RequestSource, value: u64, cas: Option<u32>) -> bool {
+    source.update_shared_data(KEY, &shared_codec::to_bytes(&Counter(value)), cas)
+}
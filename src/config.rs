@@ -0,0 +1,368 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::api_key::ApiKeyConfig;
+use crate::audit::AuditConfig;
+use crate::authn::AuthnConfig;
+use crate::authority_policy::AuthorityPolicy;
+use crate::authz_session::AuthzSessionConfig;
+use crate::basic_auth::BasicAuthConfig;
+use crate::bypass::BypassRule;
+use crate::chain::ChainStep;
+use crate::challenge::ChallengeConfig;
+use crate::client_ip::ClientIpConfig;
+use crate::coalesce::CoalesceConfig;
+use crate::concurrency::ConcurrencyLimitConfig;
+use crate::correlation::CorrelationIdConfig;
+use crate::cors::CorsConfig;
+use crate::credentials::{MissingCredentialsConfig, StripCredentialsConfig};
+use crate::deadline::DeadlineConfig;
+use crate::debug_headers::DebugHeadersConfig;
+use crate::denial_audit::DenialAuditConfig;
+use crate::deny_template::DenyTemplateConfig;
+use crate::dry_run::DryRunConfig;
+use crate::experiment::ExperimentConfig;
+use crate::expr::ExprRule;
+use crate::forwarded_headers::{
+    self, ForwardAllConfig, HeaderRules, MultiValueMode, TruncationConfig,
+};
+use crate::health::HealthCheckConfig;
+use crate::identity_headers::TrustedHeadersConfig;
+use crate::identity_signature::IdentitySigningConfig;
+use crate::jwks::JwksConfig;
+use crate::leak_check::LeakDetectionConfig;
+use crate::limits::RequestLimitsConfig;
+use crate::locale::LocalizedMessagesConfig;
+use crate::logging::LoggingConfig;
+use crate::memory_budget::MemoryBudgetConfig;
+use crate::memory_report::MemoryReportConfig;
+use crate::method_rules::MethodRule;
+use crate::mirror::MirrorConfig;
+use crate::oidc::OidcConfig;
+use crate::path::PathConfig;
+use crate::problem::ProblemDetailsConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::readiness::ReadinessConfig;
+use crate::replay::ReplayConfig;
+use crate::rollout::RolloutConfig;
+use crate::service_credential::ServiceCredentialConfig;
+use crate::signature::SignatureConfig;
+use crate::stream::StreamConfig;
+use crate::tenant::TenantRoutingConfig;
+use crate::throughput::ThroughputConfig;
+use crate::timeout_guard::TimeoutGuardConfig;
+use crate::trace::TraceConfig;
+use crate::trailers::TrailersConfig;
+use crate::upgrade::UpgradeConfig;
+use crate::warm_up::WarmUpConfig;
+
+// What to do with a request when no authz verdict can be obtained
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureMode {
+    #[default]
+    Deny,
+    Allow,
+}
+
+// Plugin configuration, parsed once in the root context from the JSON passed
+// via the Envoy `configuration` block. Every field has a default so an empty
+// `{}` keeps the original behaviour of the filter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    // Optional OIDC browser login flow (disabled when absent)
+    pub oidc: Option<OidcConfig>,
+    // Session cookie that lets allowed clients skip the authz call until it
+    // expires (disabled when absent)
+    pub authz_session: Option<AuthzSessionConfig>,
+    // Optional local API-key authentication (disabled when absent)
+    pub api_keys: Option<ApiKeyConfig>,
+    // Optional HMAC request-signature verification (disabled when absent)
+    pub request_signing: Option<SignatureConfig>,
+    // Answer requests without any credential with 401 locally (disabled when
+    // absent)
+    pub reject_missing_credentials: Option<MissingCredentialsConfig>,
+    // Remove end-user credentials from allowed upstream requests (disabled
+    // when absent)
+    pub strip_credentials: Option<StripCredentialsConfig>,
+    // Reject reused signature nonces and one-time tokens (disabled when absent)
+    pub replay_protection: Option<ReplayConfig>,
+    // Per-principal request limits (disabled when absent)
+    pub rate_limit: Option<RateLimitConfig>,
+    // Header and `:path` size limits (disabled when absent)
+    pub request_limits: Option<RequestLimitsConfig>,
+    // Client IP allow/deny lists; the client IP is sent in FilterRequest
+    // (disabled when absent)
+    pub client_ip: Option<ClientIpConfig>,
+    // Authz cluster, timeout and headers per `:authority`, first match wins
+    pub policies: Vec<AuthorityPolicy>,
+    // Authz cluster per tenant, overriding the policy's (disabled when absent)
+    pub tenant_routing: Option<TenantRoutingConfig>,
+    // Paths let through without the authz call, first match wins
+    pub path_bypass: Vec<BypassRule>,
+    // Expression rules (bypass / deny / enforce), first match wins
+    pub rules: Vec<ExprRule>,
+    // HTTP methods authorized per path prefix, first match wins (everything
+    // is authorized when empty)
+    pub method_rules: Vec<MethodRule>,
+    // Let CORS preflights skip the authz call, or answer them (disabled when
+    // absent)
+    pub cors: Option<CorsConfig>,
+    // Identity headers removed from incoming requests (disabled when absent)
+    pub strip_trusted_headers: Option<TrustedHeadersConfig>,
+    // Sign `x-uip-user` for upstream verification (disabled when absent)
+    pub identity_signing: Option<IdentitySigningConfig>,
+    // Byte budget for headers this filter adds to the upstream request;
+    // lowest-priority additions are dropped first (unbounded when unset)
+    pub added_header_budget_bytes: Option<usize>,
+    // FilterRequests larger than this are counted and logged (never rejected)
+    pub oversized_request_bytes: Option<usize>,
+    // Sent in every FilterRequest as `attributes`
+    pub request_attributes: HashMap<String, String>,
+    // Downstream TLS version, SNI and peer certificate in FilterRequest.tls
+    pub send_tls_attributes: bool,
+    // FilterRequest schema v2: version, identity, connection and body digest
+    pub filter_request_v2: bool,
+    // Per-request audit events published to a shared queue (disabled when absent)
+    pub audit: Option<AuditConfig>,
+    // Every denied request sent to an audit cluster over gRPC (disabled when
+    // absent)
+    pub denial_audit: Option<DenialAuditConfig>,
+    // Sampled copies of FilterRequests POSTed to an analytics cluster
+    // (disabled when absent)
+    pub mirror: Option<MirrorConfig>,
+    // Authorize requests with a body once their trailers arrive, sending the
+    // trailers along (disabled when absent)
+    pub request_trailers: Option<TrailersConfig>,
+    // Requests identical to one whose authz call is in flight wait for its
+    // verdict instead of calling again (disabled when absent)
+    pub coalesce: Option<CoalesceConfig>,
+    // Authz calls over a long-lived stream per worker (disabled when absent)
+    pub stream: Option<StreamConfig>,
+    // Applied when the authz verdict is not available in time
+    pub failure_mode: FailureMode,
+    // Applied when the FilterRequest cannot be serialized: `deny` answers 500
+    pub serialization_failure: FailureMode,
+    // Log, meter and export decisions without enforcing them: nothing is
+    // blocked and no request headers are changed
+    pub monitor_only: bool,
+    // Enforce only a share of requests, monitoring the rest (disabled when
+    // absent)
+    pub rollout: Option<RolloutConfig>,
+    // Monitor-only decisions for single requests from internal networks
+    // (disabled when absent)
+    pub dry_run: Option<DryRunConfig>,
+    // Settle paused requests just before their route timeout (disabled when absent)
+    pub timeout_guard: Option<TimeoutGuardConfig>,
+    // Bound authz call timeouts by the caller's deadline (disabled when absent)
+    pub deadline: Option<DeadlineConfig>,
+    // Ceiling on authz calls outstanding across workers (disabled when absent)
+    pub concurrency_limit: Option<ConcurrencyLimitConfig>,
+    // Worker heap budget (disabled when absent)
+    pub memory_budget: Option<MemoryBudgetConfig>,
+    // Authn call dispatched alongside the authz call (disabled when absent)
+    pub authn: Option<AuthnConfig>,
+    // Authz services called in order after the authz call allowed
+    pub chain: Vec<ChainStep>,
+    // Send request id, correlation id and trace context only as gRPC metadata
+    // of unary authz calls, not also as FilterRequest headers
+    pub request_context_metadata_only: bool,
+    // Headers sent in FilterRequest on top of the default set, `prefix*` for
+    // header families; a policy's `headers` replace both
+    pub forward_headers: Vec<String>,
+    // Regex rules adding headers, optionally renamed; also replaced by a
+    // policy's `headers`
+    pub forward_header_rules: HeaderRules,
+    // Forwarded headers the request carries more than once
+    pub multi_value_headers: MultiValueMode,
+    // Forward every request header but the excluded ones, up to a size cap
+    // (disabled when absent); also replaced by a policy's `headers`
+    pub forward_all_headers: Option<ForwardAllConfig>,
+    // Cap on each forwarded header value (disabled when absent)
+    pub header_truncation: Option<TruncationConfig>,
+    // Cookies sent in FilterRequest.cookies; when set, the `cookie` header
+    // is never forwarded
+    pub forward_cookies: Vec<String>,
+    pub basic_auth: BasicAuthConfig,
+    // Request rate / decision mix gauges (disabled when absent)
+    pub throughput: Option<ThroughputConfig>,
+    // Periodic worker memory report (disabled when absent)
+    pub memory_report: Option<MemoryReportConfig>,
+    // Per-request leak warning thresholds (memory-tracking builds only)
+    pub leak_detection: LeakDetectionConfig,
+    // Background JWKS refresh into shared data (disabled when absent)
+    pub jwks: Option<JwksConfig>,
+    // gRPC health probes of the authz cluster (disabled when absent)
+    pub health_check: Option<HealthCheckConfig>,
+    // Hold back authz calls until the authz cluster answers a probe
+    // (disabled when absent)
+    pub readiness: Option<ReadinessConfig>,
+    // Shadow authz calls for locally decided requests (disabled when absent)
+    pub experiment: Option<ExperimentConfig>,
+    // Credential the filter presents on authz calls, refreshed in the
+    // background (disabled when absent)
+    pub service_credential: Option<ServiceCredentialConfig>,
+    // Connect to the authz cluster at VM start (disabled when absent)
+    pub warm_up: Option<WarmUpConfig>,
+    // Generate `x-correlation-id` when missing (disabled when absent)
+    pub correlation_id: Option<CorrelationIdConfig>,
+    // Generate a W3C `traceparent` when missing (disabled when absent)
+    pub trace: Option<TraceConfig>,
+    // `:path` normalization and original-path preservation
+    pub path: PathConfig,
+    // WebSocket and other protocol upgrades
+    pub upgrade: UpgradeConfig,
+    // Log level (replaces the built-in `trace`) and event format
+    pub logging: LoggingConfig,
+    // Publish decision fields as `wasm.uipbdiauthz.*` filter state
+    pub export_filter_state: bool,
+    // Decision details as response headers (disabled when absent)
+    pub debug_headers: Option<DebugHeadersConfig>,
+    // `application/problem+json` error bodies (plain text when absent)
+    pub problem_details: Option<ProblemDetailsConfig>,
+    // Body template for denials (built-in body when absent)
+    pub deny_template: Option<DenyTemplateConfig>,
+    // Deny bodies by `Accept-Language` (disabled when absent)
+    pub localized_messages: Option<LocalizedMessagesConfig>,
+    // `WWW-Authenticate` challenge for authz denials
+    pub challenge: ChallengeConfig,
+    // Root context tick; defaults to the shortest background job interval
+    pub tick_period_ms: Option<u64>,
+}
+
+impl PluginConfig {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        // Envoy hands us an empty buffer when no configuration is set
+        if bytes.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(Self::default());
+        }
+
+        let mut config: PluginConfig =
+            serde_json::from_slice(bytes).map_err(|e| format!("invalid plugin config: {}", e))?;
+
+        if let Some(oidc) = config.oidc.as_mut() {
+            oidc.init()?;
+        }
+        if let Some(session) = config.authz_session.as_mut() {
+            session.init()?;
+        }
+        if let Some(mirror) = config.mirror.as_ref() {
+            mirror.init()?;
+        }
+        if let Some(trailers) = config.request_trailers.as_mut() {
+            trailers.init()?;
+        }
+        if let Some(coalesce) = config.coalesce.as_mut() {
+            coalesce.init()?;
+        }
+        if let Some(readiness) = config.readiness.as_ref() {
+            readiness.init()?;
+        }
+        if let Some(signing) = config.request_signing.as_mut() {
+            signing.init()?;
+        }
+        if let Some(signing) = config.identity_signing.as_mut() {
+            signing.init()?;
+        }
+        if let Some(client_ip) = config.client_ip.as_mut() {
+            client_ip.init()?;
+        }
+        for policy in &mut config.policies {
+            policy.init()?;
+        }
+        forwarded_headers::init(&mut config.forward_headers, "forward_headers")?;
+        config.forward_header_rules.init()?;
+        if let Some(forward_all) = config.forward_all_headers.as_mut() {
+            forward_all.init()?;
+        }
+        if let Some(truncation) = config.header_truncation.as_ref() {
+            truncation.init()?;
+        }
+        if let Some(limit) = config.concurrency_limit.as_ref() {
+            limit.init()?;
+        }
+        if let Some(budget) = config.memory_budget.as_ref() {
+            budget.init()?;
+        }
+        if let Some(authn) = config.authn.as_ref() {
+            authn.init()?;
+        }
+        if let Some(template) = config.deny_template.as_ref() {
+            template.init()?;
+        }
+        config.challenge.init()?;
+        if let Some(messages) = config.localized_messages.as_ref() {
+            messages.init()?;
+        }
+        for step in &config.chain {
+            step.init()?;
+        }
+        if let Some(rollout) = config.rollout.as_mut() {
+            rollout.init()?;
+        }
+        if let Some(dry_run) = config.dry_run.as_mut() {
+            dry_run.init()?;
+        }
+        if let Some(tenant_routing) = config.tenant_routing.as_mut() {
+            tenant_routing.init();
+        }
+        for rule in &mut config.path_bypass {
+            rule.init()?;
+        }
+        for rule in &mut config.method_rules {
+            rule.init();
+        }
+        for rule in &mut config.rules {
+            rule.init()?;
+        }
+        if config
+            .jwks
+            .as_ref()
+            .is_some_and(|jwks| jwks.cluster.is_empty())
+        {
+            return Err("jwks.cluster is required".into());
+        }
+        if config
+            .service_credential
+            .as_ref()
+            .is_some_and(|credential| credential.cluster.is_empty())
+        {
+            return Err("service_credential.cluster is required".into());
+        }
+        if config
+            .denial_audit
+            .as_ref()
+            .is_some_and(|denial_audit| denial_audit.cluster.is_empty())
+        {
+            return Err("denial_audit.cluster is required".into());
+        }
+        if config
+            .replay_protection
+            .as_ref()
+            .is_some_and(|replay| replay.slots == 0)
+        {
+            return Err("replay_protection.slots must be at least 1".into());
+        }
+        if config
+            .rate_limit
+            .as_ref()
+            .is_some_and(|rate_limit| rate_limit.window_secs == 0)
+        {
+            return Err("rate_limit.window_secs must be at least 1".into());
+        }
+
+        Ok(config)
+    }
+
+    // Whether any key is read from an Envoy property (and refreshed on tick)
+    pub fn has_property_secrets(&self) -> bool {
+        self.request_signing
+            .as_ref()
+            .is_some_and(|signing| signing.has_property_keys())
+            || self
+                .identity_signing
+                .as_ref()
+                .is_some_and(|signing| signing.has_property_key())
+    }
+}
@@ -0,0 +1,23 @@
+// Cookies forwarded to the authz service. Only cookies named in
+// `forward_cookies` go into FilterRequest.cookies, and the `cookie` header
+// itself is then never sent, so the rest of the jar (other applications'
+// sessions, tracking ids) stays out of the authz call. HTTP/2 clients may
+// split the jar over several `cookie` headers; all of them are read.
+
+// Configured cookies of the `cookie` header values, in request order and with
+// repeats
+pub fn selected<'a>(
+    cookie_headers: impl Iterator<Item = &'a str> + 'a,
+    names: &'a [String],
+) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+    cookie_headers
+        .flat_map(|header| header.split(';'))
+        .filter_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            let name = name.trim();
+            names
+                .iter()
+                .any(|wanted| wanted == name)
+                .then_some((name, value.trim()))
+        })
+}
@@ -0,0 +1,40 @@
+use serde::Deserialize;
+
+// `x-correlation-id` for requests that arrive without one. Generated ids
+// follow the UUIDv7 layout (48-bit unix millis, then random bits), so they
+// sort by creation time and need no coordination between workers.
+
+pub const HEADER: &str = "x-correlation-id";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CorrelationIdConfig {
+    // Return the id on locally denied requests so clients can quote it
+    pub echo_on_deny: bool,
+}
+
+impl Default for CorrelationIdConfig {
+    fn default() -> Self {
+        Self { echo_on_deny: true }
+    }
+}
+
+// None when no randomness is available
+pub fn generate(now_ms: u64) -> Option<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes[6..]).ok()?;
+    bytes[..6].copy_from_slice(&now_ms.to_be_bytes()[2..]);
+    // Version 7, RFC 4122 variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x70;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
@@ -0,0 +1,101 @@
+use serde::Deserialize;
+
+use crate::pipeline::{LocalResponse, RequestSource};
+
+// CORS preflights (`OPTIONS` with `access-control-request-method`) carry no
+// credentials, so the authz service can only deny them. They are either let
+// through to the upstream without the authz call, or answered here from the
+// configured policy. Preflights are recognized after the client IP lists and
+// before any credential check.
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightMode {
+    // Forward to the upstream, which answers with its own CORS headers
+    Bypass,
+    // Answer with the headers below
+    Respond,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    pub mode: PreflightMode,
+    // Origins allowed in `respond` mode; "*" allows any
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    // Empty allows whatever the preflight asks for
+    pub allowed_headers: Vec<String>,
+    pub max_age_secs: u64,
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            mode: PreflightMode::Bypass,
+            allowed_origins: Vec::new(),
+            allowed_methods: ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: Vec::new(),
+            max_age_secs: 600,
+            allow_credentials: false,
+        }
+    }
+}
+
+pub fn is_preflight(source: &dyn RequestSource) -> bool {
+    source.header(":method").as_deref() == Some("OPTIONS")
+        && source.header("origin").is_some()
+        && source.header("access-control-request-method").is_some()
+}
+
+impl CorsConfig {
+    // Answer to a preflight in `respond` mode; 403 without CORS headers when
+    // the origin or method is not allowed
+    pub fn preflight_response(&self, source: &dyn RequestSource) -> LocalResponse {
+        let origin = source.header("origin").unwrap_or_default();
+        let method = source
+            .header("access-control-request-method")
+            .unwrap_or_default();
+        let any_origin = self.allowed_origins.iter().any(|o| o == "*");
+        let origin_allowed = any_origin || self.allowed_origins.contains(&origin);
+        let method_allowed = self
+            .allowed_methods
+            .iter()
+            .any(|m| m.eq_ignore_ascii_case(&method));
+        if !origin_allowed || !method_allowed {
+            return LocalResponse::new(403, "Forbidden");
+        }
+
+        // Credentialed requests need the exact origin, never "*"
+        let allow_origin = if any_origin && !self.allow_credentials {
+            "*".to_string()
+        } else {
+            origin
+        };
+        let allow_headers = if self.allowed_headers.is_empty() {
+            source
+                .header("access-control-request-headers")
+                .unwrap_or_default()
+        } else {
+            self.allowed_headers.join(", ")
+        };
+        let mut response = LocalResponse::new(204, "")
+            .with_header("access-control-allow-origin", &allow_origin)
+            .with_header(
+                "access-control-allow-methods",
+                &self.allowed_methods.join(", "),
+            )
+            .with_header("access-control-max-age", &self.max_age_secs.to_string())
+            .with_header("vary", "Origin");
+        if !allow_headers.is_empty() {
+            response = response.with_header("access-control-allow-headers", &allow_headers);
+        }
+        if self.allow_credentials {
+            response = response.with_header("access-control-allow-credentials", "true");
+        }
+        response
+    }
+}
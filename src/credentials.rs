@@ -0,0 +1,87 @@
+use serde::Deserialize;
+
+use crate::config::PluginConfig;
+use crate::pipeline::RequestSource;
+
+// Requests that carry no credential at all cannot be authorized, so they get
+// a 401 challenge right away instead of a round trip to the authz service.
+// Local authenticators (OIDC login, API keys, verify-only signatures) run
+// first and settle the requests they recognize.
+
+// Always count as credentials: bearer/basic/negotiate tokens and the client
+// certificate forwarded by Envoy
+const CREDENTIAL_HEADERS: &[&str] = &["authorization", "x-forwarded-client-cert"];
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MissingCredentialsConfig {
+    // WWW-Authenticate challenges sent with the 401, one header each
+    pub challenges: Vec<String>,
+    // More headers that count as a credential
+    pub credential_headers: Vec<String>,
+}
+
+impl Default for MissingCredentialsConfig {
+    fn default() -> Self {
+        Self {
+            challenges: vec!["Bearer".into()],
+            credential_headers: Vec::new(),
+        }
+    }
+}
+
+impl MissingCredentialsConfig {
+    // The request signature and one-time token headers count as well
+    pub fn has_credentials(&self, config: &PluginConfig, source: &dyn RequestSource) -> bool {
+        let signature = config.request_signing.iter().map(|s| s.header.as_str());
+        let one_time_tokens = config
+            .replay_protection
+            .iter()
+            .flat_map(|replay| replay.one_time_token_headers.iter().map(String::as_str));
+
+        CREDENTIAL_HEADERS
+            .iter()
+            .copied()
+            .chain(signature)
+            .chain(one_time_tokens)
+            .chain(self.credential_headers.iter().map(String::as_str))
+            .any(|name| source.header(name).is_some())
+    }
+}
+
+// End-user credentials removed from allowed requests before they go upstream,
+// so backends only see the identity headers the filter adds (signed with
+// `identity_signing`), never the raw tokens. The API key header is removed
+// as well when API keys are configured.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StripCredentialsConfig {
+    pub headers: Vec<String>,
+}
+
+impl Default for StripCredentialsConfig {
+    fn default() -> Self {
+        Self {
+            headers: vec!["authorization".into(), "proxy-authorization".into()],
+        }
+    }
+}
+
+impl StripCredentialsConfig {
+    // Credential headers the request carries
+    pub fn present<'a>(
+        &'a self,
+        config: &'a PluginConfig,
+        source: &'a dyn RequestSource,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        let api_key = config
+            .api_keys
+            .iter()
+            .map(|api_key| api_key.header.as_str());
+        self.headers
+            .iter()
+            .map(String::as_str)
+            .chain(api_key)
+            .filter(move |name| source.header(name).is_some())
+    }
+}
@@ -0,0 +1,98 @@
+use serde::Deserialize;
+
+// Authz call timeouts bounded by the downstream caller's deadline, so the
+// authz call never outlives the request it authorizes. The remaining budget
+// comes from `grpc-timeout` (gRPC callers) or Envoy's
+// x-envoy-expected-rq-timeout-ms, less the time already spent on the request
+// and `margin_ms` for applying the decision. The static timeout (the policy's,
+// else 5 s) stays the upper bound.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DeadlineConfig {
+    // Left for applying the decision before the caller gives up
+    pub margin_ms: u64,
+    // Floor for the derived timeout, so nearly expired requests still get an
+    // answer instead of an immediate timeout
+    pub min_timeout_ms: u64,
+}
+
+impl Default for DeadlineConfig {
+    fn default() -> Self {
+        Self {
+            margin_ms: 50,
+            min_timeout_ms: 10,
+        }
+    }
+}
+
+impl DeadlineConfig {
+    pub fn timeout_ms(
+        &self,
+        static_timeout_ms: u64,
+        grpc_timeout: Option<&str>,
+        expected_timeout: Option<&str>,
+        elapsed_ms: u64,
+    ) -> u64 {
+        let budget = grpc_timeout.and_then(parse_grpc_timeout).or_else(|| {
+            expected_timeout
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .filter(|timeout| *timeout > 0)
+        });
+        let Some(budget) = budget else {
+            return static_timeout_ms;
+        };
+        let remaining = budget
+            .saturating_sub(elapsed_ms)
+            .saturating_sub(self.margin_ms)
+            .max(self.min_timeout_ms);
+        remaining.min(static_timeout_ms)
+    }
+}
+
+// `grpc-timeout` in milliseconds, rounded up: at most 8 digits and a unit of
+// H, M, S, m, u or n
+pub fn parse_grpc_timeout(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "H" => amount * 3_600_000,
+        "M" => amount * 60_000,
+        "S" => amount * 1000,
+        "m" => amount,
+        "u" => amount.div_ceil(1000),
+        "n" => amount.div_ceil(1_000_000),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn grpc_timeout_header_units() {
+        use super::{parse_grpc_timeout, DeadlineConfig};
+
+        assert_eq!(parse_grpc_timeout("2S"), Some(2000));
+        assert_eq!(parse_grpc_timeout("1M"), Some(60_000));
+        assert_eq!(parse_grpc_timeout("250m"), Some(250));
+        assert_eq!(parse_grpc_timeout("1500u"), Some(2));
+        assert_eq!(parse_grpc_timeout("1n"), Some(1));
+        assert_eq!(parse_grpc_timeout("123456789S"), None);
+        assert_eq!(parse_grpc_timeout("-1S"), None);
+        assert_eq!(parse_grpc_timeout("10"), None);
+
+        let deadline = DeadlineConfig::default();
+        assert_eq!(deadline.timeout_ms(5000, Some("1S"), None, 100), 850);
+        assert_eq!(deadline.timeout_ms(5000, None, Some("600"), 0), 550);
+        assert_eq!(deadline.timeout_ms(5000, Some("30m"), None, 0), 10);
+        assert_eq!(deadline.timeout_ms(500, Some("1M"), None, 0), 500);
+        assert_eq!(deadline.timeout_ms(5000, None, None, 0), 5000);
+    }
+}
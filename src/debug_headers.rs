@@ -0,0 +1,57 @@
+use serde::Deserialize;
+
+// Decision details returned to the client as response headers, for debugging
+// policies. Off unless configured; with `trigger_header` set, only requests
+// carrying that header get them, so the header must be set (and stripped from
+// client traffic) by a trusted hop. By default the client only ever sees the
+// status.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DebugHeadersConfig {
+    // Request header that turns debug headers on; empty means every request
+    pub trigger_header: String,
+}
+
+impl DebugHeadersConfig {
+    pub fn enabled(&self, trigger: Option<&str>) -> bool {
+        self.trigger_header.is_empty() || trigger.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub struct DecisionDetails<'a> {
+    pub decision: &'a str,
+    // Message of the authz verdict, when the authz service answered
+    pub authz_message: Option<&'a str>,
+    pub authz_latency_ms: Option<u64>,
+    // False for monitor-only and dry-run decisions
+    pub enforced: bool,
+}
+
+impl DecisionDetails<'_> {
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("x-uipbdiauthz-decision", self.decision.to_string()),
+            (
+                "x-uipbdiauthz-decided-by",
+                if self.authz_message.is_some() {
+                    "authz"
+                } else {
+                    "local"
+                }
+                .to_string(),
+            ),
+        ];
+        if let Some(message) = self.authz_message {
+            headers.push(("x-filter-response-pdk-response", message.to_string()));
+        }
+        if let Some(latency_ms) = self.authz_latency_ms {
+            headers.push(("x-uipbdiauthz-authz-latency-ms", latency_ms.to_string()));
+        }
+        if !self.enforced {
+            headers.push(("x-uipbdiauthz-enforced", "false".to_string()));
+        }
+        headers
+    }
+}
@@ -0,0 +1,130 @@
+use log::warn;
+use prost::encoding::{encode_key, encode_varint, WireType};
+use prost::{EncodeError, Message};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+
+use crate::uipbdiauthz::DenialRecord;
+
+// Complete trail of denied requests for security teams, independent of access
+// log sampling. Every deny, local or from the authz service, is sent to a
+// separate audit cluster as a DenialRecord holding the full FilterRequest and
+// the deny reason. Like shadow calls, the records are queued by the request
+// context and dispatched from the root tick, so a record is not lost when the
+// downstream request finishes before the call does. Nobody waits for the
+// answer; it only feeds the sent/failed stats.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DenialAuditConfig {
+    pub cluster: String,
+    pub service: String,
+    pub method: String,
+    pub timeout_ms: u64,
+    // Records waiting for the root tick beyond this are dropped
+    pub max_queued: usize,
+}
+
+impl Default for DenialAuditConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            service: "authengine.UIPBDIAuthZAudit".into(),
+            method: "recordDenial".into(),
+            timeout_ms: 5000,
+            max_queued: 1000,
+        }
+    }
+}
+
+// Serialized DenialRecords waiting for dispatch, plus the calls in flight
+#[derive(Debug, Default)]
+pub struct DenialAudits {
+    queued: VecDeque<Vec<u8>>,
+    in_flight: HashSet<u32>,
+}
+
+pub type SharedDenialAudits = Rc<RefCell<DenialAudits>>;
+
+impl DenialAudits {
+    // False when the queue is full and the record was dropped
+    pub fn queue(&mut self, config: &DenialAuditConfig, record: Vec<u8>) -> bool {
+        if self.queued.len() >= config.max_queued {
+            warn!("[DENIAL-AUDIT] Queue full, dropping denial record");
+            return false;
+        }
+        self.queued.push_back(record);
+        true
+    }
+
+    pub fn take_queued(&mut self) -> Vec<Vec<u8>> {
+        self.queued.drain(..).collect()
+    }
+
+    pub fn dispatched(&mut self, token: u32) {
+        self.in_flight.insert(token);
+    }
+
+    // Whether `token` was a denial record call
+    pub fn complete(&mut self, token: u32) -> bool {
+        self.in_flight.remove(&token)
+    }
+}
+
+// DenialRecord around an already serialized FilterRequest (static prefix,
+// per-request fields and headers, see message_buffer), which is embedded as
+// field 1 without decoding it again
+pub fn encode_record(
+    filter_request: &[u8],
+    reason: &str,
+    status: u32,
+    request_id: &str,
+    timestamp_ms: u64,
+) -> Result<Vec<u8>, EncodeError> {
+    let rest = DenialRecord {
+        request: None,
+        reason: reason.to_string(),
+        status,
+        request_id: request_id.to_string(),
+        timestamp_ms,
+    };
+    let mut out = Vec::with_capacity(filter_request.len() + rest.encoded_len() + 16);
+    encode_key(1, WireType::LengthDelimited, &mut out);
+    encode_varint(filter_request.len() as u64, &mut out);
+    out.extend_from_slice(filter_request);
+    rest.encode(&mut out)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    // Denial records embed the FilterRequest bytes without decoding them; the
+    // audit service must read the same record as a prost-encoded DenialRecord.
+    #[test]
+    fn denial_record_embeds_filter_request() {
+        use super::encode_record;
+        use crate::uipbdiauthz::{DenialRecord, FilterRequest};
+        use prost::Message;
+
+        let request = FilterRequest {
+            method: "POST".into(),
+            path: "/admin".into(),
+            ..Default::default()
+        };
+        let record =
+            encode_record(&request.encode_to_vec(), "authz: denied", 403, "rid-1", 42).unwrap();
+
+        assert_eq!(
+            DenialRecord::decode(record.as_slice()).unwrap(),
+            DenialRecord {
+                request: Some(request),
+                reason: "authz: denied".into(),
+                status: 403,
+                request_id: "rid-1".into(),
+                timestamp_ms: 42,
+            }
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use serde::Deserialize;
+
+// Operator-provided body for denials (401/403 and other 4xx decisions), so
+// teams can return branded or machine-parsable error pages. Placeholders are
+// `{{reason}}`, `{{message}}`, `{{status}}`, `{{correlation_id}}` and
+// `{{path}}`; values are escaped for JSON strings or HTML when the content
+// type says so, since the path and the reason come from the client or the
+// authz service.
+
+const PLACEHOLDERS: [&str; 5] = ["reason", "message", "status", "correlation_id", "path"];
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DenyTemplateConfig {
+    pub body: String,
+    pub content_type: String,
+}
+
+impl Default for DenyTemplateConfig {
+    fn default() -> Self {
+        Self {
+            body: String::new(),
+            content_type: "text/plain; charset=utf-8".into(),
+        }
+    }
+}
+
+// Values substituted into the template
+#[derive(Debug, Default)]
+pub struct DenyFields<'a> {
+    pub reason: &'a str,
+    // Built-in or localized body the template replaces
+    pub message: &'a str,
+    pub status: u32,
+    pub correlation_id: &'a str,
+    pub path: &'a str,
+}
+
+// Splits `template` into literal text and placeholder names
+fn segments(template: &str) -> impl Iterator<Item = Result<&str, &str>> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let Some(start) = rest.find("{{") else {
+            return Some(Ok(std::mem::take(&mut rest)));
+        };
+        if start > 0 {
+            let (text, tail) = rest.split_at(start);
+            rest = tail;
+            return Some(Ok(text));
+        }
+        match rest.find("}}") {
+            Some(end) => {
+                let name = rest[2..end].trim();
+                rest = &rest[end + 2..];
+                Some(Err(name))
+            }
+            None => Some(Ok(std::mem::take(&mut rest))),
+        }
+    })
+}
+
+fn escape_json(value: &str, out: &mut String) {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    out.push_str(&quoted[1..quoted.len() - 1]);
+}
+
+fn escape_html(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+impl DenyTemplateConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.body.is_empty() {
+            return Err("deny_template.body is required".into());
+        }
+        for segment in segments(&self.body) {
+            if let Err(name) = segment {
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!(
+                        "deny_template.body: unknown placeholder '{}'",
+                        name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, fields: &DenyFields) -> Vec<u8> {
+        let content_type = self.content_type.to_ascii_lowercase();
+        let escape: fn(&str, &mut String) = if content_type.contains("json") {
+            escape_json
+        } else if content_type.contains("html") {
+            escape_html
+        } else {
+            |value, out| out.push_str(value)
+        };
+        let status = fields.status.to_string();
+        let mut body = String::with_capacity(self.body.len());
+        for segment in segments(&self.body) {
+            match segment {
+                Ok(text) => body.push_str(text),
+                Err("reason") => escape(fields.reason, &mut body),
+                Err("message") => escape(fields.message, &mut body),
+                Err("status") => body.push_str(&status),
+                Err("correlation_id") => escape(fields.correlation_id, &mut body),
+                Err("path") => escape(fields.path, &mut body),
+                Err(_) => {}
+            }
+        }
+        body.into_bytes()
+    }
+}
@@ -0,0 +1,62 @@
+use serde::Deserialize;
+
+use crate::client_ip::{self, Cidr};
+use crate::pipeline::RequestSource;
+
+// Per-request dry run for debugging policies: a request carrying `header`
+// is decided as in monitor-only mode and gets the decision back in the debug
+// response headers. The header is only honored on connections from
+// `trusted_networks`; the peer address is used, never x-forwarded-for, so
+// clients cannot claim an internal origin.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DryRunConfig {
+    pub header: String,
+    // CIDRs of the internal networks allowed to request a dry run
+    pub trusted_networks: Vec<String>,
+
+    #[serde(skip)]
+    trusted_ranges: Vec<Cidr>,
+}
+
+impl Default for DryRunConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-uipbdiauthz-dry-run".to_string(),
+            trusted_networks: [
+                "10.0.0.0/8",
+                "172.16.0.0/12",
+                "192.168.0.0/16",
+                "127.0.0.0/8",
+                "::1",
+                "fc00::/7",
+            ]
+            .map(String::from)
+            .to_vec(),
+            trusted_ranges: Vec::new(),
+        }
+    }
+}
+
+impl DryRunConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.header.is_empty() {
+            return Err("dry_run.header must not be empty".into());
+        }
+        self.header.make_ascii_lowercase();
+        self.trusted_ranges =
+            client_ip::parse_ranges("dry_run.trusted_networks", &self.trusted_networks)?;
+        Ok(())
+    }
+
+    // None without the header, else whether it comes from a trusted network
+    pub fn requested(&self, source: &dyn RequestSource) -> Option<bool> {
+        source.header(&self.header)?;
+        let peer = source
+            .source_address()
+            .as_deref()
+            .and_then(client_ip::parse_address);
+        Some(peer.is_some_and(|ip| self.trusted_ranges.iter().any(|range| range.contains(ip))))
+    }
+}
@@ -0,0 +1,116 @@
+use log::{info, warn};
+use prost::Message;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::metrics::Metrics;
+use crate::uipbdiauthz::FilterResponse;
+
+// Latency experiment: how much does deciding a request locally (API key,
+// verify-only signature, ...) actually save over the unary authz call? For a
+// sample of locally allowed requests the FilterRequest is still built and
+// sent to the authz service as a shadow call whose verdict is discarded. The
+// root context dispatches shadow calls, so their result is recorded even when
+// the downstream request has long finished. Real unary calls are timed as
+// well, giving two comparable latency histograms.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ExperimentConfig {
+    // Share (0-100) of locally decided requests that get a shadow call
+    pub shadow_sample_percent: u32,
+    // Shadow calls waiting for the root tick beyond this are dropped
+    pub max_queued: usize,
+}
+
+impl Default for ExperimentConfig {
+    fn default() -> Self {
+        Self {
+            shadow_sample_percent: 0,
+            max_queued: 100,
+        }
+    }
+}
+
+impl ExperimentConfig {
+    pub fn sampled(&self) -> bool {
+        if self.shadow_sample_percent == 0 {
+            return false;
+        }
+        let mut bytes = [0u8; 4];
+        if getrandom::getrandom(&mut bytes).is_err() {
+            return false;
+        }
+        u32::from_le_bytes(bytes) % 100 < self.shadow_sample_percent
+    }
+}
+
+// FilterRequests of locally allowed requests waiting to be shadowed (with
+// the authz cluster of their policy), plus the shadow calls in flight
+// (token -> dispatch time)
+#[derive(Debug, Default)]
+pub struct ShadowCalls {
+    queued: VecDeque<(String, Vec<u8>)>,
+    in_flight: HashMap<u32, u64>,
+}
+
+pub type SharedShadowCalls = Rc<RefCell<ShadowCalls>>;
+
+impl ShadowCalls {
+    pub fn queue(&mut self, config: &ExperimentConfig, cluster: String, message: Vec<u8>) {
+        if self.queued.len() >= config.max_queued {
+            warn!("[EXPERIMENT] Shadow queue full, dropping sample");
+            return;
+        }
+        self.queued.push_back((cluster, message));
+    }
+
+    pub fn take_queued(&mut self) -> Vec<(String, Vec<u8>)> {
+        self.queued.drain(..).collect()
+    }
+
+    pub fn dispatched(&mut self, token: u32, now_ms: u64) {
+        self.in_flight.insert(token, now_ms);
+    }
+
+    pub fn is_shadow(&self, token: u32) -> bool {
+        self.in_flight.contains_key(&token)
+    }
+
+    // Record the shadow call's latency and whether the remote verdict agrees
+    // with the local allow
+    pub fn complete(
+        &mut self,
+        token: u32,
+        status_code: u32,
+        body: Option<&[u8]>,
+        now_ms: u64,
+        metrics: &Metrics,
+    ) {
+        let Some(started_ms) = self.in_flight.remove(&token) else {
+            return;
+        };
+        let latency_ms = now_ms.saturating_sub(started_ms);
+        metrics.shadow_latency_ms.record(latency_ms);
+
+        let remote_allow = (status_code == 0)
+            .then_some(body)
+            .flatten()
+            .and_then(|body| FilterResponse::decode(body).ok())
+            .map(|reply| reply.allow);
+        match remote_allow {
+            Some(true) => metrics.shadow_agree.increment(1),
+            Some(false) => {
+                info!("[EXPERIMENT] Authz service would have denied a locally allowed request");
+                metrics.shadow_disagree.increment(1);
+            }
+            None => metrics.shadow_failed.increment(1),
+        }
+        info!(
+            "[EXPERIMENT] Shadow call {} took {} ms (grpc status {})",
+            token, latency_ms, status_code
+        );
+    }
+}
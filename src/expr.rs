@@ -0,0 +1,562 @@
+use serde::Deserialize;
+
+use crate::pipeline::RequestSource;
+
+// Small expression language for config rules, e.g.
+//
+//   path.startsWith('/public') && method == 'GET'
+//   header('x-debug') != null || !property('connection', 'mtls')
+//
+// Values are strings, integers, booleans and null (a missing header or
+// property). Operators: `== != < <= > >=`, `&& || !` and parentheses; strings
+// have `startsWith(s)`, `endsWith(s)`, `contains(s)` and `lower()`. Variables:
+//
+//   method, path (normalized, without query), query, authority, scheme,
+//   client_ip (with `client_ip` configured, else null)
+//   header('name'), property('segment', ...)  (Envoy attribute path)
+//
+// Comparisons between different types are false (`!=` true), and only `true`
+// counts as a match. Expressions are parsed once at configure time.
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    // Let the request through without the authz call
+    Bypass,
+    // Answer with `status` locally
+    Deny,
+    // Stop evaluating rules and authorize as usual (exceptions to later rules)
+    Enforce,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ExprRule {
+    pub name: String,
+    pub when: String,
+    pub action: RuleAction,
+    // Status of `deny` responses
+    pub status: u32,
+
+    #[serde(skip)]
+    parsed: Option<Expr>,
+}
+
+impl Default for ExprRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            when: String::new(),
+            action: RuleAction::Enforce,
+            status: 403,
+            parsed: None,
+        }
+    }
+}
+
+impl ExprRule {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("rules need a name".into());
+        }
+        let parsed = parse(&self.when).map_err(|e| format!("rule '{}': {}", self.name, e))?;
+        self.parsed = Some(parsed);
+        Ok(())
+    }
+
+    pub fn matches(&self, request: &Request) -> bool {
+        self.parsed
+            .as_ref()
+            .is_some_and(|expr| expr.eval(request) == Value::Bool(true))
+    }
+}
+
+// What expressions can see of a request
+pub struct Request<'a> {
+    pub source: &'a dyn RequestSource,
+    // Effective `:path`
+    pub path: &'a str,
+    pub client_ip: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Var {
+    Method,
+    Path,
+    Query,
+    Authority,
+    Scheme,
+    ClientIp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StrMethod {
+    StartsWith,
+    EndsWith,
+    Contains,
+    Lower,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Var(Var),
+    Header(String),
+    Property(Vec<String>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CmpOp, Box<Expr>, Box<Expr>),
+    Call(StrMethod, Box<Expr>, Option<Box<Expr>>),
+}
+
+impl Expr {
+    fn eval(&self, request: &Request) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Var(var) => var.eval(request),
+            Expr::Header(name) => request.source.header(name).map_or(Value::Null, Value::Str),
+            Expr::Property(path) => {
+                let path: Vec<&str> = path.iter().map(String::as_str).collect();
+                request
+                    .source
+                    .property(&path)
+                    .map_or(Value::Null, property_value)
+            }
+            Expr::Not(inner) => Value::Bool(inner.eval(request) != Value::Bool(true)),
+            Expr::And(left, right) => Value::Bool(
+                left.eval(request) == Value::Bool(true) && right.eval(request) == Value::Bool(true),
+            ),
+            Expr::Or(left, right) => Value::Bool(
+                left.eval(request) == Value::Bool(true) || right.eval(request) == Value::Bool(true),
+            ),
+            Expr::Compare(op, left, right) => {
+                Value::Bool(compare(*op, &left.eval(request), &right.eval(request)))
+            }
+            Expr::Call(method, target, arg) => {
+                let Value::Str(target) = target.eval(request) else {
+                    return Value::Null;
+                };
+                let arg = match arg.as_ref().map(|arg| arg.eval(request)) {
+                    Some(Value::Str(arg)) => Some(arg),
+                    Some(_) => return Value::Null,
+                    None => None,
+                };
+                let arg = arg.as_deref().unwrap_or_default();
+                match method {
+                    StrMethod::StartsWith => Value::Bool(target.starts_with(arg)),
+                    StrMethod::EndsWith => Value::Bool(target.ends_with(arg)),
+                    StrMethod::Contains => Value::Bool(target.contains(arg)),
+                    StrMethod::Lower => Value::Str(target.to_lowercase()),
+                }
+            }
+        }
+    }
+}
+
+impl Var {
+    fn eval(self, request: &Request) -> Value {
+        let (path, query) = request
+            .path
+            .split_once('?')
+            .map_or((request.path, None), |(path, query)| (path, Some(query)));
+        let value = match self {
+            Var::Method => request.source.header(":method"),
+            Var::Path => Some(path.to_string()),
+            Var::Query => query.map(str::to_string),
+            Var::Authority => request.source.header(":authority"),
+            Var::Scheme => request.source.header(":scheme"),
+            Var::ClientIp => request.client_ip.map(str::to_string),
+        };
+        value.map_or(Value::Null, Value::Str)
+    }
+}
+
+// Envoy encodes boolean attributes as one byte and integers as 8 bytes
+// little endian; everything else is read as a string
+fn property_value(bytes: Vec<u8>) -> Value {
+    match bytes.as_slice() {
+        [0] => Value::Bool(false),
+        [1] => Value::Bool(true),
+        raw if raw.len() == 8 && raw.contains(&0) => {
+            Value::Int(i64::from_le_bytes(raw.try_into().unwrap_or_default()))
+        }
+        _ => String::from_utf8(bytes).map_or(Value::Null, Value::Str),
+    }
+}
+
+fn compare(op: CmpOp, left: &Value, right: &Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(std::cmp::Ordering::Equal),
+        _ => None,
+    };
+    match (op, ordering) {
+        (CmpOp::Eq, ordering) => ordering.is_some_and(|o| o.is_eq()),
+        (CmpOp::Ne, ordering) => !ordering.is_some_and(|o| o.is_eq()),
+        (_, None) => false,
+        (CmpOp::Lt, Some(o)) => o.is_lt(),
+        (CmpOp::Le, Some(o)) => o.is_le(),
+        (CmpOp::Gt, Some(o)) => o.is_gt(),
+        (CmpOp::Ge, Some(o)) => o.is_ge(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Op(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")", ",", ".",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let position = input.len() - rest.len();
+        let c = rest.chars().next().unwrap_or_default();
+        if c == '\'' || c == '"' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, ch)) if ch == c => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(format!("unterminated string at {}", position)),
+                    },
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err(format!("unterminated string at {}", position)),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value = rest[..end]
+                .parse()
+                .map_err(|_| format!("number too large at {}", position))?;
+            tokens.push(Token::Int(value));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().copied().find(|op| rest.starts_with(op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected '{}' at {}", c, position));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => "end of expression".into(),
+        Some(Token::Ident(name)) => format!("'{}'", name),
+        Some(Token::Str(value)) => format!("string '{}'", value),
+        Some(Token::Int(value)) => value.to_string(),
+        Some(Token::Op(op)) => format!("'{}'", op),
+    }
+}
+
+// Parentheses, `!` and call arguments nested deeper than this are rejected,
+// keeping parsing and evaluation off the end of the stack
+const MAX_NESTING: usize = 32;
+
+fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        next: 0,
+        depth: 0,
+    };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected {}", describe(Some(token)))),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(next)) if *next == op) {
+            self.next += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            return Ok(());
+        }
+        Err(format!(
+            "expected '{}', found {}",
+            op,
+            describe(self.peek())
+        ))
+    }
+
+    // Parse one nesting level deeper
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("nested deeper than {} levels", MAX_NESTING));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.nested(Self::not)?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.postfix()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CmpOp::Eq,
+            Some(Token::Op("!=")) => CmpOp::Ne,
+            Some(Token::Op("<")) => CmpOp::Lt,
+            Some(Token::Op("<=")) => CmpOp::Le,
+            Some(Token::Op(">")) => CmpOp::Gt,
+            Some(Token::Op(">=")) => CmpOp::Ge,
+            _ => return Ok(left),
+        };
+        self.next += 1;
+        let right = self.postfix()?;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(right)))
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        while self.eat(".") {
+            let name = match self.advance() {
+                Some(Token::Ident(name)) => name,
+                other => {
+                    return Err(format!(
+                        "expected method name, found {}",
+                        describe(other.as_ref())
+                    ))
+                }
+            };
+            let method = match name.as_str() {
+                "startsWith" => StrMethod::StartsWith,
+                "endsWith" => StrMethod::EndsWith,
+                "contains" => StrMethod::Contains,
+                "lower" => StrMethod::Lower,
+                _ => return Err(format!("unknown method '{}'", name)),
+            };
+            let mut args = self.arguments()?;
+            let arity = if method == StrMethod::Lower { 0 } else { 1 };
+            if args.len() != arity {
+                return Err(format!("{} takes {} argument(s)", name, arity));
+            }
+            expr = Expr::Call(method, Box::new(expr), args.pop().map(Box::new));
+        }
+        Ok(expr)
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.nested(Self::or)?);
+            if self.eat(")") {
+                return Ok(args);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    // String literal arguments of header() and property()
+    fn string_arguments(&mut self, name: &str) -> Result<Vec<String>, String> {
+        self.arguments()?
+            .into_iter()
+            .map(|arg| match arg {
+                Expr::Literal(Value::Str(value)) => Ok(value),
+                _ => Err(format!("{} takes string literals", name)),
+            })
+            .collect()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Str(value)) => Ok(Expr::Literal(Value::Str(value))),
+            Some(Token::Int(value)) => Ok(Expr::Literal(Value::Int(value))),
+            Some(Token::Op("(")) => {
+                let expr = self.nested(Self::or)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "method" => Ok(Expr::Var(Var::Method)),
+                "path" => Ok(Expr::Var(Var::Path)),
+                "query" => Ok(Expr::Var(Var::Query)),
+                "authority" => Ok(Expr::Var(Var::Authority)),
+                "scheme" => Ok(Expr::Var(Var::Scheme)),
+                "client_ip" => Ok(Expr::Var(Var::ClientIp)),
+                "header" => match self.string_arguments("header")?.as_mut_slice() {
+                    [header] => Ok(Expr::Header(std::mem::take(header).to_ascii_lowercase())),
+                    _ => Err("header takes 1 argument".into()),
+                },
+                "property" => {
+                    let path = self.string_arguments("property")?;
+                    if path.is_empty() {
+                        return Err("property takes at least 1 argument".into());
+                    }
+                    Ok(Expr::Property(path))
+                }
+                _ => Err(format!("unknown name '{}'", name)),
+            },
+            other => Err(format!("unexpected {}", describe(other.as_ref()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, property_value, Expr, Value};
+
+    #[test]
+    fn operator_precedence() {
+        // `!` binds tighter than `&&`, `&&` tighter than `||`
+        let expr = parse("!true || false && false").unwrap();
+        let Expr::Or(left, right) = expr else {
+            panic!("expected ||, got {:?}", expr);
+        };
+        assert!(matches!(*left, Expr::Not(_)));
+        assert!(matches!(*right, Expr::And(..)));
+
+        let expr = parse("!method == 'GET'").unwrap();
+        assert!(matches!(expr, Expr::Not(ref inner) if matches!(**inner, Expr::Compare(..))));
+
+        let expr = parse("(true || false) && false").unwrap();
+        assert!(matches!(expr, Expr::And(ref left, _) if matches!(**left, Expr::Or(..))));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input| parse(input).unwrap_err();
+        assert_eq!(error("path.lower('x')"), "lower takes 0 argument(s)");
+        assert_eq!(error("path.startsWith()"), "startsWith takes 1 argument(s)");
+        assert_eq!(
+            error("path.contains('a', 'b')"),
+            "contains takes 1 argument(s)"
+        );
+        assert_eq!(error("path.trim()"), "unknown method 'trim'");
+        assert_eq!(error("user == 'alice'"), "unknown name 'user'");
+        assert_eq!(error("header('a', 'b')"), "header takes 1 argument");
+        assert_eq!(error("property()"), "property takes at least 1 argument");
+        assert_eq!(error("(true"), "expected ')', found end of expression");
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| format!("{}true{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(32)).is_ok());
+        assert_eq!(
+            parse(&nested(33)).unwrap_err(),
+            "nested deeper than 32 levels"
+        );
+        assert!(parse(&"!".repeat(32)).is_err());
+        assert_eq!(
+            parse(&format!("{}true", "!".repeat(33))).unwrap_err(),
+            "nested deeper than 32 levels"
+        );
+        assert!(parse(&"(".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn property_values_are_typed() {
+        assert_eq!(property_value(vec![1]), Value::Bool(true));
+        assert_eq!(property_value(vec![0]), Value::Bool(false));
+        assert_eq!(
+            property_value(443i64.to_le_bytes().to_vec()),
+            Value::Int(443)
+        );
+        assert_eq!(
+            property_value(b"10.0.0.1".to_vec()),
+            Value::Str("10.0.0.1".into())
+        );
+        // Eight printable bytes are a string, not an integer
+        assert_eq!(
+            property_value(b"upstream".to_vec()),
+            Value::Str("upstream".into())
+        );
+        assert_eq!(property_value(vec![0xff, 0xfe]), Value::Null);
+    }
+}
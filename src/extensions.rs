@@ -0,0 +1,133 @@
+use log::{debug, warn};
+use prost::Message;
+
+use crate::pipeline::{Evaluation, Step};
+use crate::uipbdiauthz::{Extension, QuotaInfo, RoutingHint};
+use crate::upstream_headers;
+
+// Directives the authz service returns in `FilterResponse.extensions`, Any
+// values keyed by type URL. Each known type has a handler below; other types
+// are skipped, so services can ship new directives before every filter
+// build understands them.
+
+pub const QUOTA_INFO: &str = "type.googleapis.com/authengine.QuotaInfo";
+pub const ROUTING_HINT: &str = "type.googleapis.com/authengine.RoutingHint";
+
+// Upstream header carrying a routing hint's cluster, for a route with
+// `cluster_header`
+pub const ROUTING_HEADER: &str = "x-uipbdiauthz-cluster";
+
+type Handler = fn(&[u8], &mut Evaluation) -> Result<(), prost::DecodeError>;
+
+const REGISTRY: &[(&str, Handler)] = &[(QUOTA_INFO, quota_info), (ROUTING_HINT, routing_hint)];
+
+// Run the handlers of the reply's extensions; response headers go on a deny
+// response directly, or are kept for the upstream response
+pub fn apply(extensions: &[Extension], step: Step, evaluation: &mut Evaluation) -> Step {
+    for extension in extensions {
+        let handler = REGISTRY
+            .iter()
+            .find(|(type_url, _)| *type_url == extension.type_url)
+            .map(|(_, handler)| handler);
+        match handler {
+            Some(handler) => {
+                if let Err(e) = handler(&extension.value, evaluation) {
+                    warn!(
+                        "[EXTENSIONS] Ignoring malformed {}: {:?}",
+                        extension.type_url, e
+                    );
+                }
+            }
+            None => debug!("[EXTENSIONS] Ignoring unknown {}", extension.type_url),
+        }
+    }
+    match step {
+        Step::Respond(mut response) => {
+            for (name, value) in std::mem::take(&mut evaluation.response_headers) {
+                response.headers.push((name.to_string(), value));
+            }
+            Step::Respond(response)
+        }
+        step => step,
+    }
+}
+
+fn quota_info(value: &[u8], evaluation: &mut Evaluation) -> Result<(), prost::DecodeError> {
+    let quota = QuotaInfo::decode(value)?;
+    evaluation.response_headers.extend([
+        ("ratelimit-limit", quota.limit.to_string()),
+        ("ratelimit-remaining", quota.remaining.to_string()),
+        ("ratelimit-reset", quota.reset_secs.to_string()),
+    ]);
+    Ok(())
+}
+
+fn routing_hint(value: &[u8], evaluation: &mut Evaluation) -> Result<(), prost::DecodeError> {
+    let hint = RoutingHint::decode(value)?;
+    if !hint.cluster.is_empty() {
+        evaluation.upstream_headers.add(
+            ROUTING_HEADER,
+            hint.cluster,
+            upstream_headers::PRIORITY_ROUTING,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn known_extensions_become_headers() {
+        use super::{apply, QUOTA_INFO, ROUTING_HEADER, ROUTING_HINT};
+        use crate::pipeline::{Evaluation, LocalResponse, Step};
+        use crate::uipbdiauthz::{Extension, QuotaInfo, RoutingHint};
+        use prost::Message;
+
+        let extensions = [
+            Extension {
+                type_url: QUOTA_INFO.into(),
+                value: QuotaInfo {
+                    limit: 100,
+                    remaining: 0,
+                    reset_secs: 30,
+                }
+                .encode_to_vec(),
+            },
+            Extension {
+                type_url: "type.googleapis.com/authengine.FutureDirective".into(),
+                value: vec![0xff],
+            },
+            Extension {
+                type_url: ROUTING_HINT.into(),
+                value: RoutingHint {
+                    cluster: "canary".into(),
+                }
+                .encode_to_vec(),
+            },
+        ];
+
+        let mut evaluation = Evaluation::default();
+        let step = apply(&extensions, Step::Allow, &mut evaluation);
+        assert!(matches!(step, Step::Allow));
+        assert_eq!(
+            evaluation.response_headers[1],
+            ("ratelimit-remaining", "0".to_string())
+        );
+        let routed: Vec<_> = evaluation
+            .upstream_headers
+            .iter()
+            .map(|addition| (addition.name.as_ref(), addition.value.as_str()))
+            .collect();
+        assert_eq!(routed, [(ROUTING_HEADER, "canary")]);
+
+        let mut evaluation = Evaluation::default();
+        let deny = Step::Respond(LocalResponse::new(429, "Too Many Requests"));
+        let Step::Respond(response) = apply(&extensions[..1], deny, &mut evaluation) else {
+            panic!("deny turned into an allow");
+        };
+        assert!(response
+            .headers
+            .contains(&("ratelimit-reset".to_string(), "30".to_string())));
+        assert!(evaluation.response_headers.is_empty());
+    }
+}
@@ -0,0 +1,587 @@
+// Declarative pipeline fixtures. A fixture file bundles a plugin config, some
+// shared data and a list of synthetic requests with their expected outcome
+// (run in order; shared data written by one request is seen by the next);
+// the runner drives each request through `pipeline` exactly like AuthEngine
+// does, with a host that records the calls made instead of the proxy.
+// Fixtures live in `fixtures/*.json`; set UIPBDIAUTHZ_FIXTURES_DIR to run
+// another directory (e.g. a policy repo).
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use proxy_wasm::types::{GrpcStatusCode, Status};
+
+use crate::config::PluginConfig;
+use crate::host::{self, GrpcCall, Host};
+use crate::pipeline::{self, Evaluation, RequestSource, Step};
+use crate::uipbdiauthz::FilterResponse;
+
+#[derive(Debug, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub config: serde_json::Value,
+    #[serde(default)]
+    pub shared_data: HashMap<String, String>,
+    #[serde(default)]
+    pub now: u64,
+    pub cases: Vec<Case>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Case {
+    pub name: String,
+    pub headers: HashMap<String, String>,
+    // Downstream peer address (`source.address`)
+    #[serde(default)]
+    pub source_address: Option<String>,
+    // Envoy attributes by dotted path (`connection.mtls`); `true`/`false`
+    // and integers are encoded like Envoy does
+    #[serde(default)]
+    pub properties: HashMap<String, serde_json::Value>,
+    // Verdict returned by the simulated authz service, used when the request
+    // reaches the remote call
+    #[serde(default)]
+    pub authz_response: Option<AuthzResponse>,
+    // Memory the worker VM uses
+    #[serde(default)]
+    pub memory_used: u64,
+    pub expect: Expect,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthzResponse {
+    #[serde(default)]
+    pub allow: bool,
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub negotiate: bool,
+    #[serde(default)]
+    pub negotiate_token: String,
+    #[serde(default)]
+    pub rewrite_path: String,
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+    #[serde(default)]
+    pub upstream_token: String,
+    #[serde(default)]
+    pub quota_limit: Option<u64>,
+    #[serde(default)]
+    pub quota_remaining: Option<u64>,
+    #[serde(default)]
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Expect {
+    // respond | allow | authorize | exchange_code
+    pub outcome: String,
+    #[serde(default)]
+    pub status: Option<u32>,
+    // Values ending in `*` are prefix matches
+    #[serde(default)]
+    pub response_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub upstream_headers: HashMap<String, String>,
+    // Whether an authz session cookie is set on the response
+    #[serde(default)]
+    pub session_cookie: Option<bool>,
+    // Headers that must not be added upstream
+    #[serde(default)]
+    pub absent_upstream_headers: Vec<String>,
+    // Request headers set before the authz call (same matching rules)
+    #[serde(default)]
+    pub request_headers: HashMap<String, String>,
+    // Username sent to the authz service in `basic_auth_user`
+    #[serde(default)]
+    pub basic_auth_user: Option<String>,
+    // Request headers removed before forwarding upstream
+    #[serde(default)]
+    pub stripped_headers: Vec<String>,
+    // `service/method` sent to the authz service for gRPC downstream requests
+    #[serde(default)]
+    pub grpc_target: Option<String>,
+    // `:path` forwarded upstream / original path kept for a rewritten request
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub original_path: Option<String>,
+    // Client Negotiate token sent to the authz service
+    #[serde(default)]
+    pub negotiate_token: Option<String>,
+    // gRPC status a local response is sent with (gRPC downstream requests)
+    #[serde(default)]
+    pub grpc_status: Option<u32>,
+    // Client IP sent to the authz service
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    // Name of the authority policy selected, or "" for none
+    #[serde(default)]
+    pub policy: Option<String>,
+    // Tenant authz cluster selected, or "" for none
+    #[serde(default)]
+    pub tenant_cluster: Option<String>,
+    // Whether the decision is enforced (false in monitor-only mode)
+    #[serde(default)]
+    pub enforced: Option<bool>,
+}
+
+struct SyntheticRequest<'a> {
+    headers: &'a HashMap<String, String>,
+    source_address: Option<&'a str>,
+    properties: &'a HashMap<String, serde_json::Value>,
+    shared_data: &'a HashMap<String, String>,
+    // Entries written by earlier requests of the fixture
+    written: &'a RefCell<HashMap<String, Vec<u8>>>,
+    now: u64,
+    memory_used: u64,
+}
+
+impl RequestSource for SyntheticRequest<'_> {
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+
+    // Fixture headers are a map, so never repeated
+    fn header_list(&self, name: &str) -> Option<String> {
+        self.header(name)
+    }
+
+    fn header_totals(&self) -> (usize, usize) {
+        let bytes = self
+            .headers
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+        (self.headers.len(), bytes)
+    }
+
+    fn source_address(&self) -> Option<String> {
+        self.source_address.map(str::to_string)
+    }
+
+    fn property(&self, path: &[&str]) -> Option<Vec<u8>> {
+        match self.properties.get(&path.join("."))? {
+            serde_json::Value::Bool(value) => Some(vec![u8::from(*value)]),
+            serde_json::Value::Number(value) => Some(value.as_i64()?.to_le_bytes().to_vec()),
+            serde_json::Value::String(value) => Some(value.as_bytes().to_vec()),
+            _ => None,
+        }
+    }
+
+    // Values prefixed with `base64:` hold binary entries
+    fn shared_data(&self, key: &str) -> Option<Vec<u8>> {
+        if let Some(value) = self.written.borrow().get(key) {
+            return Some(value.clone());
+        }
+        let value = self.shared_data.get(key)?;
+        match value.strip_prefix("base64:") {
+            Some(encoded) => STANDARD.decode(encoded).ok(),
+            None => Some(value.as_bytes().to_vec()),
+        }
+    }
+
+    fn now_secs(&self) -> u64 {
+        self.now
+    }
+
+    // Requests run one at a time, so writes never conflict
+    fn shared_data_cas(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+        (self.shared_data(key), None)
+    }
+
+    fn update_shared_data(&self, key: &str, value: &[u8], _cas: Option<u32>) -> bool {
+        self.written
+            .borrow_mut()
+            .insert(key.to_string(), value.to_vec());
+        true
+    }
+
+    fn memory_used(&self) -> u64 {
+        self.memory_used
+    }
+}
+
+// Run every case of a fixture; returns one message per failed case
+#[derive(Debug, PartialEq)]
+pub enum HostCall {
+    SetHeader(String, Option<String>),
+    AddHeader(String, String),
+    Dispatch {
+        cluster: String,
+        service: String,
+        method: String,
+        metadata: Vec<String>,
+    },
+    Respond {
+        status: u32,
+        headers: Vec<(String, String)>,
+    },
+    GrpcStatus {
+        status: u32,
+        message: Option<String>,
+    },
+    Resume,
+}
+
+// Host standing in for the proxy: records every call, dispatches succeed
+#[derive(Debug, Default)]
+pub struct RecordingHost {
+    pub calls: RefCell<Vec<HostCall>>,
+}
+
+impl Host for RecordingHost {
+    fn set_header(&self, name: &str, value: Option<&str>) {
+        let call = HostCall::SetHeader(name.to_string(), value.map(str::to_string));
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn add_header(&self, name: &str, value: &str) {
+        let call = HostCall::AddHeader(name.to_string(), value.to_string());
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn dispatch(&self, call: &GrpcCall) -> Result<u32, Status> {
+        let mut calls = self.calls.borrow_mut();
+        calls.push(HostCall::Dispatch {
+            cluster: call.cluster.to_string(),
+            service: call.service.to_string(),
+            method: call.method.to_string(),
+            metadata: call
+                .metadata
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        });
+        Ok(calls.len() as u32)
+    }
+
+    fn send_response(&self, status: u32, headers: Vec<(&str, &str)>, _body: Option<&[u8]>) {
+        let headers = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.calls
+            .borrow_mut()
+            .push(HostCall::Respond { status, headers });
+    }
+
+    fn send_grpc_status(
+        &self,
+        status: GrpcStatusCode,
+        message: Option<&str>,
+        _metadata: Vec<(&str, &[u8])>,
+    ) {
+        self.calls.borrow_mut().push(HostCall::GrpcStatus {
+            status: status as u32,
+            message: message.map(str::to_string),
+        });
+    }
+
+    fn resume_request(&self) {
+        self.calls.borrow_mut().push(HostCall::Resume);
+    }
+}
+
+pub fn run(fixture: &Fixture) -> Result<Vec<String>, String> {
+    let config = match &fixture.config {
+        serde_json::Value::Null => PluginConfig::default(),
+        value => PluginConfig::from_bytes(value.to_string().as_bytes())?,
+    };
+
+    let written = RefCell::new(HashMap::new());
+    let mut failures = Vec::new();
+    for case in &fixture.cases {
+        if let Err(e) = run_case(&config, fixture, &written, case) {
+            failures.push(format!("{}: {}", case.name, e));
+        }
+    }
+    Ok(failures)
+}
+
+fn run_case(
+    config: &PluginConfig,
+    fixture: &Fixture,
+    written: &RefCell<HashMap<String, Vec<u8>>>,
+    case: &Case,
+) -> Result<(), String> {
+    let source = SyntheticRequest {
+        headers: &case.headers,
+        source_address: case.source_address.as_deref(),
+        properties: &case.properties,
+        shared_data: &fixture.shared_data,
+        written,
+        now: fixture.now,
+        memory_used: case.memory_used,
+    };
+
+    let mut evaluation = Evaluation::default();
+    let mut step = pipeline::evaluate_request(config, &source, &mut evaluation);
+
+    if let (Step::Authorize, Some(authz)) = (&step, &case.authz_response) {
+        let reply = FilterResponse {
+            allow: authz.allow,
+            user: authz.user.clone(),
+            message: authz.message.clone(),
+            negotiate: authz.negotiate,
+            negotiate_token: authz.negotiate_token.clone(),
+            rewrite_path: authz.rewrite_path.clone(),
+            attributes: authz.attributes.clone(),
+            upstream_token: authz.upstream_token.clone(),
+            quota_limit: authz.quota_limit,
+            quota_remaining: authz.quota_remaining,
+            retry_after: authz.retry_after,
+            ..Default::default()
+        };
+        let path = source.header(":path").unwrap_or_default();
+        step = pipeline::evaluate_decision(config, &source, &reply, &path, &mut evaluation);
+    }
+
+    let outcome = match &step {
+        Step::Respond(_) => "respond",
+        Step::Allow => "allow",
+        Step::Authorize => "authorize",
+        Step::ExchangeCode { .. } => "exchange_code",
+    };
+    if outcome != case.expect.outcome {
+        return Err(format!("expected {}, got {:?}", case.expect.outcome, step));
+    }
+
+    if let Step::Respond(response) = &step {
+        if let Some(status) = case.expect.status {
+            if status != response.status {
+                return Err(format!(
+                    "expected status {}, got {}",
+                    status, response.status
+                ));
+            }
+        }
+        check_headers("response", &case.expect.response_headers, &response.headers)?;
+        if let Some(grpc_status) = case.expect.grpc_status {
+            let recorder = RecordingHost::default();
+            let headers = response
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let grpc = evaluation.grpc_target.is_some();
+            host::respond(&recorder, grpc, response.status, headers, None);
+            let calls = recorder.calls.into_inner();
+            if !matches!(calls.as_slice(), [HostCall::GrpcStatus { status, .. }] if *status == grpc_status)
+            {
+                return Err(format!(
+                    "expected grpc-status {}, got {:?}",
+                    grpc_status, calls
+                ));
+            }
+        }
+    }
+
+    let client_path = source.header(":path").unwrap_or_default();
+    if let Some(path) = &case.expect.path {
+        let actual = evaluation.effective_path(&client_path);
+        if actual != path {
+            return Err(format!("expected path {}, got {}", path, actual));
+        }
+    }
+    if let Some(original) = &case.expect.original_path {
+        let actual = evaluation.path_rewrite.as_ref().map(|r| &r.original);
+        if actual != Some(original) {
+            return Err(format!(
+                "expected original path {}, got {:?}",
+                original, actual
+            ));
+        }
+    }
+
+    if let Some(policy) = &case.expect.policy {
+        let actual = evaluation
+            .policy
+            .map_or("", |index| config.policies[index].name.as_str());
+        if actual != policy {
+            return Err(format!("expected policy '{}', got '{}'", policy, actual));
+        }
+    }
+
+    if let Some(enforced) = case.expect.enforced {
+        if evaluation.monitor_only == enforced {
+            return Err(format!(
+                "expected enforced {}, got {}",
+                enforced, !evaluation.monitor_only
+            ));
+        }
+    }
+
+    if let Some(cluster) = &case.expect.tenant_cluster {
+        let actual = evaluation.tenant_cluster.as_deref().unwrap_or_default();
+        if actual != cluster {
+            return Err(format!(
+                "expected tenant cluster '{}', got '{}'",
+                cluster, actual
+            ));
+        }
+    }
+
+    if let Some(client_ip) = &case.expect.client_ip {
+        if evaluation.client_ip.as_ref() != Some(client_ip) {
+            return Err(format!(
+                "expected client ip {}, got {:?}",
+                client_ip, evaluation.client_ip
+            ));
+        }
+    }
+
+    if let Some(token) = &case.expect.negotiate_token {
+        if evaluation.negotiate_token.as_ref() != Some(token) {
+            return Err(format!(
+                "expected negotiate token {}, got {:?}",
+                token, evaluation.negotiate_token
+            ));
+        }
+    }
+
+    if let Some(target) = &case.expect.grpc_target {
+        let actual = evaluation
+            .grpc_target
+            .as_ref()
+            .map(|t| format!("{}/{}", t.service, t.method));
+        if actual.as_ref() != Some(target) {
+            return Err(format!("expected grpc target {}, got {:?}", target, actual));
+        }
+    }
+
+    if let Some(expected) = case.expect.session_cookie {
+        if evaluation.set_cookie.is_some() != expected {
+            return Err(format!(
+                "expected session cookie {}, got {:?}",
+                expected, evaluation.set_cookie
+            ));
+        }
+    }
+    if let Some(user) = &case.expect.basic_auth_user {
+        if evaluation.basic_auth_user.as_ref() != Some(user) {
+            return Err(format!(
+                "expected basic auth user {}, got {:?}",
+                user, evaluation.basic_auth_user
+            ));
+        }
+    }
+    if let Some(budget) = config.added_header_budget_bytes {
+        evaluation.upstream_headers.enforce_budget(budget);
+    }
+    let recorder = RecordingHost::default();
+    host::apply_headers(
+        &recorder,
+        &evaluation.upstream_headers,
+        &evaluation.strip_upstream_headers,
+        evaluation.upstream_authorization.as_deref(),
+    );
+    let mut upstream = Vec::new();
+    let mut stripped = Vec::new();
+    for call in recorder.calls.into_inner() {
+        match call {
+            HostCall::AddHeader(name, value) | HostCall::SetHeader(name, Some(value)) => {
+                upstream.push((name, value))
+            }
+            HostCall::SetHeader(name, None) => stripped.push(name),
+            call => return Err(format!("unexpected host call {:?}", call)),
+        }
+    }
+    for name in &case.expect.stripped_headers {
+        if !stripped.contains(name) {
+            return Err(format!(
+                "expected {} to be stripped, got {:?}",
+                name, stripped
+            ));
+        }
+    }
+
+    let request: Vec<(String, String)> = evaluation
+        .request_headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.clone()))
+        .collect();
+    check_headers("request", &case.expect.request_headers, &request)?;
+    for name in &case.expect.absent_upstream_headers {
+        if upstream
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            return Err(format!(
+                "unexpected upstream header {} in {:?}",
+                name, upstream
+            ));
+        }
+    }
+    check_headers("upstream", &case.expect.upstream_headers, &upstream)
+}
+
+fn check_headers(
+    kind: &str,
+    expected: &HashMap<String, String>,
+    actual: &[(String, String)],
+) -> Result<(), String> {
+    for (name, want) in expected {
+        let found = actual
+            .iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case(name) && value_matches(want, value));
+        if !found {
+            return Err(format!(
+                "missing {} header {}: {} (have {:?})",
+                kind, name, want, actual
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn value_matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+pub fn run_dir(dir: &Path) -> Result<Vec<String>, String> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    entries.sort();
+
+    let mut failures = Vec::new();
+    for path in entries {
+        let bytes = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let fixture: Fixture = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("{}: invalid fixture: {}", path.display(), e))?;
+        let name = path.display();
+        for failure in run(&fixture).map_err(|e| format!("{}: {}", name, e))? {
+            failures.push(format!("{}: {}", name, failure));
+        }
+    }
+    Ok(failures)
+}
+
+#[test]
+fn fixtures_pass() {
+    let dir = std::env::var("UIPBDIAUTHZ_FIXTURES_DIR")
+        .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures").to_string());
+
+    let failures = run_dir(Path::new(&dir)).unwrap();
+    assert!(
+        failures.is_empty(),
+        "fixture failures:\n{}",
+        failures.join("\n")
+    );
+}
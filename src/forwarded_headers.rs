@@ -0,0 +1,238 @@
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+// Header names sent to the authz service in FilterRequest. An entry is a
+// header name, or a prefix ending in `*` (`x-uip-*`) that forwards every
+// request header starting with it, so header families need no listing one by
+// one. Pseudo-headers are sent as dedicated fields and never match a pattern.
+
+// How a header the request carries more than once is sent
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiValueMode {
+    // One entry with the values joined (`, `; `; ` for cookie)
+    #[default]
+    Join,
+    // One entry per value
+    Repeat,
+    // The first value only (filters before multi-value support)
+    First,
+}
+
+// RFC 9110 list separator; cookie-pairs are joined as in a single header
+pub fn separator(name: &str) -> &'static str {
+    if name.eq_ignore_ascii_case("cookie") {
+        "; "
+    } else {
+        ", "
+    }
+}
+
+// Lowercase the entries and check them; `field` names the list in errors
+pub fn init(entries: &mut [String], field: &str) -> Result<(), String> {
+    for entry in entries {
+        entry.make_ascii_lowercase();
+        let name = entry.strip_suffix('*').unwrap_or(entry);
+        if name.is_empty() || name.contains('*') || name.starts_with(':') {
+            return Err(format!("{}: invalid header pattern '{}'", field, entry));
+        }
+    }
+    Ok(())
+}
+
+// Prefix of a pattern entry, None for a header name
+pub fn prefix(entry: &str) -> Option<&str> {
+    entry.strip_suffix('*')
+}
+
+pub fn matches_prefix(prefix: &str, name: &str) -> bool {
+    !name.starts_with(':')
+        && name.len() >= prefix.len()
+        && name.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+}
+
+// Regex rules for headers the name lists cannot express. A rule matches the
+// whole header name; `rename` sends the header under a new name built from
+// the capture groups (`x-legacy-(.*)` -> `x-uip-$1`). The rules are compiled
+// into one RegexSet at configure time, so a request header is tested against
+// all rules in a single pass; the first matching rule applies.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HeaderRule {
+    pub regex: String,
+    pub rename: Option<String>,
+
+    #[serde(skip)]
+    pattern: Option<Regex>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "Vec<HeaderRule>")]
+pub struct HeaderRules {
+    rules: Vec<HeaderRule>,
+    set: Option<RegexSet>,
+}
+
+impl From<Vec<HeaderRule>> for HeaderRules {
+    fn from(rules: Vec<HeaderRule>) -> Self {
+        Self { rules, set: None }
+    }
+}
+
+impl HeaderRules {
+    pub fn init(&mut self) -> Result<(), String> {
+        let mut anchored = Vec::with_capacity(self.rules.len());
+        for (index, rule) in self.rules.iter_mut().enumerate() {
+            let label = format!("forward_header_rules[{}]", index);
+            let regex = format!("^(?:{})$", rule.regex);
+            rule.pattern = Some(Regex::new(&regex).map_err(|e| format!("{}: {}", label, e))?);
+            if rule
+                .rename
+                .as_deref()
+                .is_some_and(|rename| rename.is_empty())
+            {
+                return Err(format!("{}: rename must not be empty", label));
+            }
+            anchored.push(regex);
+        }
+        if !anchored.is_empty() {
+            self.set = Some(RegexSet::new(&anchored).map_err(|e| e.to_string())?);
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Name to forward `name` under, when a rule matches it
+    pub fn forward_as<'a>(&self, name: &'a str) -> Option<Cow<'a, str>> {
+        if name.starts_with(':') {
+            return None;
+        }
+        let index = self.set.as_ref()?.matches(name).into_iter().next()?;
+        let rule = &self.rules[index];
+        let (Some(pattern), Some(rename)) = (&rule.pattern, &rule.rename) else {
+            return Some(Cow::Borrowed(name));
+        };
+        let captures = pattern.captures(name)?;
+        let mut renamed = String::new();
+        captures.expand(rename, &mut renamed);
+        renamed.make_ascii_lowercase();
+        (!renamed.is_empty() && !renamed.starts_with(':')).then_some(Cow::Owned(renamed))
+    }
+}
+
+// Forward-all mode: every request header not already sent goes to the authz
+// service, except the excluded ones (cookie jar, payload headers). Headers
+// are added in request order while FilterRequest.headers stays within
+// `max_bytes` (names and values); the rest are dropped and counted.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ForwardAllConfig {
+    // Header names or `prefix*` patterns
+    pub exclude: Vec<String>,
+    pub max_bytes: usize,
+}
+
+impl Default for ForwardAllConfig {
+    fn default() -> Self {
+        Self {
+            exclude: [
+                "cookie",
+                "content-*",
+                "transfer-encoding",
+                "proxy-authorization",
+            ]
+            .map(String::from)
+            .to_vec(),
+            max_bytes: 16 * 1024,
+        }
+    }
+}
+
+impl ForwardAllConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        init(&mut self.exclude, "forward_all_headers.exclude")
+    }
+
+    pub fn excludes(&self, name: &str) -> bool {
+        name.starts_with(':')
+            || self.exclude.iter().any(|entry| match prefix(entry) {
+                Some(prefix) => matches_prefix(prefix, name),
+                None => entry.eq_ignore_ascii_case(name),
+            })
+    }
+}
+
+// Cap on each forwarded header value, so an enormous JWT or baggage header
+// cannot push FilterRequest over the gRPC message limit. A longer value is cut
+// at a character boundary and ends with `marker`, the result at most
+// `max_value_bytes` long.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TruncationConfig {
+    pub max_value_bytes: usize,
+    pub marker: String,
+}
+
+impl Default for TruncationConfig {
+    fn default() -> Self {
+        Self {
+            max_value_bytes: 8 * 1024,
+            marker: "...[truncated]".into(),
+        }
+    }
+}
+
+impl TruncationConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.max_value_bytes <= self.marker.len() {
+            return Err("header_truncation.max_value_bytes must exceed the marker length".into());
+        }
+        Ok(())
+    }
+
+    // Truncated value, None when `value` fits
+    pub fn truncate(&self, value: &str) -> Option<String> {
+        if value.len() <= self.max_value_bytes {
+            return None;
+        }
+        let mut end = self.max_value_bytes - self.marker.len();
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        Some(format!("{}{}", &value[..end], self.marker))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn long_header_values_are_truncated_with_marker() {
+        use super::TruncationConfig;
+        let truncation = TruncationConfig {
+            max_value_bytes: 16,
+            marker: "...".into(),
+        };
+        assert!(truncation.init().is_ok());
+        assert_eq!(truncation.truncate("short"), None);
+        assert_eq!(
+            truncation
+                .truncate("eyJhbGciOiJSUzI1NiJ9.payload")
+                .as_deref(),
+            Some("eyJhbGciOiJSU...")
+        );
+        // Cut at a character boundary, never inside a multi-byte character
+        let truncated = truncation.truncate("ääääääääääää").unwrap();
+        assert_eq!(truncated, "ääääää...");
+        assert!(truncated.len() <= 16);
+
+        let marker_too_long = TruncationConfig {
+            max_value_bytes: 3,
+            marker: "...".into(),
+        };
+        assert!(marker_too_long.init().is_err());
+    }
+}
@@ -0,0 +1,129 @@
+// Golden-file tests of the FilterRequest wire format. Each `golden/<name>.json`
+// holds a plugin config, a canned header set (in order) and Envoy attributes;
+// the request is sent through the simulated host (see harness.rs) and the
+// bytes of the authz call compared with `golden/<name>.hex`. A proto, prost or
+// encoding change that alters what the authz service receives fails here,
+// even when the message still decodes the same. Set
+// UIPBDIAUTHZ_UPDATE_GOLDEN=1 to rewrite the files after an intended change.
+//
+// prost encodes map fields in hash order, so cases keep maps (attributes,
+// claims) to one entry.
+
+use prost::Message;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::harness::Simulation;
+use crate::uipbdiauthz::FilterRequest;
+
+#[derive(Debug, Deserialize)]
+struct GoldenCase {
+    #[serde(default)]
+    config: serde_json::Value,
+    headers: Vec<(String, String)>,
+    // Envoy attributes by dotted path, as strings
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+const BYTES_PER_LINE: usize = 32;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .map(|line| line.iter().map(|byte| format!("{:02x}", byte)).collect())
+        .collect::<Vec<String>>()
+        .join("\n")
+        + "\n"
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex {:?}", String::from_utf8_lossy(pair)))
+        })
+        .collect()
+}
+
+// Serialized FilterRequest the filter sends for `case`
+fn filter_request_bytes(case: &GoldenCase) -> Result<Vec<u8>, String> {
+    let properties = case
+        .properties
+        .iter()
+        .map(|(path, value)| (path.clone(), value.clone().into_bytes()))
+        .collect();
+    let mut simulation = Simulation::start_with_properties(case.config.clone(), properties);
+    let headers: Vec<(&str, &str)> = case
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    simulation.request(&headers);
+    match simulation.callouts().as_slice() {
+        [callout] => Ok(callout.message.clone()),
+        callouts => Err(format!("expected one authz call, got {}", callouts.len())),
+    }
+}
+
+fn check(path: &Path, update: bool) -> Result<(), String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let case: GoldenCase =
+        serde_json::from_slice(&bytes).map_err(|e| format!("invalid case: {}", e))?;
+    let actual = filter_request_bytes(&case)?;
+    let golden = path.with_extension("hex");
+    if update {
+        return std::fs::write(&golden, to_hex(&actual)).map_err(|e| e.to_string());
+    }
+
+    let expected = std::fs::read_to_string(&golden)
+        .map_err(|e| format!("{}: {}", golden.display(), e))
+        .and_then(|text| from_hex(&text))?;
+    if actual == expected {
+        return Ok(());
+    }
+    let decode = |bytes: &[u8]| match FilterRequest::decode(bytes) {
+        Ok(request) => format!("{:#?}", request),
+        Err(e) => format!("<undecodable: {}>", e),
+    };
+    Err(format!(
+        "wire format changed\nexpected:\n{}{}\nactual:\n{}{}",
+        to_hex(&expected),
+        decode(&expected),
+        to_hex(&actual),
+        decode(&actual)
+    ))
+}
+
+#[test]
+fn filter_requests_match_golden_files() {
+    let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/golden"));
+    let update = std::env::var_os("UIPBDIAUTHZ_UPDATE_GOLDEN").is_some();
+    let mut cases: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no golden cases in {}", dir.display());
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|path| {
+            check(path, update)
+                .err()
+                .map(|e| format!("{}: {}", path.display(), e))
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "golden failures (UIPBDIAUTHZ_UPDATE_GOLDEN=1 rewrites them):\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,48 @@
+use proxy_wasm::types::GrpcStatusCode;
+
+// Downstream requests that are themselves gRPC. Their target is forwarded to
+// the authz service as service/method, and local responses are sent as
+// trailers-only gRPC responses (`grpc-status` / `grpc-message`) because gRPC
+// clients cannot make sense of a plain HTTP 401 body.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GrpcTarget {
+    pub service: String,
+    pub method: String,
+}
+
+pub fn is_grpc(content_type: &str) -> bool {
+    let content_type = content_type.trim().to_ascii_lowercase();
+    content_type == "application/grpc"
+        || content_type.starts_with("application/grpc+")
+        || content_type.starts_with("application/grpc;")
+}
+
+// `:path` of a gRPC request is `/<package.Service>/<Method>`
+pub fn parse_target(content_type: Option<&str>, path: &str) -> Option<GrpcTarget> {
+    if !is_grpc(content_type?) {
+        return None;
+    }
+    let (service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if service.is_empty() || method.is_empty() || method.contains('/') {
+        return None;
+    }
+    Some(GrpcTarget {
+        service: service.to_string(),
+        method: method.to_string(),
+    })
+}
+
+// gRPC status equivalent to the HTTP status the filter would have answered
+pub fn status_for(http_status: u32) -> GrpcStatusCode {
+    match http_status {
+        400 => GrpcStatusCode::InvalidArgument,
+        401 => GrpcStatusCode::Unauthenticated,
+        403 => GrpcStatusCode::PermissionDenied,
+        404 => GrpcStatusCode::NotFound,
+        429 => GrpcStatusCode::ResourceExhausted,
+        502 | 503 => GrpcStatusCode::Unavailable,
+        504 => GrpcStatusCode::DeadlineExceeded,
+        _ => GrpcStatusCode::Internal,
+    }
+}
@@ -0,0 +1,204 @@
+use log::warn;
+use prost::Message;
+
+use crate::authn::LegResult;
+use crate::uipbdiauthz::FilterResponse;
+
+// Outcome of an authz call as reported by the gRPC layer. Envoy hands
+// on_grpc_call_response a numeric status only; the code and message the
+// service actually sent are in the `grpc-status` and `grpc-message` trailers
+// (the message percent-encoded as per the gRPC HTTP/2 protocol). Without
+// trailers (e.g. a local timeout), the host's status and message are used.
+
+const CODE_NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+pub const CODE_COUNT: usize = CODE_NAMES.len();
+
+#[derive(Debug, PartialEq)]
+pub struct CallStatus {
+    pub code: u32,
+    pub message: String,
+}
+
+impl CallStatus {
+    pub fn read(
+        status_code: u32,
+        trailer: impl Fn(&str) -> Option<Vec<u8>>,
+        host_message: Option<String>,
+    ) -> Self {
+        let trailer = |name| trailer(name).and_then(|value| String::from_utf8(value).ok());
+        let code = trailer("grpc-status")
+            .and_then(|code| code.trim().parse().ok())
+            .unwrap_or(status_code);
+        let message = trailer("grpc-message")
+            .map(|message| percent_decode(&message))
+            .or(host_message)
+            .unwrap_or_default();
+        Self { code, message }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.code == 0
+    }
+
+    pub fn name(&self) -> &'static str {
+        CODE_NAMES
+            .get(self.code as usize)
+            .copied()
+            .unwrap_or("UNKNOWN")
+    }
+
+    // HTTP status and body answered when the authz call failed
+    pub fn http_error(&self) -> (u32, &'static [u8]) {
+        match self.code {
+            4 => (504, b"Gateway Timeout"),
+            8 | 14 => (503, b"Service Unavailable"),
+            _ => (500, b"Internal Server Error"),
+        }
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// FilterResponse in a unary call's body, or the HTTP error to answer with
+// when the body is not one (e.g. an HTTP service behind the authz cluster)
+pub fn decode_reply(response_data: &[u8]) -> LegResult {
+    // Check if response looks like HTTP (common misconfiguration)
+    if response_data.len() > 4 && response_data.starts_with(b"HTTP") {
+        warn!("ERROR: Received HTTP response instead of gRPC protobuf! This indicates the backend service is misconfigured.");
+        warn!("Expected: gRPC service responding with FilterResponse protobuf");
+        warn!("Actual: HTTP response (likely the service is not running or wrong endpoint)");
+        return Err((
+            502,
+            b"Backend service misconfiguration - HTTP response received instead of gRPC",
+        ));
+    }
+
+    // Check for common non-protobuf patterns
+    if let Ok(text_response) = std::str::from_utf8(response_data) {
+        if text_response.contains("HTTP/")
+            || text_response.contains("GET ")
+            || text_response.contains("POST ")
+        {
+            warn!("ERROR: Backend returned HTTP log/text data instead of protobuf");
+            warn!(
+                "Response preview: {}",
+                &text_response[..text_response.len().min(200)]
+            );
+            return Err((502, b"Backend service error - non-protobuf response"));
+        }
+    }
+
+    let reply = match FilterResponse::decode(response_data) {
+        Ok(reply) => reply,
+        Err(e) => {
+            warn!("Failed to parse gRPC response: {:?}", e);
+            warn!("Response size: {} bytes", response_data.len());
+
+            // Show hex dump of first few bytes for debugging
+            let preview_bytes = &response_data[..response_data.len().min(32)];
+            let hex_preview: String = preview_bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<String>>()
+                .join(" ");
+            warn!("Response hex preview (first 32 bytes): {}", hex_preview);
+
+            if let Ok(raw_str) = String::from_utf8(response_data.to_vec()) {
+                warn!("Raw response content: {}", raw_str);
+            }
+            return Err((500, b"Internal Server Error"));
+        }
+    };
+
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn grpc_status_trailers_override_host_status() {
+        use super::CallStatus;
+
+        let trailers = |name: &str| match name {
+            "grpc-status" => Some(b"14".to_vec()),
+            "grpc-message" => Some(b"backend%20draining%3A%20retry".to_vec()),
+            _ => None,
+        };
+        let status = CallStatus::read(2, trailers, Some("upstream reset".into()));
+        assert_eq!(
+            status,
+            CallStatus {
+                code: 14,
+                message: "backend draining: retry".into(),
+            }
+        );
+        assert_eq!(status.name(), "UNAVAILABLE");
+        assert_eq!(status.http_error().0, 503);
+
+        let status = CallStatus::read(4, |_| None, Some("timeout".into()));
+        assert_eq!((status.code, status.message.as_str()), (4, "timeout"));
+        assert_eq!(status.http_error().0, 504);
+    }
+
+    #[test]
+    fn reply_bodies_map_to_http_errors() {
+        use super::decode_reply;
+        use crate::uipbdiauthz::FilterResponse;
+        use prost::Message;
+
+        let allow = FilterResponse {
+            allow: true,
+            user: "alice".into(),
+            ..Default::default()
+        };
+        assert_eq!(decode_reply(&allow.encode_to_vec()), Ok(allow));
+        assert_eq!(
+            decode_reply(b"HTTP/1.1 404 Not Found\r\n\r\n")
+                .unwrap_err()
+                .0,
+            502
+        );
+        assert_eq!(decode_reply(b"POST /x was logged").unwrap_err().0, 502);
+        assert_eq!(decode_reply(&[0xff, 0xff, 0xff]).unwrap_err().0, 500);
+    }
+}
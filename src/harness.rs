@@ -0,0 +1,1544 @@
+// Integration harness: the filter's proxy-wasm entry points (`proxy_on_*`)
+// driven natively against a simulated host. The host implements the
+// `proxy_*` ABI imports the module links against and records what the filter
+// asks of it, so a test goes through the same dispatcher, contexts and
+// hostcalls as under Envoy, without a Wasm runtime. Map lengths are `usize`
+// when the module serializes them (4 bytes on wasm32, 8 here) and `u32` when
+// it parses them, as in proxy-wasm. Also built for the fuzz targets
+// (`--cfg fuzzing`, see fuzz/) and the hot_path bench (`simulated-host`).
+
+use prost::Message;
+use proxy_wasm::types::{Action, BufferType, LogLevel, MapType, MetricType, Status, StreamType};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+// Replies are built by callers outside the crate too
+pub use crate::uipbdiauthz::FilterResponse;
+
+extern "C" {
+    fn proxy_on_context_create(context_id: u32, root_context_id: u32);
+    fn proxy_on_vm_start(context_id: u32, vm_configuration_size: usize) -> bool;
+    fn proxy_on_configure(context_id: u32, plugin_configuration_size: usize) -> bool;
+    fn proxy_on_request_headers(context_id: u32, num_headers: usize, end_of_stream: bool)
+        -> Action;
+    fn proxy_on_request_body(context_id: u32, body_size: usize, end_of_stream: bool) -> Action;
+    fn proxy_on_request_trailers(context_id: u32, num_trailers: usize) -> Action;
+    fn proxy_on_grpc_receive(context_id: u32, token_id: u32, response_size: usize);
+    fn proxy_on_grpc_close(context_id: u32, token_id: u32, status_code: u32);
+    fn proxy_on_tick(context_id: u32);
+    fn proxy_on_done(context_id: u32) -> bool;
+    fn proxy_on_delete(context_id: u32);
+}
+
+const START_NANOS: u64 = 1_700_000_000_000_000_000;
+
+#[derive(Debug, Clone)]
+pub struct GrpcCallout {
+    pub token: u32,
+    pub cluster: String,
+    pub service: String,
+    pub method: String,
+    pub metadata: Vec<(String, Vec<u8>)>,
+    pub message: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpCallout {
+    pub token: u32,
+    pub cluster: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalReply {
+    pub status: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedHost {
+    plugin_configuration: Vec<u8>,
+    request_headers: Vec<(String, String)>,
+    request_trailers: Vec<(String, String)>,
+    // Envoy attributes by dotted path
+    properties: HashMap<String, Vec<u8>>,
+    shared_data: HashMap<String, (Vec<u8>, u32)>,
+    grpc_receive_buffer: Vec<u8>,
+    grpc_status: u32,
+    callouts: Vec<GrpcCallout>,
+    // proxy_grpc_call fails instead of dispatching
+    reject_grpc_calls: bool,
+    http_callouts: Vec<HttpCallout>,
+    local_reply: Option<LocalReply>,
+    continued: bool,
+    // Requests resumed since the start, by any context
+    resumes: usize,
+    // Time since START_NANOS
+    elapsed_ms: u64,
+    next_metric: u32,
+    metric_names: HashMap<u32, String>,
+    // Counter totals by metric name
+    counters: HashMap<String, i64>,
+    // proxy_* imports called
+    host_calls: u64,
+}
+
+thread_local! {
+    static HOST: RefCell<SimulatedHost> = RefCell::new(SimulatedHost::default());
+    // The proxy-wasm dispatcher outlives a simulation, so context ids and
+    // call tokens are never reused on a thread
+    static NEXT_CONTEXT_ID: Cell<u32> = const { Cell::new(1) };
+    static NEXT_TOKEN: Cell<u32> = const { Cell::new(1) };
+}
+
+// The proxy-wasm logger and panic hook are process-wide and installed on
+// first configure, so simulations run one at a time
+static SIMULATIONS: Mutex<()> = Mutex::new(());
+
+// Host calls (`proxy_*` imports) made since the simulation started; a free
+// function so a bench measurement can read it
+pub fn host_calls() -> u64 {
+    HOST.with(|host| host.borrow().host_calls)
+}
+
+fn next_context_id() -> u32 {
+    NEXT_CONTEXT_ID.with(|id| id.replace(id.get() + 1))
+}
+
+pub struct Simulation {
+    root_context_id: u32,
+    live_contexts: Vec<u32>,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Simulation {
+    // Start the VM and configure the root context with `config`
+    pub fn start(config: serde_json::Value) -> Self {
+        Self::start_with_properties(config, HashMap::new())
+    }
+
+    // Same, with Envoy attributes by dotted path (`xds.route_name`), seen
+    // from configuration on
+    pub fn start_with_properties(
+        config: serde_json::Value,
+        properties: HashMap<String, Vec<u8>>,
+    ) -> Self {
+        let serial = SIMULATIONS.lock().unwrap_or_else(|e| e.into_inner());
+        HOST.with(|host| {
+            *host.borrow_mut() = SimulatedHost {
+                plugin_configuration: config.to_string().into_bytes(),
+                properties,
+                ..Default::default()
+            }
+        });
+        #[cfg(test)]
+        crate::message_buffer::FAIL_ENCODING.with(|fail| fail.set(false));
+        crate::_initialize();
+        let root_context_id = next_context_id();
+        let configured = unsafe {
+            proxy_on_context_create(root_context_id, 0);
+            proxy_on_vm_start(root_context_id, 0)
+                && proxy_on_configure(root_context_id, config.to_string().len())
+        };
+        assert!(configured, "plugin configuration rejected");
+        Self {
+            root_context_id,
+            live_contexts: Vec::new(),
+            _serial: serial,
+        }
+    }
+
+    // New request through on_http_request_headers; returns its context id
+    pub fn request(&mut self, headers: &[(&str, &str)]) -> (u32, Action) {
+        self.start_request(headers, true)
+    }
+
+    // New request whose body and trailers follow
+    pub fn request_with_body(&mut self, headers: &[(&str, &str)]) -> (u32, Action) {
+        self.start_request(headers, false)
+    }
+
+    pub fn body(&self, context_id: u32, size: usize, end_of_stream: bool) -> Action {
+        unsafe { proxy_on_request_body(context_id, size, end_of_stream) }
+    }
+
+    pub fn trailers(&self, context_id: u32, trailers: &[(&str, &str)]) -> Action {
+        HOST.with(|host| {
+            host.borrow_mut().request_trailers = trailers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        });
+        unsafe { proxy_on_request_trailers(context_id, trailers.len()) }
+    }
+
+    fn start_request(&mut self, headers: &[(&str, &str)], end_of_stream: bool) -> (u32, Action) {
+        let context_id = next_context_id();
+        self.live_contexts.push(context_id);
+        HOST.with(|host| {
+            let mut host = host.borrow_mut();
+            host.request_headers = headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            host.local_reply = None;
+            host.continued = false;
+        });
+        let action = unsafe {
+            proxy_on_context_create(context_id, self.root_context_id);
+            proxy_on_request_headers(context_id, headers.len(), end_of_stream)
+        };
+        (context_id, action)
+    }
+
+    // Answer a unary gRPC call with `reply`
+    pub fn grpc_reply(&self, callout: &GrpcCallout, reply: &FilterResponse) {
+        self.grpc_reply_bytes(callout, &reply.encode_to_vec());
+    }
+
+    // Answer a unary gRPC call with a raw body, which need not be a valid
+    // FilterResponse
+    pub fn grpc_reply_bytes(&self, callout: &GrpcCallout, body: &[u8]) {
+        HOST.with(|host| {
+            let mut host = host.borrow_mut();
+            host.grpc_receive_buffer = body.to_vec();
+            host.grpc_status = 0;
+        });
+        unsafe { proxy_on_grpc_receive(self.root_context_id, callout.token, body.len()) };
+    }
+
+    // Fail a unary gRPC call with `status`
+    pub fn grpc_failure(&self, callout: &GrpcCallout, status: u32) {
+        HOST.with(|host| host.borrow_mut().grpc_status = status);
+        unsafe { proxy_on_grpc_close(self.root_context_id, callout.token, status) };
+    }
+
+    // Make the host refuse every further gRPC call
+    pub fn reject_grpc_calls(&self) {
+        HOST.with(|host| host.borrow_mut().reject_grpc_calls = true);
+    }
+
+    // Set an Envoy attribute by dotted path, seen by the requests that follow
+    pub fn set_property(&self, path: &str, value: &[u8]) {
+        HOST.with(|host| {
+            host.borrow_mut()
+                .properties
+                .insert(path.to_string(), value.to_vec())
+        });
+    }
+
+    // Move the host clock forward
+    pub fn advance(&self, ms: u64) {
+        HOST.with(|host| host.borrow_mut().elapsed_ms += ms);
+    }
+
+    // Run the root context's timer
+    pub fn tick(&self) {
+        unsafe { proxy_on_tick(self.root_context_id) };
+    }
+
+    pub fn http_callouts(&self) -> Vec<HttpCallout> {
+        HOST.with(|host| host.borrow().http_callouts.clone())
+    }
+
+    pub fn callouts(&self) -> Vec<GrpcCallout> {
+        HOST.with(|host| host.borrow().callouts.clone())
+    }
+
+    // Calls made since the last take, for long runs
+    pub fn take_callouts(&self) -> Vec<GrpcCallout> {
+        HOST.with(|host| std::mem::take(&mut host.borrow_mut().callouts))
+    }
+
+    // Total of the counter `name` since the start
+    pub fn counter(&self, name: &str) -> i64 {
+        HOST.with(|host| {
+            host.borrow()
+                .counters
+                .get(name)
+                .copied()
+                .unwrap_or_default()
+        })
+    }
+
+    // Make every further FilterRequest fail to serialize
+    #[cfg(test)]
+    pub fn fail_encoding(&self) {
+        crate::message_buffer::FAIL_ENCODING.with(|fail| fail.set(true));
+    }
+
+    // Finish a request early: proxy_on_done and proxy_on_delete
+    pub fn end(&mut self, context_id: u32) {
+        self.live_contexts.retain(|id| *id != context_id);
+        unsafe {
+            if proxy_on_done(context_id) {
+                proxy_on_delete(context_id);
+            }
+        }
+    }
+
+    pub fn local_reply(&self) -> Option<LocalReply> {
+        HOST.with(|host| host.borrow().local_reply.clone())
+    }
+
+    pub fn continued(&self) -> bool {
+        HOST.with(|host| host.borrow().continued)
+    }
+
+    pub fn resumes(&self) -> usize {
+        HOST.with(|host| host.borrow().resumes)
+    }
+
+    pub fn request_header(&self, name: &str) -> Option<String> {
+        HOST.with(|host| {
+            host.borrow()
+                .request_headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        })
+    }
+}
+
+impl Drop for Simulation {
+    // Request contexts are torn down while the simulated host is still there
+    fn drop(&mut self) {
+        let mut contexts = std::mem::take(&mut self.live_contexts);
+        contexts.push(self.root_context_id);
+        for context_id in contexts {
+            unsafe {
+                if proxy_on_done(context_id) {
+                    proxy_on_delete(context_id);
+                }
+            }
+        }
+    }
+}
+
+fn count_host_call() {
+    HOST.with(|host| host.borrow_mut().host_calls += 1);
+}
+
+unsafe fn slice<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, size)
+    }
+}
+
+unsafe fn string(data: *const u8, size: usize) -> String {
+    String::from_utf8_lossy(slice(data, size)).into_owned()
+}
+
+// Hand bytes to the module, which takes ownership with Vec::from_raw_parts
+unsafe fn give(bytes: &[u8], data: *mut *mut u8, size: *mut usize) {
+    let boxed: Box<[u8]> = bytes.into();
+    *size = boxed.len();
+    *data = Box::into_raw(boxed) as *mut u8;
+}
+
+// Map serialized by the module
+fn parse_map(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+    const WIDTH: usize = std::mem::size_of::<usize>();
+    let read = |at: usize| {
+        let mut raw = [0; WIDTH];
+        raw.copy_from_slice(&bytes[at..at + WIDTH]);
+        usize::from_le_bytes(raw)
+    };
+    if bytes.len() < WIDTH {
+        return Vec::new();
+    }
+    let count = read(0);
+    let mut data = WIDTH + count * 2 * WIDTH;
+    (0..count)
+        .map(|i| {
+            let key_len = read(WIDTH + i * 2 * WIDTH);
+            let value_len = read(WIDTH + i * 2 * WIDTH + WIDTH);
+            let key = String::from_utf8_lossy(&bytes[data..data + key_len]).into_owned();
+            data += key_len + 1;
+            let value = bytes[data..data + value_len].to_vec();
+            data += value_len + 1;
+            (key, value)
+        })
+        .collect()
+}
+
+// Map in the layout the module parses
+fn serialize_map(map: &[(String, String)]) -> Vec<u8> {
+    let mut bytes = (map.len() as u32).to_le_bytes().to_vec();
+    for (key, value) in map {
+        bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    }
+    for (key, value) in map {
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(value.as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_log(
+    level: LogLevel,
+    message_data: *const u8,
+    message_size: usize,
+) -> Status {
+    count_host_call();
+    // Panics reach the host as critical logs
+    if level == LogLevel::Critical {
+        eprintln!("{}", string(message_data, message_size));
+    }
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_log_level(return_level: *mut LogLevel) -> Status {
+    count_host_call();
+    *return_level = LogLevel::Info;
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_current_time_nanoseconds(return_time: *mut u64) -> Status {
+    count_host_call();
+    *return_time = HOST.with(|host| START_NANOS + host.borrow().elapsed_ms * 1_000_000);
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_set_tick_period_milliseconds(_period: u32) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_buffer_bytes(
+    buffer_type: BufferType,
+    start: usize,
+    max_size: usize,
+    return_buffer_data: *mut *mut u8,
+    return_buffer_size: *mut usize,
+) -> Status {
+    count_host_call();
+    HOST.with(|host| {
+        let host = host.borrow();
+        let buffer = match buffer_type {
+            BufferType::PluginConfiguration => &host.plugin_configuration,
+            BufferType::GrpcReceiveBuffer => &host.grpc_receive_buffer,
+            _ => return Status::NotFound,
+        };
+        let start = start.min(buffer.len());
+        let end = start.saturating_add(max_size).min(buffer.len());
+        give(&buffer[start..end], return_buffer_data, return_buffer_size);
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+extern "C" fn proxy_set_buffer_bytes(
+    _buffer_type: BufferType,
+    _start: usize,
+    _size: usize,
+    _buffer_data: *const u8,
+    _buffer_size: usize,
+) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_header_map_pairs(
+    map_type: MapType,
+    return_map_data: *mut *mut u8,
+    return_map_size: *mut usize,
+) -> Status {
+    count_host_call();
+    HOST.with(|host| {
+        let host = host.borrow();
+        match map_type {
+            MapType::HttpRequestHeaders => {
+                give(
+                    &serialize_map(&host.request_headers),
+                    return_map_data,
+                    return_map_size,
+                );
+            }
+            MapType::HttpRequestTrailers => {
+                give(
+                    &serialize_map(&host.request_trailers),
+                    return_map_data,
+                    return_map_size,
+                );
+            }
+            _ => *return_map_data = std::ptr::null_mut(),
+        }
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_set_header_map_pairs(
+    map_type: MapType,
+    map_data: *const u8,
+    map_size: usize,
+) -> Status {
+    count_host_call();
+    if map_type == MapType::HttpRequestHeaders {
+        let headers = parse_map(slice(map_data, map_size))
+            .into_iter()
+            .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned()))
+            .collect();
+        HOST.with(|host| host.borrow_mut().request_headers = headers);
+    }
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> Status {
+    count_host_call();
+    if map_type != MapType::HttpRequestHeaders {
+        return Status::NotFound;
+    }
+    let key = string(key_data, key_size);
+    HOST.with(|host| {
+        let host = host.borrow();
+        match host
+            .request_headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&key))
+        {
+            Some((_, value)) => {
+                give(value.as_bytes(), return_value_data, return_value_size);
+                Status::Ok
+            }
+            None => Status::NotFound,
+        }
+    })
+}
+
+fn remove_request_header(host: &mut SimulatedHost, key: &str) {
+    host.request_headers
+        .retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_replace_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    count_host_call();
+    if map_type == MapType::HttpRequestHeaders {
+        let key = string(key_data, key_size);
+        let value = string(value_data, value_size);
+        HOST.with(|host| {
+            let mut host = host.borrow_mut();
+            remove_request_header(&mut host, &key);
+            host.request_headers.push((key, value));
+        });
+    }
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_remove_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+) -> Status {
+    count_host_call();
+    if map_type == MapType::HttpRequestHeaders {
+        let key = string(key_data, key_size);
+        HOST.with(|host| remove_request_header(&mut host.borrow_mut(), &key));
+    }
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_add_header_map_value(
+    map_type: MapType,
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    count_host_call();
+    if map_type == MapType::HttpRequestHeaders {
+        let header = (string(key_data, key_size), string(value_data, value_size));
+        HOST.with(|host| host.borrow_mut().request_headers.push(header));
+    }
+    Status::Ok
+}
+
+// Property paths are NUL-terminated segments
+unsafe fn property_path(path_data: *const u8, path_size: usize) -> String {
+    slice(path_data, path_size)
+        .split(|byte| *byte == 0)
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| String::from_utf8_lossy(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_property(
+    path_data: *const u8,
+    path_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+) -> Status {
+    count_host_call();
+    let path = property_path(path_data, path_size);
+    HOST.with(|host| match host.borrow().properties.get(&path) {
+        Some(value) => {
+            give(value, return_value_data, return_value_size);
+            Status::Ok
+        }
+        None => Status::NotFound,
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_set_property(
+    path_data: *const u8,
+    path_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+) -> Status {
+    count_host_call();
+    let path = property_path(path_data, path_size);
+    let value = slice(value_data, value_size).to_vec();
+    HOST.with(|host| host.borrow_mut().properties.insert(path, value));
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    return_value_data: *mut *mut u8,
+    return_value_size: *mut usize,
+    return_cas: *mut u32,
+) -> Status {
+    count_host_call();
+    let key = string(key_data, key_size);
+    HOST.with(|host| match host.borrow().shared_data.get(&key) {
+        Some((value, cas)) => {
+            give(value, return_value_data, return_value_size);
+            *return_cas = *cas;
+            Status::Ok
+        }
+        None => Status::NotFound,
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_set_shared_data(
+    key_data: *const u8,
+    key_size: usize,
+    value_data: *const u8,
+    value_size: usize,
+    cas: u32,
+) -> Status {
+    count_host_call();
+    let key = string(key_data, key_size);
+    let value = slice(value_data, value_size).to_vec();
+    HOST.with(|host| {
+        let mut host = host.borrow_mut();
+        let current = host.shared_data.get(&key).map_or(0, |(_, cas)| *cas);
+        if cas != 0 && cas != current {
+            return Status::CasMismatch;
+        }
+        host.shared_data.insert(key, (value, current + 1));
+        Status::Ok
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_register_shared_queue(
+    _name_data: *const u8,
+    _name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    count_host_call();
+    *return_id = 1;
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_resolve_shared_queue(
+    _vm_id_data: *const u8,
+    _vm_id_size: usize,
+    _name_data: *const u8,
+    _name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    count_host_call();
+    *return_id = 1;
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_dequeue_shared_queue(
+    _queue_id: u32,
+    _return_value_data: *mut *mut u8,
+    _return_value_size: *mut usize,
+) -> Status {
+    count_host_call();
+    Status::Empty
+}
+
+#[no_mangle]
+extern "C" fn proxy_enqueue_shared_queue(
+    _queue_id: u32,
+    _value_data: *const u8,
+    _value_size: usize,
+) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_continue_stream(stream_type: StreamType) -> Status {
+    count_host_call();
+    if stream_type == StreamType::HttpRequest {
+        HOST.with(|host| {
+            let mut host = host.borrow_mut();
+            host.continued = true;
+            host.resumes += 1;
+        });
+    }
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_close_stream(_stream_type: StreamType) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_send_local_response(
+    status_code: u32,
+    _status_code_details_data: *const u8,
+    _status_code_details_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    _grpc_status: i32,
+) -> Status {
+    count_host_call();
+    let headers = parse_map(slice(headers_data, headers_size))
+        .into_iter()
+        .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned()))
+        .collect();
+    let reply = LocalReply {
+        status: status_code,
+        headers,
+        body: slice(body_data, body_size).to_vec(),
+    };
+    HOST.with(|host| host.borrow_mut().local_reply = Some(reply));
+    Status::Ok
+}
+
+fn next_token() -> u32 {
+    NEXT_TOKEN.with(|token| token.replace(token.get() + 1))
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_http_call(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    headers_data: *const u8,
+    headers_size: usize,
+    body_data: *const u8,
+    body_size: usize,
+    _trailers_data: *const u8,
+    _trailers_size: usize,
+    _timeout: u32,
+    return_token: *mut u32,
+) -> Status {
+    count_host_call();
+    let token = next_token();
+    let callout = HttpCallout {
+        token,
+        cluster: string(upstream_data, upstream_size),
+        headers: parse_map(slice(headers_data, headers_size))
+            .into_iter()
+            .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned()))
+            .collect(),
+        body: slice(body_data, body_size).to_vec(),
+    };
+    HOST.with(|host| host.borrow_mut().http_callouts.push(callout));
+    *return_token = token;
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_grpc_call(
+    upstream_data: *const u8,
+    upstream_size: usize,
+    service_name_data: *const u8,
+    service_name_size: usize,
+    method_name_data: *const u8,
+    method_name_size: usize,
+    initial_metadata_data: *const u8,
+    initial_metadata_size: usize,
+    message_data_data: *const u8,
+    message_data_size: usize,
+    _timeout: u32,
+    return_callout_id: *mut u32,
+) -> Status {
+    count_host_call();
+    if HOST.with(|host| host.borrow().reject_grpc_calls) {
+        return Status::InternalFailure;
+    }
+    let token = next_token();
+    let callout = GrpcCallout {
+        token,
+        cluster: string(upstream_data, upstream_size),
+        service: string(service_name_data, service_name_size),
+        method: string(method_name_data, method_name_size),
+        metadata: parse_map(slice(initial_metadata_data, initial_metadata_size)),
+        message: slice(message_data_data, message_data_size).to_vec(),
+    };
+    HOST.with(|host| host.borrow_mut().callouts.push(callout));
+    *return_callout_id = token;
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_grpc_stream(
+    _upstream_data: *const u8,
+    _upstream_size: usize,
+    _service_name_data: *const u8,
+    _service_name_size: usize,
+    _method_name_data: *const u8,
+    _method_name_size: usize,
+    _initial_metadata_data: *const u8,
+    _initial_metadata_size: usize,
+    return_stream_id: *mut u32,
+) -> Status {
+    count_host_call();
+    *return_stream_id = next_token();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_grpc_send(
+    _token: u32,
+    _message_ptr: *const u8,
+    _message_len: usize,
+    _end_stream: bool,
+) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_grpc_cancel(_token_id: u32) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_grpc_close(_token_id: u32) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_status(
+    return_code: *mut u32,
+    return_message_data: *mut *mut u8,
+    _return_message_size: *mut usize,
+) -> Status {
+    count_host_call();
+    *return_code = HOST.with(|host| host.borrow().grpc_status);
+    *return_message_data = std::ptr::null_mut();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_set_effective_context(_context_id: u32) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_call_foreign_function(
+    _function_name_data: *const u8,
+    _function_name_size: usize,
+    _arguments_data: *const u8,
+    _arguments_size: usize,
+    _results_data: *mut *mut u8,
+    _results_size: *mut usize,
+) -> Status {
+    count_host_call();
+    Status::NotFound
+}
+
+#[no_mangle]
+extern "C" fn proxy_done() -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_define_metric(
+    _metric_type: MetricType,
+    name_data: *const u8,
+    name_size: usize,
+    return_id: *mut u32,
+) -> Status {
+    count_host_call();
+    let name = string(name_data, name_size);
+    *return_id = HOST.with(|host| {
+        let mut host = host.borrow_mut();
+        host.next_metric += 1;
+        let id = host.next_metric;
+        host.metric_names.insert(id, name);
+        id
+    });
+    Status::Ok
+}
+
+#[no_mangle]
+unsafe extern "C" fn proxy_get_metric(_metric_id: u32, return_value: *mut u64) -> Status {
+    count_host_call();
+    *return_value = 0;
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_record_metric(_metric_id: u32, _value: u64) -> Status {
+    count_host_call();
+    Status::Ok
+}
+
+#[no_mangle]
+extern "C" fn proxy_increment_metric(metric_id: u32, offset: i64) -> Status {
+    count_host_call();
+    HOST.with(|host| {
+        let mut host = host.borrow_mut();
+        if let Some(name) = host.metric_names.get(&metric_id).cloned() {
+            *host.counters.entry(name).or_default() += offset;
+        }
+    });
+    Status::Ok
+}
+
+#[cfg(test)]
+const REQUEST: &[(&str, &str)] = &[
+    (":method", "GET"),
+    (":scheme", "https"),
+    (":authority", "api.example.com"),
+    (":path", "/orders?limit=5"),
+    ("x-request-id", "req-1"),
+    ("authorization", "Bearer token"),
+];
+
+// Start a request and return the authz call it made
+#[cfg(test)]
+fn authorize(simulation: &mut Simulation) -> GrpcCallout {
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    let callouts = simulation.callouts();
+    assert_eq!(callouts.len(), 1, "expected one authz call");
+    callouts[0].clone()
+}
+
+#[test]
+fn allowed_request_resumes_with_identity() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    let callout = authorize(&mut simulation);
+    assert!(callout.cluster.starts_with("outbound|50051||"));
+    assert_eq!(
+        (callout.service.as_str(), callout.method.as_str()),
+        ("authengine.UIPBDIAuthZProcessor", "processReq")
+    );
+    let request = crate::uipbdiauthz::FilterRequest::decode(callout.message.as_slice()).unwrap();
+    assert_eq!(request.path, "/orders?limit=5");
+    assert!(callout
+        .metadata
+        .iter()
+        .any(|(name, value)| name == "x-request-id" && value == b"req-1"));
+
+    let reply = FilterResponse {
+        allow: true,
+        user: "alice".into(),
+        ..Default::default()
+    };
+    simulation.grpc_reply(&callout, &reply);
+    assert!(simulation.continued());
+    assert!(simulation.local_reply().is_none());
+    assert_eq!(
+        simulation.request_header("x-uip-user").as_deref(),
+        Some("alice")
+    );
+}
+
+#[test]
+fn denied_request_gets_401() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    let callout = authorize(&mut simulation);
+    let reply = FilterResponse {
+        message: "token \"abc\" expired".into(),
+        ..Default::default()
+    };
+    simulation.grpc_reply(&callout, &reply);
+    let local = simulation.local_reply().expect("no local response");
+    assert_eq!(local.status, 401);
+    assert!(local.headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("www-authenticate")
+            && value == r#"Bearer error="invalid_token", error_description="token \"abc\" expired""#
+    }));
+    assert!(!simulation.continued());
+}
+
+#[test]
+fn unavailable_authz_service_gets_503() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    let callout = authorize(&mut simulation);
+    simulation.grpc_failure(&callout, 14);
+    let local = simulation.local_reply().expect("no local response");
+    assert_eq!(local.status, 503);
+    assert_eq!(local.body, b"Service Unavailable");
+    assert!(!simulation.continued());
+}
+
+#[test]
+fn problem_details_render_error_bodies() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "problem_details": { "type_base": "https://errors.example.com/" }
+    }));
+    let (_, action) = simulation.request(&[
+        (":method", "GET"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("x-correlation-id", "corr-7"),
+    ]);
+    assert_eq!(action, Action::Pause);
+    let callout = simulation.callouts()[0].clone();
+    simulation.grpc_failure(&callout, 14);
+    let local = simulation.local_reply().expect("no local response");
+    assert_eq!(local.status, 503);
+    assert!(local
+        .headers
+        .iter()
+        .any(|(name, value)| name == "content-type" && value == "application/problem+json"));
+    let problem: serde_json::Value = serde_json::from_slice(&local.body).unwrap();
+    assert_eq!(
+        problem,
+        serde_json::json!({
+            "type": "https://errors.example.com/503",
+            "title": "Service Unavailable",
+            "status": 503,
+            "correlation_id": "corr-7"
+        })
+    );
+}
+
+#[test]
+fn deny_template_renders_escaped_fields() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "deny_template": {
+            "body": "{\"error\":\"{{reason}}\",\"status\":{{status}},\"path\":\"{{path}}\"}",
+            "content_type": "application/json"
+        }
+    }));
+    let callout = authorize(&mut simulation);
+    let reply = FilterResponse {
+        message: "Bearer error=\"invalid_token\"".into(),
+        ..Default::default()
+    };
+    simulation.grpc_reply(&callout, &reply);
+    let local = simulation.local_reply().expect("no local response");
+    assert_eq!(local.status, 401);
+    assert!(local
+        .headers
+        .iter()
+        .any(|(name, value)| name == "content-type" && value == "application/json"));
+    let body: serde_json::Value = serde_json::from_slice(&local.body).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "error": "Bearer error=\"invalid_token\"",
+            "status": 401,
+            "path": "/orders?limit=5"
+        })
+    );
+}
+
+#[test]
+fn deny_body_follows_accept_language() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "localized_messages": {
+            "default_locale": "en",
+            "catalogs": {
+                "en": { "401": "Please sign in" },
+                "de": { "401": "Bitte melden Sie sich an" },
+                "fr": { "401": "Veuillez vous connecter" }
+            }
+        }
+    }));
+    simulation.request(&[
+        (":method", "GET"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("accept-language", "fr;q=0.5, de-CH, en;q=0.8"),
+    ]);
+    let callout = simulation.callouts()[0].clone();
+    simulation.grpc_reply(&callout, &FilterResponse::default());
+    let local = simulation.local_reply().expect("no local response");
+    assert_eq!(local.status, 401);
+    assert_eq!(local.body, "Bitte melden Sie sich an".as_bytes());
+    assert!(local
+        .headers
+        .iter()
+        .any(|(name, value)| name == "content-language" && value == "de"));
+}
+
+#[test]
+fn repeated_headers_keep_every_value() {
+    let repeated = [
+        (":method", "GET"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("x-tenant", "acme"),
+        ("x-tenant", "globex"),
+        ("x-forwarded-for", "203.0.113.7"),
+        ("x-forwarded-for", "10.0.0.5"),
+    ];
+    let forwarded = |mode: &str| {
+        let mut simulation = Simulation::start(serde_json::json!({
+            "forward_headers": ["x-tenant"],
+            "multi_value_headers": mode
+        }));
+        simulation.request(&repeated);
+        let callout = simulation.callouts()[0].clone();
+        let request =
+            crate::uipbdiauthz::FilterRequest::decode(callout.message.as_slice()).unwrap();
+        request
+            .headers
+            .into_iter()
+            .filter(|header| header.key == "x-tenant")
+            .map(|header| header.value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(forwarded("join"), ["acme, globex"]);
+    assert_eq!(forwarded("repeat"), ["acme", "globex"]);
+    assert_eq!(forwarded("first"), ["acme"]);
+
+    // The hop counted from the right spans both x-forwarded-for headers
+    let mut simulation = Simulation::start(serde_json::json!({
+        "client_ip": { "xff_trusted_hops": 1, "deny": ["10.0.0.0/8"] }
+    }));
+    let (_, action) = simulation.request(&repeated);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(403)
+    );
+}
+
+#[test]
+fn forward_all_headers_honors_exclusions_and_cap() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "forward_all_headers": { "max_bytes": 200 }
+    }));
+    let baggage = "k=v,".repeat(40);
+    simulation.request(&[
+        (":method", "POST"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("cookie", "session=abc"),
+        ("content-type", "application/json"),
+        ("baggage", &baggage),
+        ("user-agent", "curl/8.5"),
+    ]);
+    let callout = simulation.callouts()[0].clone();
+    let request = crate::uipbdiauthz::FilterRequest::decode(callout.message.as_slice()).unwrap();
+    let keys: Vec<&str> = request
+        .headers
+        .iter()
+        .map(|header| header.key.as_str())
+        .filter(|key| !key.starts_with("x-original-req-"))
+        .collect();
+    assert_eq!(keys, ["user-agent"]);
+}
+
+#[test]
+fn mirror_posts_filter_request_copies_from_the_tick() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "mirror": { "cluster": "analytics", "authority": "analytics.internal" }
+    }));
+    let callout = authorize(&mut simulation);
+    // Nothing is sent until the root tick, and the decision does not wait
+    assert!(simulation.http_callouts().is_empty());
+    simulation.grpc_reply(
+        &callout,
+        &FilterResponse {
+            allow: true,
+            user: "alice".into(),
+            ..Default::default()
+        },
+    );
+    assert!(simulation.continued());
+
+    simulation.tick();
+    let mirrored = simulation.http_callouts();
+    assert_eq!(mirrored.len(), 1);
+    assert_eq!(mirrored[0].cluster, "analytics");
+    assert_eq!(mirrored[0].body, callout.message);
+    let header = |name: &str| {
+        mirrored[0]
+            .headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(header(":path"), Some("/mirror"));
+    assert_eq!(header("content-type"), Some("application/x-protobuf"));
+    assert_eq!(header("x-request-id"), Some("req-1"));
+}
+
+#[test]
+fn grpc_request_is_authorized_with_its_trailers() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "request_trailers": { "names": ["x-checksum"] }
+    }));
+    let (context_id, action) = simulation.request_with_body(&[
+        (":method", "POST"),
+        (":path", "/orders.v1.Orders/Create"),
+        (":authority", "api.example.com"),
+        ("content-type", "application/grpc"),
+        ("authorization", "Bearer token"),
+    ]);
+    assert_eq!(action, Action::Pause);
+    assert!(
+        simulation.callouts().is_empty(),
+        "called before the trailers"
+    );
+    assert_eq!(simulation.body(context_id, 64, false), Action::Pause);
+    assert!(simulation.callouts().is_empty());
+
+    let action = simulation.trailers(
+        context_id,
+        &[("x-checksum", "sha256=abc"), ("x-debug", "1")],
+    );
+    assert_eq!(action, Action::Pause);
+    let callout = simulation.callouts()[0].clone();
+    let request = crate::uipbdiauthz::FilterRequest::decode(callout.message.as_slice()).unwrap();
+    let trailers: Vec<(&str, &str)> = request
+        .trailers
+        .iter()
+        .map(|trailer| (trailer.key.as_str(), trailer.value.as_str()))
+        .collect();
+    assert_eq!(trailers, [("x-checksum", "sha256=abc")]);
+
+    simulation.grpc_reply(
+        &callout,
+        &FilterResponse {
+            allow: true,
+            ..Default::default()
+        },
+    );
+    assert!(simulation.continued());
+
+    // Plain requests with a body are authorized from their headers
+    let (_, action) = simulation.request_with_body(&[
+        (":method", "POST"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("content-type", "application/json"),
+    ]);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(simulation.callouts().len(), 2);
+}
+
+#[test]
+fn identical_concurrent_requests_share_one_authz_call() {
+    let mut simulation = Simulation::start(serde_json::json!({ "coalesce": {} }));
+    let callout = authorize(&mut simulation);
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(
+        simulation.callouts().len(),
+        1,
+        "identical request called again"
+    );
+
+    // Another credential is another call
+    let mut other = REQUEST.to_vec();
+    other.retain(|(name, _)| *name != "authorization");
+    other.push(("authorization", "Bearer other"));
+    simulation.request(&other);
+    assert_eq!(simulation.callouts().len(), 2);
+
+    simulation.grpc_reply(
+        &callout,
+        &FilterResponse {
+            allow: true,
+            user: "alice".into(),
+            ..Default::default()
+        },
+    );
+    assert_eq!(simulation.resumes(), 2);
+    assert_eq!(
+        simulation.request_header("x-uip-user").as_deref(),
+        Some("alice")
+    );
+
+    // Once answered, the call is not shared any more
+    simulation.request(REQUEST);
+    assert_eq!(simulation.callouts().len(), 3);
+}
+
+#[test]
+fn requests_from_other_clients_are_not_coalesced() {
+    let mut simulation = Simulation::start(serde_json::json!({ "coalesce": {} }));
+    simulation.set_property("source.address", b"203.0.113.7:41000");
+    authorize(&mut simulation);
+    // Same request, other trace context: still shared
+    let mut retried = REQUEST.to_vec();
+    retried.retain(|(name, _)| *name != "x-request-id");
+    retried.push(("x-request-id", "req-2"));
+    simulation.request(&retried);
+    assert_eq!(simulation.callouts().len(), 1);
+
+    // Same credential from another address is decided on its own
+    simulation.set_property("source.address", b"198.51.100.9:52000");
+    simulation.request(REQUEST);
+    let callouts = simulation.callouts();
+    assert_eq!(callouts.len(), 2, "verdict shared across client addresses");
+    let request =
+        crate::uipbdiauthz::FilterRequest::decode(callouts[1].message.as_slice()).unwrap();
+    assert_eq!(request.client_ip, "198.51.100.9");
+}
+
+#[test]
+fn parked_requests_call_on_their_own_when_the_leader_goes_away() {
+    let mut simulation = Simulation::start(serde_json::json!({ "coalesce": {} }));
+    authorize(&mut simulation);
+    let leader = simulation.live_contexts[0];
+    simulation.request(REQUEST);
+    simulation.end(leader);
+    assert_eq!(simulation.callouts().len(), 1);
+
+    // The next tick sends the parked request's own call, whose answer the
+    // root context passes on
+    simulation.tick();
+    let callouts = simulation.callouts();
+    assert_eq!(callouts.len(), 2);
+
+    simulation.grpc_reply(
+        &callouts[1],
+        &FilterResponse {
+            allow: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(simulation.resumes(), 1);
+}
+
+#[test]
+fn requests_queue_at_the_concurrency_limit_until_a_slot_frees() {
+    let mut simulation = Simulation::start(serde_json::json!({
+        "concurrency_limit": { "max_in_flight": 1, "queue_size": 1, "queue_timeout_ms": 500 }
+    }));
+    let callout = authorize(&mut simulation);
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(simulation.callouts().len(), 1, "queued request called");
+
+    // The queue is full
+    simulation.request(REQUEST);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(503)
+    );
+
+    let allow = FilterResponse {
+        allow: true,
+        ..Default::default()
+    };
+    simulation.grpc_reply(&callout, &allow);
+    assert_eq!(simulation.resumes(), 1);
+    // The freed slot goes to the queued request on the tick, and its answer
+    // comes through the root context
+    simulation.tick();
+    let callouts = simulation.callouts();
+    assert_eq!(callouts.len(), 2);
+    simulation.grpc_reply(&callouts[1], &allow);
+    assert_eq!(simulation.resumes(), 2);
+
+    // A request that waits past the queue timeout gets the failure mode
+    simulation.request(REQUEST);
+    let callout = simulation.callouts()[2].clone();
+    simulation.request(REQUEST);
+    simulation.advance(500);
+    simulation.tick();
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(503)
+    );
+    assert_eq!(simulation.callouts().len(), 3);
+    simulation.grpc_reply(&callout, &allow);
+}
+
+#[test]
+fn requests_wait_for_the_authz_cluster_to_answer_a_probe() {
+    let mut simulation = Simulation::start(serde_json::json!({ "readiness": {} }));
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(503)
+    );
+    assert!(simulation.callouts().is_empty());
+
+    simulation.tick();
+    let probes = simulation.take_callouts();
+    assert_eq!(probes.len(), 1);
+    assert_eq!(
+        (probes[0].service.as_str(), probes[0].method.as_str()),
+        ("grpc.health.v1.Health", "Check")
+    );
+    // HealthCheckResponse { status: SERVING }
+    simulation.grpc_reply_bytes(&probes[0], &[0x08, 0x01]);
+
+    authorize(&mut simulation);
+    simulation.tick();
+    assert_eq!(simulation.callouts().len(), 1, "probed once ready");
+}
+
+#[test]
+fn request_body_is_held_until_the_verdict() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    let (context_id, action) = simulation.request_with_body(&[
+        (":method", "POST"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+        ("content-type", "application/json"),
+    ]);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(simulation.body(context_id, 64, false), Action::Pause);
+    assert_eq!(
+        simulation.trailers(context_id, &[("x-checksum", "sha256=abc")]),
+        Action::Pause
+    );
+
+    let callout = simulation.callouts()[0].clone();
+    simulation.grpc_reply(
+        &callout,
+        &FilterResponse {
+            allow: true,
+            ..Default::default()
+        },
+    );
+    assert!(simulation.continued());
+    assert_eq!(simulation.body(context_id, 64, true), Action::Continue);
+
+    // A denied request's body is not held either, Envoy drops it
+    let (context_id, _) = simulation.request_with_body(&[
+        (":method", "POST"),
+        (":path", "/orders"),
+        (":authority", "api.example.com"),
+    ]);
+    assert_eq!(simulation.body(context_id, 64, false), Action::Pause);
+    let callout = simulation.callouts()[1].clone();
+    simulation.grpc_reply(&callout, &FilterResponse::default());
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(401)
+    );
+    assert_eq!(simulation.body(context_id, 64, true), Action::Continue);
+}
+
+#[test]
+fn failed_dispatch_applies_the_failure_mode() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    simulation.reject_grpc_calls();
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(503)
+    );
+    drop(simulation);
+
+    let mut simulation = Simulation::start(serde_json::json!({ "failure_mode": "allow" }));
+    simulation.reject_grpc_calls();
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Continue);
+    assert!(simulation.local_reply().is_none());
+}
+
+#[test]
+fn failed_chain_dispatch_applies_the_failure_mode() {
+    let chain = serde_json::json!([{ "cluster": "entitlements" }]);
+    let allow = FilterResponse {
+        allow: true,
+        user: "alice".into(),
+        ..Default::default()
+    };
+    let mut simulation = Simulation::start(serde_json::json!({ "chain": chain }));
+    simulation.request(REQUEST);
+    let callout = simulation.take_callouts().pop().expect("no authz call");
+    simulation.reject_grpc_calls();
+    simulation.grpc_reply(&callout, &allow);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(503)
+    );
+    drop(simulation);
+
+    let mut simulation = Simulation::start(serde_json::json!({
+        "chain": chain,
+        "failure_mode": "allow",
+    }));
+    simulation.request(REQUEST);
+    let callout = simulation.take_callouts().pop().expect("no authz call");
+    simulation.reject_grpc_calls();
+    simulation.grpc_reply(&callout, &allow);
+    assert!(simulation.continued());
+    assert!(simulation.local_reply().is_none());
+}
+
+#[test]
+fn unserializable_filter_request_fails_closed() {
+    let mut simulation = Simulation::start(serde_json::json!({}));
+    simulation.fail_encoding();
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Pause);
+    assert_eq!(
+        simulation.local_reply().map(|reply| reply.status),
+        Some(500)
+    );
+    assert!(simulation.callouts().is_empty());
+    assert_eq!(simulation.counter("uipbdiauthz.serialization_failures"), 1);
+    drop(simulation);
+
+    let mut simulation = Simulation::start(serde_json::json!({ "serialization_failure": "allow" }));
+    simulation.fail_encoding();
+    let (_, action) = simulation.request(REQUEST);
+    assert_eq!(action, Action::Continue);
+    assert!(simulation.local_reply().is_none());
+    assert_eq!(simulation.counter("uipbdiauthz.serialization_failures"), 1);
+}
@@ -0,0 +1,56 @@
+// Request headers fetched with a single host call. Headers the filter sets on
+// the request are mirrored here, so later reads see the new values without
+// going back to the host.
+
+#[derive(Debug, Default)]
+pub struct HeaderSnapshot(Vec<(String, String)>);
+
+impl HeaderSnapshot {
+    pub fn new(headers: Vec<(String, String)>) -> Self {
+        Self(headers)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Values of a header sent more than once, in request order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    // (count, bytes) of all headers
+    pub fn totals(&self) -> (usize, usize) {
+        let bytes = self
+            .0
+            .iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum();
+        (self.0.len(), bytes)
+    }
+
+    // Whether the header was present
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.0.len();
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.0.len() != len
+    }
+
+    // Same semantics as replacing the header on the request
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.0.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        self.0.push((name.to_string(), value.to_string()));
+    }
+}
@@ -0,0 +1,164 @@
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+use crate::shared_counter;
+
+// Background health checking of the authz cluster with the standard gRPC
+// health protocol (grpc.health.v1.Health/Check). One worker at a time probes
+// (a lease in shared data) and records the result there; request contexts
+// read it and go straight to the failure mode while the backend is known to
+// be down instead of waiting for each call to fail.
+
+pub const STATE_KEY: &str = "uipbdiauthz.health";
+const LEASE_KEY: &str = "uipbdiauthz.health.lease";
+
+pub const SERVICE: &str = "grpc.health.v1.Health";
+pub const METHOD: &str = "Check";
+// HealthCheckResponse.ServingStatus
+const SERVING: u64 = 1;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HealthCheckConfig {
+    pub interval_ms: u64,
+    pub timeout_ms: u64,
+    // Service name sent in HealthCheckRequest; empty checks the whole server
+    pub service: String,
+    // Consecutive failed probes before the backend is marked down
+    pub unhealthy_threshold: u64,
+    // Results older than this many intervals are ignored (prober gone)
+    pub stale_after_intervals: u64,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 5000,
+            timeout_ms: 1000,
+            service: String::new(),
+            unhealthy_threshold: 3,
+            stale_after_intervals: 3,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct HealthState {
+    pub healthy: bool,
+    pub consecutive_failures: u64,
+    // Unix millis of the last probe result
+    pub checked_at_ms: u64,
+}
+
+impl SharedValue for HealthState {
+    const KIND: u8 = shared_codec::KIND_HEALTH;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.u8(self.healthy as u8)
+            .u64(self.consecutive_failures)
+            .u64(self.checked_at_ms);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            healthy: input.u8()? != 0,
+            consecutive_failures: input.u64()?,
+            checked_at_ms: input.u64()?,
+        })
+    }
+}
+
+impl HealthCheckConfig {
+    pub fn request(&self) -> Vec<u8> {
+        check_request(&self.service)
+    }
+
+    // Whether a request at `now_ms` should skip the authz call; unknown or
+    // stale state counts as healthy
+    pub fn known_down(&self, state: Option<&[u8]>, now_ms: u64) -> bool {
+        let Some(state) = state.and_then(shared_codec::from_bytes::<HealthState>) else {
+            return false;
+        };
+        let max_age = self.interval_ms * self.stale_after_intervals.max(1);
+        !state.healthy && now_ms.saturating_sub(state.checked_at_ms) <= max_age
+    }
+
+    // Whether this worker should probe now
+    pub fn should_probe(&self, now_ms: u64) -> bool {
+        let (lease_until, cas) = shared_counter::read(LEASE_KEY);
+        if now_ms < lease_until {
+            return false;
+        }
+        shared_counter::write(LEASE_KEY, now_ms + self.interval_ms, cas)
+    }
+
+    // Fold a probe result into the shared state
+    pub fn record(&self, serving: bool, now_ms: u64) {
+        let (current, cas) = shared_codec::get::<HealthState>(STATE_KEY);
+        let current = current.unwrap_or(HealthState {
+            healthy: true,
+            ..Default::default()
+        });
+
+        let consecutive_failures = if serving {
+            0
+        } else {
+            current.consecutive_failures + 1
+        };
+        let healthy = consecutive_failures < self.unhealthy_threshold.max(1);
+        if healthy != current.healthy {
+            if healthy {
+                info!("[HEALTH] Authz cluster is back up");
+            } else {
+                warn!(
+                    "[HEALTH] Authz cluster down after {} failed probes",
+                    consecutive_failures
+                );
+            }
+        }
+
+        let state = HealthState {
+            healthy,
+            consecutive_failures,
+            checked_at_ms: now_ms,
+        };
+        if let Err(e) = shared_codec::set(STATE_KEY, &state, cas) {
+            warn!("[HEALTH] Failed to record health state: {:?}", e);
+        }
+    }
+}
+
+// Serialized HealthCheckRequest { string service = 1; } (the shared-data
+// codec's varints are protobuf varints)
+pub fn check_request(service: &str) -> Vec<u8> {
+    if service.is_empty() {
+        return Vec::new();
+    }
+    let mut message = Encoder::default();
+    message.u8(0x0a).bytes(service.as_bytes());
+    message.into_bytes()
+}
+
+// Whether a HealthCheckResponse { ServingStatus status = 1; } says SERVING
+pub fn is_serving(response: &[u8]) -> bool {
+    let mut input = Decoder::new(response);
+    while let Some(tag) = input.u64() {
+        match tag {
+            // status, varint
+            0x08 => return input.u64() == Some(SERVING),
+            // Skip other fields by wire type
+            _ => match tag & 0x07 {
+                0 => {
+                    input.u64();
+                }
+                2 => {
+                    input.bytes();
+                }
+                _ => return false,
+            },
+        }
+    }
+    false
+}
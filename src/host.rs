@@ -0,0 +1,79 @@
+use proxy_wasm::types::{GrpcStatusCode, Status};
+use std::time::Duration;
+
+use crate::grpc_downstream;
+use crate::upstream_headers::UpstreamHeaders;
+
+// Host calls AuthEngine makes to act on a request, behind a trait so the code
+// deciding which calls to make runs natively against a recording host (see
+// fixtures.rs). Reads go through pipeline::RequestSource.
+pub trait Host {
+    // Replace a request header; None removes it
+    fn set_header(&self, name: &str, value: Option<&str>);
+    fn add_header(&self, name: &str, value: &str);
+    fn dispatch(&self, call: &GrpcCall) -> Result<u32, Status>;
+    fn send_response(&self, status: u32, headers: Vec<(&str, &str)>, body: Option<&[u8]>);
+    fn send_grpc_status(
+        &self,
+        status: GrpcStatusCode,
+        message: Option<&str>,
+        metadata: Vec<(&str, &[u8])>,
+    );
+    fn resume_request(&self);
+}
+
+#[derive(Debug)]
+pub struct GrpcCall<'a> {
+    pub cluster: &'a str,
+    pub service: &'a str,
+    pub method: &'a str,
+    pub metadata: Vec<(&'a str, &'a [u8])>,
+    pub message: &'a [u8],
+    pub timeout: Duration,
+}
+
+// Answer the downstream request locally; gRPC clients get a trailers-only
+// response where the body becomes grpc-message and headers are passed on as
+// metadata
+pub fn respond(
+    host: &dyn Host,
+    grpc: bool,
+    status: u32,
+    headers: Vec<(&str, &str)>,
+    body: Option<&[u8]>,
+) {
+    if grpc {
+        let message = body.map(String::from_utf8_lossy);
+        let metadata = headers
+            .into_iter()
+            .map(|(name, value)| (name, value.as_bytes()))
+            .collect();
+        host.send_grpc_status(
+            grpc_downstream::status_for(status),
+            message.as_deref(),
+            metadata,
+        );
+    } else {
+        host.send_response(status, headers, body);
+    }
+}
+
+// Remove the stripped headers from the upstream request and add the decided
+// ones; an exchanged token replaces the client's `authorization` even when it
+// is stripped
+pub fn apply_headers(
+    host: &dyn Host,
+    headers: &UpstreamHeaders,
+    strip: &[String],
+    authorization: Option<&str>,
+) {
+    for name in strip {
+        host.set_header(name, None);
+    }
+    if let Some(authorization) = authorization {
+        host.set_header("authorization", Some(authorization));
+    }
+    for addition in headers.iter() {
+        host.add_header(&addition.name, &addition.value);
+    }
+}
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+// Identity headers that only this filter, or the authz service behind it, may
+// set. A client sending one of them could pass as another user to the authz
+// service or the upstream, so they are removed from every incoming request
+// before anything reads or forwards them.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TrustedHeadersConfig {
+    pub headers: Vec<String>,
+}
+
+impl Default for TrustedHeadersConfig {
+    fn default() -> Self {
+        Self {
+            headers: vec![
+                "x-uip-user".into(),
+                "x-uip-wasm-impersonated-user".into(),
+                "x-event-service-user".into(),
+            ],
+        }
+    }
+}
+
+// Identity attributes from the authz verdict go upstream as `x-uip-<name>`
+// headers. Names are lowercased with `_` turned into `-`; names with other
+// characters are refused. Control characters are removed from values, so a
+// value cannot end the header line or smuggle in another header.
+pub const ATTRIBUTE_PREFIX: &str = "x-uip-";
+
+// Upstream header for an attribute, None when the name cannot be one
+pub fn attribute_header(name: &str) -> Option<String> {
+    let name = name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    Some(format!(
+        "{}{}",
+        ATTRIBUTE_PREFIX,
+        name.to_ascii_lowercase().replace('_', "-")
+    ))
+}
+
+pub fn sanitize_value(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
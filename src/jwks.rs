@@ -0,0 +1,158 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+use crate::shared_counter;
+
+// JSON Web Key Sets fetched in the background by the root context. The set is
+// stored in shared data as one value, so every worker switches from the old
+// keys to the new ones at once; a failed or malformed fetch leaves the current
+// set in place. Request contexts only ever read the stored set.
+
+pub const KEYS_KEY: &str = "uipbdiauthz.jwks";
+// Until when (unix secs) a worker holds the right to refresh the set
+const LEASE_KEY: &str = "uipbdiauthz.jwks.lease";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct JwksConfig {
+    pub cluster: String,
+    pub authority: String,
+    pub path: String,
+    pub refresh_interval_secs: u64,
+    pub timeout_ms: u64,
+    // Reject bearer JWTs whose `kid` is not in the current set before the
+    // authz call (only once a set has been fetched)
+    pub reject_unknown_kid: bool,
+}
+
+impl Default for JwksConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            authority: String::new(),
+            path: "/.well-known/jwks.json".into(),
+            refresh_interval_secs: 300,
+            timeout_ms: 5000,
+            reject_unknown_kid: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct KeySet {
+    // Incremented on every rotation
+    pub generation: u64,
+    pub fetched_at: u64,
+    // The JWKS document as served
+    pub document: Vec<u8>,
+}
+
+impl SharedValue for KeySet {
+    const KIND: u8 = shared_codec::KIND_JWKS;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.u64(self.generation)
+            .u64(self.fetched_at)
+            .bytes(&self.document);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            generation: input.u64()?,
+            fetched_at: input.u64()?,
+            document: input.bytes()?.to_vec(),
+        })
+    }
+
+    // A bare JWKS document, e.g. provisioned by a control plane
+    fn decode_unversioned(bytes: &[u8]) -> Option<Self> {
+        parse_kids(bytes)?;
+        Some(Self {
+            document: bytes.to_vec(),
+            ..Default::default()
+        })
+    }
+}
+
+impl KeySet {
+    pub fn contains_kid(&self, kid: &str) -> bool {
+        parse_kids(&self.document).is_some_and(|kids| kids.iter().any(|k| k == kid))
+    }
+}
+
+// Key ids of a JWKS document; None unless it is a non-empty key set
+fn parse_kids(document: &[u8]) -> Option<Vec<String>> {
+    #[derive(Deserialize)]
+    struct Jwks {
+        keys: Vec<Jwk>,
+    }
+    #[derive(Deserialize)]
+    struct Jwk {
+        #[serde(default)]
+        kid: Option<String>,
+    }
+
+    let jwks: Jwks = serde_json::from_slice(document).ok()?;
+    if jwks.keys.is_empty() {
+        return None;
+    }
+    Some(jwks.keys.into_iter().filter_map(|key| key.kid).collect())
+}
+
+// `kid` from the header of a compact JWT
+pub fn token_kid(token: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Header {
+        kid: Option<String>,
+    }
+
+    let header = token.split('.').next()?;
+    let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    header.kid
+}
+
+// Whether this worker should fetch now: the stored set is stale and no other
+// worker holds the refresh lease
+pub fn should_refresh(config: &JwksConfig, now: u64) -> bool {
+    let (current, _) = shared_codec::get::<KeySet>(KEYS_KEY);
+    if current.is_some_and(|set| now < set.fetched_at + config.refresh_interval_secs) {
+        return false;
+    }
+
+    let (lease_until, cas) = shared_counter::read(LEASE_KEY);
+    if now < lease_until {
+        return false;
+    }
+    let lease_secs = config.timeout_ms.div_ceil(1000) + 1;
+    shared_counter::write(LEASE_KEY, now + lease_secs, cas)
+}
+
+// Replace the stored set with a freshly fetched document
+pub fn rotate(status: Option<&str>, body: Option<&[u8]>, now: u64) {
+    let document = match (status, body) {
+        (Some("200"), Some(body)) if parse_kids(body).is_some() => body,
+        _ => {
+            warn!(
+                "[JWKS] Refresh failed with status {:?}, keeping current keys",
+                status
+            );
+            return;
+        }
+    };
+
+    let (current, cas) = shared_codec::get::<KeySet>(KEYS_KEY);
+    let generation = current.map_or(0, |set| set.generation) + 1;
+    let set = KeySet {
+        generation,
+        fetched_at: now,
+        document: document.to_vec(),
+    };
+    match shared_codec::set(KEYS_KEY, &set, cas) {
+        Ok(()) => info!("[JWKS] Rotated to key set generation {}", generation),
+        Err(e) => warn!("[JWKS] Failed to store key set: {:?}", e),
+    }
+}
@@ -0,0 +1,129 @@
+use serde::Deserialize;
+
+// Per-request leak accounting for builds with memory-tracking. Each stage of
+// a request (one host callback) is charged the net heap bytes it left
+// allocated: bytes allocated minus bytes deallocated, reallocations included.
+// Measuring inside the callback keeps the worker's other requests, which
+// interleave between callbacks, out of the count. The request total is the
+// sum of its stages when the request is logged; it still holds the request
+// context's own state, freed when the context is deleted, so warnings only go
+// out above the thresholds.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LeakDetectionConfig {
+    // Net bytes a whole request may retain
+    pub request_bytes: u64,
+    // Net bytes one stage may retain
+    pub stage_bytes: u64,
+}
+
+impl Default for LeakDetectionConfig {
+    fn default() -> Self {
+        Self {
+            request_bytes: 64 * 1024,
+            stage_bytes: 32 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    RequestHeaders,
+    // Authz, authn and chained replies of the request
+    AuthzReply,
+    ResponseHeaders,
+}
+
+const STAGES: [Stage; 3] = [
+    Stage::RequestHeaders,
+    Stage::AuthzReply,
+    Stage::ResponseHeaders,
+];
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::RequestHeaders => "request headers",
+            Stage::AuthzReply => "authz reply",
+            Stage::ResponseHeaders => "response headers",
+        }
+    }
+}
+
+// Net bytes per stage; fixed slots, so recording does not allocate
+#[derive(Debug, Default)]
+pub struct LeakTracker {
+    net_bytes: [i64; STAGES.len()],
+    recorded: bool,
+}
+
+impl LeakTracker {
+    pub fn record(&mut self, stage: Stage, before: u64, after: u64) {
+        self.net_bytes[stage as usize] += after as i64 - before as i64;
+        self.recorded = true;
+    }
+
+    // Warning text when the request or one of its stages retained more than
+    // allowed; resets the tracker
+    pub fn finish(&mut self, config: &LeakDetectionConfig) -> Option<String> {
+        let tracker = std::mem::take(self);
+        if !tracker.recorded {
+            return None;
+        }
+        let total: i64 = tracker.net_bytes.iter().sum();
+        let over_stage = tracker
+            .net_bytes
+            .iter()
+            .any(|bytes| *bytes > config.stage_bytes as i64);
+        if total <= config.request_bytes as i64 && !over_stage {
+            return None;
+        }
+        let stages: Vec<String> = STAGES
+            .iter()
+            .map(|stage| format!("{} {:+}", stage.name(), tracker.net_bytes[*stage as usize]))
+            .collect();
+        Some(format!(
+            "retained {} net bytes ({})",
+            total,
+            stages.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn leak_tracker_warns_on_net_bytes_over_thresholds() {
+        use super::{LeakDetectionConfig, LeakTracker, Stage};
+
+        let config = LeakDetectionConfig {
+            request_bytes: 1000,
+            stage_bytes: 800,
+        };
+        let mut tracker = LeakTracker::default();
+        // Lots allocated and freed again is not a leak
+        tracker.record(Stage::RequestHeaders, 10_000, 10_600);
+        tracker.record(Stage::AuthzReply, 10_600, 10_300);
+        tracker.record(Stage::ResponseHeaders, 10_300, 10_400);
+        assert_eq!(tracker.finish(&config), None);
+
+        tracker.record(Stage::RequestHeaders, 10_000, 10_500);
+        tracker.record(Stage::AuthzReply, 10_500, 10_800);
+        // Replies of parallel calls add up
+        tracker.record(Stage::AuthzReply, 12_000, 12_300);
+        assert_eq!(
+            tracker.finish(&config).as_deref(),
+            Some(
+                "retained 1100 net bytes (request headers +500, authz reply +600, response headers +0)"
+            )
+        );
+
+        // One stage over its threshold is reported even when the total is not
+        tracker.record(Stage::RequestHeaders, 0, 900);
+        tracker.record(Stage::ResponseHeaders, 900, 300);
+        assert!(tracker.finish(&config).is_some());
+        // Nothing recorded without memory-tracking
+        assert_eq!(tracker.finish(&config), None);
+    }
+}
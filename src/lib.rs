@@ -1,11 +1,132 @@
-mod uipbdiauthz;
-use log::{info, warn};
-use protobuf::Message;
+mod api_key;
+mod audit;
+mod authn;
+mod authority_policy;
+mod authz_session;
+mod basic_auth;
+mod bypass;
+mod chain;
+mod challenge;
+mod client_ip;
+mod coalesce;
+mod concurrency;
+mod config;
+mod cookies;
+mod correlation;
+mod cors;
+mod credentials;
+mod deadline;
+mod debug_headers;
+mod denial_audit;
+mod deny_template;
+mod dry_run;
+mod experiment;
+mod expr;
+mod extensions;
+#[cfg(test)]
+mod fixtures;
+mod forwarded_headers;
+#[cfg(test)]
+mod golden;
+mod grpc_downstream;
+mod grpc_status;
+#[cfg(any(test, fuzzing, feature = "simulated-host"))]
+#[doc(hidden)]
+pub mod harness;
+mod header_snapshot;
+mod health;
+mod host;
+mod identity_headers;
+mod identity_signature;
+mod jwks;
+mod leak_check;
+mod limits;
+mod locale;
+mod logging;
+mod memory_budget;
+mod memory_report;
+mod message_buffer;
+mod method_rules;
+mod metrics;
+mod mirror;
+mod negotiate;
+mod oidc;
+mod path;
+mod pipeline;
+mod problem;
+mod query;
+mod rate_limit;
+mod readiness;
+mod replay;
+mod reroute;
+mod rollout;
+mod schedule;
+mod schema;
+mod scratch;
+mod secrets;
+mod service_credential;
+mod shared_codec;
+#[cfg(test)]
+mod shared_compat;
+mod shared_counter;
+mod signature;
+mod stream;
+mod tenant;
+mod terminal;
+mod throughput;
+mod timeout_guard;
+mod tls;
+mod trace;
+mod trailers;
+// Generated by prost-build from protos/uipbdiauthz.proto (see build.rs)
+mod uipbdiauthz {
+    include!(concat!(env!("OUT_DIR"), "/authengine.rs"));
+}
+mod upgrade;
+mod upstream_headers;
+mod warm_up;
+use audit::{AuditEvent, AuditSinkConfig};
+use authn::{Leg, LegResult};
+use authority_policy::AuthorityPolicy;
+use coalesce::{CoalesceConfig, Member, Membership, SharedCoalescer};
+use concurrency::{ConcurrencyLimitConfig, Queued, SharedCallQueue};
+use config::{FailureMode, PluginConfig};
+use debug_headers::DecisionDetails;
+use denial_audit::{DenialAuditConfig, SharedDenialAudits};
+use deny_template::DenyFields;
+use experiment::SharedShadowCalls;
+use forwarded_headers::{ForwardAllConfig, MultiValueMode};
+use grpc_status::CallStatus;
+use header_snapshot::HeaderSnapshot;
+use health::HealthCheckConfig;
+use host::{GrpcCall, Host};
+use jwks::JwksConfig;
+use leak_check::{LeakTracker, Stage};
+use log::{debug, info, warn};
+use message_buffer::SharedMessageBuffer;
+use metrics::Metrics;
+use mirror::{MirrorConfig, SharedMirrors};
+use oidc::OidcConfig;
+use pipeline::{Evaluation, LocalResponse, RequestSource, Step};
+use prost::{EncodeError, Message};
+use proxy_wasm::hostcalls;
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
-use std::collections::HashMap;
-use std::time::Duration;
+use readiness::ReadinessConfig;
+use reroute::SharedReroutes;
+use schedule::Interval;
+use scratch::{HeaderList, SharedScratch};
+use service_credential::ServiceCredentialConfig;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, UNIX_EPOCH};
+use stream::{SharedAuthzStream, StreamConfig, Waiter};
+use terminal::TerminalGuard;
+use throughput::SharedWorkerStats;
+use timeout_guard::{PendingCall, SharedPendingCalls};
 use uipbdiauthz::{FilterRequest, FilterResponse};
+use warm_up::WarmUpConfig;
 
 // Memory tracking for leak detection (only when feature is enabled)
 #[cfg(feature = "memory-tracking")]
@@ -17,6 +138,11 @@ use std::alloc::System;
 #[global_allocator]
 static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
 
+// Compact TLSF allocator for memory-constrained sidecars
+#[cfg(all(feature = "small-allocator", not(feature = "memory-tracking")))]
+#[global_allocator]
+static GLOBAL: rlsf::SmallGlobalTlsf = rlsf::SmallGlobalTlsf::new();
+
 // Pre-computed pseudo-header mappings as fixed array - no heap allocation
 const PSEUDO_HEADER_MAP: [(&str, &str); 4] = [
     ("method", "x-original-req-method"),
@@ -28,438 +154,3233 @@ const PSEUDO_HEADER_MAP: [(&str, &str); 4] = [
 // Memory tracking utilities
 #[cfg(feature = "memory-tracking")]
 mod memory_tracking {
-    use super::*;
     use stats_alloc::{Stats, INSTRUMENTED_SYSTEM};
 
     pub fn get_memory_stats() -> Stats {
         INSTRUMENTED_SYSTEM.stats()
     }
 
-    pub fn log_memory_change(stage: &str, before: Option<Stats>) {
-        let current = get_memory_stats();
-        
-        if let Some(before) = before {
-            let bytes_delta = current.bytes_allocated as i64 - before.bytes_allocated as i64;
-            let allocs_delta = current.allocations as i64 - before.allocations as i64;
-            let deallocs_delta = current.deallocations as i64 - before.deallocations as i64;
-            
-            info!(
-                "[MEMORY-TRACK] {}: bytes_allocated={} ({:+}), allocations={} ({:+}), deallocations={} ({:+}), leaked_bytes={}",
-                stage,
-                current.bytes_allocated, 
-                bytes_delta,
-                current.allocations,
-                allocs_delta,
-                current.deallocations,
-                deallocs_delta,
-                current.bytes_allocated as i64 - current.deallocations as i64
-            );
-        } else {
-            info!(
-                "[MEMORY-TRACK] {}: bytes_allocated={}, allocations={}, deallocations={}",
-                stage,
-                current.bytes_allocated,
-                current.allocations,
-                current.deallocations
-            );
-        }
-    }
-
-    pub fn detect_memory_leak(stage: &str, before: Stats) {
-        let current = get_memory_stats();
-        let net_allocations = (current.allocations - current.deallocations) as i64 
-                            - (before.allocations - before.deallocations) as i64;
-        
-        if net_allocations > 0 {
-            warn!(
-                "[MEMORY-LEAK] Potential leak at {}: {} net allocations, {} bytes potentially leaked",
-                stage,
-                net_allocations,
-                current.bytes_allocated as i64 - before.bytes_allocated as i64
-            );
-        }
+    // Live heap bytes
+    pub fn heap_bytes() -> Option<u64> {
+        let stats = get_memory_stats();
+        let live = stats.bytes_allocated as i64 - stats.bytes_deallocated as i64
+            + stats.bytes_reallocated as i64;
+        Some(live.max(0) as u64)
     }
 }
 
 #[cfg(not(feature = "memory-tracking"))]
 mod memory_tracking {
-    #[derive(Clone, Copy)]
-    pub struct Stats {
-        pub bytes_allocated: usize,
-        pub allocations: usize,
-        pub deallocations: usize,
-    }
-    
-    pub fn get_memory_stats() -> Stats {
-        Stats { bytes_allocated: 0, allocations: 0, deallocations: 0 }
+    // No instrumented allocator
+    pub fn heap_bytes() -> Option<u64> {
+        None
     }
-    
-    pub fn log_memory_change(_stage: &str, _before: Option<Stats>) {}
-    pub fn detect_memory_leak(_stage: &str, _before: Stats) {}
 }
 
 proxy_wasm::main! {{
     proxy_wasm::set_log_level(LogLevel::Trace);
-    proxy_wasm::set_http_context(|_, _| -> Box<dyn HttpContext> { Box::new(AuthEngine::new()) });
+    proxy_wasm::set_root_context(|context_id| -> Box<dyn RootContext> {
+        Box::new(AuthRoot {
+            context_id,
+            ..Default::default()
+        })
+    });
 }}
 
-struct AuthEngine {
-    // Pre-allocate collections to avoid repeated allocations
-    headers_buffer: HashMap<String, String>,
-    // Cache cluster name to avoid rebuilding on each request
-    cluster_name: String,
-    // Track memory usage per request
-    request_memory_bytes: usize,
-    // Memory tracking baseline for leak detection
-    #[cfg(feature = "memory-tracking")]
-    request_start_stats: Option<stats_alloc::Stats>,
+// Per-request diagnostics, only for requests picked by log sampling
+macro_rules! request_debug {
+    ($engine:expr, $($arg:tt)+) => {
+        if $engine.log_sampled {
+            debug!($($arg)+);
+        }
+    };
 }
 
-impl AuthEngine {
-    fn new() -> Self {
-        // Log plugin initialization memory state
-        memory_tracking::log_memory_change("Plugin Initialization", None);
-        
-        Self {
-            // Pre-allocate with expected capacity
-            headers_buffer: HashMap::with_capacity(10),
-            // Cache cluster name at initialization
-            cluster_name: Self::build_cluster_name(),
-            // Initialize memory tracking
-            request_memory_bytes: 0,
-            // Initialize memory tracking baseline
-            #[cfg(feature = "memory-tracking")]
-            request_start_stats: None,
-        }
-    }
+// How often a worker checks whether the stored JWKS is due for a refresh
+const JWKS_CHECK_INTERVAL_MS: u64 = 1000;
+// How often a worker checks whether the service credential is about to expire
+const CREDENTIAL_CHECK_INTERVAL_MS: u64 = 1000;
+// Upper bound on how long a sampled shadow call waits for dispatch
+const SHADOW_DISPATCH_INTERVAL_MS: u64 = 100;
+// Upper bound on how long a denial record waits for dispatch
+const DENIAL_AUDIT_DISPATCH_INTERVAL_MS: u64 = 1000;
+// Upper bound on how long a mirrored request waits for dispatch
+const MIRROR_DISPATCH_INTERVAL_MS: u64 = 1000;
+// Upper bound on how long requests parked on a call that ended unanswered
+// wait to call again
+const COALESCE_HANDOVER_INTERVAL_MS: u64 = 100;
+// Granularity of dispatching queued requests as concurrency slots free up
+const CALL_QUEUE_CHECK_INTERVAL_MS: u64 = 50;
+// How often keys read from Envoy properties are re-read for rotation
+const SECRET_REFRESH_INTERVAL_MS: u64 = 5000;
+// Granularity of authz stream deadlines and reconnects
+const STREAM_CHECK_INTERVAL_MS: u64 = 100;
+// gRPC status for requests the authz stream did not answer in time
+const GRPC_DEADLINE_EXCEEDED: u32 = 4;
 
-    // Helper to estimate memory usage of strings and collections
-    fn estimate_memory_usage(&self) -> usize {
-        let mut total_bytes = 0;
+// Request headers sent as gRPC initial metadata of the authz call, so the
+// authz service can trace and correlate calls without parsing the payload
+const REQUEST_CONTEXT_METADATA: [&str; 4] = [
+    "x-request-id",
+    correlation::HEADER,
+    trace::TRACEPARENT,
+    trace::TRACESTATE,
+];
 
-        // Cluster name (cached, amortized over all requests)
-        total_bytes += self.cluster_name.len();
+// Request context as seen by the root context, which finishes requests
+// answered on the authz stream
+type EngineHandle = Weak<RefCell<AuthEngine>>;
 
-        // Headers buffer
-        for (key, value) in &self.headers_buffer {
-            total_bytes += key.len() + value.len() + 48; // HashMap overhead estimate
+// Root context: owns the parsed plugin configuration and hands it to each
+// request context
+#[derive(Default)]
+struct AuthRoot {
+    context_id: u32,
+    config: Rc<PluginConfig>,
+    metrics: Metrics,
+    // Shared queue audit events are published to
+    audit_queue: Option<u32>,
+    // Requests of this VM paused on the authz call
+    pending_calls: SharedPendingCalls,
+    // Counters of this worker's request contexts
+    worker_stats: SharedWorkerStats,
+    // FilterRequest serialization buffer reused by this worker's requests
+    message_buffer: SharedMessageBuffer,
+    // Scratch region reset by each request of this worker
+    scratch: SharedScratch,
+    throughput: throughput::Reporter,
+    memory: memory_report::Reporter,
+    // Background jobs run from on_tick
+    audit_summary: Interval,
+    throughput_report: Interval,
+    memory_report: Interval,
+    jwks_check: Interval,
+    health_probe: Interval,
+    readiness_probe: Interval,
+    credential_check: Interval,
+    secret_refresh: Interval,
+    // Outstanding JWKS fetch
+    jwks_call: Option<u32>,
+    // Outstanding service-credential fetch
+    credential_call: Option<u32>,
+    // Outstanding health probe
+    health_call: Option<u32>,
+    // Outstanding readiness probe
+    readiness_call: Option<u32>,
+    // Shadow calls queued by request contexts, dispatched from the tick
+    shadow_calls: SharedShadowCalls,
+    // Denial records queued by request contexts, dispatched from the tick
+    denial_audits: SharedDenialAudits,
+    // Mirrored FilterRequests queued by request contexts, dispatched from the
+    // tick
+    mirrors: SharedMirrors,
+    // Authz calls in flight by coalescing key, with the requests parked on
+    // them
+    coalescer: SharedCoalescer<EngineHandle>,
+    // Calls request contexts made from the tick, whose answers come here
+    reroutes: SharedReroutes<EngineHandle>,
+    // Requests waiting for a slot of the concurrency limit
+    call_queue: SharedCallQueue<EngineHandle>,
+    // Authz stream shared with request contexts
+    authz_stream: SharedAuthzStream<EngineHandle>,
+    audit_flush: Interval,
+    // Audit batch being shipped: (token, event count)
+    audit_batch: Option<(u32, usize)>,
+    // Regular tick period, restored once the warm-up call is out
+    tick_period_ms: Option<u64>,
+    warm_up_pending: bool,
+    // Outstanding warm-up call: (token, dispatch time)
+    warm_up_call: Option<(u32, u64)>,
+}
+
+impl Context for AuthRoot {
+    fn on_http_call_response(&mut self, token_id: u32, _: usize, body_size: usize, _: usize) {
+        if self.mirrors.borrow_mut().complete(token_id) {
+            let status = self.get_http_call_response_header(":status");
+            if status.as_deref().is_some_and(|s| s.starts_with('2')) {
+                self.metrics.mirror_sent.increment(1);
+            } else {
+                warn!(
+                    "[MIRROR] Mirror cluster rejected a request with status {:?}",
+                    status
+                );
+                self.metrics.mirror_failed.increment(1);
+            }
+            return;
         }
 
-        total_bytes
-    }
+        if let Some((token, count)) = self.audit_batch {
+            if token == token_id {
+                self.audit_batch = None;
+                self.audit_batch_shipped(count);
+                self.ship_audit_batch();
+                return;
+            }
+        }
 
-    // Use string slice instead of returning reference - more efficient for empty check
-    fn get_value_or_space(value: &str) -> &str {
-        if value.trim().is_empty() {
-            " " // Return single space for null/empty values
-        } else {
-            value
+        if self.credential_call == Some(token_id) {
+            self.credential_call = None;
+            if let Some(credential) = self.config.service_credential.as_ref() {
+                let status = self.get_http_call_response_header(":status");
+                let body = self.get_http_call_response_body(0, body_size);
+                credential.store(status.as_deref(), body.as_deref(), self.now_ms() / 1000);
+            }
+            return;
         }
-    }
 
-    // Optimized headers map building - build final HashMap directly
-    fn build_protobuf_headers_map(&mut self) -> HashMap<String, String> {
-        // Build HashMap directly with pre-allocated capacity instead of using buffer
-        let mut headers_map = HashMap::with_capacity(11); // 4 pseudo + 7 regular headers max
+        if self.jwks_call != Some(token_id) {
+            return;
+        }
+        self.jwks_call = None;
 
-        // Use const slice instead of Vec + HashSet for better performance
-        const HEADERS_TO_SEND: &[&str] = &[
-            "x-forwarded-client-cert",
-            "x-request-id",
-            "x-correlation-id",
-            "authorization",
-            "x-uip-wasm-impersonated-user",
-            "x-event-service-user",
-            "x-trino-user",
-        ];
+        let status = self.get_http_call_response_header(":status");
+        let body = self.get_http_call_response_body(0, body_size);
+        jwks::rotate(status.as_deref(), body.as_deref(), self.now_ms() / 1000);
+    }
 
-        // Process specific pseudo-headers individually to avoid Vec allocation
-        // Use const array to avoid format! allocations
-        const PSEUDO_HEADERS: [(&str, &str); 4] = [
-            (":method", "method"),
-            (":scheme", "scheme"),
-            (":authority", "authority"),
-            (":path", "path"),
-        ];
+    fn on_grpc_call_response(&mut self, token_id: u32, status_code: u32, response_size: usize) {
+        let rerouted = self.reroutes.borrow_mut().take(token_id);
+        if let Some((context_id, engine)) = rerouted {
+            wake_context(context_id, &engine, self.context_id, |engine| {
+                engine.on_grpc_call_response(token_id, status_code, response_size)
+            });
+            return;
+        }
 
-        for &(header_name, pseudo_key) in &PSEUDO_HEADERS {
-            if let Some(value) = self.get_http_request_header(header_name) {
-                if let Some((_, new_header_name)) =
-                    PSEUDO_HEADER_MAP.iter().find(|(key, _)| *key == pseudo_key)
-                {
-                    info!(
-                        "Converting pseudo-header '{}' to '{}' for protobuf",
-                        header_name, new_header_name
-                    );
-                    headers_map.insert(new_header_name.to_string(), value);
-                }
+        if let Some((token, dispatched_ms)) = self.warm_up_call {
+            if token == token_id {
+                self.warm_up_call = None;
+                self.warm_up_finished(status_code, dispatched_ms);
+                return;
             }
         }
 
-        // Then handle specific headers we want to forward
-        for &header_name in HEADERS_TO_SEND {
-            if let Some(value) = self.get_http_request_header(header_name) {
-                headers_map.insert(header_name.to_string(), value);
-                info!("Added specific header to protobuf: '{}'", header_name);
+        if self.denial_audits.borrow_mut().complete(token_id) {
+            if status_code == 0 {
+                self.metrics.denial_audit_sent.increment(1);
+            } else {
+                warn!(
+                    "[DENIAL-AUDIT] Audit cluster rejected denial record with grpc status {}",
+                    status_code
+                );
+                self.metrics.denial_audit_failed.increment(1);
             }
+            return;
         }
 
-        info!(
-            "Built protobuf headers map with {} entries",
-            headers_map.len()
-        );
+        if self.shadow_calls.borrow().is_shadow(token_id) {
+            let body = self.get_grpc_call_response_body(0, response_size);
+            self.shadow_calls.borrow_mut().complete(
+                token_id,
+                status_code,
+                body.as_deref(),
+                self.now_ms(),
+                &self.metrics,
+            );
+            return;
+        }
 
-        headers_map
-    }
+        if self.readiness_call == Some(token_id) {
+            self.readiness_call = None;
+            let body = self.get_grpc_call_response_body(0, response_size);
+            readiness::record_probe(status_code, body.as_deref());
+            return;
+        }
 
-    // Extract common gRPC call logic to reduce code duplication
-    fn make_grpc_call(&self, cluster_name: &str, message: &[u8]) -> Result<u32, Status> {
-        info!("Making gRPC call to:");
-        info!("  Cluster: {}", cluster_name);
-        info!("  Service: authengine.UIPBDIAuthZProcessor");
-        info!("  Method: processReq");
-        info!("  Message size: {} bytes", message.len());
-        info!("  Timeout: 5 seconds");
-        
-        self.dispatch_grpc_call(
-            cluster_name,
-            "authengine.UIPBDIAuthZProcessor",
-            "processReq",
-            vec![],
-            Some(message),
-            Duration::from_secs(5),
-        )
-    }
+        if self.health_call != Some(token_id) {
+            return;
+        }
+        self.health_call = None;
 
-    // Build cluster name once at initialization
-    fn build_cluster_name() -> String {
-        let service_instance =
-            std::env::var("SERVICE_INSTANCE").unwrap_or_else(|_| "localhost".into());
-        format!(
-            "outbound|50051||{}.localhost.for.grpc.call",
-            service_instance
-        )
+        let Some(health_check) = self.config.health_check.as_ref() else {
+            return;
+        };
+        let serving = status_code == 0
+            && self
+                .get_grpc_call_response_body(0, response_size)
+                .is_some_and(|body| health::is_serving(&body));
+        if !serving {
+            warn!(
+                "[HEALTH] Probe failed (grpc status {}), backend not serving",
+                status_code
+            );
+        }
+        health_check.record(serving, self.now_ms());
     }
-}
 
-impl HttpContext for AuthEngine {
-    fn on_http_request_headers(&mut self, _: usize, _end_of_stream: bool) -> Action {
-        info!("Entering on_http_request_headers");
-        info!("Initializing gRPC OAuth 2.0 policy");
+    fn on_grpc_stream_message(&mut self, token_id: u32, message_size: usize) {
+        if !self.authz_stream.borrow().is_stream(token_id) {
+            return;
+        }
+        let message = self.get_grpc_stream_message(0, message_size);
+        let Some((id, reply)) = message.as_deref().and_then(stream::decode_response) else {
+            warn!("[STREAM] Dropping undecodable authz stream message");
+            return;
+        };
+        let waiter = self.authz_stream.borrow_mut().take(id);
+        match waiter {
+            Some(waiter) => self.wake(waiter, |engine| engine.on_stream_reply(0, reply)),
+            None => debug!("[STREAM] No request waiting for stream response {}", id),
+        }
+    }
 
-        // Initialize memory tracking for this request
-        #[cfg(feature = "memory-tracking")]
-        {
-            self.request_start_stats = Some(memory_tracking::get_memory_stats());
-            memory_tracking::log_memory_change("Request Start", None);
+    fn on_grpc_stream_close(&mut self, token_id: u32, status_code: u32) {
+        if !self.authz_stream.borrow().is_stream(token_id) {
+            return;
+        }
+        let config = Rc::clone(&self.config);
+        let Some(stream_config) = config.stream.as_ref() else {
+            return;
+        };
+        warn!(
+            "[STREAM] Authz stream closed with grpc status {}, reopening in {} ms",
+            status_code, stream_config.reconnect_ms
+        );
+        self.metrics.stream_closed.increment(1);
+        let waiters = self
+            .authz_stream
+            .borrow_mut()
+            .closed(stream_config, self.now_ms());
+        for waiter in waiters {
+            self.wake(waiter, AuthEngine::retry_unary);
         }
+    }
+}
 
-        // Reset and track memory for this request
-        self.request_memory_bytes = 0;
-        let initial_memory = self.estimate_memory_usage();
-        info!("[MEMORY] Initial memory usage: {} bytes", initial_memory);
+impl AuthRoot {
+    fn now_ms(&self) -> u64 {
+        self.get_current_time()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
 
-        // Get headers for logging - use as_deref to get &str for display
-        let method_opt = self.get_http_request_header(":method");
-        let scheme_opt = self.get_http_request_header(":scheme");
-        let authority_opt = self.get_http_request_header(":authority");
-        let path_opt = self.get_http_request_header(":path");
+    // Settle requests whose downstream timeout is about to fire: apply the
+    // failure mode on their behalf and drop the outstanding gRPC call
+    fn settle_expired_calls(&self, now_ms: u64) {
+        let expired = self.pending_calls.borrow_mut().take_expired(now_ms);
+        for call in expired {
+            if !call
+                .guard
+                .claim(self.metrics.suppressed_terminal_actions, "timeout")
+            {
+                continue;
+            }
 
-        info!(
-            "Request details - Method: {}, Scheme: {}, Authority: {}, Path: {}",
-            method_opt.as_deref().unwrap_or(""),
-            scheme_opt.as_deref().unwrap_or(""),
-            authority_opt.as_deref().unwrap_or(""),
-            path_opt.as_deref().unwrap_or("")
-        );
+            warn!(
+                "[TIMEOUT] Authz call of context {} still pending at deadline, applying failure mode {:?}",
+                call.context_id, self.config.failure_mode
+            );
+            if hostcalls::set_effective_context(call.context_id).is_err() {
+                continue;
+            }
+            match call.token {
+                Some(token) => {
+                    let _ = hostcalls::cancel_grpc_call(token);
+                }
+                None => self
+                    .authz_stream
+                    .borrow_mut()
+                    .remove_context(call.context_id),
+            }
+            self.worker_stats.borrow_mut().record_decision("error");
+            let _ = match self.config.failure_mode {
+                _ if call.fail_open => hostcalls::resume_http_request(),
+                FailureMode::Allow => hostcalls::resume_http_request(),
+                FailureMode::Deny if call.grpc => hostcalls::send_grpc_response(
+                    grpc_downstream::status_for(504),
+                    Some("Gateway Timeout"),
+                    vec![],
+                ),
+                FailureMode::Deny => {
+                    hostcalls::send_http_response(504, vec![], Some(b"Gateway Timeout"))
+                }
+            };
+        }
+        let _ = hostcalls::set_effective_context(self.context_id);
+    }
 
-        // Build headers map for protobuf (takes ownership to avoid clones)
-        let headers_map = self.build_protobuf_headers_map();
-        let after_headers_memory = self.estimate_memory_usage();
-        info!(
-            "[MEMORY] After header processing: {} bytes (+{} bytes)",
-            after_headers_memory,
-            after_headers_memory - initial_memory
-        );
+    fn refresh_jwks(&mut self, jwks: &JwksConfig, now_ms: u64) {
+        if self.jwks_call.is_some() || !jwks::should_refresh(jwks, now_ms / 1000) {
+            return;
+        }
 
-        // Track memory after header processing
-        #[cfg(feature = "memory-tracking")]
-        memory_tracking::log_memory_change("After Header Processing", self.request_start_stats);
+        match self.dispatch_http_call(
+            &jwks.cluster,
+            vec![
+                (":method", "GET"),
+                (":path", &jwks.path),
+                (":authority", &jwks.authority),
+                ("accept", "application/json"),
+            ],
+            None,
+            vec![],
+            Duration::from_millis(jwks.timeout_ms),
+        ) {
+            Ok(token) => {
+                info!("[JWKS] Dispatched key set refresh with token: {}", token);
+                self.jwks_call = Some(token);
+            }
+            Err(e) => warn!("[JWKS] Failed to dispatch key set refresh: {:?}", e),
+        }
+    }
 
-        // Log all headers that will be sent in the protobuf message
-        info!(
-            "[HEADERS] Headers to be sent in gRPC call ({} total):",
-            headers_map.len()
-        );
-        for (key, value) in &headers_map {
-            info!("[HEADERS]   '{}' = '{}'", key, value);
+    fn refresh_service_credential(&mut self, credential: &ServiceCredentialConfig, now_ms: u64) {
+        if self.credential_call.is_some() || !credential.should_refresh(now_ms / 1000) {
+            return;
         }
 
-        // Create FilterRequest
-        let mut req = FilterRequest::new();
-        // Insert headers by taking ownership - no clones needed!
-        *req.mut_headers() = headers_map;
+        match self.dispatch_http_call(
+            &credential.cluster,
+            vec![
+                (":method", "POST"),
+                (":path", &credential.path),
+                (":authority", &credential.authority),
+                ("content-type", &credential.content_type),
+                ("accept", "application/json"),
+            ],
+            Some(credential.body.as_bytes()),
+            vec![],
+            Duration::from_millis(credential.timeout_ms),
+        ) {
+            Ok(token) => {
+                info!(
+                    "[CREDENTIAL] Dispatched service credential refresh with token: {}",
+                    token
+                );
+                self.credential_call = Some(token);
+            }
+            Err(e) => warn!(
+                "[CREDENTIAL] Failed to dispatch service credential refresh: {:?}",
+                e
+            ),
+        }
+    }
 
-        // Set protobuf fields - use unwrap_or_default for String types (minimal allocation for empty strings)
-        req.set_method(method_opt.unwrap_or_default());
-        req.set_path(path_opt.unwrap_or_default());
-        req.set_scheme(scheme_opt.unwrap_or_default());
+    // Ship the next batch of queued audit events (one batch in flight)
+    fn ship_audit_batch(&mut self) {
+        let config = Rc::clone(&self.config);
+        let (Some(audit), Some(queue_id)) = (config.audit.as_ref(), self.audit_queue) else {
+            return;
+        };
+        let Some(sink) = audit.sink.as_ref() else {
+            return;
+        };
+        if self.audit_batch.is_some() {
+            return;
+        }
+        let Some((body, count)) = audit::take_batch(queue_id, sink.batch_size) else {
+            return;
+        };
 
-        let message = match req.write_to_bytes() {
-            Ok(bytes) => bytes,
+        match self.dispatch_audit_batch(sink, &body) {
+            Ok(token) => {
+                info!(
+                    "[AUDIT] Shipping {} audit events with token: {}",
+                    count, token
+                );
+                self.audit_batch = Some((token, count));
+            }
             Err(e) => {
-                warn!("Failed to serialize request: {:?}", e);
-                return Action::Continue;
+                warn!("[AUDIT] Failed to ship {} audit events: {:?}", count, e);
+                self.metrics.audit_ship_failed.increment(count as i64);
             }
-        };
+        }
+    }
 
-        info!(
-            "Constructed FilterRequest with {} protobuf headers, message size: {} bytes",
-            req.get_headers().len(),
-            message.len()
-        );
+    fn dispatch_audit_batch(&self, sink: &AuditSinkConfig, body: &[u8]) -> Result<u32, Status> {
+        self.dispatch_http_call(
+            &sink.cluster,
+            vec![
+                (":method", "POST"),
+                (":path", &sink.path),
+                (":authority", &sink.authority),
+                ("content-type", "application/x-ndjson"),
+            ],
+            Some(body),
+            vec![],
+            Duration::from_millis(sink.timeout_ms),
+        )
+    }
 
-        // Track memory after protobuf creation
-        #[cfg(feature = "memory-tracking")]
-        memory_tracking::log_memory_change("After Protobuf Creation", self.request_start_stats);
+    fn audit_batch_shipped(&self, count: usize) {
+        let status = self.get_http_call_response_header(":status");
+        if status.as_deref().is_some_and(|s| s.starts_with('2')) {
+            self.metrics.audit_shipped.increment(count as i64);
+        } else {
+            warn!(
+                "[AUDIT] Audit cluster rejected {} events with status {:?}",
+                count, status
+            );
+            self.metrics.audit_ship_failed.increment(count as i64);
+        }
+    }
 
-        // Use cached cluster name
-        info!("[DEBUG] Using cached cluster name: {}", self.cluster_name);
+    // Answer requests the authz stream left waiting past their deadline, and
+    // reopen the stream when it is down
+    fn maintain_stream(&mut self, stream_config: &StreamConfig, now_ms: u64) {
+        let expired = self.authz_stream.borrow_mut().take_expired(now_ms);
+        for waiter in expired {
+            self.wake(waiter, |engine| {
+                engine.on_stream_reply(GRPC_DEADLINE_EXCEEDED, None)
+            });
+        }
 
-        match self.make_grpc_call(&self.cluster_name, &message) {
+        if !self.authz_stream.borrow().reopen_due(now_ms) {
+            return;
+        }
+        let credential = service_credential_metadata(&self.config, now_ms);
+        match self.open_grpc_stream(
+            &AuthEngine::build_cluster_name(),
+            "authengine.UIPBDIAuthZProcessor",
+            &stream_config.method,
+            grpc_metadata(&credential),
+        ) {
             Ok(token) => {
-                info!("Successfully dispatched gRPC call with token: {}", token);
-                Action::Pause
+                info!("[STREAM] Opened authz stream {}", token);
+                self.authz_stream.borrow_mut().opened(token);
             }
             Err(e) => {
-                warn!("Failed to dispatch gRPC call: {:?}", e);
-                Action::Continue
+                warn!("[STREAM] Failed to open authz stream: {:?}", e);
+                self.authz_stream
+                    .borrow_mut()
+                    .open_failed(stream_config, now_ms);
             }
         }
     }
 
-    fn on_http_response_headers(&mut self, _: usize, _end_of_stream: bool) -> Action {
-        // Response header is now set directly in on_grpc_call_response to avoid string storage
-        Action::Continue
+    // Run `f` on a request context waiting on the authz stream, with host
+    // calls made on its behalf
+    fn wake(&self, waiter: Waiter<EngineHandle>, f: impl FnOnce(&mut AuthEngine)) {
+        wake_context(waiter.context_id, &waiter.engine, self.context_id, f);
     }
-}
-
-impl Context for AuthEngine {
-    fn on_grpc_call_response(&mut self, token_id: u32, status_code: u32, response_size: usize) {
-        info!(
-            "gRPC response received - Token: {}, Status: {}, Size: {}",
-            token_id, status_code, response_size
-        );
 
-        // Track memory at start of gRPC response processing
-        #[cfg(feature = "memory-tracking")]
-        memory_tracking::log_memory_change("gRPC Response Start", self.request_start_stats);
+    // Requests parked on authz calls that ended unanswered call again
+    fn redispatch_orphans(&self) {
+        let orphans = self.coalescer.borrow_mut().take_orphans();
+        for member in orphans {
+            wake_context(
+                member.context_id,
+                &member.engine,
+                self.context_id,
+                |engine| engine.coalesced_answer(None),
+            );
+        }
+    }
 
-        let response_data = match self.get_grpc_call_response_body(0, response_size) {
-            Some(data) => data,
-            None => {
-                warn!("No response data received from auth service");
-                self.send_http_response(500, vec![], Some(b"Internal Server Error"));
+    // Dispatch the calls of queued requests, oldest first, while slots of the
+    // concurrency limit are free, and give the failure mode to those that
+    // waited too long
+    fn admit_queued_calls(&self, now_ms: u64) {
+        let expired = self.call_queue.borrow_mut().take_expired(now_ms);
+        for entry in expired {
+            wake_context(entry.context_id, &entry.engine, self.context_id, |engine| {
+                engine.queue_expired()
+            });
+        }
+        loop {
+            let Some(entry) = self.call_queue.borrow_mut().pop() else {
+                return;
+            };
+            let mut admitted = false;
+            wake_context(entry.context_id, &entry.engine, self.context_id, |engine| {
+                admitted = engine.admit_queued()
+            });
+            if !admitted {
+                self.call_queue.borrow_mut().push_front(entry);
                 return;
             }
-        };
+        }
+    }
 
-        info!(
-            "Received raw response data of size: {}",
-            response_data.len()
-        );
+    fn dispatch_shadow_calls(&mut self, now_ms: u64) {
+        let queued = self.shadow_calls.borrow_mut().take_queued();
+        let credential = service_credential_metadata(&self.config, now_ms);
+        for (cluster_name, message) in queued {
+            match self.dispatch_grpc_call(
+                &cluster_name,
+                "authengine.UIPBDIAuthZProcessor",
+                "processReq",
+                grpc_metadata(&credential),
+                Some(&message),
+                Duration::from_secs(5),
+            ) {
+                Ok(token) => self.shadow_calls.borrow_mut().dispatched(token, now_ms),
+                Err(e) => {
+                    warn!("[EXPERIMENT] Failed to dispatch shadow call: {:?}", e);
+                    self.metrics.shadow_failed.increment(1);
+                }
+            }
+        }
+    }
 
-        // Add detailed debugging for response format
-        info!("Attempting to parse {} bytes as protobuf FilterResponse", response_data.len());
-        
-        // Check if response looks like HTTP (common misconfiguration)
-        if response_data.len() > 4 && response_data.starts_with(b"HTTP") {
-            warn!("ERROR: Received HTTP response instead of gRPC protobuf! This indicates the backend service is misconfigured.");
-            warn!("Expected: gRPC service responding with FilterResponse protobuf");
-            warn!("Actual: HTTP response (likely the service is not running or wrong endpoint)");
-            self.send_http_response(502, vec![], Some(b"Backend service misconfiguration - HTTP response received instead of gRPC"));
-            return;
-        }
-        
-        // Check for common non-protobuf patterns
-        if let Ok(text_response) = std::str::from_utf8(&response_data) {
-            if text_response.contains("HTTP/") || text_response.contains("GET ") || text_response.contains("POST ") {
-                warn!("ERROR: Backend returned HTTP log/text data instead of protobuf");
-                warn!("Response preview: {}", &text_response[..text_response.len().min(200)]);
-                self.send_http_response(502, vec![], Some(b"Backend service error - non-protobuf response"));
-                return;
+    // Read the signing keys configured as `property:` references into this
+    // worker's configuration
+    fn refresh_secrets(&self, config: &PluginConfig) -> Result<(), String> {
+        let property = |path: Vec<&str>| self.get_property(path);
+        let mut errors = Vec::new();
+        if let Some(signing) = config.request_signing.as_ref() {
+            errors.extend(signing.refresh_keys(&property).err());
+        }
+        if let Some(signing) = config.identity_signing.as_ref() {
+            errors.extend(signing.refresh_key(&property).err());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    fn dispatch_denial_audits(&mut self, denial_audit: &DenialAuditConfig, now_ms: u64) {
+        let queued = self.denial_audits.borrow_mut().take_queued();
+        let credential = service_credential_metadata(&self.config, now_ms);
+        for record in queued {
+            match self.dispatch_grpc_call(
+                &denial_audit.cluster,
+                &denial_audit.service,
+                &denial_audit.method,
+                grpc_metadata(&credential),
+                Some(&record),
+                Duration::from_millis(denial_audit.timeout_ms),
+            ) {
+                Ok(token) => self.denial_audits.borrow_mut().dispatched(token),
+                Err(e) => {
+                    warn!("[DENIAL-AUDIT] Failed to dispatch denial record: {:?}", e);
+                    self.metrics.denial_audit_failed.increment(1);
+                }
             }
         }
+    }
 
-        let reply = match FilterResponse::parse_from_bytes(&response_data) {
-            Ok(reply) => reply,
-            Err(e) => {
-                warn!("Failed to parse gRPC response: {:?}", e);
-                warn!("Response size: {} bytes", response_data.len());
-                
-                // Show hex dump of first few bytes for debugging
-                let preview_bytes = &response_data[..response_data.len().min(32)];
-                let hex_preview: String = preview_bytes.iter()
-                    .map(|b| format!("{:02x}", b))
-                    .collect::<Vec<String>>()
-                    .join(" ");
-                warn!("Response hex preview (first 32 bytes): {}", hex_preview);
-                
-                if let Ok(raw_str) = String::from_utf8(response_data.clone()) {
-                    warn!("Raw response content: {}", raw_str);
-                }
-                self.send_http_response(500, vec![], Some(b"Internal Server Error"));
-                return;
+    fn dispatch_mirrors(&mut self, mirror: &MirrorConfig) {
+        let queued = self.mirrors.borrow_mut().take_queued();
+        for (request_id, message) in queued {
+            match self.dispatch_http_call(
+                &mirror.cluster,
+                vec![
+                    (":method", "POST"),
+                    (":path", &mirror.path),
+                    (":authority", &mirror.authority),
+                    ("content-type", mirror::CONTENT_TYPE),
+                    ("x-request-id", &request_id),
+                ],
+                Some(&message),
+                vec![],
+                Duration::from_millis(mirror.timeout_ms),
+            ) {
+                Ok(token) => self.mirrors.borrow_mut().dispatched(token),
+                Err(e) => {
+                    warn!("[MIRROR] Failed to dispatch mirrored request: {:?}", e);
+                    self.metrics.mirror_failed.increment(1);
+                }
             }
+        }
+    }
+
+    // FilterRequest fields that are the same for every request of this VM,
+    // encoded once and sent ahead of each request's own fields
+    fn encode_static_fields(&self, config: &PluginConfig) {
+        let node_id = self
+            .get_property(vec!["node", "id"])
+            .and_then(|id| String::from_utf8(id).ok())
+            .unwrap_or_default();
+        let fields = FilterRequest {
+            node_id,
+            attributes: config.request_attributes.clone(),
+            version: if config.filter_request_v2 {
+                schema::VERSION
+            } else {
+                0
+            },
+            ..Default::default()
         };
+        self.message_buffer.borrow_mut().static_fields = fields.encode_to_vec();
+    }
 
-        let response_message = reply.get_message();
-        info!(
-            "Successfully parsed filter service response: {}",
-            response_message
-        );
+    // Dispatch the warm-up call once, then drop back to the regular tick
+    fn warm_up(&mut self, warm_up: &WarmUpConfig, now_ms: u64) {
+        self.warm_up_pending = false;
+        let period = self.tick_period_ms.unwrap_or(0);
+        self.set_tick_period(Duration::from_millis(period));
 
-        // Check if access is denied
-        if !reply.get_allow() {
-            info!("Access denied: allow=false, message={}", response_message);
-            self.send_http_response(
-                401,
-                vec![("WWW-Authenticate", response_message)], // Avoid string allocation
-                Some(b"Unauthorized"),
-            );
-            return;
+        let cluster_name = AuthEngine::build_cluster_name();
+        let credential = service_credential_metadata(&self.config, now_ms);
+        match self.dispatch_grpc_call(
+            &cluster_name,
+            health::SERVICE,
+            health::METHOD,
+            grpc_metadata(&credential),
+            Some(&[]),
+            Duration::from_millis(warm_up.timeout_ms),
+        ) {
+            Ok(token) => {
+                info!(
+                    "[WARMUP] Dispatched warm-up call to {} with token: {}",
+                    cluster_name, token
+                );
+                self.warm_up_call = Some((token, now_ms));
+            }
+            Err(e) => warn!("[WARMUP] Failed to dispatch warm-up call: {:?}", e),
         }
+    }
 
-        // Use the optimized helper function
-        let user = Self::get_value_or_space(reply.get_user());
-        self.add_http_request_header("x-uip-user", user);
-        info!("Set user header: '{}'", user);
+    fn warm_up_finished(&self, status_code: u32, dispatched_ms: u64) {
+        let elapsed_ms = self.now_ms().saturating_sub(dispatched_ms);
+        if warm_up::connected(status_code) {
+            info!(
+                "[WARMUP] Authz cluster connection ready after {} ms (grpc status {})",
+                elapsed_ms, status_code
+            );
+        } else {
+            warn!(
+                "[WARMUP] Authz cluster unreachable after {} ms, first requests will connect",
+                elapsed_ms
+            );
+        }
+    }
+
+    // Probe the authz cluster until any worker has seen it answer
+    fn probe_readiness(&mut self, readiness: &ReadinessConfig, now_ms: u64) {
+        if self.readiness_call.is_some() || !readiness::pending() {
+            return;
+        }
+
+        let cluster_name = AuthEngine::build_cluster_name();
+        let credential = service_credential_metadata(&self.config, now_ms);
+        match self.dispatch_grpc_call(
+            &cluster_name,
+            health::SERVICE,
+            health::METHOD,
+            grpc_metadata(&credential),
+            Some(&readiness.request()),
+            Duration::from_millis(readiness.timeout_ms),
+        ) {
+            Ok(token) => self.readiness_call = Some(token),
+            Err(e) => warn!("[READY] Failed to dispatch readiness probe: {:?}", e),
+        }
+    }
+
+    fn probe_health(&mut self, health_check: &HealthCheckConfig, now_ms: u64) {
+        if self.health_call.is_some() || !health_check.should_probe(now_ms) {
+            return;
+        }
+
+        let cluster_name = AuthEngine::build_cluster_name();
+        let request = health_check.request();
+        let credential = service_credential_metadata(&self.config, now_ms);
+        match self.dispatch_grpc_call(
+            &cluster_name,
+            health::SERVICE,
+            health::METHOD,
+            grpc_metadata(&credential),
+            Some(&request),
+            Duration::from_millis(health_check.timeout_ms),
+        ) {
+            Ok(token) => self.health_call = Some(token),
+            Err(e) => {
+                warn!("[HEALTH] Failed to dispatch health probe: {:?}", e);
+                health_check.record(false, now_ms);
+            }
+        }
+    }
+}
+
+impl RootContext for AuthRoot {
+    fn on_configure(&mut self, _plugin_configuration_size: usize) -> bool {
+        self.metrics = Metrics::define();
+
+        let bytes = self.get_plugin_configuration().unwrap_or_default();
+        match PluginConfig::from_bytes(&bytes) {
+            Ok(mut config) => {
+                proxy_wasm::set_log_level(config.logging.level.host_level());
+                info!("Plugin configured (oidc: {})", config.oidc.is_some());
+                // Unless configured, the tick runs at the rate of the most
+                // frequent background job
+                let mut job_periods = Vec::new();
+                if let Some(audit) = config.audit.as_ref() {
+                    self.audit_queue = Some(self.register_shared_queue(&audit.queue_name));
+                    self.audit_summary = Interval::new(audit.summary_interval_secs * 1000);
+                    job_periods.push(audit.summary_interval_secs * 1000);
+                    if let Some(sink) = audit.sink.as_ref() {
+                        self.audit_flush = Interval::new(sink.flush_interval_ms);
+                        job_periods.push(sink.flush_interval_ms);
+                    }
+                }
+                if let Some(guard) = config.timeout_guard.as_ref() {
+                    job_periods.push(guard.check_interval_ms);
+                }
+                if let Some(health_check) = config.health_check.as_ref() {
+                    self.health_probe = Interval::new(health_check.interval_ms);
+                    job_periods.push(health_check.interval_ms);
+                }
+                if let Some(readiness) = config.readiness.as_ref() {
+                    self.readiness_probe = Interval::new(readiness.probe_interval_ms);
+                    job_periods.push(readiness.probe_interval_ms);
+                }
+                if let Some(report) = config.throughput.as_ref() {
+                    self.throughput_report = Interval::new(report.report_interval_secs * 1000);
+                    job_periods.push(report.report_interval_secs * 1000);
+                }
+                if let Some(report) = config.memory_report.as_ref() {
+                    self.memory_report = Interval::new(report.interval_secs * 1000);
+                    job_periods.push(report.interval_secs * 1000);
+                }
+                if config.experiment.is_some() {
+                    job_periods.push(SHADOW_DISPATCH_INTERVAL_MS);
+                }
+                if config.denial_audit.is_some() {
+                    job_periods.push(DENIAL_AUDIT_DISPATCH_INTERVAL_MS);
+                }
+                if config.mirror.is_some() {
+                    job_periods.push(MIRROR_DISPATCH_INTERVAL_MS);
+                }
+                if config.coalesce.is_some() {
+                    job_periods.push(COALESCE_HANDOVER_INTERVAL_MS);
+                }
+                if config
+                    .concurrency_limit
+                    .as_ref()
+                    .is_some_and(|limit| limit.queue_size > 0)
+                {
+                    job_periods.push(CALL_QUEUE_CHECK_INTERVAL_MS);
+                }
+                if config.stream.is_some() {
+                    // The stream is opened on the first tick
+                    self.authz_stream
+                        .borrow_mut()
+                        .set_root_context(self.context_id);
+                    job_periods.push(STREAM_CHECK_INTERVAL_MS);
+                }
+                if config.jwks.is_some() {
+                    self.jwks_check = Interval::new(JWKS_CHECK_INTERVAL_MS);
+                    job_periods.push(JWKS_CHECK_INTERVAL_MS);
+                }
+                if config.service_credential.is_some() {
+                    self.credential_check = Interval::new(CREDENTIAL_CHECK_INTERVAL_MS);
+                    job_periods.push(CREDENTIAL_CHECK_INTERVAL_MS);
+                }
+                if config.has_property_secrets() {
+                    // Keys must be readable from the start
+                    if let Err(e) = self.refresh_secrets(&config) {
+                        warn!("Rejecting plugin configuration: {}", e);
+                        return false;
+                    }
+                    self.secret_refresh = Interval::new(SECRET_REFRESH_INTERVAL_MS);
+                    job_periods.push(SECRET_REFRESH_INTERVAL_MS);
+                }
+                for rule in &mut config.path_bypass {
+                    rule.define_counter();
+                }
+                let tick_ms = schedule::tick_period_ms(config.tick_period_ms, &job_periods);
+                if let Some(tick_ms) = tick_ms {
+                    info!("Background tick period: {} ms", tick_ms);
+                    self.set_tick_period(Duration::from_millis(tick_ms));
+                }
+                self.tick_period_ms = tick_ms;
+                self.encode_static_fields(&config);
+                // The warm-up call goes out on the first tick
+                if let Some(warm_up) = config.warm_up.as_ref() {
+                    self.warm_up_pending = true;
+                    let delay_ms = tick_ms.map_or(warm_up.delay_ms, |ms| ms.min(warm_up.delay_ms));
+                    self.set_tick_period(Duration::from_millis(delay_ms.max(1)));
+                }
+                self.config = Rc::new(config);
+                true
+            }
+            Err(e) => {
+                warn!("Rejecting plugin configuration: {}", e);
+                false
+            }
+        }
+    }
+
+    fn on_tick(&mut self) {
+        let now_ms = self.now_ms();
+
+        if self.warm_up_pending {
+            let config = Rc::clone(&self.config);
+            if let Some(warm_up) = config.warm_up.as_ref() {
+                self.warm_up(warm_up, now_ms);
+            }
+        }
+
+        if self.config.timeout_guard.is_some() {
+            self.settle_expired_calls(now_ms);
+        }
+
+        let config = Rc::clone(&self.config);
+
+        if let (Some(audit), Some(queue_id)) = (config.audit.as_ref(), self.audit_queue) {
+            if self.audit_summary.due(now_ms) {
+                audit::publish_drop_summary(audit, queue_id, now_ms / 1000, &self.metrics);
+            }
+            if audit.sink.is_some() && self.audit_flush.due(now_ms) {
+                self.ship_audit_batch();
+            }
+        }
+
+        if let Some(report) = config.throughput.as_ref() {
+            if self.throughput_report.due(now_ms) {
+                self.throughput
+                    .report(report, &self.worker_stats, now_ms / 1000);
+            }
+        }
+
+        if config.memory_report.is_some() && self.memory_report.due(now_ms) {
+            let sample = memory_report::Sample::current(memory_tracking::heap_bytes());
+            self.memory.report(&sample, now_ms);
+        }
+
+        if let Some(jwks) = config.jwks.as_ref() {
+            if self.jwks_check.due(now_ms) {
+                self.refresh_jwks(jwks, now_ms);
+            }
+        }
+
+        if let Some(credential) = config.service_credential.as_ref() {
+            if self.credential_check.due(now_ms) {
+                self.refresh_service_credential(credential, now_ms);
+            }
+        }
+
+        if config.has_property_secrets() && self.secret_refresh.due(now_ms) {
+            if let Err(e) = self.refresh_secrets(&config) {
+                warn!("[SECRETS] Keeping previous keys: {}", e);
+            }
+        }
+
+        if let Some(health_check) = config.health_check.as_ref() {
+            if self.health_probe.due(now_ms) {
+                self.probe_health(health_check, now_ms);
+            }
+        }
+
+        if let Some(readiness) = config.readiness.as_ref() {
+            if self.readiness_probe.due(now_ms) {
+                self.probe_readiness(readiness, now_ms);
+            }
+        }
+
+        if config.experiment.is_some() {
+            self.dispatch_shadow_calls(now_ms);
+        }
+
+        if let Some(denial_audit) = config.denial_audit.as_ref() {
+            self.dispatch_denial_audits(denial_audit, now_ms);
+        }
+
+        if let Some(mirror) = config.mirror.as_ref() {
+            self.dispatch_mirrors(mirror);
+        }
+
+        if config.coalesce.is_some() {
+            self.redispatch_orphans();
+        }
+
+        if config
+            .concurrency_limit
+            .as_ref()
+            .is_some_and(|limit| limit.queue_size > 0)
+        {
+            self.admit_queued_calls(now_ms);
+        }
+
+        if let Some(stream_config) = config.stream.as_ref() {
+            self.maintain_stream(stream_config, now_ms);
+        }
+    }
+
+    fn on_queue_ready(&mut self, queue_id: u32) {
+        if Some(queue_id) != self.audit_queue {
+            return;
+        }
+        let full_batch = self
+            .config
+            .audit
+            .as_ref()
+            .and_then(|audit| audit.sink.as_ref())
+            .is_some_and(|sink| audit::queue_depth() >= sink.batch_size as u64);
+        // Partial batches wait for the flush interval
+        if full_batch {
+            self.ship_audit_batch();
+        }
+    }
+
+    fn create_http_context(&self, context_id: u32) -> Option<Box<dyn HttpContext>> {
+        let engine = Rc::new_cyclic(|this| {
+            RefCell::new(AuthEngine::new(
+                context_id,
+                Rc::clone(&self.config),
+                self.metrics,
+                self.audit_queue,
+                Rc::clone(&self.pending_calls),
+                Rc::clone(&self.worker_stats),
+                Rc::clone(&self.shadow_calls),
+                Rc::clone(&self.denial_audits),
+                Rc::clone(&self.mirrors),
+                Rc::clone(&self.coalescer),
+                Rc::clone(&self.reroutes),
+                Rc::clone(&self.call_queue),
+                Rc::clone(&self.authz_stream),
+                this.clone(),
+                Rc::clone(&self.message_buffer),
+                Rc::clone(&self.scratch),
+            ))
+        });
+        Some(Box::new(SharedEngine(engine)))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
+
+// Run `f` on another request context, with host calls made on its behalf,
+// then return to `current_context_id`
+fn wake_context(
+    context_id: u32,
+    engine: &EngineHandle,
+    current_context_id: u32,
+    f: impl FnOnce(&mut AuthEngine),
+) {
+    let Some(engine) = engine.upgrade() else {
+        return;
+    };
+    let Ok(mut engine) = engine.try_borrow_mut() else {
+        warn!("Request context {} is busy", context_id);
+        return;
+    };
+    if hostcalls::set_effective_context(context_id).is_ok() {
+        f(&mut engine);
+    }
+    let _ = hostcalls::set_effective_context(current_context_id);
+}
+
+// Service credential for an authz-cluster call, when one is configured
+fn service_credential_metadata(config: &PluginConfig, now_ms: u64) -> Option<(&str, String)> {
+    config
+        .service_credential
+        .as_ref()
+        .and_then(|credential| credential.metadata(now_ms / 1000))
+}
+
+fn grpc_metadata<'a>(credential: &'a Option<(&'a str, String)>) -> Vec<(&'a str, &'a [u8])> {
+    credential
+        .iter()
+        .map(|(key, value)| (*key, value.as_bytes()))
+        .collect()
+}
+
+// Header value safe to log: Basic and Negotiate credentials are withheld
+fn redact_credentials(value: &str) -> &str {
+    if basic_auth::is_basic(value) {
+        "Basic <redacted>"
+    } else if negotiate::is_negotiate(value) {
+        "Negotiate <redacted>"
+    } else {
+        value
+    }
+}
+
+struct AuthEngine {
+    context_id: u32,
+    // Shared plugin configuration from the root context
+    config: Rc<PluginConfig>,
+    metrics: Metrics,
+    audit_queue: Option<u32>,
+    pending_calls: SharedPendingCalls,
+    worker_stats: SharedWorkerStats,
+    // Whether the authz call is registered with the timeout guard
+    timeout_tracked: bool,
+    // Whether an authz call is outstanding (dispatch depth)
+    grpc_in_flight: bool,
+    // When the authz call was dispatched, for the latency experiment
+    grpc_dispatched_ms: u64,
+    shadow_calls: SharedShadowCalls,
+    denial_audits: SharedDenialAudits,
+    mirrors: SharedMirrors,
+    coalescer: SharedCoalescer<EngineHandle>,
+    reroutes: SharedReroutes<EngineHandle>,
+    call_queue: SharedCallQueue<EngineHandle>,
+    // Waiting in the call queue for a slot of the concurrency limit
+    call_queued: bool,
+    // The request's coalesced authz call, if any
+    coalesced: Option<Membership>,
+    // Calls are dispatched from the root tick, whose context gets the answers
+    rerouted: bool,
+    authz_stream: SharedAuthzStream<EngineHandle>,
+    // This context, for registering as a waiter on the authz stream
+    this: EngineHandle,
+    // Whether the authz call went out on the stream and is unanswered
+    stream_waiting: bool,
+    // The authz call waits for the request trailers (request_trailers)
+    awaiting_trailers: bool,
+    // The headers were paused for a verdict; body and trailers are held
+    // until the request is resumed or answered
+    awaiting_decision: bool,
+    request_trailers: Vec<(String, String)>,
+    // Parallel authn call: its token while unanswered, and the answer of
+    // whichever call came back first
+    authn_call: Option<u32>,
+    authn_leg: Option<LegResult>,
+    authz_leg: Option<(u32, LegResult)>,
+    // Chained authz services: the unanswered call, the next step and the
+    // verdict so far
+    chain_call: Option<u32>,
+    chain_step: usize,
+    chain_reply: Option<FilterResponse>,
+    // Shared with the root context, which may settle the request at its
+    // timeout deadline
+    terminal: TerminalGuard,
+    // Request headers as fetched at the start of the request
+    headers: HeaderSnapshot,
+    // Request line and start, kept for audit events and log events
+    request_start_ms: u64,
+    request_id: String,
+    request_method: String,
+    request_path: String,
+    request_authority: String,
+    // gRPC status of the authz call, once answered
+    authz_status: Option<u32>,
+    authz_latency_ms: Option<u64>,
+    authz_message: Option<String>,
+    // Picked by log sampling: verbose logs and lifecycle events are kept
+    log_sampled: bool,
+    // Decision details go back to the client as response headers
+    debug_headers: bool,
+    // Debug headers for an allowed request, added to the upstream response
+    pending_debug_headers: Vec<(&'static str, String)>,
+    // Original request target while an OIDC code exchange is in flight
+    oidc_return_to: Option<String>,
+    // Request mutations from local checks and the authz decision
+    evaluation: Evaluation,
+    // Reused FilterRequest serialization buffer (shared by the worker)
+    message_buffer: SharedMessageBuffer,
+    // Per-request scratch region (shared by the worker)
+    scratch: SharedScratch,
+    // Cache cluster name to avoid rebuilding on each request
+    cluster_name: String,
+    // Track memory usage per request
+    request_memory_bytes: usize,
+    // Net heap bytes retained per stage (memory-tracking builds)
+    leak_tracker: LeakTracker,
+}
+
+impl AuthEngine {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        context_id: u32,
+        config: Rc<PluginConfig>,
+        metrics: Metrics,
+        audit_queue: Option<u32>,
+        pending_calls: SharedPendingCalls,
+        worker_stats: SharedWorkerStats,
+        shadow_calls: SharedShadowCalls,
+        denial_audits: SharedDenialAudits,
+        mirrors: SharedMirrors,
+        coalescer: SharedCoalescer<EngineHandle>,
+        reroutes: SharedReroutes<EngineHandle>,
+        call_queue: SharedCallQueue<EngineHandle>,
+        authz_stream: SharedAuthzStream<EngineHandle>,
+        this: EngineHandle,
+        message_buffer: SharedMessageBuffer,
+        scratch: SharedScratch,
+    ) -> Self {
+        Self {
+            context_id,
+            config,
+            metrics,
+            audit_queue,
+            pending_calls,
+            worker_stats,
+            timeout_tracked: false,
+            grpc_in_flight: false,
+            grpc_dispatched_ms: 0,
+            shadow_calls,
+            denial_audits,
+            mirrors,
+            coalescer,
+            reroutes,
+            call_queue,
+            call_queued: false,
+            coalesced: None,
+            rerouted: false,
+            authz_stream,
+            this,
+            stream_waiting: false,
+            awaiting_trailers: false,
+            awaiting_decision: false,
+            request_trailers: Vec::new(),
+            authn_call: None,
+            authn_leg: None,
+            authz_leg: None,
+            chain_call: None,
+            chain_step: 0,
+            chain_reply: None,
+            terminal: TerminalGuard::default(),
+            headers: HeaderSnapshot::default(),
+            request_start_ms: 0,
+            request_id: String::new(),
+            request_authority: String::new(),
+            authz_status: None,
+            authz_latency_ms: None,
+            authz_message: None,
+            debug_headers: false,
+            log_sampled: true,
+            pending_debug_headers: Vec::new(),
+            request_method: String::new(),
+            request_path: String::new(),
+            oidc_return_to: None,
+            evaluation: Evaluation::default(),
+            message_buffer,
+            scratch,
+            // Cache cluster name at initialization
+            cluster_name: Self::build_cluster_name(),
+            // Initialize memory tracking
+            request_memory_bytes: 0,
+            leak_tracker: LeakTracker::default(),
+        }
+    }
+
+    // Helper to estimate memory usage of strings and collections
+    fn estimate_memory_usage(&self) -> usize {
+        let mut total_bytes = 0;
+
+        // Cluster name (cached, amortized over all requests)
+        total_bytes += self.cluster_name.len();
+
+        // Serialization buffer (shared, amortized over all requests)
+        total_bytes += self.message_buffer.borrow().bytes.capacity();
+
+        // Scratch region (shared, amortized over all requests)
+        total_bytes += self.scratch.borrow().allocated_bytes();
+
+        total_bytes
+    }
+
+    // Headers for the FilterRequest, appended to a reused Vec (4 pseudo + 9
+    // default headers, plus configured ones)
+    fn build_protobuf_headers<'a>(&'a self, headers: &mut HeaderList<'a>) {
+
+        // Use const slice instead of Vec + HashSet for better performance
+        const HEADERS_TO_SEND: &[&str] = &[
+            "x-forwarded-client-cert",
+            "x-request-id",
+            "x-correlation-id",
+            "authorization",
+            "x-uip-wasm-impersonated-user",
+            "x-event-service-user",
+            "x-trino-user",
+            "traceparent",
+            "tracestate",
+        ];
+
+        // Process specific pseudo-headers individually to avoid Vec allocation
+        // Use const array to avoid format! allocations
+        const PSEUDO_HEADERS: [(&str, &str); 4] = [
+            (":method", "method"),
+            (":scheme", "scheme"),
+            (":authority", "authority"),
+            (":path", "path"),
+        ];
+
+        for &(header_name, pseudo_key) in &PSEUDO_HEADERS {
+            if let Some(value) = self.headers.get(header_name) {
+                if let Some((_, new_header_name)) =
+                    PSEUDO_HEADER_MAP.iter().find(|(key, _)| *key == pseudo_key)
+                {
+                    request_debug!(
+                        self,
+                        "Converting pseudo-header '{}' to '{}' for protobuf",
+                        header_name,
+                        new_header_name
+                    );
+                    headers.push(new_header_name, value);
+                }
+            }
+        }
+
+        // Then handle specific headers we want to forward (the policy's set
+        // when the request's authority selected one)
+        let policy_headers = self.policy().and_then(|policy| policy.headers.as_deref());
+        let header_names = policy_headers
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(
+                HEADERS_TO_SEND
+                    .iter()
+                    .copied()
+                    .chain(self.config.forward_headers.iter().map(String::as_str))
+                    .filter(|_| policy_headers.is_none()),
+            );
+        // Streamed calls have no per-request metadata
+        let metadata_only =
+            self.config.request_context_metadata_only && self.config.stream.is_none();
+        let header_names =
+            header_names.filter(|name| !(metadata_only && REQUEST_CONTEXT_METADATA.contains(name)));
+        for header_name in header_names {
+            if let Some(prefix) = forwarded_headers::prefix(header_name) {
+                for (name, _) in self.headers.iter() {
+                    if forwarded_headers::matches_prefix(prefix, name)
+                        && self.push_header_values(headers, Cow::Borrowed(name), name)
+                    {
+                        request_debug!(
+                            self,
+                            "Added '{}' to protobuf (matched '{}')",
+                            name,
+                            header_name
+                        );
+                    }
+                }
+            } else if self.push_header_values(headers, Cow::Borrowed(header_name), header_name) {
+                request_debug!(self, "Added specific header to protobuf: '{}'", header_name);
+            }
+        }
+        let rules = &self.config.forward_header_rules;
+        if policy_headers.is_none() && !rules.is_empty() {
+            for (name, _) in self.headers.iter() {
+                let Some(forward_as) = rules.forward_as(name) else {
+                    continue;
+                };
+                let key = forward_as.to_string();
+                if self.push_header_values(headers, forward_as, name) {
+                    request_debug!(self, "Added '{}' to protobuf as '{}'", name, key);
+                }
+            }
+        }
+        // Only the configured cookies are sent, in FilterRequest.cookies
+        if !self.config.forward_cookies.is_empty() {
+            headers.remove("cookie");
+        }
+        if let (None, Some(forward_all)) = (policy_headers, &self.config.forward_all_headers) {
+            self.forward_all_headers(headers, forward_all);
+        }
+
+        request_debug!(
+            self,
+            "Built protobuf headers with {} entries",
+            headers.len()
+        );
+    }
+
+    // Add the request headers not sent yet, except excluded ones, while the
+    // list stays within the size cap
+    fn forward_all_headers<'a>(&'a self, headers: &mut HeaderList<'a>, config: &ForwardAllConfig) {
+        let mut dropped = 0;
+        let cookies_only = !self.config.forward_cookies.is_empty();
+        for (index, (name, _)) in self.headers.iter().enumerate() {
+            // Repeats of a header were handled with its first occurrence
+            let repeat = self
+                .headers
+                .iter()
+                .take(index)
+                .any(|(earlier, _)| earlier.eq_ignore_ascii_case(name));
+            if repeat
+                || config.excludes(name)
+                || (cookies_only && name.eq_ignore_ascii_case("cookie"))
+            {
+                continue;
+            }
+            let len = headers.len();
+            if !self.push_header_values(headers, Cow::Borrowed(name), name) {
+                continue;
+            }
+            if headers.bytes() > config.max_bytes {
+                headers.truncate(len);
+                dropped += 1;
+                continue;
+            }
+            request_debug!(self, "Added '{}' to protobuf (forward all)", name);
+        }
+        if dropped > 0 {
+            warn!(
+                "[HEADERS] Forward-all headers over {} bytes, dropped {} header(s)",
+                config.max_bytes, dropped
+            );
+            self.metrics.forwarded_header_drops.increment(dropped);
+        }
+    }
+
+    // Add the values of request header `name` under `key`, all of them
+    // repeated or joined per `multi_value_headers`; false when the request
+    // has no such header or `key` is already listed
+    fn push_header_values<'a>(
+        &'a self,
+        headers: &mut HeaderList<'a>,
+        key: Cow<'a, str>,
+        name: &'a str,
+    ) -> bool {
+        let mut values = self.headers.get_all(name);
+        let Some(first) = values.next() else {
+            return false;
+        };
+        if headers
+            .iter()
+            .any(|(listed, _)| listed.eq_ignore_ascii_case(&key))
+        {
+            return false;
+        }
+        let key = match key {
+            Cow::Borrowed(key) => key,
+            Cow::Owned(key) => headers.alloc(&key),
+        };
+        let len = headers.len();
+        match self.config.multi_value_headers {
+            MultiValueMode::First => headers.push(key, first),
+            MultiValueMode::Repeat => {
+                headers.push(key, first);
+                for value in values {
+                    headers.push(key, value);
+                }
+            }
+            MultiValueMode::Join => {
+                let separator = forwarded_headers::separator(name);
+                let mut joined: Option<String> = None;
+                for value in values {
+                    let joined = joined.get_or_insert_with(|| first.to_string());
+                    joined.push_str(separator);
+                    joined.push_str(value);
+                }
+                let value = match joined {
+                    Some(joined) => headers.alloc(&joined),
+                    None => first,
+                };
+                headers.push(key, value);
+            }
+        }
+        if let Some(truncation) = self.config.header_truncation.as_ref() {
+            let truncated = headers.truncate_values(len, |value| truncation.truncate(value));
+            if truncated > 0 {
+                warn!(
+                    "[HEADERS] Truncated {} value(s) of '{}' to {} bytes",
+                    truncated, key, truncation.max_value_bytes
+                );
+                self.metrics
+                    .truncated_header_values
+                    .increment(truncated as i64);
+            }
+        }
+        true
+    }
+
+    // Serialize the request's FilterRequest into the worker's message
+    // buffer; returns the number of protobuf headers
+    fn encode_filter_request(&self) -> Result<usize, EncodeError> {
+        let initial_memory = self.estimate_memory_usage();
+
+        // Get headers for logging - use as_deref to get &str for display
+        let header = |name| self.headers.get(name).map(str::to_string);
+        let method_opt = header(":method");
+        let scheme_opt = header(":scheme");
+        let authority_opt = header(":authority");
+        let path_opt = header(":path");
+
+        request_debug!(
+            self,
+            "Request details - Method: {}, Scheme: {}, Authority: {}, Path: {}",
+            method_opt.as_deref().unwrap_or(""),
+            scheme_opt.as_deref().unwrap_or(""),
+            authority_opt.as_deref().unwrap_or(""),
+            path_opt.as_deref().unwrap_or("")
+        );
+
+        // Pick protobuf headers into the worker's scratch region
+        let scratch = Rc::clone(&self.scratch);
+        let scratch = scratch.borrow();
+        let mut headers = scratch.headers();
+        self.build_protobuf_headers(&mut headers);
+        let after_headers_memory = self.estimate_memory_usage();
+        request_debug!(
+            self,
+            "[MEMORY] After header processing: {} bytes (+{} bytes)",
+            after_headers_memory,
+            after_headers_memory - initial_memory
+        );
+
+        // Log all headers that will be sent in the protobuf message
+        request_debug!(
+            self,
+            "[HEADERS] Headers to be sent in gRPC call ({} total):",
+            headers.len()
+        );
+        for (key, value) in headers.iter() {
+            request_debug!(
+                self,
+                "[HEADERS]   '{}' = '{}'",
+                key,
+                redact_credentials(value)
+            );
+        }
+
+        // Create FilterRequest; the headers are encoded from the scratch region
+        // Use unwrap_or_default for String types (minimal allocation for empty strings)
+        let mut req = FilterRequest {
+            method: method_opt.unwrap_or_default(),
+            path: path_opt.unwrap_or_default(),
+            scheme: scheme_opt.unwrap_or_default(),
+            ..Default::default()
+        };
+        if let Some(target) = self.evaluation.grpc_target.clone() {
+            request_debug!(
+                self,
+                "[GRPC] Downstream gRPC call to {}/{}",
+                target.service,
+                target.method
+            );
+            req.grpc_service = target.service;
+            req.grpc_method = target.method;
+        }
+        if let Some(rewrite) = self.evaluation.path_rewrite.as_ref() {
+            req.original_path = rewrite.original.clone();
+        }
+        req.client_ip = match self.evaluation.client_ip.clone() {
+            Some(client_ip) => client_ip,
+            None => self
+                .source_address()
+                .as_deref()
+                .and_then(client_ip::parse_address)
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+        };
+        req.query = query::query_params(&req.path)
+            .map(|(key, value)| uipbdiauthz::Header { key, value })
+            .collect();
+        let cookie_headers = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+            .map(|(_, value)| value);
+        req.cookies = cookies::selected(cookie_headers, &self.config.forward_cookies)
+            .map(|(key, value)| uipbdiauthz::Header {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect();
+        req.trailers = self
+            .request_trailers
+            .iter()
+            .map(|(key, value)| uipbdiauthz::Header {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        let property = |path: Vec<&str>| {
+            self.get_property(path)
+                .and_then(|value| String::from_utf8(value).ok())
+                .unwrap_or_default()
+        };
+        req.route_name = property(vec!["xds", "route_name"]);
+        req.virtual_host = property(vec!["xds", "virtual_host_name"]);
+        let tls = if self.config.send_tls_attributes || self.config.filter_request_v2 {
+            tls::connection_info(|name| self.get_property(vec!["connection", name]))
+        } else {
+            None
+        };
+        if self.config.filter_request_v2 {
+            req.identity = schema::identity(&self.evaluation);
+            req.connection = Some(schema::connection(
+                self.evaluation.client_ip.as_deref(),
+                self.source_address(),
+                tls.clone(),
+            ));
+            req.body_digest = header("content-digest").unwrap_or_default();
+        }
+        if self.config.send_tls_attributes {
+            req.tls = tls;
+        }
+        if let Some(token) = self.evaluation.negotiate_token.clone() {
+            request_debug!(self, "[NEGOTIATE] Forwarding Negotiate client token");
+            req.negotiate_token = token;
+        }
+        if let Some(user) = self.evaluation.basic_auth_user.clone() {
+            request_debug!(
+                self,
+                "[BASIC-AUTH] Forwarding Basic auth username '{}'",
+                user
+            );
+            req.basic_auth_user = user;
+        }
+
+        let header_count = headers.len();
+        let encoded = self.message_buffer.borrow_mut().encode(&req, &headers);
+        // Done with the scratch region
+        drop(headers);
+        drop(scratch);
+        encoded.map(|()| header_count)
+    }
+
+    // Authority policy selected for this request
+    fn policy(&self) -> Option<&AuthorityPolicy> {
+        self.evaluation
+            .policy
+            .and_then(|index| self.config.policies.get(index))
+    }
+
+    // Authz cluster of the request's tenant or policy, else the cached
+    // default
+    fn authz_cluster(&self) -> &str {
+        self.evaluation
+            .tenant_cluster
+            .as_deref()
+            .or_else(|| self.policy().and_then(|policy| policy.cluster.as_deref()))
+            .unwrap_or(&self.cluster_name)
+    }
+
+    // Timeout of the request's policy, else 5 s, bounded by the caller's
+    // remaining deadline when configured
+    fn authz_timeout_ms(&self) -> u64 {
+        let static_timeout_ms = self.policy().map_or(5000, |policy| policy.timeout_ms);
+        let Some(deadline) = self.config.deadline.as_ref() else {
+            return static_timeout_ms;
+        };
+        let timeout_ms = deadline.timeout_ms(
+            static_timeout_ms,
+            self.headers.get("grpc-timeout"),
+            self.headers.get("x-envoy-expected-rq-timeout-ms"),
+            self.now_ms().saturating_sub(self.request_start_ms),
+        );
+        if timeout_ms < static_timeout_ms {
+            request_debug!(
+                self,
+                "[DEADLINE] Authz timeout {} ms from the caller's deadline",
+                timeout_ms
+            );
+        }
+        timeout_ms
+    }
+
+    // Extract common gRPC call logic to reduce code duplication
+    fn make_grpc_call(&self, message: &[u8]) -> Result<u32, Status> {
+        let cluster_name = self.authz_cluster();
+        let timeout_ms = self.authz_timeout_ms();
+        request_debug!(self, "Making gRPC call to:");
+        request_debug!(self, "  Cluster: {}", cluster_name);
+        request_debug!(self, "  Service: authengine.UIPBDIAuthZProcessor");
+        request_debug!(self, "  Method: processReq");
+        request_debug!(self, "  Message size: {} bytes", message.len());
+        request_debug!(self, "  Timeout: {} ms", timeout_ms);
+
+        self.dispatch_call(
+            cluster_name,
+            "authengine.UIPBDIAuthZProcessor",
+            "processReq",
+            message,
+            timeout_ms,
+        )
+    }
+
+    // gRPC call carrying the service credential and request context metadata
+    fn dispatch_call(
+        &self,
+        cluster: &str,
+        service: &str,
+        method: &str,
+        message: &[u8],
+        timeout_ms: u64,
+    ) -> Result<u32, Status> {
+        let credential = service_credential_metadata(&self.config, self.now_ms());
+        let mut metadata = grpc_metadata(&credential);
+        for name in REQUEST_CONTEXT_METADATA {
+            if let Some(value) = self.headers.get(name) {
+                metadata.push((name, value.as_bytes()));
+            }
+        }
+        self.dispatch(&GrpcCall {
+            cluster,
+            service,
+            method,
+            metadata,
+            message,
+            timeout: Duration::from_millis(timeout_ms),
+        })
+    }
+
+    // Enforce the authz verdict
+    fn apply_reply(&mut self, reply: FilterResponse) {
+        let response_message = reply.message.as_str();
+        request_debug!(
+            self,
+            "Successfully parsed filter service response: {}",
+            response_message
+        );
+
+        if self.debug_headers {
+            self.authz_message = Some(response_message.to_string());
+        }
+        let config = Rc::clone(&self.config);
+        let mut evaluation = std::mem::take(&mut self.evaluation);
+        let step =
+            pipeline::evaluate_decision(&config, self, &reply, &self.request_path, &mut evaluation);
+        self.evaluation = evaluation;
+        if let Step::Respond(response) = step {
+            self.record_decision("deny", response.status);
+            if self.unenforced("deny", response.status) {
+                return;
+            }
+            self.audit_denial(&format!("authz: {}", reply.message), response.status);
+            let reason = if reply.message.is_empty() {
+                String::from_utf8_lossy(response.body.as_deref().unwrap_or_default())
+            } else {
+                reply.message.as_str().into()
+            };
+            self.send_denial(&response, &reason);
+            return;
+        }
+        self.record_decision("allow", 200);
+        self.stash_debug_headers("allow");
+
+        // Calculate final memory usage for this request
+        let final_memory = self.estimate_memory_usage();
+        let total_request_memory = final_memory; // Approximate total for this request
+
+        request_debug!(
+            self,
+            "[MEMORY] Final memory usage: {} bytes, total request memory: ~{} bytes",
+            final_memory, total_request_memory
+        );
+
+        request_debug!(self, "Resuming request processing");
+
+        // Resume the request
+        if !self.evaluation.monitor_only {
+            self.apply_upstream_headers();
+        }
+        self.resume();
+    }
+
+    // FilterResponse of an answered unary call, or the error to answer with
+    fn read_reply(&self, response_size: usize) -> LegResult {
+        let response_data = match self.get_grpc_call_response_body(0, response_size) {
+            Some(data) => data,
+            None => {
+                warn!("No response data received from auth service");
+                return Err((500, b"Internal Server Error"));
+            }
+        };
+
+        request_debug!(
+            self,
+            "Received raw response data of size: {}",
+            response_data.len()
+        );
+
+        grpc_status::decode_reply(&response_data)
+    }
+
+    fn authz_dispatched(&mut self) {
+        self.grpc_in_flight = true;
+        self.grpc_dispatched_ms = self.now_ms();
+        self.worker_stats.borrow_mut().dispatch_started();
+    }
+
+    // Send the FilterRequest on the worker's authz stream; None when the
+    // request has to use a unary call (no stream, stream down or full, or a
+    // tenant or policy cluster other than the default)
+    fn send_on_stream(&mut self, message: &[u8]) -> Option<u64> {
+        let stream_config = self.config.stream.as_ref()?;
+        if self.authz_cluster() != self.cluster_name {
+            return None;
+        }
+        let mut authz_stream = self.authz_stream.borrow_mut();
+        let (token, id) = authz_stream.reserve(stream_config)?;
+        let framed = stream::encode_request(id, message);
+        // The stream belongs to the root context
+        let sent = hostcalls::set_effective_context(authz_stream.root_context_id())
+            .and_then(|()| hostcalls::send_grpc_stream_message(token, Some(&framed), false));
+        let _ = hostcalls::set_effective_context(self.context_id);
+        if let Err(e) = sent {
+            warn!("[STREAM] Failed to send on authz stream: {:?}", e);
+            return None;
+        }
+        let timeout_ms = self.authz_timeout_ms();
+        authz_stream.wait(
+            id,
+            Waiter {
+                context_id: self.context_id,
+                deadline_ms: self.now_ms() + timeout_ms,
+                engine: self.this.clone(),
+            },
+        );
+        self.stream_waiting = true;
+        Some(id)
+    }
+
+    // Answer from the authz stream, or GRPC_DEADLINE_EXCEEDED without a
+    // reply when it took too long (called by the root context)
+    fn on_stream_reply(&mut self, status_code: u32, reply: Option<FilterResponse>) {
+        self.stream_waiting = false;
+        logging::event("authz_response")
+            .at(self.config.logging.lifecycle_level())
+            .sampled(self.log_sampled)
+            .field("request_id", self.request_id.as_str())
+            .field("grpc_status", status_code)
+            .field("stream", true)
+            .emit(&self.config.logging);
+        self.count_grpc_status(status_code);
+        let result = match reply {
+            Some(reply) => Ok(reply),
+            None if status_code != 0 => {
+                warn!(
+                    "[STREAM] Authz stream request failed with grpc status {}",
+                    status_code
+                );
+                Err(CallStatus {
+                    code: status_code,
+                    message: String::new(),
+                }
+                .http_error())
+            }
+            None => {
+                warn!("No response data received from auth service");
+                Err((500, b"Internal Server Error".as_slice()))
+            }
+        };
+        self.leg_answered(Leg::Authz, status_code, result);
+    }
+
+    // Send the FilterRequest to the authn service as well, when configured
+    fn dispatch_authn(&mut self, message: &[u8]) {
+        let Some(authn) = self.config.authn.as_ref() else {
+            return;
+        };
+        let dispatched = self.dispatch_call(
+            &authn.cluster,
+            &authn.service,
+            &authn.method,
+            message,
+            authn.timeout_ms,
+        );
+        match dispatched {
+            Ok(token) => {
+                request_debug!(self, "[AUTHN] Dispatched authn call {}", token);
+                self.authn_call = Some(token);
+            }
+            Err(e) => {
+                warn!("[AUTHN] Failed to dispatch authn call: {:?}", e);
+                self.authn_leg = Some(Err((500, b"Internal Server Error")));
+            }
+        }
+    }
+
+    // One of the calls answered; the request is decided once the authz call
+    // and, when dispatched, the authn call both have
+    fn leg_answered(&mut self, leg: Leg, status_code: u32, result: LegResult) {
+        let result = result.map(schema::normalize);
+        let latency_ms = self.now_ms().saturating_sub(self.grpc_dispatched_ms);
+        match leg {
+            Leg::Authn => {
+                self.metrics.authn_leg_latency_ms.record(latency_ms);
+                match &result {
+                    Ok(reply) if !reply.allow => self.metrics.authn_denied.increment(1),
+                    Err(_) => self.metrics.authn_failed.increment(1),
+                    Ok(_) => {}
+                }
+                self.authn_leg = Some(result);
+            }
+            Leg::Authz => {
+                if self.config.authn.is_some() {
+                    self.metrics.authz_leg_latency_ms.record(latency_ms);
+                }
+                self.authz_leg = Some((status_code, result));
+            }
+            Leg::Chain => {
+                let result = match self.chain_reply.take() {
+                    Some(earlier) => authn::combine(Ok(earlier), result),
+                    None => result,
+                };
+                self.chain_or_decide(status_code, result);
+                return;
+            }
+        }
+        if self.authn_call.is_some() || self.authz_leg.is_none() {
+            return;
+        }
+        let Some((status_code, result)) = self.authz_leg.take() else {
+            return;
+        };
+        let result = match self.authn_leg.take() {
+            Some(authn) => authn::combine(authn, result),
+            None => result,
+        };
+        self.chain_or_decide(status_code, result);
+    }
+
+    // Call the next chained authz service while the verdict so far allows,
+    // else decide the request (and the requests parked on its call)
+    fn chain_or_decide(&mut self, status_code: u32, result: LegResult) {
+        let result = match result {
+            Ok(reply)
+                if reply.allow
+                    && self.chain_step < self.config.chain.len()
+                    && !self.terminal.is_settled() =>
+            {
+                match self.dispatch_chain_step(&reply) {
+                    Ok(token) => {
+                        request_debug!(
+                            self,
+                            "[CHAIN] Dispatched step {} as call {}",
+                            self.chain_step,
+                            token
+                        );
+                        self.chain_call = Some(token);
+                        self.chain_reply = Some(reply);
+                        if self.timeout_tracked {
+                            self.pending_calls
+                                .borrow_mut()
+                                .retarget(self.context_id, token);
+                        }
+                        return;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "[CHAIN] Failed to dispatch chained authz call: {:?}, applying failure mode {:?}",
+                            e, self.config.failure_mode
+                        );
+                        let answered = self.authz_answered(status_code);
+                        self.release_followers(None);
+                        if answered && self.call_unavailable() == Action::Continue {
+                            self.resume();
+                        }
+                        return;
+                    }
+                }
+            }
+            result => result,
+        };
+        let answered = self.authz_answered(status_code);
+        self.release_followers(Some((status_code, &result)));
+        if !answered {
+            return;
+        }
+        match result {
+            Ok(reply) => self.apply_reply(reply),
+            Err((status, body)) => self.respond_error(status, body),
+        }
+    }
+
+    // Send the FilterRequest, enriched with the headers returned so far, to
+    // the next chained service
+    fn dispatch_chain_step(&mut self, reply: &FilterResponse) -> Result<u32, Status> {
+        let config = Rc::clone(&self.config);
+        let step = &config.chain[self.chain_step];
+        self.chain_step += 1;
+        // The buffer is shared by the worker's requests; encode this one again
+        if let Err(e) = self.encode_filter_request() {
+            self.serialization_error(&e);
+            return Err(Status::InternalFailure);
+        }
+        let mut request = FilterRequest::decode(self.message_buffer.borrow().bytes.as_slice())
+            .map_err(|_| Status::InternalFailure)?;
+        chain::enrich(&mut request, reply);
+        let message = request.encode_to_vec();
+        self.dispatch_call(
+            &step.cluster,
+            &step.service,
+            &step.method,
+            &message,
+            step.timeout_ms,
+        )
+    }
+
+    // The authz stream closed before answering: send the request again as a
+    // unary call (called by the root context)
+    fn retry_unary(&mut self) {
+        self.stream_waiting = false;
+        if self.terminal.is_settled() {
+            return;
+        }
+        self.metrics.stream_unary_fallbacks.increment(1);
+        let message_buffer = Rc::clone(&self.message_buffer);
+        if let Err(e) = self.encode_filter_request() {
+            self.serialization_error(&e);
+            self.release_call_slot();
+            self.release_followers(None);
+            if self.serialization_failed() == Action::Continue {
+                self.resume();
+            }
+            return;
+        }
+        let dispatched = self.make_grpc_call(&message_buffer.borrow().bytes);
+        match dispatched {
+            Ok(token) => {
+                request_debug!(self, "[STREAM] Retried request as unary call {}", token);
+                if std::mem::take(&mut self.timeout_tracked) {
+                    self.pending_calls.borrow_mut().remove(self.context_id);
+                    self.guard_timeout(Some(token));
+                }
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to dispatch gRPC call: {:?}, applying failure mode {:?}",
+                    e, self.config.failure_mode
+                );
+                self.release_call_slot();
+                self.release_followers(None);
+                if self.call_unavailable() == Action::Continue {
+                    self.resume();
+                }
+            }
+        }
+    }
+
+    // Hold the request at the concurrency limit until the root tick finds it
+    // a free slot; the failure mode applies when the queue is full
+    fn queue_call(&mut self, limit: &ConcurrencyLimitConfig) -> Action {
+        let entry = Queued {
+            context_id: self.context_id,
+            deadline_ms: self.now_ms() + limit.queue_timeout_ms,
+            engine: self.this.clone(),
+        };
+        if self.call_queue.borrow_mut().push(limit, entry) {
+            request_debug!(self, "[CONCURRENCY] Queued for a free slot");
+            self.metrics.concurrency_queued.increment(1);
+            self.call_queued = true;
+            return Action::Pause;
+        }
+        warn!(
+            "[CONCURRENCY] {} requests queued, applying failure mode {:?}",
+            limit.queue_size, self.config.failure_mode
+        );
+        self.metrics.concurrency_limited.increment(1);
+        self.call_unavailable()
+    }
+
+    // Take a slot for the queued request and dispatch its call; false when
+    // none is free (called by the root tick)
+    fn admit_queued(&mut self) -> bool {
+        let config = Rc::clone(&self.config);
+        if let Some(limit) = config.concurrency_limit.as_ref() {
+            match limit.acquire(self) {
+                Some(false) => return false,
+                Some(true) => self.evaluation.call_slot = true,
+                None => {}
+            }
+        }
+        self.call_queued = false;
+        if self.terminal.is_settled() {
+            self.release_call_slot();
+            return true;
+        }
+        self.rerouted = true;
+        if self.dispatch_authz(false) == Action::Continue {
+            self.resume();
+        }
+        true
+    }
+
+    // The request waited in the call queue past its timeout (called by the
+    // root tick)
+    fn queue_expired(&mut self) {
+        self.call_queued = false;
+        warn!(
+            "[CONCURRENCY] Request {} found no free slot in time, applying failure mode {:?}",
+            self.request_id, self.config.failure_mode
+        );
+        self.metrics.concurrency_queue_expired.increment(1);
+        if self.call_unavailable() == Action::Continue {
+            self.resume();
+        }
+    }
+
+    // Failure mode for a request whose authz call could not be made: no slot
+    // of the concurrency limit, or the dispatch failed
+    fn call_unavailable(&mut self) -> Action {
+        match pipeline::failure_step(self.config.failure_mode) {
+            Step::Respond(response) if !self.evaluation.monitor_only => {
+                self.record_decision("error", response.status);
+                self.send_local_response(&response);
+                Action::Pause
+            }
+            _ => {
+                self.record_decision("error", 0);
+                Action::Continue
+            }
+        }
+    }
+
+    // Park the request on the identical authz call in flight; false when
+    // there is none or it has enough requests parked
+    fn park(&mut self, coalesce: &CoalesceConfig, key: &str) -> bool {
+        let member = Member {
+            context_id: self.context_id,
+            engine: self.this.clone(),
+        };
+        if self
+            .coalescer
+            .borrow_mut()
+            .follow(coalesce, key, member)
+            .is_err()
+        {
+            return false;
+        }
+        request_debug!(self, "[COALESCE] Parked on the authz call for key {}", key);
+        self.metrics.coalesced_requests.increment(1);
+        self.coalesced = Some(Membership::Follower);
+        self.grpc_dispatched_ms = self.now_ms();
+        self.guard_timeout(None);
+        true
+    }
+
+    // Decide the requests parked on this request's call with its answer, or
+    // leave them to dispatch again from the root tick when there is none
+    fn release_followers(&mut self, answer: Option<(u32, &LegResult)>) {
+        let Some(Membership::Leader(key)) = self.coalesced.take() else {
+            return;
+        };
+        let Some(answer) = answer else {
+            self.coalescer.borrow_mut().abandon(&key, self.context_id);
+            return;
+        };
+        let followers = self.coalescer.borrow_mut().finish(&key, self.context_id);
+        for follower in followers {
+            let (status_code, result) = answer;
+            let answer = Some((status_code, result.clone()));
+            wake_context(
+                follower.context_id,
+                &follower.engine,
+                self.context_id,
+                |engine| engine.coalesced_answer(answer),
+            );
+        }
+    }
+
+    // Answer of the call the request was parked on, or None when that call
+    // ended unanswered and the request has to call again (from the root tick)
+    fn coalesced_answer(&mut self, answer: Option<(u32, LegResult)>) {
+        self.coalesced = None;
+        let Some((status_code, result)) = answer else {
+            if self.terminal.is_settled() {
+                return;
+            }
+            self.rerouted = true;
+            if std::mem::take(&mut self.timeout_tracked) {
+                self.pending_calls.borrow_mut().remove(self.context_id);
+            }
+            if self.dispatch_authz(false) == Action::Continue {
+                self.resume();
+            }
+            return;
+        };
+        if !self.authz_answered(status_code) {
+            return;
+        }
+        match result {
+            Ok(reply) => self.apply_reply(reply),
+            Err((status, body)) => self.respond_error(status, body),
+        }
+    }
+
+    // Give back the request's slot of the concurrency limit, if it holds one
+    fn release_call_slot(&mut self) {
+        if std::mem::take(&mut self.evaluation.call_slot) {
+            concurrency::release(self);
+        }
+    }
+
+    fn count_grpc_status(&self, code: u32) {
+        if let Some(counter) = self.metrics.authz_grpc_status.get(code as usize) {
+            counter.increment(1);
+        }
+    }
+
+    // Bookkeeping once the authz service answered; false when the request
+    // was already settled
+    fn authz_answered(&mut self, status_code: u32) -> bool {
+        self.release_call_slot();
+        self.authz_status = Some(status_code);
+        self.authz_latency_ms = Some(self.now_ms().saturating_sub(self.grpc_dispatched_ms));
+
+        if std::mem::take(&mut self.grpc_in_flight) {
+            self.worker_stats.borrow_mut().dispatch_finished();
+            if self.config.experiment.is_some() {
+                let latency_ms = self.now_ms().saturating_sub(self.grpc_dispatched_ms);
+                self.metrics.unary_latency_ms.record(latency_ms);
+            }
+        }
+        if std::mem::take(&mut self.timeout_tracked) {
+            self.pending_calls.borrow_mut().remove(self.context_id);
+        }
+        if self.terminal.is_settled() {
+            request_debug!(
+                self,
+                "Ignoring late gRPC response for request already settled"
+            );
+            self.metrics.suppressed_terminal_actions.increment(1);
+            return false;
+        }
+        true
+    }
+
+    // Apply the collected upstream headers, dropping the lowest-priority ones
+    // if they exceed the configured budget
+    fn apply_upstream_headers(&mut self) {
+        if let Some(budget) = self.config.added_header_budget_bytes {
+            let dropped = self.evaluation.upstream_headers.enforce_budget(budget);
+            if !dropped.is_empty() {
+                warn!(
+                    "[HEADERS] Added headers exceed {} byte budget, dropped: {:?}",
+                    budget, dropped
+                );
+                self.metrics
+                    .upstream_header_drops
+                    .increment(dropped.len() as i64);
+            }
+        }
+
+        let headers = std::mem::take(&mut self.evaluation.upstream_headers);
+        let strip = std::mem::take(&mut self.evaluation.strip_upstream_headers);
+        let authorization = self.evaluation.upstream_authorization.take();
+        request_debug!(
+            self,
+            "[HEADERS] Adding {} bytes of headers to upstream request, removing {:?}",
+            headers.total_size(),
+            strip
+        );
+        self.apply_path_rewrite();
+        self.strip_query_params();
+        host::apply_headers(self, &headers, &strip, authorization.as_deref());
+    }
+
+    // Remove sensitive query parameters from the upstream path, and from the
+    // original path header of a rewritten request
+    fn strip_query_params(&mut self) {
+        let config = Rc::clone(&self.config);
+        let names = &config.path.strip_query_params;
+        if names.is_empty() {
+            return;
+        }
+        for header in [":path", config.path.original_path_header.as_str()] {
+            let stripped = self
+                .headers
+                .get(header)
+                .and_then(|path| query::strip_params(path, names));
+            if let Some(stripped) = stripped {
+                request_debug!(self, "[PATH] Stripped query parameters from '{}'", header);
+                self.set_request_header(header, &stripped);
+            }
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.get_current_time()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+
+    // Forward the rewritten `:path`, with the client's path in the configured
+    // header
+    fn apply_path_rewrite(&mut self) {
+        if let Some(rewrite) = self.evaluation.path_rewrite.take() {
+            self.set_request_header(":path", &rewrite.rewritten);
+            let original_path_header = &self.config.path.original_path_header;
+            self.set_http_request_header(original_path_header, Some(&rewrite.original));
+            self.headers.set(original_path_header, &rewrite.original);
+            self.evaluation.path_rewrite = Some(rewrite);
+        }
+    }
+
+    // Build the FilterRequest and send it to the authz service (or queue it
+    // as a shadow call)
+    fn dispatch_authz(&mut self, shadow: bool) -> Action {
+        let config = Rc::clone(&self.config);
+        // Reset and track memory for this request
+        self.request_memory_bytes = 0;
+        let initial_memory = self.estimate_memory_usage();
+        request_debug!(
+            self,
+            "[MEMORY] Initial memory usage: {} bytes",
+            initial_memory
+        );
+
+        let header_count = match self.encode_filter_request() {
+            Ok(header_count) => header_count,
+            Err(e) => {
+                self.serialization_error(&e);
+                self.release_call_slot();
+                // A locally allowed request stays allowed without its shadow
+                // call
+                if shadow {
+                    return Action::Continue;
+                }
+                return self.serialization_failed();
+            }
+        };
+        let message_buffer = Rc::clone(&self.message_buffer);
+        let buffer = message_buffer.borrow();
+        let message = &buffer.bytes;
+
+        // Keyed on the FilterRequest itself, so requests only share a verdict
+        // when the authz service would see the same request
+        let coalesce_key = match (shadow, config.coalesce.as_ref()) {
+            (false, Some(coalesce)) if self.request_trailers.is_empty() => {
+                let key = coalesce.key(self.authz_cluster(), message);
+                if self.park(coalesce, &key) {
+                    return Action::Pause;
+                }
+                Some(key)
+            }
+            _ => None,
+        };
+
+        if std::mem::take(&mut self.evaluation.call_queued) {
+            if let Some(limit) = config.concurrency_limit.as_ref() {
+                return self.queue_call(limit);
+            }
+        }
+
+        self.metrics
+            .filter_request_bytes
+            .record(message.len() as u64);
+        if let Some(limit) = config.oversized_request_bytes {
+            if message.len() > limit {
+                warn!(
+                    "[SIZE] FilterRequest of {} bytes exceeds {} bytes (request {})",
+                    message.len(),
+                    limit,
+                    self.request_id
+                );
+                self.metrics.oversized_filter_requests.increment(1);
+            }
+        }
+
+        request_debug!(
+            self,
+            "Constructed FilterRequest with {} protobuf headers, message size: {} bytes",
+            header_count,
+            message.len()
+        );
+
+        if let (false, Some(mirror)) = (shadow, config.mirror.as_ref()) {
+            if mirror.sampled()
+                && !self
+                    .mirrors
+                    .borrow_mut()
+                    .queue(mirror, &self.request_id, message)
+            {
+                self.metrics.mirror_dropped.increment(1);
+            }
+        }
+
+        if let (true, Some(experiment)) = (shadow, config.experiment.as_ref()) {
+            request_debug!(
+                self,
+                "[EXPERIMENT] Queueing shadow call for locally allowed request"
+            );
+            self.shadow_calls.borrow_mut().queue(
+                experiment,
+                self.authz_cluster().to_string(),
+                message.to_vec(),
+            );
+            return Action::Continue;
+        }
+
+        if let Some(policy) = self.policy() {
+            request_debug!(self, "[POLICY] Authorizing under policy '{}'", policy.name);
+        }
+
+        if let Some(id) = self.send_on_stream(message) {
+            logging::event("authz_dispatched")
+                .at(self.config.logging.lifecycle_level())
+                .sampled(self.log_sampled)
+                .field("request_id", self.request_id.as_str())
+                .field("stream_id", id)
+                .field("message_bytes", message.len())
+                .emit(&self.config.logging);
+            self.guard_timeout(None);
+            self.authz_dispatched();
+            self.lead(coalesce_key);
+            self.dispatch_authn(message);
+            return Action::Pause;
+        }
+
+        match self.make_grpc_call(message) {
+            Ok(token) => {
+                logging::event("authz_dispatched")
+                    .at(self.config.logging.lifecycle_level())
+                    .sampled(self.log_sampled)
+                    .field("request_id", self.request_id.as_str())
+                    .field("token", token)
+                    .field("message_bytes", message.len())
+                    .emit(&self.config.logging);
+                self.guard_timeout(Some(token));
+                self.authz_dispatched();
+                self.lead(coalesce_key);
+                self.dispatch_authn(message);
+                Action::Pause
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to dispatch gRPC call: {:?}, applying failure mode {:?}",
+                    e, self.config.failure_mode
+                );
+                self.release_call_slot();
+                self.call_unavailable()
+            }
+        }
+    }
+
+    // Let identical requests park on the call just dispatched
+    fn lead(&mut self, key: Option<String>) {
+        if let Some(key) = key {
+            self.coalescer.borrow_mut().lead(&key, self.context_id);
+            self.coalesced = Some(Membership::Leader(key));
+        }
+    }
 
-        // Set response header immediately to avoid storing the message
-        // Note: This bypasses on_http_response_headers() but achieves the same result
-        self.set_http_response_header("x-filter-response-pdk-response", Some(response_message));
+    fn serialization_error(&self, error: &EncodeError) {
+        warn!(
+            "[SERIALIZE] Failed to serialize FilterRequest for request {}: {:?}",
+            self.request_id, error
+        );
+        self.metrics.serialization_failures.increment(1);
+    }
 
-        // Calculate final memory usage for this request
-        let final_memory = self.estimate_memory_usage();
-        let total_request_memory = final_memory; // Approximate total for this request
+    // No authz call could be made for lack of a FilterRequest: apply
+    // `serialization_failure`
+    fn serialization_failed(&mut self) -> Action {
+        if self.config.serialization_failure == FailureMode::Allow {
+            self.record_decision("error", 0);
+            return Action::Continue;
+        }
+        self.record_decision("error", 500);
+        if self.evaluation.monitor_only {
+            self.metrics.unenforced.increment(1);
+            return Action::Continue;
+        }
+        self.respond(500, vec![], Some(b"Internal Server Error"));
+        Action::Pause
+    }
+
+    // Whether the authz call of a request with a body waits for its trailers
+    fn defer_for_trailers(&self, config: &PluginConfig) -> bool {
+        let Some(trailers) = config.request_trailers.as_ref() else {
+            return false;
+        };
+        self.evaluation.upgrade.is_none()
+            && trailers.applies(
+                self,
+                &self.request_path,
+                self.evaluation.grpc_target.is_some(),
+            )
+    }
+
+    // Body data and trailers of a request whose headers are paused stay in
+    // Envoy until the verdict, so no payload reaches the upstream before the
+    // request is authorized
+    fn hold_for_decision(&self) -> Action {
+        if self.awaiting_decision && !self.terminal.is_settled() {
+            Action::Pause
+        } else {
+            Action::Continue
+        }
+    }
 
+    // The stream switched protocols: nothing is left to decide, so drop the
+    // request state instead of holding it for the life of the connection
+    fn finish_upgrade(&mut self) {
         info!(
-            "[MEMORY] Final memory usage: {} bytes, total request memory: ~{} bytes",
-            final_memory, total_request_memory
+            "[UPGRADE] Request {} switched to '{}'",
+            self.request_id,
+            self.evaluation.upgrade.as_deref().unwrap_or_default()
+        );
+        self.release_call_slot();
+        self.evaluation = Evaluation::default();
+        self.headers = HeaderSnapshot::default();
+        self.pending_debug_headers = Vec::new();
+    }
+
+    // Drop client-supplied identity headers before anything reads them. The
+    // identity signature header is always dropped when the filter signs.
+    fn strip_trusted_headers(&mut self) {
+        let config = Rc::clone(&self.config);
+        let trusted = config
+            .strip_trusted_headers
+            .iter()
+            .flat_map(|trusted| trusted.headers.iter().map(String::as_str));
+        let signature = config
+            .identity_signing
+            .as_ref()
+            .map(|_| identity_signature::HEADER);
+        for name in trusted.chain(signature) {
+            if self.headers.remove(name) {
+                self.set_http_request_header(name, None);
+                self.metrics.stripped_trusted_headers.increment(1);
+                warn!(
+                    "[SANITIZE] Removed client-supplied '{}' header (request {})",
+                    name, self.request_id
+                );
+            }
+        }
+    }
+
+    // Set a request header, keeping the snapshot in step
+    fn set_request_header(&mut self, name: &str, value: &str) {
+        self.set_http_request_header(name, Some(value));
+        self.headers.set(name, value);
+    }
+
+    // Register the pending call so the root context can settle this request
+    // before the downstream/route timeout fires
+    fn guard_timeout(&mut self, token: Option<u32>) {
+        let Some(guard) = self.config.timeout_guard.as_ref() else {
+            return;
+        };
+
+        let now_ms = self.now_ms();
+        let expected = self.headers.get("x-envoy-expected-rq-timeout-ms");
+        if let Some(deadline_ms) = guard.deadline_ms(now_ms, expected) {
+            self.pending_calls.borrow_mut().track(PendingCall {
+                context_id: self.context_id,
+                token,
+                deadline_ms,
+                grpc: self.evaluation.grpc_target.is_some(),
+                fail_open: self.evaluation.monitor_only,
+                guard: self.terminal.clone(),
+            });
+            self.timeout_tracked = true;
+        }
+    }
+
+    // Count the request's decision for throughput reporting and publish it to
+    // the audit queue (if configured)
+    fn record_decision(&self, decision: &str, status: u32) {
+        self.worker_stats.borrow_mut().record_decision(decision);
+
+        let latency_ms = self.now_ms().saturating_sub(self.request_start_ms);
+        let logging = &self.config.logging;
+        let mut event = if logging.access_log {
+            logging::event("access")
+                .field("method", self.request_method.as_str())
+                .field("path", self.request_path.as_str())
+                .field("authority", self.request_authority.as_str())
+        } else {
+            logging::event("decision")
+        }
+        .field("request_id", self.request_id.as_str())
+        .field("decision", decision)
+        .field("status", status)
+        .field("enforced", !self.evaluation.monitor_only)
+        .field("latency_ms", latency_ms)
+        .field(
+            "principal",
+            self.evaluation.principal.as_deref().unwrap_or(""),
+        );
+        if logging.access_log {
+            // Requests decided without the authz call (API keys, signatures,
+            // fail-open) have no gRPC status
+            event = event
+                .field("grpc_status", self.authz_status)
+                .field("authz_called", self.authz_status.is_some());
+        }
+        if decision == "error" {
+            event = event.at(log::Level::Warn);
+        }
+        event
+            .sampled(self.log_sampled || decision != "allow")
+            .emit(logging);
+
+        if self.config.export_filter_state || self.evaluation.monitor_only {
+            self.export_filter_state(decision, status, latency_ms);
+        }
+
+        let (Some(audit), Some(queue_id)) = (self.config.audit.as_ref(), self.audit_queue) else {
+            return;
+        };
+
+        let event = AuditEvent {
+            ts: self.now_secs(),
+            principal: self.evaluation.principal.as_deref().unwrap_or(""),
+            method: &self.request_method,
+            path: if self.config.path.audit_original_path {
+                &self.request_path
+            } else {
+                self.evaluation.effective_path(&self.request_path)
+            },
+            decision,
+            status,
+            latency_ms,
+        };
+        audit::publish(audit, queue_id, &event, &self.metrics);
+    }
+
+    // Decision fields as filter state (`wasm.uipbdiauthz.*`) for Envoy access
+    // log format strings, e.g. %FILTER_STATE(wasm.uipbdiauthz.decision:PLAIN)%
+    fn export_filter_state(&self, decision: &str, status: u32, latency_ms: u64) {
+        let principal = self.evaluation.principal.as_deref().unwrap_or("");
+        let authz_status = self.authz_status.map(|s| s.to_string());
+        let enforced = !self.evaluation.monitor_only;
+        let fields = [
+            ("uipbdiauthz.enforced", Some(enforced.to_string())),
+            ("uipbdiauthz.decision", Some(decision.to_string())),
+            ("uipbdiauthz.status", Some(status.to_string())),
+            ("uipbdiauthz.principal", Some(principal.to_string())),
+            ("uipbdiauthz.latency_ms", Some(latency_ms.to_string())),
+            ("uipbdiauthz.grpc_status", authz_status),
+        ];
+        for (name, value) in fields {
+            let Some(value) = value else {
+                continue;
+            };
+            if let Err(e) = hostcalls::set_property(vec![name], Some(value.as_bytes())) {
+                warn!("[FILTER-STATE] Failed to set {}: {:?}", name, e);
+            }
+        }
+    }
+
+    fn decision_details(&self, decision: &'static str) -> Vec<(&'static str, String)> {
+        DecisionDetails {
+            decision,
+            authz_message: self.authz_message.as_deref(),
+            authz_latency_ms: self.authz_latency_ms,
+            enforced: !self.evaluation.monitor_only,
+        }
+        .headers()
+    }
+
+    fn stash_debug_headers(&mut self, decision: &'static str) {
+        if self.debug_headers {
+            self.pending_debug_headers = self.decision_details(decision);
+        }
+    }
+
+    // Let a denied request through in monitor-only mode, after it was
+    // recorded; false when the decision is enforced
+    fn unenforced(&mut self, decision: &'static str, status: u32) -> bool {
+        if !self.evaluation.monitor_only {
+            return false;
+        }
+        info!(
+            "[MONITOR] Not enforcing {} ({}) for request {}",
+            decision, status, self.request_id
         );
+        self.metrics.unenforced.increment(1);
+        self.stash_debug_headers(decision);
+        self.resume();
+        true
+    }
+
+    // Answer with an error, unless in monitor-only mode
+    fn respond_error(&mut self, status: u32, body: &[u8]) {
+        self.record_decision("error", status);
+        if !self.unenforced("error", status) {
+            self.respond(status, vec![], Some(body));
+        }
+    }
+
+    fn send_local_response(&self, response: &LocalResponse) {
+        let details = if self.debug_headers && response.status >= 400 {
+            self.decision_details("deny")
+        } else {
+            Vec::new()
+        };
+        let headers = response
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain(details.iter().map(|(name, value)| (*name, value.as_str())))
+            .collect();
+        self.respond(response.status, headers, response.body.as_deref());
+    }
+
+    // Deny with the configured body template, if any; gRPC clients keep the
+    // built-in grpc-message
+    fn send_denial(&self, response: &LocalResponse, reason: &str) {
+        if self.evaluation.grpc_target.is_some() {
+            return self.send_local_response(response);
+        }
+        let mut response = response.clone();
+
+        let accept_language = self.header("accept-language");
+        let localized = self
+            .config
+            .localized_messages
+            .as_ref()
+            .and_then(|messages| messages.message(accept_language.as_deref(), response.status));
+        if let Some((locale, message)) = localized {
+            response.body = Some(message.as_bytes().to_vec());
+            response
+                .headers
+                .push(("content-language".into(), locale.to_string()));
+        }
+
+        if let Some(template) = self.config.deny_template.as_ref() {
+            let correlation_id = self.correlation_id().unwrap_or_default();
+            let message = String::from_utf8_lossy(response.body.as_deref().unwrap_or_default());
+            let body = template.render(&DenyFields {
+                reason,
+                message: &message,
+                status: response.status,
+                correlation_id: &correlation_id,
+                path: &self.request_path,
+            });
+            response.body = Some(body);
+            response
+                .headers
+                .retain(|(name, _)| !name.eq_ignore_ascii_case("content-type"));
+            response
+                .headers
+                .push(("content-type".into(), template.content_type.clone()));
+        }
+        self.send_local_response(&response);
+    }
+
+    // Received or generated `x-correlation-id` of the request
+    fn correlation_id(&self) -> Option<String> {
+        self.evaluation
+            .echo_correlation_id
+            .clone()
+            .or_else(|| self.header(correlation::HEADER))
+    }
+
+    // Queue a denial record for the denial audit cluster (if configured). The
+    // FilterRequest is encoded again, as the worker's message buffer may hold
+    // another request's by now, and local denies never built one.
+    fn audit_denial(&self, reason: &str, status: u32) {
+        let Some(denial_audit) = self.config.denial_audit.as_ref() else {
+            return;
+        };
+        let record = self.encode_filter_request().and_then(|_| {
+            denial_audit::encode_record(
+                &self.message_buffer.borrow().bytes,
+                reason,
+                status,
+                &self.request_id,
+                self.now_ms(),
+            )
+        });
+        let queued = match record {
+            Ok(record) => self.denial_audits.borrow_mut().queue(denial_audit, record),
+            Err(e) => {
+                warn!("[DENIAL-AUDIT] Failed to serialize denial record: {:?}", e);
+                false
+            }
+        };
+        if !queued {
+            self.metrics.denial_audit_dropped.increment(1);
+        }
+    }
 
-        info!("Resuming request processing");
+    // Terminal actions go through the request's guard so racing callbacks
+    // cannot resume or answer the same stream twice
+    fn respond(&self, status: u32, mut headers: Vec<(&str, &str)>, body: Option<&[u8]>) {
+        if !self
+            .terminal
+            .claim(self.metrics.suppressed_terminal_actions, "respond")
+        {
+            return;
+        }
+        let grpc = self.evaluation.grpc_target.is_some();
+        // Bodies that already have a content type (deny template) are kept
+        let typed = headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-type"));
+        let problem = match (self.config.problem_details.as_ref(), body) {
+            (Some(problem_details), Some(body)) if !grpc && !typed && status >= 400 => {
+                let correlation_id = self.correlation_id();
+                headers.push(("content-type", problem::CONTENT_TYPE));
+                Some(problem_details.render(status, body, correlation_id.as_deref()))
+            }
+            _ => None,
+        };
+        host::respond(self, grpc, status, headers, problem.as_deref().or(body));
+    }
 
-        // Track memory and detect leaks at end of request processing
-        #[cfg(feature = "memory-tracking")]
+    fn resume(&self) {
+        if self
+            .terminal
+            .claim(self.metrics.suppressed_terminal_actions, "resume")
         {
-            memory_tracking::log_memory_change("Request End", self.request_start_stats);
-            if let Some(start_stats) = self.request_start_stats {
-                memory_tracking::detect_memory_leak("Request Complete", start_stats);
+            self.resume_request();
+        }
+    }
+
+    // Redeem the OIDC authorization code; the response is handled in
+    // on_http_call_response
+    fn dispatch_code_exchange(&mut self, oidc: &OidcConfig, code: &str, return_to: String) {
+        let body = oidc.token_request_body(code);
+        match self.dispatch_http_call(
+            &oidc.token_cluster,
+            vec![
+                (":method", "POST"),
+                (":path", &oidc.token_path),
+                (":authority", &oidc.token_authority),
+                ("content-type", "application/x-www-form-urlencoded"),
+                ("accept", "application/json"),
+            ],
+            Some(body.as_bytes()),
+            vec![],
+            Duration::from_millis(oidc.timeout_ms),
+        ) {
+            Ok(token) => {
+                info!("[OIDC] Dispatched code exchange with token: {}", token);
+                self.oidc_return_to = Some(return_to);
+            }
+            Err(e) => {
+                warn!("[OIDC] Failed to dispatch code exchange: {:?}", e);
+                self.respond(502, vec![], Some(b"Bad Gateway"));
             }
         }
+    }
 
-        // Resume the request
+    // Build cluster name once at initialization
+    fn build_cluster_name() -> String {
+        let service_instance =
+            std::env::var("SERVICE_INSTANCE").unwrap_or_else(|_| "localhost".into());
+        format!(
+            "outbound|50051||{}.localhost.for.grpc.call",
+            service_instance
+        )
+    }
+}
+
+impl HttpContext for AuthEngine {
+    fn on_http_request_headers(&mut self, _: usize, end_of_stream: bool) -> Action {
+        self.log_sampled = self.config.logging.sampled();
+        self.scratch.borrow_mut().reset();
+        request_debug!(self, "Entering on_http_request_headers");
+        request_debug!(self, "Initializing gRPC OAuth 2.0 policy");
+
+        self.worker_stats.borrow_mut().record_request();
+        self.request_start_ms = self.now_ms();
+        // One host call for all request headers; everything below reads the
+        // snapshot
+        self.headers = HeaderSnapshot::new(self.get_http_request_headers());
+        let header = |name| self.headers.get(name).unwrap_or_default().to_string();
+        self.request_method = header(":method");
+        self.request_path = header(":path");
+        self.request_authority = header(":authority");
+        self.request_id = header("x-request-id");
+        self.strip_trusted_headers();
+        if let Some(debug) = self.config.debug_headers.as_ref() {
+            let trigger = (!debug.trigger_header.is_empty())
+                .then(|| self.headers.get(&debug.trigger_header))
+                .flatten();
+            self.debug_headers = debug.enabled(trigger);
+        }
+        logging::event("request_start")
+            .at(self.config.logging.lifecycle_level())
+            .sampled(self.log_sampled)
+            .field("request_id", self.request_id.as_str())
+            .field("method", self.request_method.as_str())
+            .field("path", self.request_path.as_str())
+            .emit(&self.config.logging);
+
+        // Local checks (OIDC login, API keys) may settle the request before
+        // any authz call
+        let config = Rc::clone(&self.config);
+        let mut evaluation = Evaluation::default();
+        let step = pipeline::evaluate_request(&config, self, &mut evaluation);
+        self.evaluation = evaluation;
+        if self.evaluation.replay_rejected {
+            self.metrics.replay_rejected.increment(1);
+        }
+        if self.evaluation.rate_limited {
+            self.metrics.rate_limited.increment(1);
+        }
+        if let Some(index) = self.evaluation.bypass_rule {
+            config.path_bypass[index].counter.increment(1);
+        }
+        if self.evaluation.method_exempt {
+            self.metrics.method_exempt.increment(1);
+        }
+        if self.evaluation.concurrency_limited {
+            self.metrics.concurrency_limited.increment(1);
+        }
+        if self.evaluation.not_ready {
+            self.metrics.not_ready.increment(1);
+        }
+        if self.evaluation.over_memory_budget {
+            self.metrics.over_memory_budget.increment(1);
+            self.scratch.borrow_mut().release();
+            self.message_buffer.borrow_mut().release();
+        }
+        if self.evaluation.dry_run {
+            self.debug_headers = true;
+        }
+        if !self.evaluation.monitor_only {
+            for (name, value) in std::mem::take(&mut self.evaluation.request_headers) {
+                self.set_request_header(name, &value);
+            }
+            self.apply_path_rewrite();
+        }
+
+        // Locally allowed request sampled for a shadow authz call
+        let mut shadow = false;
+        match step {
+            Step::Authorize => {}
+            Step::Allow => {
+                self.record_decision("allow", 200);
+                self.stash_debug_headers("allow");
+                if !self.evaluation.monitor_only {
+                    self.apply_upstream_headers();
+                }
+                match config.experiment.as_ref() {
+                    Some(experiment)
+                        if !self.evaluation.over_memory_budget && experiment.sampled() =>
+                    {
+                        shadow = true
+                    }
+                    _ => return Action::Continue,
+                }
+            }
+            Step::Respond(response) => {
+                if response.status >= 400 && self.evaluation.monitor_only {
+                    self.record_decision("deny", response.status);
+                    self.metrics.unenforced.increment(1);
+                    self.stash_debug_headers("deny");
+                    return Action::Continue;
+                }
+                if response.status >= 400 {
+                    self.record_decision("deny", response.status);
+                    let body = response.body.as_deref().unwrap_or_default();
+                    let reason = String::from_utf8_lossy(body);
+                    self.audit_denial(&format!("local: {}", reason), response.status);
+                    self.send_denial(&response, &reason);
+                } else {
+                    self.send_local_response(&response);
+                }
+                return Action::Pause;
+            }
+            Step::ExchangeCode { code, return_to } => {
+                if let Some(oidc) = config.oidc.as_ref() {
+                    self.dispatch_code_exchange(oidc, &code, return_to);
+                }
+                return Action::Pause;
+            }
+        }
+
+        if !shadow && !end_of_stream && self.defer_for_trailers(&config) {
+            request_debug!(
+                self,
+                "[TRAILERS] Deferring the authz call until the request trailers"
+            );
+            self.awaiting_trailers = true;
+            return Action::Pause;
+        }
+        self.dispatch_authz(shadow)
+    }
+
+    // Hold the body of a request whose authz call waits for trailers; a body
+    // that ends without trailers is authorized as it is
+    fn on_http_request_body(&mut self, _: usize, end_of_stream: bool) -> Action {
+        if !self.awaiting_trailers {
+            return self.hold_for_decision();
+        }
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        self.awaiting_trailers = false;
+        self.dispatch_authz(false)
+    }
+
+    fn on_http_request_trailers(&mut self, _: usize) -> Action {
+        if !self.awaiting_trailers {
+            return self.hold_for_decision();
+        }
+        self.awaiting_trailers = false;
+        let config = Rc::clone(&self.config);
+        if let Some(trailers) = config.request_trailers.as_ref() {
+            self.request_trailers = self
+                .get_http_request_trailers()
+                .into_iter()
+                .filter(|(name, _)| trailers.forwards(name))
+                .collect();
+        }
+        request_debug!(
+            self,
+            "[TRAILERS] Authorizing with {} request trailers",
+            self.request_trailers.len()
+        );
+        self.dispatch_authz(false)
+    }
+
+    fn on_log(&mut self) {
+        if let Some(leak) = self.leak_tracker.finish(&self.config.leak_detection) {
+            warn!("[MEMORY-LEAK] Request '{}' {}", self.request_id, leak);
+        }
+    }
+
+    fn on_http_response_headers(&mut self, _: usize, _end_of_stream: bool) -> Action {
+        for (name, value) in std::mem::take(&mut self.pending_debug_headers) {
+            self.set_http_response_header(name, Some(&value));
+        }
+        for (name, value) in std::mem::take(&mut self.evaluation.response_headers) {
+            self.set_http_response_header(name, Some(&value));
+        }
+        // Added, so the upstream's own cookies are kept
+        if let Some(cookie) = self.evaluation.set_cookie.take() {
+            self.add_http_response_header("set-cookie", &cookie);
+        }
+        if self.evaluation.upgrade.is_some()
+            && self.get_http_response_header(":status").as_deref() == Some("101")
+        {
+            self.finish_upgrade();
+        }
+        Action::Continue
+    }
+}
+
+impl Host for AuthEngine {
+    fn set_header(&self, name: &str, value: Option<&str>) {
+        self.set_http_request_header(name, value);
+    }
+
+    fn add_header(&self, name: &str, value: &str) {
+        self.add_http_request_header(name, value);
+    }
+
+    fn dispatch(&self, call: &GrpcCall) -> Result<u32, Status> {
+        let token = self.dispatch_grpc_call(
+            call.cluster,
+            call.service,
+            call.method,
+            call.metadata.clone(),
+            Some(call.message),
+            call.timeout,
+        )?;
+        if self.rerouted {
+            self.reroutes
+                .borrow_mut()
+                .add(token, self.context_id, self.this.clone());
+        }
+        Ok(token)
+    }
+
+    fn send_response(&self, status: u32, headers: Vec<(&str, &str)>, body: Option<&[u8]>) {
+        self.send_http_response(status, headers, body);
+    }
+
+    fn send_grpc_status(
+        &self,
+        status: GrpcStatusCode,
+        message: Option<&str>,
+        metadata: Vec<(&str, &[u8])>,
+    ) {
+        self.send_grpc_response(status, message, metadata);
+    }
+
+    fn resume_request(&self) {
         self.resume_http_request();
     }
 }
+
+impl Drop for AuthEngine {
+    fn drop(&mut self) {
+        if self.grpc_in_flight {
+            self.worker_stats.borrow_mut().dispatch_finished();
+        }
+        if self.timeout_tracked {
+            self.pending_calls.borrow_mut().remove(self.context_id);
+        }
+        self.release_call_slot();
+        if self.stream_waiting {
+            self.authz_stream
+                .borrow_mut()
+                .remove_context(self.context_id);
+        }
+        if matches!(self.coalesced, Some(Membership::Follower)) {
+            self.coalescer.borrow_mut().leave(self.context_id);
+        }
+        if self.rerouted {
+            self.reroutes.borrow_mut().remove_context(self.context_id);
+        }
+        if self.call_queued {
+            self.call_queue.borrow_mut().remove_context(self.context_id);
+        }
+        // Parked requests dispatch their own calls
+        self.release_followers(None);
+    }
+}
+
+// Request context as registered with the host. The engine is shared with the
+// root context, which hands it authz stream responses.
+struct SharedEngine(Rc<RefCell<AuthEngine>>);
+
+impl SharedEngine {
+    // Run a callback, charging the net heap bytes it retained to `stage`
+    fn tracked<T>(&self, stage: Stage, callback: impl FnOnce(&mut AuthEngine) -> T) -> T {
+        let before = memory_tracking::heap_bytes();
+        let mut engine = self.0.borrow_mut();
+        let result = callback(&mut engine);
+        if let (Some(before), Some(after)) = (before, memory_tracking::heap_bytes()) {
+            engine.leak_tracker.record(stage, before, after);
+        }
+        result
+    }
+}
+
+impl Context for SharedEngine {
+    fn on_http_call_response(
+        &mut self,
+        token_id: u32,
+        num_headers: usize,
+        body_size: usize,
+        num_trailers: usize,
+    ) {
+        self.0
+            .borrow_mut()
+            .on_http_call_response(token_id, num_headers, body_size, num_trailers);
+    }
+
+    fn on_grpc_call_response(&mut self, token_id: u32, status_code: u32, response_size: usize) {
+        self.tracked(Stage::AuthzReply, |engine| {
+            engine.on_grpc_call_response(token_id, status_code, response_size)
+        });
+    }
+}
+
+impl HttpContext for SharedEngine {
+    fn on_http_request_headers(&mut self, num_headers: usize, end_of_stream: bool) -> Action {
+        let action = self.tracked(Stage::RequestHeaders, |engine| {
+            engine.on_http_request_headers(num_headers, end_of_stream)
+        });
+        if action == Action::Pause {
+            self.0.borrow_mut().awaiting_decision = true;
+        }
+        action
+    }
+
+    fn on_http_response_headers(&mut self, num_headers: usize, end_of_stream: bool) -> Action {
+        self.tracked(Stage::ResponseHeaders, |engine| {
+            engine.on_http_response_headers(num_headers, end_of_stream)
+        })
+    }
+
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        self.0
+            .borrow_mut()
+            .on_http_request_body(body_size, end_of_stream)
+    }
+
+    fn on_http_request_trailers(&mut self, num_trailers: usize) -> Action {
+        self.0.borrow_mut().on_http_request_trailers(num_trailers)
+    }
+
+    fn on_log(&mut self) {
+        self.0.borrow_mut().on_log();
+    }
+}
+
+impl RequestSource for AuthEngine {
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers.get(name).map(str::to_string)
+    }
+
+    fn header_list(&self, name: &str) -> Option<String> {
+        let values: Vec<&str> = self.headers.get_all(name).collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    fn header_totals(&self) -> (usize, usize) {
+        self.headers.totals()
+    }
+
+    fn source_address(&self) -> Option<String> {
+        self.get_property(vec!["source", "address"])
+            .and_then(|address| String::from_utf8(address).ok())
+    }
+
+    fn property(&self, path: &[&str]) -> Option<Vec<u8>> {
+        self.get_property(path.to_vec())
+    }
+
+    fn shared_data(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_shared_data(key).0
+    }
+
+    fn now_secs(&self) -> u64 {
+        self.get_current_time()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+
+    fn shared_data_cas(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+        self.get_shared_data(key)
+    }
+
+    fn update_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+        match self.set_shared_data(key, Some(value), cas) {
+            Ok(()) => true,
+            Err(Status::CasMismatch) => false,
+            Err(e) => {
+                warn!("[SHARED] Failed to update '{}': {:?}", key, e);
+                false
+            }
+        }
+    }
+
+    fn memory_used(&self) -> u64 {
+        memory_report::Sample::current(memory_tracking::heap_bytes()).used_bytes()
+    }
+}
+
+impl Context for AuthEngine {
+    fn on_http_call_response(&mut self, token_id: u32, _: usize, body_size: usize, _: usize) {
+        request_debug!(self, "HTTP call response received - Token: {}", token_id);
+
+        let config = Rc::clone(&self.config);
+        if let (Some(oidc), Some(return_to)) = (config.oidc.as_ref(), self.oidc_return_to.take()) {
+            let status = self.get_http_call_response_header(":status");
+            let body = self.get_http_call_response_body(0, body_size);
+            let response = pipeline::complete_login(
+                oidc,
+                status.as_deref(),
+                body.as_deref(),
+                &return_to,
+                self.now_secs(),
+            );
+            self.send_local_response(&response);
+        }
+    }
+
+    fn on_grpc_call_response(&mut self, token_id: u32, status_code: u32, response_size: usize) {
+        let latency_ms = self.now_ms().saturating_sub(self.grpc_dispatched_ms);
+        let leg = if self.authn_call == Some(token_id) {
+            self.authn_call = None;
+            Leg::Authn
+        } else if self.chain_call == Some(token_id) {
+            self.chain_call = None;
+            Leg::Chain
+        } else {
+            Leg::Authz
+        };
+        let call_status = CallStatus::read(
+            status_code,
+            |name| {
+                hostcalls::get_map_value_bytes(MapType::GrpcReceiveTrailingMetadata, name)
+                    .ok()
+                    .flatten()
+            },
+            hostcalls::get_grpc_status()
+                .ok()
+                .and_then(|(_, message)| message),
+        );
+        self.count_grpc_status(call_status.code);
+        logging::event("authz_response")
+            .at(self.config.logging.lifecycle_level())
+            .sampled(self.log_sampled)
+            .field("request_id", self.request_id.as_str())
+            .field("token", token_id)
+            .field("leg", leg.name())
+            .field("grpc_status", call_status.code)
+            .field("grpc_message", call_status.message.as_str())
+            .field("response_bytes", response_size)
+            .field("latency_ms", latency_ms)
+            .emit(&self.config.logging);
+
+        let result = if call_status.is_ok() {
+            self.read_reply(response_size)
+        } else {
+            warn!(
+                "[AUTHZ] {} call failed: {} ({}): {}",
+                leg.name(),
+                call_status.name(),
+                call_status.code,
+                call_status.message
+            );
+            Err(call_status.http_error())
+        };
+        self.leg_answered(leg, call_status.code, result);
+    }
+}
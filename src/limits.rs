@@ -0,0 +1,62 @@
+use serde::Deserialize;
+
+// Size guardrails checked before anything else looks at a request, so
+// oversized requests are rejected locally instead of being copied into a
+// FilterRequest and sent to the authz service. The defaults match Envoy's own
+// listener limits (60 KiB of headers, 100 headers).
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RequestLimitsConfig {
+    // Sum of header name and value lengths, pseudo-headers included
+    pub max_header_bytes: usize,
+    pub max_header_count: usize,
+    // `:path` including the query string
+    pub max_path_length: usize,
+}
+
+impl Default for RequestLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_header_bytes: 60 * 1024,
+            max_header_count: 100,
+            max_path_length: 8192,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    HeaderBytes(usize),
+    HeaderCount(usize),
+    PathLength(usize),
+}
+
+impl Violation {
+    // Status and body of the local response
+    pub fn response(&self) -> (u32, &'static str) {
+        match self {
+            Violation::HeaderBytes(_) | Violation::HeaderCount(_) => {
+                (431, "Request Header Fields Too Large")
+            }
+            Violation::PathLength(_) => (414, "URI Too Long"),
+        }
+    }
+}
+
+impl RequestLimitsConfig {
+    // `header_totals` is (count, bytes) of the request headers
+    pub fn check(&self, header_totals: (usize, usize), path: &str) -> Option<Violation> {
+        let (count, bytes) = header_totals;
+        if count > self.max_header_count {
+            return Some(Violation::HeaderCount(count));
+        }
+        if bytes > self.max_header_bytes {
+            return Some(Violation::HeaderBytes(bytes));
+        }
+        if path.len() > self.max_path_length {
+            return Some(Violation::PathLength(path.len()));
+        }
+        None
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Localized deny messages: catalogs of message per status code, keyed by
+// language tag. The catalog is picked from the request's `Accept-Language`
+// (highest q first, then a tag's primary language), else `default_locale`.
+// A status missing from the picked catalog keeps the built-in body.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LocalizedMessagesConfig {
+    pub default_locale: String,
+    // Locale -> status code -> message
+    pub catalogs: HashMap<String, HashMap<u32, String>>,
+}
+
+impl Default for LocalizedMessagesConfig {
+    fn default() -> Self {
+        Self {
+            default_locale: "en".into(),
+            catalogs: HashMap::new(),
+        }
+    }
+}
+
+// Language ranges of an Accept-Language value, most preferred first; q=0
+// ranges are dropped
+fn preferences(accept_language: &str) -> Vec<&str> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let range = parts.next()?.trim();
+            let q = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!range.is_empty() && q > 0.0).then_some((range, q))
+        })
+        .collect();
+    // Stable, so equal weights keep the client's order
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().map(|(range, _)| range).collect()
+}
+
+impl LocalizedMessagesConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if !self.catalogs.contains_key(&self.default_locale) {
+            return Err(format!(
+                "localized_messages: no catalog for default_locale '{}'",
+                self.default_locale
+            ));
+        }
+        Ok(())
+    }
+
+    fn catalog(&self, tag: &str) -> Option<(&str, &HashMap<u32, String>)> {
+        self.catalogs
+            .iter()
+            .find(|(locale, _)| locale.eq_ignore_ascii_case(tag))
+            .map(|(locale, catalog)| (locale.as_str(), catalog))
+    }
+
+    // Locale picked for the request
+    pub fn negotiate(&self, accept_language: Option<&str>) -> &str {
+        for range in preferences(accept_language.unwrap_or_default()) {
+            if range == "*" {
+                break;
+            }
+            let primary = range.split('-').next().unwrap_or(range);
+            if let Some((locale, _)) = self.catalog(range).or_else(|| self.catalog(primary)) {
+                return locale;
+            }
+        }
+        &self.default_locale
+    }
+
+    // (locale, message) for a deny status, when the catalog has one
+    pub fn message(&self, accept_language: Option<&str>, status: u32) -> Option<(&str, &str)> {
+        let locale = self.negotiate(accept_language);
+        let (locale, catalog) = self.catalog(locale)?;
+        Some((locale, catalog.get(&status)?.as_str()))
+    }
+}
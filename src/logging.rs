@@ -0,0 +1,151 @@
+use proxy_wasm::types::LogLevel;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+// Log settings. The request lifecycle (start, authz call, verdict) is logged
+// as structured events with a fixed set of fields; with `format: json` each
+// event is one JSON object per line so log pipelines can index it without
+// parsing free-form text. Other diagnostics stay free-form and log at debug.
+// `access_log` condenses a request into one `access` event at decision time
+// and moves the other lifecycle events to debug.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub level: Level,
+    pub format: LogFormat,
+    pub access_log: bool,
+    // Share of requests whose per-request logs are kept; denials and errors
+    // are always logged
+    pub sample_percent: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::default(),
+            format: LogFormat::default(),
+            access_log: false,
+            sample_percent: 100,
+        }
+    }
+}
+
+impl LoggingConfig {
+    // Whether a new request keeps its per-request logs
+    pub fn sampled(&self) -> bool {
+        if self.sample_percent >= 100 {
+            return true;
+        }
+        let mut bytes = [0u8; 4];
+        if getrandom::getrandom(&mut bytes).is_err() {
+            return true;
+        }
+        u32::from_le_bytes(bytes) % 100 < self.sample_percent
+    }
+
+    // Level of the per-step lifecycle events
+    pub fn lifecycle_level(&self) -> log::Level {
+        if self.access_log {
+            log::Level::Debug
+        } else {
+            log::Level::Info
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    #[default]
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub fn host_level(self) -> LogLevel {
+        match self {
+            Level::Trace => LogLevel::Trace,
+            Level::Debug => LogLevel::Debug,
+            Level::Info => LogLevel::Info,
+            Level::Warn => LogLevel::Warn,
+            Level::Error => LogLevel::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    // `[EVENT] name key=value ...`
+    #[default]
+    Text,
+    Json,
+}
+
+pub struct Event {
+    name: &'static str,
+    level: log::Level,
+    sampled: bool,
+    fields: Vec<(&'static str, Value)>,
+}
+
+pub fn event(name: &'static str) -> Event {
+    Event {
+        name,
+        level: log::Level::Info,
+        sampled: true,
+        fields: Vec::new(),
+    }
+}
+
+impl Event {
+    pub fn at(mut self, level: log::Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    // Drop the event for requests not picked by log sampling
+    pub fn sampled(mut self, sampled: bool) -> Self {
+        self.sampled = sampled;
+        self
+    }
+
+    pub fn field(mut self, key: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((key, value.into()));
+        self
+    }
+
+    pub fn emit(self, config: &LoggingConfig) {
+        if !self.sampled || !log::log_enabled!(self.level) {
+            return;
+        }
+        log::log!(self.level, "{}", self.render(config.format));
+    }
+
+    fn render(self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Text => {
+                let mut line = format!("[EVENT] {}", self.name);
+                for (key, value) in &self.fields {
+                    match value {
+                        Value::String(s) => line.push_str(&format!(" {}={}", key, s)),
+                        other => line.push_str(&format!(" {}={}", key, other)),
+                    }
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("event".into(), self.name.into());
+                for (key, value) in self.fields {
+                    object.insert(key.into(), value);
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+// Memory budget of a worker VM. An allocation the Wasm VM cannot grow its
+// linear memory for aborts the VM, and with it every request the worker has
+// in flight. Past the budget the filter gives back what it keeps between
+// requests (serialization buffer, scratch region), skips optional work
+// (shadow calls) and, with `fail_open`, lets new requests through without the
+// authz call instead of allocating one more paused request.
+//
+// Usage is the live heap in builds with memory-tracking, else the size of the
+// linear memory, which never shrinks: without the instrumented allocator a
+// VM that reached the budget stays over it.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MemoryBudgetConfig {
+    pub max_bytes: u64,
+    pub fail_open: bool,
+}
+
+impl MemoryBudgetConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.max_bytes == 0 {
+            return Err("memory_budget.max_bytes must be at least 1".into());
+        }
+        Ok(())
+    }
+
+    pub fn exceeded(&self, used_bytes: u64) -> bool {
+        used_bytes > self.max_bytes
+    }
+}
@@ -0,0 +1,175 @@
+use log::info;
+use serde::Deserialize;
+
+use crate::metrics::Metric;
+use crate::shared_counter;
+
+// Periodic memory report of the worker VM from the root context tick, so slow
+// leaks show up without per-request logging. Each report logs the current
+// usage, its high-watermark since the VM started and its growth rate, and
+// records per-worker gauges. Usage is the live heap when the build has
+// memory-tracking (instrumented allocator), else the Wasm linear memory,
+// which only grows. The watermark is over the reports, not every allocation.
+
+// Number of worker slots handed out so far
+const WORKERS_KEY: &str = "uipbdiauthz.memory.workers";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MemoryReportConfig {
+    pub interval_secs: u64,
+}
+
+impl Default for MemoryReportConfig {
+    fn default() -> Self {
+        Self { interval_secs: 60 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sample {
+    // Live heap bytes, with memory-tracking only
+    pub heap_bytes: Option<u64>,
+    pub linear_memory_bytes: u64,
+}
+
+impl Sample {
+    pub fn current(heap_bytes: Option<u64>) -> Self {
+        Self {
+            heap_bytes,
+            linear_memory_bytes: linear_memory_bytes(),
+        }
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.heap_bytes.unwrap_or(self.linear_memory_bytes)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn linear_memory_bytes() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64 * 65536
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn linear_memory_bytes() -> u64 {
+    0
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub used_bytes: u64,
+    pub high_watermark_bytes: u64,
+    // Bytes per minute since the previous report and since the first one
+    pub growth_per_min: i64,
+    pub average_growth_per_min: i64,
+}
+
+#[derive(Debug)]
+struct Gauges {
+    used: Metric,
+    high_watermark: Metric,
+    linear_memory: Metric,
+}
+
+// Owned by the root context
+#[derive(Debug, Default)]
+pub struct Reporter {
+    // Defined on the first report, once the worker slot is known
+    gauges: Option<Gauges>,
+    // (time ms, used bytes) of the first and the previous report
+    first: Option<(u64, u64)>,
+    previous: Option<(u64, u64)>,
+    high_watermark_bytes: u64,
+}
+
+fn per_min(from: (u64, u64), to: (u64, u64)) -> i64 {
+    let elapsed_ms = to.0.saturating_sub(from.0).max(1) as i128;
+    let delta = to.1 as i128 - from.1 as i128;
+    (delta * 60_000 / elapsed_ms) as i64
+}
+
+impl Reporter {
+    pub fn observe(&mut self, sample: &Sample, now_ms: u64) -> Report {
+        let current = (now_ms, sample.used_bytes());
+        let first = *self.first.get_or_insert(current);
+        let previous = self.previous.replace(current).unwrap_or(current);
+        self.high_watermark_bytes = self.high_watermark_bytes.max(current.1);
+        Report {
+            used_bytes: current.1,
+            high_watermark_bytes: self.high_watermark_bytes,
+            growth_per_min: per_min(previous, current),
+            average_growth_per_min: per_min(first, current),
+        }
+    }
+
+    pub fn report(&mut self, sample: &Sample, now_ms: u64) {
+        let report = self.observe(sample, now_ms);
+        let source = if sample.heap_bytes.is_some() {
+            "heap"
+        } else {
+            "linear memory"
+        };
+        info!(
+            "[MEMORY] {}: {} bytes, high-watermark {} bytes, {:+} bytes/min ({:+} bytes/min since start)",
+            source,
+            report.used_bytes,
+            report.high_watermark_bytes,
+            report.growth_per_min,
+            report.average_growth_per_min
+        );
+
+        if self.gauges.is_none() {
+            let Some(slots) = shared_counter::add(WORKERS_KEY, 1) else {
+                return;
+            };
+            let prefix = format!("uipbdiauthz.worker.{}.memory", slots - 1);
+            self.gauges = Some(Gauges {
+                used: Metric::gauge(&format!("{}.used_bytes", prefix)),
+                high_watermark: Metric::gauge(&format!("{}.high_watermark_bytes", prefix)),
+                linear_memory: Metric::gauge(&format!("{}.linear_memory_bytes", prefix)),
+            });
+        }
+        if let Some(gauges) = &self.gauges {
+            gauges.used.record(report.used_bytes);
+            gauges.high_watermark.record(report.high_watermark_bytes);
+            gauges.linear_memory.record(sample.linear_memory_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn memory_report_tracks_watermark_and_growth() {
+        use super::{Report, Reporter, Sample};
+
+        let heap = |bytes| Sample {
+            heap_bytes: Some(bytes),
+            linear_memory_bytes: 4 << 20,
+        };
+        let mut reporter = Reporter::default();
+        let first = reporter.observe(&heap(1_000_000), 0);
+        assert_eq!(first.growth_per_min, 0);
+        reporter.observe(&heap(1_600_000), 60_000);
+        // A dip after a burst keeps the watermark
+        let report = reporter.observe(&heap(1_300_000), 120_000);
+        assert_eq!(
+            report,
+            Report {
+                used_bytes: 1_300_000,
+                high_watermark_bytes: 1_600_000,
+                growth_per_min: -300_000,
+                average_growth_per_min: 150_000,
+            }
+        );
+
+        // Without memory-tracking the linear memory is reported
+        let mut reporter = Reporter::default();
+        let linear = Sample {
+            heap_bytes: None,
+            linear_memory_bytes: 2 << 20,
+        };
+        assert_eq!(reporter.observe(&linear, 0).used_bytes, 2 << 20);
+    }
+}
@@ -0,0 +1,116 @@
+use prost::{EncodeError, Message};
+#[cfg(test)]
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::scratch::HeaderList;
+
+// FilterRequest buffers shared by the request contexts of one worker. A
+// request context lives for a single request, so buffers of its own would be
+// allocated afresh every time; the root context owns these and hands them to
+// each request context instead. A message far larger than usual leaves the
+// byte buffer oversized, so it is shrunk again on the next use.
+//
+// FilterRequest fields that are the same for every request of the VM are
+// serialized once at configuration. Protobuf parsers merge concatenated
+// encodings of a message, so each request only encodes its own fields after
+// that prefix. The headers are appended from the request's scratch region
+// the same way.
+
+pub type SharedMessageBuffer = Rc<RefCell<MessageBuffer>>;
+
+#[cfg(test)]
+thread_local! {
+    // Makes `encode` fail as if the message did not fit its buffer, for tests
+    // of the serialization failure path
+    pub static FAIL_ENCODING: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Debug, Default)]
+pub struct MessageBuffer {
+    // Serialized FilterRequest
+    pub bytes: Vec<u8>,
+    // Encoded per-VM FilterRequest fields
+    pub static_fields: Vec<u8>,
+}
+
+impl MessageBuffer {
+    // Static prefix followed by the per-request fields and headers
+    pub fn encode(
+        &mut self,
+        message: &impl Message,
+        headers: &HeaderList,
+    ) -> Result<(), EncodeError> {
+        self.bytes.clear();
+        if self.bytes.capacity() > RETAIN_BYTES {
+            self.bytes.shrink_to(RETAIN_BYTES);
+        }
+        self.bytes.extend_from_slice(&self.static_fields);
+        #[cfg(test)]
+        if FAIL_ENCODING.with(Cell::get) {
+            return message.encode(&mut &mut [0u8; 0][..]);
+        }
+        message.encode(&mut self.bytes)?;
+        headers.encode(&mut self.bytes);
+        Ok(())
+    }
+
+    // Give the byte buffer back to the allocator (memory budget exceeded)
+    pub fn release(&mut self) {
+        self.bytes = Vec::new();
+    }
+}
+
+// Capacity kept between requests
+const RETAIN_BYTES: usize = 64 * 1024;
+
+#[cfg(test)]
+mod tests {
+    // FilterRequest headers are encoded by hand from the scratch region; the
+    // authz service must read them exactly like prost-encoded `Header` entries.
+    #[test]
+    fn scratch_headers_match_prost_encoding() {
+        use super::MessageBuffer;
+        use crate::scratch::Scratch;
+        use crate::uipbdiauthz::{FilterRequest, Header};
+        use prost::Message;
+
+        let scratch = Scratch::default();
+        let mut headers = scratch.headers();
+        headers.push("method", "GET");
+        headers.push("x-request-id", "");
+        let request = FilterRequest {
+            path: "/api".into(),
+            ..Default::default()
+        };
+        let mut buffer = MessageBuffer::default();
+        buffer.encode(&request, &headers).unwrap();
+
+        let expected = FilterRequest {
+            headers: vec![
+                Header {
+                    key: "method".into(),
+                    value: "GET".into(),
+                },
+                Header {
+                    key: "x-request-id".into(),
+                    value: String::new(),
+                },
+            ],
+            ..request.clone()
+        };
+        assert_eq!(
+            FilterRequest::decode(buffer.bytes.as_slice()).unwrap(),
+            expected
+        );
+        let headers_only = FilterRequest {
+            headers: expected.headers.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            buffer.bytes[request.encoded_len()..],
+            headers_only.encode_to_vec()
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+// Per-route choice of the HTTP methods that need authorization, e.g. only
+// mutating methods on a public read API. Without rules every request is
+// authorized. A rule can only exempt safe methods (RFC 9110: GET, HEAD,
+// OPTIONS, TRACE); any other method, including unknown ones, is always
+// authorized, so a rule that forgets a method cannot open up writes.
+
+const SAFE_METHODS: &[&str] = &["GET", "HEAD", "OPTIONS", "TRACE"];
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MethodRule {
+    // Applies to paths starting with this; empty applies to all
+    pub path_prefix: String,
+    // Methods authorized on matching paths
+    pub enforce: Vec<String>,
+}
+
+impl Default for MethodRule {
+    fn default() -> Self {
+        Self {
+            path_prefix: String::new(),
+            enforce: ["POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+impl MethodRule {
+    // `:method` is upper case
+    pub fn init(&mut self) {
+        for method in &mut self.enforce {
+            method.make_ascii_uppercase();
+        }
+    }
+}
+
+// Whether the first rule matching `path` exempts `method`
+pub fn exempt(rules: &[MethodRule], method: &str, path: &str) -> bool {
+    let Some(rule) = rules
+        .iter()
+        .find(|rule| path.starts_with(rule.path_prefix.as_str()))
+    else {
+        return false;
+    };
+    SAFE_METHODS.contains(&method) && !rule.enforce.iter().any(|m| m == method)
+}
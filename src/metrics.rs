@@ -0,0 +1,233 @@
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::MetricType;
+
+use crate::grpc_status::{self, CallStatus};
+
+// Handle to an Envoy stat. Definition failures are logged once and the metric
+// becomes a no-op rather than breaking request handling.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Metric(Option<u32>);
+
+impl Metric {
+    fn define(metric_type: MetricType, name: &str) -> Self {
+        match hostcalls::define_metric(metric_type, name) {
+            Ok(id) => Metric(Some(id)),
+            Err(e) => {
+                log::warn!("Failed to define metric '{}': {:?}", name, e);
+                Metric(None)
+            }
+        }
+    }
+
+    // Gauges whose names are only known at runtime (e.g. per worker)
+    pub fn gauge(name: &str) -> Self {
+        Self::define(MetricType::Gauge, name)
+    }
+
+    // Counters named by the configuration (e.g. per rule)
+    pub fn counter(name: &str) -> Self {
+        Self::define(MetricType::Counter, name)
+    }
+
+    // Gauge value or histogram sample
+    pub fn record(self, value: u64) {
+        if let Some(id) = self.0 {
+            let _ = hostcalls::record_metric(id, value);
+        }
+    }
+
+    pub fn increment(self, offset: i64) {
+        if let Some(id) = self.0 {
+            let _ = hostcalls::increment_metric(id, offset);
+        }
+    }
+}
+
+// All stats emitted by the filter, defined once per VM in the root context
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Metrics {
+    pub upstream_header_drops: Metric,
+    // Headers forward-all mode left out to stay within its size cap
+    pub forwarded_header_drops: Metric,
+    // Forwarded header values cut to `header_truncation.max_value_bytes`
+    pub truncated_header_values: Metric,
+    pub audit_dropped: Metric,
+    pub audit_overflow: Metric,
+    pub audit_shipped: Metric,
+    pub audit_ship_failed: Metric,
+    // Denial records sent to / rejected by the audit cluster, or dropped
+    // before dispatch
+    pub denial_audit_sent: Metric,
+    pub denial_audit_failed: Metric,
+    pub denial_audit_dropped: Metric,
+    // Mirrored FilterRequests accepted / rejected by the mirror cluster, or
+    // dropped before dispatch
+    pub mirror_sent: Metric,
+    pub mirror_failed: Metric,
+    pub mirror_dropped: Metric,
+    // Requests decided by another request's identical authz call
+    pub coalesced_requests: Metric,
+    pub suppressed_terminal_actions: Metric,
+    // FilterRequests that could not be serialized
+    pub serialization_failures: Metric,
+    // Client-supplied trusted identity headers removed
+    pub stripped_trusted_headers: Metric,
+    // Requests rejected for reusing a nonce or one-time token
+    pub replay_rejected: Metric,
+    // Requests rejected by the per-principal rate limit
+    pub rate_limited: Metric,
+    // Requests let through because method rules do not enforce their method
+    pub method_exempt: Metric,
+    // Denies and errors let through by monitor-only mode
+    pub unenforced: Metric,
+    // Requests given the failure mode at the concurrency limit
+    pub concurrency_limited: Metric,
+    // Requests queued at the concurrency limit, and those that waited past
+    // the queue timeout
+    pub concurrency_queued: Metric,
+    pub concurrency_queue_expired: Metric,
+    // Requests given the not-ready policy at startup
+    pub not_ready: Metric,
+    // Requests seen while the worker was over its memory budget
+    pub over_memory_budget: Metric,
+    // Authz stream closures, and requests retried as unary calls because of
+    // them
+    pub stream_closed: Metric,
+    pub stream_unary_fallbacks: Metric,
+    // Per-call latency and authn outcomes of parallel authn/authz calls
+    pub authz_leg_latency_ms: Metric,
+    pub authn_leg_latency_ms: Metric,
+    pub authn_denied: Metric,
+    pub authn_failed: Metric,
+    // Authz calls by gRPC status code
+    pub authz_grpc_status: [Metric; grpc_status::CODE_COUNT],
+    // Serialized FilterRequest sizes
+    pub filter_request_bytes: Metric,
+    pub oversized_filter_requests: Metric,
+    // Latency experiment
+    pub unary_latency_ms: Metric,
+    pub shadow_latency_ms: Metric,
+    pub shadow_agree: Metric,
+    pub shadow_disagree: Metric,
+    pub shadow_failed: Metric,
+}
+
+impl Metrics {
+    pub fn define() -> Self {
+        Self {
+            upstream_header_drops: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.upstream_header_budget_drops",
+            ),
+            forwarded_header_drops: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.forward_all_headers.dropped",
+            ),
+            truncated_header_values: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.header_truncation.truncated",
+            ),
+            audit_dropped: Metric::define(MetricType::Counter, "uipbdiauthz.audit_dropped"),
+            audit_overflow: Metric::define(MetricType::Counter, "uipbdiauthz.audit_overflow"),
+            audit_shipped: Metric::define(MetricType::Counter, "uipbdiauthz.audit_shipped"),
+            audit_ship_failed: Metric::define(MetricType::Counter, "uipbdiauthz.audit_ship_failed"),
+            denial_audit_sent: Metric::define(MetricType::Counter, "uipbdiauthz.denial_audit.sent"),
+            denial_audit_failed: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.denial_audit.failed",
+            ),
+            denial_audit_dropped: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.denial_audit.dropped",
+            ),
+            mirror_sent: Metric::define(MetricType::Counter, "uipbdiauthz.mirror.sent"),
+            mirror_failed: Metric::define(MetricType::Counter, "uipbdiauthz.mirror.failed"),
+            mirror_dropped: Metric::define(MetricType::Counter, "uipbdiauthz.mirror.dropped"),
+            coalesced_requests: Metric::define(MetricType::Counter, "uipbdiauthz.coalesced"),
+            serialization_failures: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.serialization_failures",
+            ),
+            suppressed_terminal_actions: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.suppressed_terminal_actions",
+            ),
+            stripped_trusted_headers: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.stripped_trusted_headers",
+            ),
+            replay_rejected: Metric::define(MetricType::Counter, "uipbdiauthz.replay_rejected"),
+            rate_limited: Metric::define(MetricType::Counter, "uipbdiauthz.rate_limited"),
+            method_exempt: Metric::define(MetricType::Counter, "uipbdiauthz.method_exempt"),
+            unenforced: Metric::define(MetricType::Counter, "uipbdiauthz.monitor.unenforced"),
+            concurrency_limited: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.concurrency_limited",
+            ),
+            concurrency_queued: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.concurrency_queued",
+            ),
+            concurrency_queue_expired: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.concurrency_queue_expired",
+            ),
+            not_ready: Metric::define(MetricType::Counter, "uipbdiauthz.not_ready"),
+            over_memory_budget: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.memory_budget.exceeded",
+            ),
+            authz_grpc_status: std::array::from_fn(|code| {
+                let name = CallStatus {
+                    code: code as u32,
+                    message: String::new(),
+                }
+                .name();
+                Metric::counter(&format!("uipbdiauthz.authz.grpc_status.{}", name))
+            }),
+            stream_closed: Metric::define(MetricType::Counter, "uipbdiauthz.stream.closed"),
+            stream_unary_fallbacks: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.stream.unary_fallbacks",
+            ),
+            authz_leg_latency_ms: Metric::define(
+                MetricType::Histogram,
+                "uipbdiauthz.leg.authz.latency_ms",
+            ),
+            authn_leg_latency_ms: Metric::define(
+                MetricType::Histogram,
+                "uipbdiauthz.leg.authn.latency_ms",
+            ),
+            authn_denied: Metric::define(MetricType::Counter, "uipbdiauthz.leg.authn.denied"),
+            authn_failed: Metric::define(MetricType::Counter, "uipbdiauthz.leg.authn.failed"),
+            filter_request_bytes: Metric::define(
+                MetricType::Histogram,
+                "uipbdiauthz.filter_request_bytes",
+            ),
+            oversized_filter_requests: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.oversized_filter_requests",
+            ),
+            unary_latency_ms: Metric::define(
+                MetricType::Histogram,
+                "uipbdiauthz.experiment.unary_latency_ms",
+            ),
+            shadow_latency_ms: Metric::define(
+                MetricType::Histogram,
+                "uipbdiauthz.experiment.shadow_latency_ms",
+            ),
+            shadow_agree: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.experiment.shadow_agree",
+            ),
+            shadow_disagree: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.experiment.shadow_disagree",
+            ),
+            shadow_failed: Metric::define(
+                MetricType::Counter,
+                "uipbdiauthz.experiment.shadow_failed",
+            ),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use log::warn;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::rc::Rc;
+
+// Copies of FilterRequests for traffic analytics and policy development. A
+// sample of the requests sent to the authz service is also POSTed, as the
+// same serialized protobuf, to a separate cluster. Like denial records, the
+// copies are queued by the request context and dispatched from the root tick,
+// so the decision never waits on the mirror and a slow or failing analytics
+// cluster cannot change it.
+
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    pub cluster: String,
+    pub authority: String,
+    pub path: String,
+    // Share (0-100) of authz calls that are mirrored
+    pub sample_percent: u32,
+    pub timeout_ms: u64,
+    // Copies waiting for the root tick beyond this are dropped
+    pub max_queued: usize,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            authority: String::new(),
+            path: "/mirror".into(),
+            sample_percent: 100,
+            timeout_ms: 1000,
+            max_queued: 1000,
+        }
+    }
+}
+
+impl MirrorConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.cluster.is_empty() {
+            return Err("mirror.cluster is required".into());
+        }
+        if self.sample_percent > 100 {
+            return Err("mirror.sample_percent must be at most 100".into());
+        }
+        Ok(())
+    }
+
+    pub fn sampled(&self) -> bool {
+        if self.sample_percent == 0 {
+            return false;
+        }
+        let mut bytes = [0u8; 4];
+        if getrandom::getrandom(&mut bytes).is_err() {
+            return false;
+        }
+        u32::from_le_bytes(bytes) % 100 < self.sample_percent
+    }
+}
+
+// Copies waiting for dispatch (request id, FilterRequest), plus the calls in
+// flight
+#[derive(Debug, Default)]
+pub struct Mirrors {
+    queued: VecDeque<(String, Vec<u8>)>,
+    in_flight: HashSet<u32>,
+}
+
+pub type SharedMirrors = Rc<RefCell<Mirrors>>;
+
+impl Mirrors {
+    // False when the queue is full and the copy was dropped
+    pub fn queue(&mut self, config: &MirrorConfig, request_id: &str, message: &[u8]) -> bool {
+        if self.queued.len() >= config.max_queued {
+            warn!("[MIRROR] Queue full, dropping mirrored request");
+            return false;
+        }
+        self.queued
+            .push_back((request_id.to_string(), message.to_vec()));
+        true
+    }
+
+    pub fn take_queued(&mut self) -> Vec<(String, Vec<u8>)> {
+        self.queued.drain(..).collect()
+    }
+
+    pub fn dispatched(&mut self, token: u32) {
+        self.in_flight.insert(token);
+    }
+
+    // Whether `token` was a mirror call
+    pub fn complete(&mut self, token: u32) -> bool {
+        self.in_flight.remove(&token)
+    }
+}
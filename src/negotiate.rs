@@ -0,0 +1,30 @@
+// SPNEGO / Kerberos (`Authorization: Negotiate`, RFC 4559). The filter does
+// not speak GSS-API itself: the client token is handed to the authz service,
+// which answers with a challenge (and its own token) until the context is
+// established.
+
+const SCHEME: &str = "negotiate ";
+
+pub fn is_negotiate(authorization: &str) -> bool {
+    authorization
+        .get(..SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+}
+
+// Base64 GSS token from an `Authorization: Negotiate <token>` value
+pub fn client_token(authorization: &str) -> Option<String> {
+    if !is_negotiate(authorization) {
+        return None;
+    }
+    let token = authorization[SCHEME.len()..].trim();
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+// WWW-Authenticate value for a challenge, optionally carrying the server token
+pub fn challenge(server_token: &str) -> String {
+    if server_token.is_empty() {
+        "Negotiate".to_string()
+    } else {
+        format!("Negotiate {}", server_token)
+    }
+}
@@ -0,0 +1,72 @@
+use serde::Deserialize;
+
+// Path rewriting, either by normalization before the authz call or directed by
+// the authz service. Whenever the path changes, the client's original path is
+// kept: it goes to the authz service in FilterRequest.original_path and to the
+// upstream in a header, so policy and upstream logs both see what the client
+// actually asked for.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct PathConfig {
+    // Collapse repeated slashes and resolve `.` / `..` segments
+    pub normalize: bool,
+    // Upstream header carrying the original path of a rewritten request
+    pub original_path_header: String,
+    // Audit events show the original path instead of the rewritten one
+    pub audit_original_path: bool,
+    // Query parameters (e.g. `access_token`) removed from the path of allowed
+    // requests before they go upstream; the authz service still sees them
+    pub strip_query_params: Vec<String>,
+}
+
+impl Default for PathConfig {
+    fn default() -> Self {
+        Self {
+            normalize: false,
+            original_path_header: "x-envoy-original-path".into(),
+            audit_original_path: true,
+            strip_query_params: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathRewrite {
+    pub original: String,
+    pub rewritten: String,
+}
+
+// Normalized `:path`; the query string is left untouched
+pub fn normalize(path: &str) -> String {
+    let (path_only, query) = match path.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (path, None),
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path_only.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = String::with_capacity(path.len());
+    normalized.push('/');
+    normalized.push_str(&segments.join("/"));
+    // Keep a trailing slash (`/a/` and `/a` may route differently)
+    let trailing =
+        path_only.ends_with('/') || path_only.ends_with("/.") || path_only.ends_with("/..");
+    if trailing && !segments.is_empty() {
+        normalized.push('/');
+    }
+    if let Some(query) = query {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    normalized
+}
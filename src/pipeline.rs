@@ -0,0 +1,1179 @@
+use log::{info, warn};
+
+use crate::api_key::ApiKeyConfig;
+use crate::authority_policy;
+use crate::authz_session::{self, AuthzSession, AuthzSessionConfig};
+use crate::basic_auth;
+use crate::bypass;
+use crate::challenge::ChallengeConfig;
+use crate::client_ip::{ClientIpConfig, Verdict};
+use crate::concurrency::ConcurrencyLimitConfig;
+use crate::config::{FailureMode, PluginConfig};
+use crate::correlation::{self, CorrelationIdConfig};
+use crate::cors::{self, CorsConfig, PreflightMode};
+use crate::credentials::{MissingCredentialsConfig, StripCredentialsConfig};
+use crate::dry_run::DryRunConfig;
+use crate::expr::{self, ExprRule, RuleAction};
+use crate::extensions;
+use crate::grpc_downstream::{self, GrpcTarget};
+use crate::health;
+use crate::identity_headers;
+use crate::identity_signature::{self, IdentitySigningConfig};
+use crate::jwks::{self, KeySet};
+use crate::limits::RequestLimitsConfig;
+use crate::memory_budget::MemoryBudgetConfig;
+use crate::method_rules;
+use crate::negotiate;
+use crate::oidc::{self, LoginState, OidcConfig, Session, TokenResponse};
+use crate::path::{self, PathRewrite};
+use crate::query;
+use crate::rate_limit::{Admission, RateLimitConfig};
+use crate::readiness::{self, NotReadyPolicy};
+use crate::replay::{Claim, ReplayConfig};
+use crate::shared_codec;
+use crate::signature::{SignatureConfig, SignatureMode, Verification};
+use crate::tenant::TenantRoutingConfig;
+use crate::trace::{self, TraceConfig};
+use crate::uipbdiauthz::FilterResponse;
+use crate::upgrade;
+use crate::upstream_headers::{self, UpstreamHeaders};
+
+// Host-independent request evaluation. AuthEngine feeds it data read from
+// Envoy and turns the resulting Step into host calls; the fixture runner feeds
+// it synthetic requests and compares the Step with the expected outcome.
+
+pub trait RequestSource {
+    fn header(&self, name: &str) -> Option<String>;
+    // All values of a list header sent more than once, joined with `, `
+    fn header_list(&self, name: &str) -> Option<String>;
+    // Number of request headers and their total size
+    fn header_totals(&self) -> (usize, usize);
+    // Downstream connection's peer address (`source.address`)
+    fn source_address(&self) -> Option<String>;
+    // Envoy attribute, e.g. ["connection", "mtls"]
+    fn property(&self, path: &[&str]) -> Option<Vec<u8>>;
+    fn shared_data(&self, key: &str) -> Option<Vec<u8>>;
+    fn now_secs(&self) -> u64;
+    // Entry with its CAS token, for read-modify-write updates
+    fn shared_data_cas(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>);
+    // False when the entry changed since it was read
+    fn update_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool;
+    // Memory the worker VM uses (see memory_budget.rs)
+    fn memory_used(&self) -> u64;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    // Answer the downstream request locally
+    Respond(LocalResponse),
+    // Let the request through without (further) remote calls
+    Allow,
+    // Redeem an OIDC authorization code at the token endpoint
+    ExchangeCode { code: String, return_to: String },
+    // Ask the remote authz service
+    Authorize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalResponse {
+    pub status: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl LocalResponse {
+    pub fn new(status: u32, body: &str) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: (!body.is_empty()).then(|| body.as_bytes().to_vec()),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// Request mutations accumulated while evaluating a request
+#[derive(Debug, Default)]
+pub struct Evaluation {
+    // Request headers to overwrite before the authz call
+    pub request_headers: Vec<(&'static str, String)>,
+    // Headers to add to the upstream request once the decision is made
+    pub upstream_headers: UpstreamHeaders,
+    // Identity the request was authorized as
+    pub principal: Option<String>,
+    // Username presented with `Authorization: Basic`
+    pub basic_auth_user: Option<String>,
+    // Request headers to remove before forwarding upstream
+    pub strip_upstream_headers: Vec<String>,
+    // Service/method when the downstream request is gRPC
+    pub grpc_target: Option<GrpcTarget>,
+    // Protocol of an upgrade request (WebSocket handshake)
+    pub upgrade: Option<String>,
+    // Client token presented with `Authorization: Negotiate`
+    pub negotiate_token: Option<String>,
+    // Set once `:path` was rewritten (normalization or authz verdict)
+    pub path_rewrite: Option<PathRewrite>,
+    // Correlation id to return on deny responses
+    pub echo_correlation_id: Option<String>,
+    // Rejected for reusing a nonce or one-time token
+    pub replay_rejected: bool,
+    // Rejected for exceeding its principal's rate limit
+    pub rate_limited: bool,
+    // Normalized client IP, sent to the authz service
+    pub client_ip: Option<String>,
+    // Index of the authority policy the request falls under
+    pub policy: Option<usize>,
+    // Authz cluster of the request's tenant
+    pub tenant_cluster: Option<String>,
+    // Index of the path bypass rule that let the request through
+    pub bypass_rule: Option<usize>,
+    // Let through because its method is not enforced on the path
+    pub method_exempt: bool,
+    // Decision is recorded but not enforced
+    pub monitor_only: bool,
+    // Trusted dry-run request: return the decision in response headers
+    pub dry_run: bool,
+    // Holds a slot of the concurrency limit until the authz call is answered
+    pub call_slot: bool,
+    // Got the failure mode because the concurrency limit was reached
+    pub concurrency_limited: bool,
+    // At the concurrency limit, to wait in the worker's queue for a slot
+    pub call_queued: bool,
+    // Got the not-ready policy: the authz cluster has not answered a probe
+    pub not_ready: bool,
+    // The worker was over its memory budget
+    pub over_memory_budget: bool,
+    // Headers for the client's response, from authz directives
+    pub response_headers: Vec<(&'static str, String)>,
+    // Set-Cookie for the client's response (authz session)
+    pub set_cookie: Option<String>,
+    // Replaces the client's `authorization` header upstream (token exchange)
+    pub upstream_authorization: Option<String>,
+}
+
+impl Evaluation {
+    // Record a new `:path`, keeping the client's original across rewrites
+    pub fn rewrite_path(&mut self, current: &str, rewritten: String) {
+        if current == rewritten {
+            return;
+        }
+        let original = self
+            .path_rewrite
+            .take()
+            .map_or_else(|| current.to_string(), |rewrite| rewrite.original);
+        info!("[PATH] Rewriting '{}' to '{}'", current, rewritten);
+        self.path_rewrite = Some(PathRewrite {
+            original,
+            rewritten,
+        });
+    }
+
+    // Add the correlation id to a local deny
+    fn echo_correlation_id(&self, step: Step) -> Step {
+        match (step, self.echo_correlation_id.as_deref()) {
+            (Step::Respond(response), Some(id)) if response.status >= 400 => {
+                Step::Respond(response.with_header(correlation::HEADER, id))
+            }
+            (step, _) => step,
+        }
+    }
+
+    // `:path` as it will be forwarded
+    pub fn effective_path<'a>(&'a self, path: &'a str) -> &'a str {
+        self.path_rewrite
+            .as_ref()
+            .map_or(path, |rewrite| rewrite.rewritten.as_str())
+    }
+}
+
+// Everything that happens before the remote authz call
+pub fn evaluate_request(
+    config: &PluginConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Step {
+    evaluation.monitor_only = config.monitor_only;
+    if let Some(rollout) = config.rollout.as_ref() {
+        let request_id = source.header(&rollout.header);
+        evaluation.monitor_only |= !rollout.enforced(request_id.as_deref());
+    }
+    if let Some(dry_run) = config.dry_run.as_ref() {
+        evaluate_dry_run(dry_run, source, evaluation);
+    }
+    if let Some(correlation_id) = config.correlation_id.as_ref() {
+        evaluate_correlation_id(correlation_id, source, evaluation);
+    }
+    if !config.policies.is_empty() {
+        let authority = source.header(":authority").unwrap_or_default();
+        evaluation.policy = authority_policy::select(&config.policies, &authority);
+    }
+    let mut step = local_checks(config, source, evaluation);
+    if let (Step::Allow | Step::Authorize, Some(rate_limit)) = (&step, config.rate_limit.as_ref()) {
+        if let Some(limited) = evaluate_rate_limit(rate_limit, source, evaluation) {
+            step = limited;
+        }
+    }
+    if let (Step::Authorize, Some(budget)) = (&step, config.memory_budget.as_ref()) {
+        if let Some(over) = evaluate_memory_budget(budget, source, evaluation) {
+            step = over;
+        }
+    }
+    // Last, so only requests that do dispatch take a slot
+    if let (Step::Authorize, Some(limit)) = (&step, config.concurrency_limit.as_ref()) {
+        if let Some(limited) = evaluate_concurrency(config, limit, source, evaluation) {
+            step = limited;
+        }
+    }
+    if let (Step::Allow, Some(signing)) = (&step, config.identity_signing.as_ref()) {
+        sign_identity(signing, source, evaluation);
+    }
+    if let (Step::Allow, Some(strip)) = (&step, config.strip_credentials.as_ref()) {
+        strip_credentials(strip, config, source, evaluation);
+    }
+    evaluation.echo_correlation_id(step)
+}
+
+fn local_checks(
+    config: &PluginConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Step {
+    let content_type = source.header("content-type");
+    let path = source.header(":path").unwrap_or_default();
+    if let Some(limits) = config.request_limits.as_ref() {
+        if let Some(step) = evaluate_limits(limits, source, &path) {
+            return step;
+        }
+    }
+    if let Some(tenant_routing) = config.tenant_routing.as_ref() {
+        if let Some(step) = evaluate_tenant(tenant_routing, source, evaluation) {
+            return step;
+        }
+    }
+    if config.path.normalize {
+        evaluation.rewrite_path(&path, path::normalize(&path));
+    }
+    evaluation.grpc_target = grpc_downstream::parse_target(content_type.as_deref(), &path);
+
+    if let Some(trace) = config.trace.as_ref() {
+        evaluate_trace(trace, source, evaluation);
+    }
+
+    if let Some(client_ip) = config.client_ip.as_ref() {
+        if let Some(step) = evaluate_client_ip(client_ip, source, evaluation) {
+            return step;
+        }
+    }
+
+    if let Some(cors) = config.cors.as_ref() {
+        if cors::is_preflight(source) {
+            return evaluate_preflight(cors, source);
+        }
+    }
+
+    evaluation.upgrade = upgrade::protocol(source);
+    if let Some(protocol) = evaluation.upgrade.as_deref() {
+        if config.upgrade.deny {
+            warn!("[UPGRADE] Denying '{}' upgrade request", protocol);
+            return Step::Respond(LocalResponse::new(403, "Forbidden"));
+        }
+        info!("[UPGRADE] Authorizing '{}' handshake", protocol);
+    }
+
+    let effective_path = evaluation.effective_path(&path);
+    if let Some(index) = bypass::matching_rule(&config.path_bypass, effective_path) {
+        info!(
+            "[BYPASS] '{}' matches rule '{}', skipping the authz call",
+            effective_path, config.path_bypass[index].name
+        );
+        evaluation.bypass_rule = Some(index);
+        return Step::Allow;
+    }
+
+    let method = source.header(":method").unwrap_or_default();
+    if method_rules::exempt(&config.method_rules, &method, effective_path) {
+        info!(
+            "[METHOD] {} is not enforced on '{}', skipping the authz call",
+            method, effective_path
+        );
+        evaluation.method_exempt = true;
+        return Step::Allow;
+    }
+
+    if let Some(step) = evaluate_rules(&config.rules, source, effective_path, evaluation) {
+        return step;
+    }
+
+    if let Some(replay) = config.replay_protection.as_ref() {
+        if let Some(step) = evaluate_one_time_tokens(replay, source, evaluation) {
+            return step;
+        }
+    }
+
+    if let Some(oidc) = config.oidc.as_ref() {
+        if let Some(step) = evaluate_oidc(oidc, source, evaluation) {
+            return step;
+        }
+    }
+
+    if let Some(api_keys) = config.api_keys.as_ref() {
+        if let Some(step) = evaluate_api_key(api_keys, source, evaluation) {
+            return step;
+        }
+    }
+
+    if let Some(signing) = config.request_signing.as_ref() {
+        let replay = config.replay_protection.as_ref();
+        if let Some(step) = evaluate_signature(signing, replay, source, evaluation) {
+            return step;
+        }
+    }
+
+    // After replay protection and signatures, so a session cookie cannot
+    // stand in for a spent one-time token or a bad signature
+    if let Some(session) = config.authz_session.as_ref() {
+        if let Some(step) = evaluate_authz_session(session, source, &path, evaluation) {
+            return step;
+        }
+    }
+
+    if let Some(missing) = config.reject_missing_credentials.as_ref() {
+        if let Some(step) = evaluate_missing_credentials(missing, config, source) {
+            return step;
+        }
+    }
+
+    if config
+        .jwks
+        .as_ref()
+        .is_some_and(|jwks| jwks.reject_unknown_kid)
+    {
+        if let Some(step) = evaluate_token_kid(source) {
+            return step;
+        }
+    }
+
+    if let Some(authorization) = source.header("authorization") {
+        if basic_auth::is_basic(&authorization) {
+            evaluation.basic_auth_user = basic_auth::username(&authorization);
+            if config.basic_auth.strip_credentials {
+                evaluation
+                    .strip_upstream_headers
+                    .push("authorization".into());
+            }
+        }
+        evaluation.negotiate_token = negotiate::client_token(&authorization);
+    }
+
+    if let Some(readiness) = config.readiness.as_ref() {
+        if !readiness::is_ready(source) {
+            warn!(
+                "[READY] Authz cluster not ready, applying {:?}",
+                readiness.not_ready
+            );
+            evaluation.not_ready = true;
+            return match readiness.not_ready {
+                NotReadyPolicy::Deny => {
+                    Step::Respond(LocalResponse::new(503, "Service Unavailable"))
+                }
+                NotReadyPolicy::Bypass => Step::Allow,
+            };
+        }
+    }
+
+    if let Some(health_check) = config.health_check.as_ref() {
+        let state = source.shared_data(health::STATE_KEY);
+        if health_check.known_down(state.as_deref(), source.now_secs() * 1000) {
+            warn!("[HEALTH] Authz cluster is down, skipping the authz call");
+            return failure_step(config.failure_mode);
+        }
+    }
+
+    Step::Authorize
+}
+
+fn evaluate_concurrency(
+    config: &PluginConfig,
+    limit: &ConcurrencyLimitConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    match limit.acquire(source) {
+        Some(true) => evaluation.call_slot = true,
+        Some(false) if limit.queue_size > 0 => evaluation.call_queued = true,
+        Some(false) => {
+            warn!(
+                "[CONCURRENCY] {} authz calls outstanding, applying failure mode {:?}",
+                limit.max_in_flight, config.failure_mode
+            );
+            evaluation.concurrency_limited = true;
+            return Some(failure_step(config.failure_mode));
+        }
+        None => {}
+    }
+    None
+}
+
+fn evaluate_memory_budget(
+    budget: &MemoryBudgetConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let used = source.memory_used();
+    if !budget.exceeded(used) {
+        return None;
+    }
+    evaluation.over_memory_budget = true;
+    if !budget.fail_open {
+        return None;
+    }
+    warn!(
+        "[MEMORY-BUDGET] {} bytes in use, over the {} byte budget; allowing without the authz call",
+        used, budget.max_bytes
+    );
+    Some(Step::Allow)
+}
+
+fn evaluate_dry_run(
+    config: &DryRunConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) {
+    match config.requested(source) {
+        Some(true) => {
+            info!("[DRY-RUN] Deciding request without enforcing");
+            evaluation.monitor_only = true;
+            evaluation.dry_run = true;
+        }
+        Some(false) => warn!(
+            "[DRY-RUN] Ignoring '{}' from outside the trusted networks",
+            config.header
+        ),
+        None => {}
+    }
+}
+
+fn evaluate_tenant(
+    config: &TenantRoutingConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let (tenant, cluster) = config.route(source);
+    if cluster.is_none() && config.reject_unknown {
+        warn!(
+            "[TENANT] No authz cluster for tenant {:?}, rejecting",
+            tenant
+        );
+        return Some(Step::Respond(LocalResponse::new(403, "Forbidden")));
+    }
+    evaluation.tenant_cluster = cluster.map(str::to_string);
+    None
+}
+
+fn evaluate_client_ip(
+    config: &ClientIpConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let ip = config.client_ip(source);
+    evaluation.client_ip = ip.map(|ip| ip.to_string());
+    match config.evaluate(ip) {
+        Verdict::Denied => {
+            info!("[CLIENT-IP] Denying client {:?}", evaluation.client_ip);
+            Some(Step::Respond(LocalResponse::new(403, "Forbidden")))
+        }
+        Verdict::Allowlisted => {
+            info!(
+                "[CLIENT-IP] Allowing allowlisted client {:?} without the authz call",
+                evaluation.client_ip
+            );
+            Some(Step::Allow)
+        }
+        Verdict::Undecided => None,
+    }
+}
+
+fn evaluate_preflight(config: &CorsConfig, source: &dyn RequestSource) -> Step {
+    match config.mode {
+        PreflightMode::Bypass => {
+            info!("[CORS] Forwarding preflight without the authz call");
+            Step::Allow
+        }
+        PreflightMode::Respond => {
+            let response = config.preflight_response(source);
+            info!("[CORS] Answering preflight with {}", response.status);
+            Step::Respond(response)
+        }
+    }
+}
+
+// First matching expression rule; `enforce` ends the rules without a step
+fn evaluate_rules(
+    rules: &[ExprRule],
+    source: &dyn RequestSource,
+    path: &str,
+    evaluation: &Evaluation,
+) -> Option<Step> {
+    let request = expr::Request {
+        source,
+        path,
+        client_ip: evaluation.client_ip.as_deref(),
+    };
+    let rule = rules.iter().find(|rule| rule.matches(&request))?;
+    info!("[RULES] Rule '{}' matched ({:?})", rule.name, rule.action);
+    match rule.action {
+        RuleAction::Bypass => Some(Step::Allow),
+        RuleAction::Deny => Some(Step::Respond(LocalResponse::new(rule.status, "Forbidden"))),
+        RuleAction::Enforce => None,
+    }
+}
+
+fn evaluate_missing_credentials(
+    missing: &MissingCredentialsConfig,
+    config: &PluginConfig,
+    source: &dyn RequestSource,
+) -> Option<Step> {
+    if missing.has_credentials(config, source) {
+        return None;
+    }
+    info!("[CREDENTIALS] Rejecting request without credentials");
+    let response = missing.challenges.iter().fold(
+        LocalResponse::new(401, "Unauthorized"),
+        |response, challenge| response.with_header("WWW-Authenticate", challenge),
+    );
+    Some(Step::Respond(response))
+}
+
+fn evaluate_limits(
+    limits: &RequestLimitsConfig,
+    source: &dyn RequestSource,
+    path: &str,
+) -> Option<Step> {
+    let violation = limits.check(source.header_totals(), path)?;
+    warn!("[LIMITS] Rejecting oversized request: {:?}", violation);
+    let (status, body) = violation.response();
+    Some(Step::Respond(LocalResponse::new(status, body)))
+}
+
+// Give the request a correlation id unless the client sent one; the header
+// is set before the authz call, so FilterRequest and upstream both carry it
+fn evaluate_correlation_id(
+    config: &CorrelationIdConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) {
+    let id = match source.header(correlation::HEADER) {
+        Some(id) if !id.is_empty() => id,
+        _ => {
+            let Some(id) = correlation::generate(source.now_secs() * 1000) else {
+                warn!("[CORRELATION] No randomness available, request keeps no correlation id");
+                return;
+            };
+            info!("[CORRELATION] Generated correlation id {}", id);
+            evaluation
+                .request_headers
+                .push((correlation::HEADER, id.clone()));
+            id
+        }
+    };
+    if config.echo_on_deny {
+        evaluation.echo_correlation_id = Some(id);
+    }
+}
+
+// Start a trace for requests without a usable trace context; the new
+// `traceparent` reaches both the authz call and the upstream
+fn evaluate_trace(config: &TraceConfig, source: &dyn RequestSource, evaluation: &mut Evaluation) {
+    let current = source.header(trace::TRACEPARENT);
+    if current.as_deref().is_some_and(trace::is_valid) {
+        return;
+    }
+    let Some(traceparent) = trace::generate(config) else {
+        warn!("[TRACE] No randomness available, request stays untraced");
+        return;
+    };
+    info!("[TRACE] Starting trace {}", traceparent);
+    if current.is_some() {
+        // tracestate belongs to the discarded trace
+        evaluation
+            .strip_upstream_headers
+            .push(trace::TRACESTATE.into());
+    }
+    evaluation
+        .request_headers
+        .push((trace::TRACEPARENT, traceparent));
+}
+
+// What the request gets when no authz verdict can be obtained
+pub fn failure_step(failure_mode: FailureMode) -> Step {
+    match failure_mode {
+        FailureMode::Allow => Step::Allow,
+        FailureMode::Deny => Step::Respond(LocalResponse::new(503, "Service Unavailable")),
+    }
+}
+
+// Apply the remote authz verdict
+pub fn evaluate_decision(
+    config: &PluginConfig,
+    source: &dyn RequestSource,
+    reply: &FilterResponse,
+    path: &str,
+    evaluation: &mut Evaluation,
+) -> Step {
+    let requested = evaluation.effective_path(path).to_string();
+    let step = apply_verdict(reply, &config.challenge, path, evaluation);
+    let step = extensions::apply(&reply.extensions, step, evaluation);
+    if let (Step::Allow, Some(session)) = (&step, config.authz_session.as_ref()) {
+        issue_authz_session(session, source, path, requested, evaluation);
+    }
+    if let (Step::Allow, Some(signing)) = (&step, config.identity_signing.as_ref()) {
+        sign_identity(signing, source, evaluation);
+    }
+    if let (Step::Allow, Some(strip)) = (&step, config.strip_credentials.as_ref()) {
+        strip_credentials(strip, config, source, evaluation);
+    }
+    evaluation.echo_correlation_id(step)
+}
+
+// Session cookie for a request the authz service allowed, holding the
+// identity headers to restore on the requests that present it
+fn issue_authz_session(
+    config: &AuthzSessionConfig,
+    source: &dyn RequestSource,
+    path: &str,
+    requested: String,
+    evaluation: &mut Evaluation,
+) {
+    let headers = evaluation
+        .upstream_headers
+        .iter()
+        .filter(|addition| {
+            addition
+                .name
+                .starts_with(identity_headers::ATTRIBUTE_PREFIX)
+        })
+        .filter(|addition| addition.name != identity_signature::HEADER)
+        .map(|addition| (addition.name.to_string(), addition.value.clone()))
+        .collect();
+    let authorization = source.header("authorization");
+    let rewritten = evaluation.effective_path(path);
+    let path_rewrite = (rewritten != requested).then(|| (requested.clone(), rewritten.to_string()));
+    let session = AuthzSession {
+        user: evaluation.principal.clone().unwrap_or_default(),
+        headers,
+        authority: source.header(":authority").unwrap_or_default(),
+        method: source.header(":method").unwrap_or_default(),
+        route: authz_session::route(source, &requested),
+        credential: authz_session::credential_digest(authorization.as_deref()),
+        upstream_authorization: evaluation.upstream_authorization.clone(),
+        path_rewrite,
+        exp: source.now_secs() + config.ttl_secs,
+    };
+    match config.issue(&session) {
+        Some(cookie) => evaluation.set_cookie = Some(cookie),
+        None => warn!("[SESSION] Failed to seal the authz session cookie"),
+    }
+}
+
+// Keep the end-user credentials of an allowed request from the upstream
+fn strip_credentials(
+    strip: &StripCredentialsConfig,
+    config: &PluginConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) {
+    for name in strip.present(config, source) {
+        if !evaluation.strip_upstream_headers.iter().any(|s| s == name) {
+            evaluation.strip_upstream_headers.push(name.to_string());
+        }
+    }
+}
+
+// Sign the identity header of an allowed request
+fn sign_identity(
+    signing: &IdentitySigningConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) {
+    let Some(user) = evaluation
+        .upstream_headers
+        .iter()
+        .find(|addition| addition.name == identity_signature::SIGNED_HEADER)
+        .map(|addition| addition.value.clone())
+    else {
+        return;
+    };
+    let request_id = source.header("x-request-id").unwrap_or_default();
+    match signing.sign(&user, source.now_secs(), &request_id) {
+        Some(signature) => evaluation.upstream_headers.add(
+            identity_signature::HEADER,
+            signature,
+            upstream_headers::PRIORITY_IDENTITY,
+        ),
+        None => warn!("[IDENTITY] Could not sign the identity header"),
+    }
+}
+
+fn apply_verdict(
+    reply: &FilterResponse,
+    challenge: &ChallengeConfig,
+    path: &str,
+    evaluation: &mut Evaluation,
+) -> Step {
+    let response_message = reply.message.as_str();
+
+    if !reply.allow && reply.negotiate {
+        info!(
+            "[NEGOTIATE] Challenging client, message={}",
+            response_message
+        );
+        let challenge = negotiate::challenge(&reply.negotiate_token);
+        return Step::Respond(
+            LocalResponse::new(401, "Unauthorized").with_header("WWW-Authenticate", &challenge),
+        );
+    }
+
+    add_quota_headers(reply, evaluation);
+    if !reply.allow && reply.quota_remaining == Some(0) {
+        info!("[QUOTA] Quota exhausted, message={}", response_message);
+        let mut response = LocalResponse::new(429, "Too Many Requests");
+        if let Some(retry_after) = reply.retry_after {
+            response = response.with_header("retry-after", &retry_after.to_string());
+        }
+        return Step::Respond(response);
+    }
+
+    if !reply.allow {
+        info!("Access denied: allow=false, message={}", response_message);
+        return Step::Respond(
+            LocalResponse::new(401, "Unauthorized")
+                .with_header("WWW-Authenticate", &challenge.header(response_message)),
+        );
+    }
+
+    if !reply.rewrite_path.is_empty() {
+        let current = evaluation.effective_path(path).to_string();
+        evaluation.rewrite_path(&current, reply.rewrite_path.clone());
+    }
+
+    let user = reply.attributes.get("user").unwrap_or(&reply.user);
+    let user = identity_headers::sanitize_value(user);
+    evaluation.principal = Some(user.clone());
+    evaluation.upstream_headers.add(
+        "x-uip-user",
+        get_value_or_space(&user).to_string(),
+        upstream_headers::PRIORITY_IDENTITY,
+    );
+    info!("Set user header: '{}'", user);
+    add_attribute_headers(reply, evaluation);
+
+    let token = identity_headers::sanitize_value(&reply.upstream_token);
+    if !token.is_empty() {
+        info!("[TOKEN-EXCHANGE] Replacing the authorization header upstream");
+        evaluation.upstream_authorization = Some(format!("Bearer {}", token));
+    }
+
+    Step::Allow
+}
+
+// X-RateLimit-* headers for the client's response; extensions::apply moves
+// them onto a deny
+fn add_quota_headers(reply: &FilterResponse, evaluation: &mut Evaluation) {
+    let headers = [
+        ("x-ratelimit-limit", reply.quota_limit),
+        ("x-ratelimit-remaining", reply.quota_remaining),
+        ("x-ratelimit-reset", reply.retry_after),
+    ];
+    for (name, value) in headers {
+        if let Some(value) = value {
+            evaluation.response_headers.push((name, value.to_string()));
+        }
+    }
+}
+
+// `x-uip-<name>` upstream headers for the verdict's identity attributes, in
+// name order; `user` is sent as `x-uip-user` above
+fn add_attribute_headers(reply: &FilterResponse, evaluation: &mut Evaluation) {
+    let mut attributes: Vec<_> = reply
+        .attributes
+        .iter()
+        .filter(|(name, _)| name.as_str() != "user")
+        .collect();
+    attributes.sort();
+    for (name, value) in attributes {
+        let header = identity_headers::attribute_header(name)
+            .filter(|header| header != identity_signature::SIGNED_HEADER)
+            .filter(|header| header != identity_signature::HEADER);
+        let Some(header) = header else {
+            warn!("[IDENTITY] Ignoring attribute with invalid name '{}'", name);
+            continue;
+        };
+        let value = identity_headers::sanitize_value(value);
+        if value.is_empty() {
+            continue;
+        }
+        evaluation
+            .upstream_headers
+            .add(header, value, upstream_headers::PRIORITY_ATTRIBUTE);
+    }
+}
+
+// Turn the token endpoint response into the session cookie redirect
+pub fn complete_login(
+    oidc: &OidcConfig,
+    status: Option<&str>,
+    body: Option<&[u8]>,
+    return_to: &str,
+    now: u64,
+) -> LocalResponse {
+    let token = match (status, body) {
+        (Some("200"), Some(body)) => serde_json::from_slice::<TokenResponse>(body).ok(),
+        _ => None,
+    };
+
+    let Some(token) = token else {
+        warn!("[OIDC] Code exchange failed with status {:?}", status);
+        return LocalResponse::new(502, "Bad Gateway");
+    };
+
+    let ttl = token
+        .expires_in
+        .map_or(oidc.session_ttl_secs, |expires_in| {
+            expires_in.min(oidc.session_ttl_secs)
+        });
+    let session = Session {
+        sub: token.subject().unwrap_or_default(),
+        access_token: token.access_token,
+        exp: now + ttl,
+    };
+
+    let Some(sealed) = oidc.seal(&oidc.cookie_name, &session) else {
+        warn!("[OIDC] Failed to seal session cookie");
+        return LocalResponse::new(500, "Internal Server Error");
+    };
+
+    info!("[OIDC] Login complete for subject '{}'", session.sub);
+    LocalResponse::new(302, "")
+        .with_header("location", return_to)
+        .with_header("set-cookie", &oidc.cookie(&oidc.cookie_name, &sealed, ttl))
+        .with_header("set-cookie", &oidc.cookie(&oidc.state_cookie_name(), "", 0))
+}
+
+// Use string slice instead of returning reference - more efficient for empty check
+fn get_value_or_space(value: &str) -> &str {
+    if value.trim().is_empty() {
+        " " // Return single space for null/empty values
+    } else {
+        value
+    }
+}
+
+fn request_cookie<T: serde::de::DeserializeOwned>(
+    source: &dyn RequestSource,
+    oidc: &OidcConfig,
+    name: &str,
+) -> Option<T> {
+    let cookies = source.header("cookie")?;
+    oidc.open(name, oidc::find_cookie(&cookies, name)?)
+}
+
+// OIDC browser login. Returns a step when the filter answers the request
+// itself (IdP redirect, callback handling or an error response).
+fn evaluate_oidc(
+    oidc: &OidcConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let path = source.header(":path").unwrap_or_default();
+    let now = source.now_secs();
+
+    if query::path_without_query(&path) == oidc.callback_path() {
+        return Some(evaluate_oidc_callback(oidc, source, &path, now));
+    }
+
+    let has_authorization = source.header("authorization").is_some();
+
+    let session = request_cookie::<Session>(source, oidc, &oidc.cookie_name)
+        .filter(|session| session.exp > now);
+    if let Some(session) = session {
+        info!("[OIDC] Valid session for subject '{}'", session.sub);
+        // Hand the session token to the authz call like any other bearer token
+        if !has_authorization {
+            let bearer = format!("Bearer {}", session.access_token);
+            evaluation.request_headers.push(("authorization", bearer));
+        }
+        return None;
+    }
+
+    let method = source.header(":method").unwrap_or_default();
+    let accept = source.header("accept");
+    if has_authorization || !oidc::is_browser_request(&method, accept.as_deref()) {
+        return None;
+    }
+
+    let state_cookie = oidc.state_cookie_name();
+    let login = LoginState::new(&path, now)
+        .and_then(|login| Some((oidc.seal(&state_cookie, &login)?, login.state)));
+    let response = match login {
+        Some((sealed, state)) => {
+            info!("[OIDC] Redirecting unauthenticated browser request to IdP");
+            let cookie = oidc.cookie(&state_cookie, &sealed, oidc::LOGIN_STATE_TTL_SECS);
+            LocalResponse::new(302, "")
+                .with_header("location", &oidc.authorize_url(&state))
+                .with_header("set-cookie", &cookie)
+        }
+        None => {
+            warn!("[OIDC] Failed to create login state");
+            LocalResponse::new(500, "Internal Server Error")
+        }
+    };
+    Some(Step::Respond(response))
+}
+
+fn evaluate_oidc_callback(
+    oidc: &OidcConfig,
+    source: &dyn RequestSource,
+    path: &str,
+    now: u64,
+) -> Step {
+    let state_cookie = oidc.state_cookie_name();
+    let login =
+        request_cookie::<LoginState>(source, oidc, &state_cookie).filter(|login| login.exp > now);
+    let code = query::query_param(path, "code");
+    let state = query::query_param(path, "state");
+
+    match (login, code, state) {
+        (Some(login), Some(code), Some(state)) if login.state == state => Step::ExchangeCode {
+            code,
+            return_to: login.return_to,
+        },
+        _ => {
+            warn!("[OIDC] Rejecting callback: missing code or state mismatch");
+            Step::Respond(LocalResponse::new(400, "Bad Request"))
+        }
+    }
+}
+
+// Requests with a valid authz session cookie are allowed with its identity
+fn evaluate_authz_session(
+    config: &AuthzSessionConfig,
+    source: &dyn RequestSource,
+    path: &str,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let current = evaluation.effective_path(path).to_string();
+    let route = authz_session::route(source, &current);
+    let session = config.validate(source, &route, source.now_secs())?;
+    // A rewrite holds for the path it was given for, not the whole route
+    if let Some((requested, rewritten)) = session.path_rewrite {
+        if requested != current {
+            info!(
+                "[SESSION] Authz session rewrites '{}', not '{}', calling the authz service",
+                requested, current
+            );
+            return None;
+        }
+        evaluation.rewrite_path(&current, rewritten);
+    }
+    info!(
+        "[SESSION] Valid authz session for '{}', skipping the authz call",
+        session.user
+    );
+    for (name, value) in session.headers {
+        let priority = if name == identity_signature::SIGNED_HEADER {
+            upstream_headers::PRIORITY_IDENTITY
+        } else {
+            upstream_headers::PRIORITY_ATTRIBUTE
+        };
+        evaluation.upstream_headers.add(name, value, priority);
+    }
+    evaluation.principal = Some(session.user);
+    evaluation.upstream_authorization = session.upstream_authorization;
+    Some(Step::Allow)
+}
+
+// API-key authentication. Returns a step when a key was presented: valid keys
+// skip the remote call, unknown keys are rejected outright.
+fn evaluate_api_key(
+    api_keys: &ApiKeyConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let path = source.header(":path").unwrap_or_default();
+    let key = api_keys.extract(source.header(&api_keys.header), &path)?;
+
+    let shared_keys = api_keys
+        .shared_data_key
+        .as_deref()
+        .and_then(|name| source.shared_data(name));
+
+    match api_keys.lookup(&key, shared_keys.as_deref()) {
+        Some(principal) => {
+            info!("[API-KEY] Authenticated principal '{}'", principal);
+            evaluation.principal = Some(principal.clone());
+            evaluation.upstream_headers.add(
+                "x-uip-user",
+                principal,
+                upstream_headers::PRIORITY_IDENTITY,
+            );
+            Some(Step::Allow)
+        }
+        None => {
+            warn!("[API-KEY] Rejecting request with unknown API key");
+            Some(Step::Respond(LocalResponse::new(401, "Unauthorized")))
+        }
+    }
+}
+
+// HMAC request signatures. Returns a step when the request is rejected or, in
+// verify-only mode, authorized by the signature alone.
+fn evaluate_signature(
+    signing: &SignatureConfig,
+    replay: Option<&ReplayConfig>,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    match signing.verify(|name| source.header(name), source.now_secs()) {
+        Verification::Missing if !signing.required => None,
+        Verification::Missing => {
+            warn!("[SIGNATURE] Rejecting unsigned request");
+            Some(Step::Respond(LocalResponse::new(401, "Unauthorized")))
+        }
+        Verification::Invalid(reason) => {
+            warn!("[SIGNATURE] Rejecting request: {}", reason);
+            Some(Step::Respond(LocalResponse::new(401, "Unauthorized")))
+        }
+        Verification::Valid {
+            key_id,
+            nonce,
+            timestamp,
+        } => {
+            info!("[SIGNATURE] Valid signature from key '{}'", key_id);
+            if let Some(replay) = replay {
+                let expires_at = timestamp + signing.clock_skew_secs;
+                let step = evaluate_nonce(replay, source, evaluation, &key_id, nonce, expires_at);
+                if step.is_some() {
+                    return step;
+                }
+            }
+            if signing.mode == SignatureMode::VerifyThenAuthorize {
+                return None;
+            }
+            evaluation.principal = Some(key_id.clone());
+            evaluation.upstream_headers.add(
+                "x-uip-user",
+                key_id,
+                upstream_headers::PRIORITY_IDENTITY,
+            );
+            Some(Step::Allow)
+        }
+    }
+}
+
+// Applies to requests that would otherwise go ahead, whether allowed locally
+// or sent to the authz service
+fn evaluate_rate_limit(
+    rate_limit: &RateLimitConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let principal = evaluation.principal.as_deref();
+    match rate_limit.admit(source, principal)? {
+        Admission::Admitted => None,
+        Admission::Limited { retry_after } => {
+            warn!(
+                "[RATE-LIMIT] Rejecting request over the limit of {}",
+                principal.unwrap_or("its credentials")
+            );
+            evaluation.rate_limited = true;
+            Some(Step::Respond(
+                LocalResponse::new(429, "Too Many Requests")
+                    .with_header("retry-after", &retry_after.to_string()),
+            ))
+        }
+    }
+}
+
+// Signature nonces are unique per key id; a signature can only be replayed
+// within its clock skew window, so that is how long the nonce is kept
+fn evaluate_nonce(
+    replay: &ReplayConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+    key_id: &str,
+    nonce: Option<String>,
+    expires_at: u64,
+) -> Option<Step> {
+    let Some(nonce) = nonce else {
+        if !replay.require_nonce {
+            return None;
+        }
+        warn!("[REPLAY] Rejecting signed request without a nonce");
+        return Some(Step::Respond(LocalResponse::new(401, "Unauthorized")));
+    };
+    let claim = replay.claim(source, &format!("signature:{}", key_id), &nonce, expires_at);
+    replay_step(claim, "signature nonce", evaluation)
+}
+
+fn evaluate_one_time_tokens(
+    replay: &ReplayConfig,
+    source: &dyn RequestSource,
+    evaluation: &mut Evaluation,
+) -> Option<Step> {
+    let expires_at = source.now_secs() + replay.token_ttl_secs;
+    for header in &replay.one_time_token_headers {
+        let Some(token) = source.header(header) else {
+            continue;
+        };
+        let scope = format!("token:{}", header.to_ascii_lowercase());
+        let claim = replay.claim(source, &scope, &token, expires_at);
+        if let Some(step) = replay_step(claim, "one-time token", evaluation) {
+            return Some(step);
+        }
+    }
+    None
+}
+
+fn replay_step(claim: Claim, what: &str, evaluation: &mut Evaluation) -> Option<Step> {
+    match claim {
+        Claim::First => None,
+        Claim::Replay => {
+            warn!("[REPLAY] Rejecting request reusing a {}", what);
+            evaluation.replay_rejected = true;
+            Some(Step::Respond(LocalResponse::new(401, "Unauthorized")))
+        }
+        Claim::Unavailable => {
+            warn!("[REPLAY] Could not record {}, rejecting request", what);
+            Some(Step::Respond(LocalResponse::new(
+                503,
+                "Service Unavailable",
+            )))
+        }
+    }
+}
+
+// Cheap pre-check against the background-refreshed key set: a bearer JWT
+// signed with a key the issuer does not publish cannot be valid
+fn evaluate_token_kid(source: &dyn RequestSource) -> Option<Step> {
+    let authorization = source.header("authorization")?;
+    let token = authorization
+        .strip_prefix("Bearer ")
+        .or_else(|| authorization.strip_prefix("bearer "))?;
+    let kid = jwks::token_kid(token.trim())?;
+
+    let keys = source.shared_data(jwks::KEYS_KEY)?;
+    let keys: KeySet = shared_codec::from_bytes(&keys)?;
+    if keys.contains_kid(&kid) {
+        return None;
+    }
+
+    warn!("[JWKS] Rejecting token signed with unknown key '{}'", kid);
+    Some(Step::Respond(
+        LocalResponse::new(401, "Unauthorized")
+            .with_header("WWW-Authenticate", "Bearer error=\"invalid_token\""),
+    ))
+}
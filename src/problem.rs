@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+// RFC 7807 problem details for local error responses, for API clients that
+// parse error bodies. The plain-text body a response would have carried
+// becomes `detail` when it says more than the status phrase. gRPC clients
+// keep their trailers-only responses.
+
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProblemDetailsConfig {
+    // URI prefix for `type`, followed by the status code; empty uses
+    // "about:blank"
+    pub type_base: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    kind: String,
+    title: &'a str,
+    status: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<&'a str>,
+}
+
+fn title(status: u32) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        414 => "URI Too Long",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ if status < 500 => "Client Error",
+        _ => "Server Error",
+    }
+}
+
+impl ProblemDetailsConfig {
+    // Problem document for an error response with the given plain-text body
+    pub fn render(&self, status: u32, body: &[u8], correlation_id: Option<&str>) -> Vec<u8> {
+        let title = title(status);
+        let detail = std::str::from_utf8(body)
+            .ok()
+            .filter(|detail| !detail.is_empty() && *detail != title);
+        let problem = Problem {
+            kind: if self.type_base.is_empty() {
+                "about:blank".to_string()
+            } else {
+                format!("{}{}", self.type_base, status)
+            },
+            title,
+            status,
+            detail,
+            correlation_id,
+        };
+        serde_json::to_vec(&problem).unwrap_or_default()
+    }
+}
@@ -0,0 +1,122 @@
+// Helpers for the `:path` query string and URL (percent) encoding
+
+// Returns the decoded value of a query parameter from a `:path`
+pub fn query_param(path: &str, name: &str) -> Option<String> {
+    let (_, query) = path.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| url_decode(value))
+    })
+}
+
+// Decoded query parameters of a `:path`, in order and with repeats
+pub fn query_params(path: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    let query = path.split_once('?').map_or("", |(_, query)| query);
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (url_decode(key), url_decode(value))
+        })
+}
+
+// `:path` without the parameters whose decoded name is in `names`; the other
+// parameters keep their encoding and order. None when nothing was removed
+pub fn strip_params(path: &str, names: &[String]) -> Option<String> {
+    let (path_only, query) = path.split_once('?')?;
+    let mut removed = false;
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split_once('=').map_or(*pair, |(key, _)| key);
+            let sensitive = !pair.is_empty() && names.iter().any(|name| *name == url_decode(key));
+            removed |= sensitive;
+            !sensitive
+        })
+        .collect();
+    if !removed {
+        return None;
+    }
+    let query = kept.join("&");
+    Some(if query.is_empty() {
+        path_only.to_string()
+    } else {
+        format!("{}?{}", path_only, query)
+    })
+}
+
+pub fn path_without_query(path: &str) -> &str {
+    path.split_once('?').map_or(path, |(p, _)| p)
+}
+
+pub fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn query_params_keep_order_and_repeats() {
+        let params: Vec<_> = super::query_params("/search?q=a+b&tag=x&tag=y%2Fz&flag&").collect();
+        let params: Vec<_> = params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            params,
+            [("q", "a b"), ("tag", "x"), ("tag", "y/z"), ("flag", "")]
+        );
+        assert_eq!(super::query_params("/plain").count(), 0);
+    }
+
+    #[test]
+    fn sensitive_query_params_are_stripped() {
+        use super::strip_params;
+        let names = vec!["access_token".to_string()];
+        assert_eq!(
+            strip_params("/feed?access_token=abc&page=2&q=a%20b", &names).as_deref(),
+            Some("/feed?page=2&q=a%20b")
+        );
+        // Matched by decoded name, every occurrence
+        assert_eq!(
+            strip_params("/feed?access%5Ftoken=abc&access_token=def", &names).as_deref(),
+            Some("/feed")
+        );
+        assert_eq!(strip_params("/feed?page=2", &names), None);
+        assert_eq!(strip_params("/feed", &names), None);
+    }
+}
@@ -0,0 +1,141 @@
+use log::warn;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::pipeline::RequestSource;
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+
+// Per-principal request limits, enforced before the authz call and whatever
+// its verdict. Each principal has a counter in shared data, updated with CAS
+// so all workers share it. The sliding window is approximated from two fixed
+// windows: the previous window's count is weighted by how much of it still
+// overlaps the sliding window. Requests are keyed by the locally authenticated
+// principal, or else by a hash of their Authorization header; requests
+// without either are not limited.
+
+const KEY_PREFIX: &str = "uipbdiauthz.rate_limit.";
+const CAS_RETRIES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub requests_per_window: u64,
+    pub window_secs: u64,
+    // Principal -> limit replacing `requests_per_window`
+    pub overrides: HashMap<String, u64>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_window: 100,
+            window_secs: 60,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    Admitted,
+    // Seconds until the oldest counted requests start leaving the window
+    Limited { retry_after: u64 },
+}
+
+impl RateLimitConfig {
+    // Count a request against its rate-limit key; None when the request has
+    // nothing to key it by
+    pub fn admit(&self, source: &dyn RequestSource, principal: Option<&str>) -> Option<Admission> {
+        let (key, limit) = match principal {
+            Some(principal) => (
+                format!("principal:{}", principal),
+                self.overrides
+                    .get(principal)
+                    .copied()
+                    .unwrap_or(self.requests_per_window),
+            ),
+            None => (
+                format!("token:{}", token_hash(&source.header("authorization")?)),
+                self.requests_per_window,
+            ),
+        };
+        let key = format!("{}{}", KEY_PREFIX, key);
+        let now = source.now_secs();
+        let window = now / self.window_secs;
+        let elapsed = now % self.window_secs;
+
+        for _ in 0..CAS_RETRIES {
+            let (bytes, cas) = source.shared_data_cas(&key);
+            let mut counts: WindowCounts = bytes
+                .as_deref()
+                .and_then(shared_codec::from_bytes)
+                .unwrap_or_default();
+            counts.roll(window);
+
+            let overlap = self.window_secs - elapsed;
+            let estimate = counts.previous * overlap / self.window_secs + counts.current;
+            if estimate >= limit {
+                return Some(Admission::Limited {
+                    retry_after: overlap.max(1),
+                });
+            }
+
+            counts.current += 1;
+            if source.update_shared_data(&key, &shared_codec::to_bytes(&counts), cas) {
+                return Some(Admission::Admitted);
+            }
+        }
+        // Contention only loses the count, never the request
+        warn!("[RATE-LIMIT] Could not update '{}', admitting request", key);
+        Some(Admission::Admitted)
+    }
+}
+
+fn token_hash(authorization: &str) -> String {
+    Sha256::digest(authorization.as_bytes())[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Request counts of the current and previous fixed windows
+#[derive(Debug, Default, PartialEq)]
+pub struct WindowCounts {
+    // now / window_secs
+    pub window: u64,
+    pub current: u64,
+    pub previous: u64,
+}
+
+impl WindowCounts {
+    fn roll(&mut self, window: u64) {
+        if self.window == window {
+            return;
+        }
+        self.previous = if self.window + 1 == window {
+            self.current
+        } else {
+            0
+        };
+        self.current = 0;
+        self.window = window;
+    }
+}
+
+impl SharedValue for WindowCounts {
+    const KIND: u8 = shared_codec::KIND_RATE_LIMIT_WINDOW;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.u64(self.window).u64(self.current).u64(self.previous);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            window: input.u64()?,
+            current: input.u64()?,
+            previous: input.u64()?,
+        })
+    }
+}
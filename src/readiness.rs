@@ -0,0 +1,99 @@
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::health;
+use crate::pipeline::RequestSource;
+use crate::shared_codec;
+use crate::shared_counter::{self, Counter};
+
+// Gating of traffic at startup. A VM only serves requests once its
+// configuration loaded (Envoy applies the plugin's `fail_open` to traffic of a
+// plugin whose configuration is rejected), but the authz cluster may not be
+// reachable yet. Until it has answered a probe, requests that would call it
+// get the `not_ready` policy rather than a failed dispatch and `failure_mode`.
+// The probe is a grpc.health.v1.Health/Check from the root tick; once one
+// succeeds on any worker, every worker is ready for the life of the VM.
+
+pub const READY_KEY: &str = "uipbdiauthz.ready";
+
+// gRPC status of a server without the health service: it answered, so the
+// cluster is reachable
+const UNIMPLEMENTED: u32 = 12;
+
+// What requests get while the authz cluster is not ready
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotReadyPolicy {
+    // 503 Service Unavailable
+    #[default]
+    Deny,
+    // Let the request through without an authz call
+    Bypass,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReadinessConfig {
+    pub not_ready: NotReadyPolicy,
+    pub probe_interval_ms: u64,
+    pub timeout_ms: u64,
+    // Service name sent in HealthCheckRequest; empty checks the whole server
+    pub service: String,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            not_ready: NotReadyPolicy::Deny,
+            probe_interval_ms: 1000,
+            timeout_ms: 1000,
+            service: String::new(),
+        }
+    }
+}
+
+impl ReadinessConfig {
+    pub fn init(&self) -> Result<(), String> {
+        if self.probe_interval_ms == 0 {
+            return Err("readiness.probe_interval_ms must be positive".into());
+        }
+        Ok(())
+    }
+
+    pub fn request(&self) -> Vec<u8> {
+        health::check_request(&self.service)
+    }
+}
+
+pub fn is_ready(source: &dyn RequestSource) -> bool {
+    source
+        .shared_data(READY_KEY)
+        .as_deref()
+        .and_then(shared_codec::from_bytes::<Counter>)
+        .is_some_and(|ready| ready.0 > 0)
+}
+
+// Whether the root context still has to probe
+pub fn pending() -> bool {
+    shared_counter::read(READY_KEY).0 == 0
+}
+
+// Fold a probe answer in; SERVING, or a server without the health service,
+// makes every worker ready
+pub fn record_probe(status_code: u32, response: Option<&[u8]>) {
+    let answered = match status_code {
+        0 => response.is_some_and(health::is_serving),
+        UNIMPLEMENTED => true,
+        _ => false,
+    };
+    if !answered {
+        warn!(
+            "[READY] Authz cluster not ready yet (grpc status {})",
+            status_code
+        );
+        return;
+    }
+    if shared_counter::write(READY_KEY, 1, None) {
+        info!("[READY] Authz cluster answered, serving traffic");
+    }
+}
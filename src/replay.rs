@@ -0,0 +1,117 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::pipeline::RequestSource;
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+
+// Replay protection for signed and one-time-token requests. Every signature
+// nonce and one-time token is remembered in shared data until it expires, so
+// a second request carrying the same value is rejected locally, on any
+// worker, before the authz call. Values are hashed into a fixed number of
+// slots (one shared data entry each) holding fingerprints and expiry times;
+// expired fingerprints are dropped whenever a slot is written, which keeps
+// shared data bounded by request rate times TTL.
+
+const SLOT_KEY_PREFIX: &str = "uipbdiauthz.nonce.";
+const CAS_RETRIES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    // Reject signed requests that carry no nonce
+    pub require_nonce: bool,
+    // Request headers carrying one-time tokens
+    pub one_time_token_headers: Vec<String>,
+    // How long a one-time token is remembered; signature nonces are kept
+    // until their timestamp leaves the clock skew window
+    pub token_ttl_secs: u64,
+    pub slots: u64,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            require_nonce: false,
+            one_time_token_headers: Vec::new(),
+            token_ttl_secs: 3600,
+            slots: 1024,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    // First use, now recorded
+    First,
+    Replay,
+    // The slot kept changing under us; the value could not be recorded
+    Unavailable,
+}
+
+impl ReplayConfig {
+    // Record `value` (unique within `scope`) until `expires_at` (unix secs)
+    pub fn claim(
+        &self,
+        source: &dyn RequestSource,
+        scope: &str,
+        value: &str,
+        expires_at: u64,
+    ) -> Claim {
+        let fingerprint = fingerprint(scope, value);
+        let key = format!("{}{}", SLOT_KEY_PREFIX, fingerprint % self.slots);
+        let now = source.now_secs();
+
+        for _ in 0..CAS_RETRIES {
+            let (bytes, cas) = source.shared_data_cas(&key);
+            let mut slot: NonceSlot = bytes
+                .as_deref()
+                .and_then(shared_codec::from_bytes)
+                .unwrap_or_default();
+            slot.entries.retain(|&(_, expiry)| expiry > now);
+            if slot.entries.iter().any(|&(seen, _)| seen == fingerprint) {
+                return Claim::Replay;
+            }
+            slot.entries.push((fingerprint, expires_at));
+            if source.update_shared_data(&key, &shared_codec::to_bytes(&slot), cas) {
+                return Claim::First;
+            }
+        }
+        Claim::Unavailable
+    }
+}
+
+fn fingerprint(scope: &str, value: &str) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(scope.as_bytes())
+        .chain_update([0])
+        .chain_update(value.as_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+}
+
+// (fingerprint, expires_at) of the values recorded in one slot
+#[derive(Debug, Default, PartialEq)]
+pub struct NonceSlot {
+    pub entries: Vec<(u64, u64)>,
+}
+
+impl SharedValue for NonceSlot {
+    const KIND: u8 = shared_codec::KIND_NONCE_SLOT;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.u64(self.entries.len() as u64);
+        for &(fingerprint, expires_at) in &self.entries {
+            out.fixed_u64(fingerprint).u64(expires_at);
+        }
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        let count = input.u64()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push((input.fixed_u64()?, input.u64()?));
+        }
+        Some(Self { entries })
+    }
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Calls a request context makes from a root context callback (the root tick
+// dispatching for requests that were waiting). The SDK hands a call's answer
+// to the context whose callback made the call, so these answers reach the
+// root context, which passes them on to the request that made the call.
+
+pub struct Reroutes<T> {
+    // Token -> (context id, request context)
+    calls: HashMap<u32, (u32, T)>,
+}
+
+pub type SharedReroutes<T> = Rc<RefCell<Reroutes<T>>>;
+
+impl<T> Default for Reroutes<T> {
+    fn default() -> Self {
+        Self {
+            calls: HashMap::new(),
+        }
+    }
+}
+
+impl<T> Reroutes<T> {
+    pub fn add(&mut self, token: u32, context_id: u32, engine: T) {
+        self.calls.insert(token, (context_id, engine));
+    }
+
+    pub fn take(&mut self, token: u32) -> Option<(u32, T)> {
+        self.calls.remove(&token)
+    }
+
+    // The request went away; its answers are dropped
+    pub fn remove_context(&mut self, context_id: u32) {
+        self.calls.retain(|_, (id, _)| *id != context_id);
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// Gradual rollout of enforcement: only `enforce_percent` of requests are
+// enforced, the rest are decided in monitor-only mode. The share is picked by
+// hashing the request id, so retries and every filter on a request's path
+// agree. Requests without an id are enforced.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RolloutConfig {
+    // 0-100
+    pub enforce_percent: u32,
+    // Header holding the request id
+    pub header: String,
+}
+
+impl Default for RolloutConfig {
+    fn default() -> Self {
+        Self {
+            enforce_percent: 100,
+            header: "x-request-id".to_string(),
+        }
+    }
+}
+
+impl RolloutConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        if self.enforce_percent > 100 {
+            return Err("rollout.enforce_percent must be at most 100".into());
+        }
+        self.header.make_ascii_lowercase();
+        Ok(())
+    }
+
+    pub fn enforced(&self, request_id: Option<&str>) -> bool {
+        let Some(request_id) = request_id.filter(|id| !id.is_empty()) else {
+            return true;
+        };
+        let digest = Sha256::digest(request_id.as_bytes());
+        let bucket = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) % 100;
+        bucket < self.enforce_percent
+    }
+}
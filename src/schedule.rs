@@ -0,0 +1,32 @@
+// Background jobs of the root context share one tick; each job keeps its own
+// interval and runs on the first tick at or after it is due.
+
+#[derive(Debug, Default)]
+pub struct Interval {
+    period_ms: u64,
+    next_ms: u64,
+}
+
+impl Interval {
+    pub fn new(period_ms: u64) -> Self {
+        Self {
+            period_ms,
+            next_ms: 0,
+        }
+    }
+
+    pub fn due(&mut self, now_ms: u64) -> bool {
+        if now_ms < self.next_ms {
+            return false;
+        }
+        self.next_ms = now_ms + self.period_ms;
+        true
+    }
+}
+
+// Tick period: the configured one, else the shortest job interval
+pub fn tick_period_ms(configured: Option<u64>, job_periods: &[u64]) -> Option<u64> {
+    configured
+        .or_else(|| job_periods.iter().copied().min())
+        .map(|ms| ms.max(1))
+}
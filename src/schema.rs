@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::pipeline::Evaluation;
+use crate::uipbdiauthz::{Connection, FilterResponse, Identity, TlsInfo};
+
+// FilterRequest / FilterResponse schema version 2. v2 only adds fields, so
+// the v1 fields are still filled in and v1 services keep working; a v2
+// request carries `version: 2` for services that want to branch on it.
+// Replies of either version are accepted: a v2 reply's identity is folded
+// into the v1 `user` and `headers` the rest of the filter works with.
+
+pub const VERSION: u32 = 2;
+
+// Credentials the filter read from the request, if any
+pub fn identity(evaluation: &Evaluation) -> Option<Identity> {
+    let (user, mechanism) = if let Some(user) = evaluation.basic_auth_user.as_ref() {
+        (user.clone(), "basic")
+    } else if evaluation.negotiate_token.is_some() {
+        (String::new(), "negotiate")
+    } else {
+        return None;
+    };
+    Some(Identity {
+        user,
+        mechanism: mechanism.to_string(),
+        ..Default::default()
+    })
+}
+
+pub fn connection(
+    client_ip: Option<&str>,
+    peer_address: Option<String>,
+    tls: Option<TlsInfo>,
+) -> Connection {
+    let peer_address = peer_address.unwrap_or_default();
+    let client_ip = match client_ip {
+        Some(client_ip) => client_ip.to_string(),
+        None => crate::client_ip::parse_address(&peer_address)
+            .map(|address| address.to_string())
+            .unwrap_or_default(),
+    };
+    Connection {
+        client_ip,
+        peer_address,
+        tls,
+    }
+}
+
+// The reply in v1 terms: a v2 identity's user replaces `user`, its groups
+// become the comma-separated `groups` header and its claims further headers
+// (headers the service set itself take precedence)
+pub fn normalize(mut reply: FilterResponse) -> FilterResponse {
+    let Some(identity) = reply.identity.take() else {
+        return reply;
+    };
+    if reply.version < VERSION {
+        return reply;
+    }
+    if !identity.user.is_empty() {
+        reply.user = identity.user;
+    }
+    let mut headers: HashMap<String, String> = identity.claims;
+    if !identity.groups.is_empty() {
+        headers.insert("groups".to_string(), identity.groups.join(","));
+    }
+    for (name, value) in headers {
+        reply.headers.entry(name).or_insert(value);
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn v2_replies_fold_into_v1_fields() {
+        use super::normalize;
+        use crate::uipbdiauthz::{FilterResponse, Identity};
+        use prost::Message;
+
+        let v2 = FilterResponse {
+            allow: true,
+            user: "legacy".into(),
+            headers: [("groups".to_string(), "admins".to_string())].into(),
+            version: 2,
+            identity: Some(Identity {
+                user: "alice".into(),
+                groups: vec!["dev".into(), "ops".into()],
+                claims: [("tenant".to_string(), "acme".to_string())].into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let reply = normalize(FilterResponse::decode(v2.encode_to_vec().as_slice()).unwrap());
+        assert_eq!(reply.user, "alice");
+        assert_eq!(reply.headers["groups"], "admins");
+        assert_eq!(reply.headers["tenant"], "acme");
+        assert!(reply.identity.is_none());
+
+        // v1 replies are left alone
+        let v1 = FilterResponse {
+            allow: true,
+            user: "bob".into(),
+            ..Default::default()
+        };
+        assert_eq!(normalize(v1.clone()), v1);
+    }
+}
@@ -0,0 +1,133 @@
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use prost::encoding::{encode_key, encode_varint, encoded_len_varint, key_len, WireType};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Per-request scratch region. Data that only lives while a request builds its
+// FilterRequest (the list of headers picked for it) is allocated by bumping a
+// pointer in chunks the worker keeps between requests, instead of as many
+// small heap allocations that fragment the heap of a long-lived wasm VM. The
+// region is reset when the next request arrives; if a request grew it far
+// beyond the usual size, the chunks are released instead.
+//
+// Header names and values are borrowed from the request's header snapshot,
+// so picking headers copies no strings at all.
+
+pub type SharedScratch = Rc<RefCell<Scratch>>;
+
+#[derive(Debug, Default)]
+pub struct Scratch(Bump);
+
+impl Scratch {
+    pub fn reset(&mut self) {
+        if self.0.allocated_bytes() > RETAIN_BYTES {
+            self.0 = Bump::new();
+        } else {
+            self.0.reset();
+        }
+    }
+
+    // Give all chunks back to the allocator (memory budget exceeded)
+    pub fn release(&mut self) {
+        self.0 = Bump::new();
+    }
+
+    pub fn headers<'a>(&'a self) -> HeaderList<'a> {
+        HeaderList(BumpVec::with_capacity_in(HEADER_CAPACITY, &self.0))
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        self.0.allocated_bytes()
+    }
+}
+
+// FilterRequest.headers as (key, value) pairs
+pub struct HeaderList<'a>(BumpVec<'a, (&'a str, &'a str)>);
+
+impl<'a> HeaderList<'a> {
+    pub fn push(&mut self, key: &'a str, value: &'a str) {
+        self.0.push((key, value));
+    }
+
+    // Copy a string that is not borrowed from the request (renamed header,
+    // joined values) into the scratch region
+    pub fn alloc(&self, value: &str) -> &'a str {
+        self.0.bump().alloc_str(value)
+    }
+
+    // Total size of names and values
+    pub fn bytes(&self) -> usize {
+        self.iter()
+            .map(|(key, value)| key.len() + value.len())
+            .sum()
+    }
+
+    // Replace the values of the entries from `start` on that `truncate`
+    // shortens; returns how many it did
+    pub fn truncate_values(
+        &mut self,
+        start: usize,
+        truncate: impl Fn(&str) -> Option<String>,
+    ) -> usize {
+        let bump = self.0.bump();
+        let mut truncated = 0;
+        for (_, value) in self.0.iter_mut().skip(start) {
+            if let Some(shorter) = truncate(value) {
+                *value = bump.alloc_str(&shorter);
+                truncated += 1;
+            }
+        }
+        truncated
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.0.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(&'a str, &'a str)> {
+        self.0.iter()
+    }
+
+    // Append the entries as FilterRequest field 1, encoded exactly like
+    // `repeated Header headers = 1`
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        for &(key, value) in self.iter() {
+            encode_key(HEADERS_TAG, WireType::LengthDelimited, buf);
+            encode_varint((string_len(1, key) + string_len(2, value)) as u64, buf);
+            encode_string(1, key, buf);
+            encode_string(2, value, buf);
+        }
+    }
+}
+
+const HEADERS_TAG: u32 = 1;
+// 4 pseudo + 9 default headers; grows for configured ones
+const HEADER_CAPACITY: usize = 16;
+// Chunk size kept between requests
+const RETAIN_BYTES: usize = 16 * 1024;
+
+// proto3 leaves empty strings off the wire
+fn string_len(tag: u32, value: &str) -> usize {
+    if value.is_empty() {
+        return 0;
+    }
+    key_len(tag) + encoded_len_varint(value.len() as u64) + value.len()
+}
+
+fn encode_string(tag: u32, value: &str, buf: &mut Vec<u8>) {
+    if value.is_empty() {
+        return;
+    }
+    encode_key(tag, WireType::LengthDelimited, buf);
+    encode_varint(value.len() as u64, buf);
+    buf.extend_from_slice(value.as_bytes());
+}
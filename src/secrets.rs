@@ -0,0 +1,56 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+
+// Key material referenced from the plugin config. A key setting is one of
+//
+//   <base64>              the key itself
+//   env:NAME              VM environment variable (`vm_config.environment_variables`)
+//   property:a/b/c        Envoy property, read through the property API
+//
+// Inline and environment keys are decoded once at configure time. Property
+// keys keep the key material out of the filter config: the control plane
+// serves them from its secret store as xDS metadata (e.g.
+// `property:xds/listener_metadata/filter_metadata/uip.secrets/partner-a`),
+// and each worker re-reads them on tick, so a rotated key takes effect
+// without a config push. Path segments are separated by '/' because metadata
+// namespaces contain dots.
+
+const PROPERTY_PREFIX: &str = "property:";
+
+// Property path of a `property:` reference
+pub fn property_path(value: &str) -> Option<Vec<&str>> {
+    value
+        .strip_prefix(PROPERTY_PREFIX)
+        .map(|path| path.split('/').filter(|s| !s.is_empty()).collect())
+}
+
+// Inline or `env:` key; None for property references, which are resolved
+// later by the root context. `label` names the setting in errors.
+pub fn decode_static(label: &str, value: &str) -> Result<Option<Vec<u8>>, String> {
+    if property_path(value).is_some() {
+        return Ok(None);
+    }
+    let encoded = match value.strip_prefix("env:") {
+        Some(var) => std::env::var(var).map_err(|_| format!("{}: ${} is not set", label, var))?,
+        None => value.to_string(),
+    };
+    decode(label, &encoded).map(Some)
+}
+
+// Key read from a property reference; `property` is the host property lookup
+pub fn resolve_property(
+    label: &str,
+    value: &str,
+    property: &dyn Fn(Vec<&str>) -> Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    let path = property_path(value).unwrap_or_default();
+    let bytes = property(path).ok_or_else(|| format!("{}: {} is not set", label, value))?;
+    let encoded = String::from_utf8(bytes).map_err(|_| format!("{} is not UTF-8", label))?;
+    decode(label, &encoded)
+}
+
+fn decode(label: &str, encoded: &str) -> Result<Vec<u8>, String> {
+    STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("{} is not valid base64: {}", label, e))
+}
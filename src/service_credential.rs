@@ -0,0 +1,155 @@
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+use crate::shared_counter;
+
+// Credential the filter itself presents to the authz service, e.g. a bearer
+// token on top of mTLS. The root context fetches it from a token endpoint
+// (OAuth2 client-credentials style) before it expires and stores it in shared
+// data; every authz call reads the stored value and sends it as gRPC initial
+// metadata. A failed fetch keeps the current credential until it expires.
+
+pub const CREDENTIAL_KEY: &str = "uipbdiauthz.service_credential";
+// Until when (unix secs) a worker holds the right to refresh the credential
+const LEASE_KEY: &str = "uipbdiauthz.service_credential.lease";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ServiceCredentialConfig {
+    pub cluster: String,
+    pub authority: String,
+    pub path: String,
+    // Token request body, e.g. `grant_type=client_credentials&scope=authz`
+    pub body: String,
+    pub content_type: String,
+    pub timeout_ms: u64,
+    // Refresh this long before the credential expires
+    pub refresh_before_expiry_secs: u64,
+    // Lifetime assumed when the token response has no `expires_in`
+    pub default_lifetime_secs: u64,
+    // gRPC metadata entry the credential is sent in
+    pub metadata_key: String,
+    pub metadata_prefix: String,
+}
+
+impl Default for ServiceCredentialConfig {
+    fn default() -> Self {
+        Self {
+            cluster: String::new(),
+            authority: String::new(),
+            path: "/oauth2/token".into(),
+            body: "grant_type=client_credentials".into(),
+            content_type: "application/x-www-form-urlencoded".into(),
+            timeout_ms: 5000,
+            refresh_before_expiry_secs: 60,
+            default_lifetime_secs: 300,
+            metadata_key: "authorization".into(),
+            metadata_prefix: "Bearer ".into(),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Credential {
+    pub token: String,
+    pub fetched_at: u64,
+    pub expires_at: u64,
+}
+
+impl SharedValue for Credential {
+    const KIND: u8 = shared_codec::KIND_SERVICE_CREDENTIAL;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.bytes(self.token.as_bytes())
+            .u64(self.fetched_at)
+            .u64(self.expires_at);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            token: String::from_utf8(input.bytes()?.to_vec()).ok()?,
+            fetched_at: input.u64()?,
+            expires_at: input.u64()?,
+        })
+    }
+}
+
+impl ServiceCredentialConfig {
+    // Whether this worker should fetch now: the stored credential is missing
+    // or about to expire and no other worker holds the refresh lease
+    pub fn should_refresh(&self, now: u64) -> bool {
+        let (current, _) = shared_codec::get::<Credential>(CREDENTIAL_KEY);
+        if current.is_some_and(|c| now + self.refresh_before_expiry_secs < c.expires_at) {
+            return false;
+        }
+
+        let (lease_until, cas) = shared_counter::read(LEASE_KEY);
+        if now < lease_until {
+            return false;
+        }
+        let lease_secs = self.timeout_ms.div_ceil(1000) + 1;
+        shared_counter::write(LEASE_KEY, now + lease_secs, cas)
+    }
+
+    // Store a freshly fetched token response
+    pub fn store(&self, status: Option<&str>, body: Option<&[u8]>, now: u64) {
+        let Some((token, lifetime)) = body
+            .filter(|_| status == Some("200"))
+            .and_then(parse_token_response)
+        else {
+            warn!(
+                "[CREDENTIAL] Refresh failed with status {:?}, keeping current credential",
+                status
+            );
+            return;
+        };
+
+        let credential = Credential {
+            token,
+            fetched_at: now,
+            expires_at: now + lifetime.unwrap_or(self.default_lifetime_secs),
+        };
+        // Last writer wins; the lease keeps concurrent refreshes rare
+        match shared_codec::set(CREDENTIAL_KEY, &credential, None) {
+            Ok(()) => info!(
+                "[CREDENTIAL] Refreshed service credential, expires at {}",
+                credential.expires_at
+            ),
+            Err(e) => warn!("[CREDENTIAL] Failed to store service credential: {:?}", e),
+        }
+    }
+
+    // Metadata entry for an authz call; None until a credential is available
+    // or once it has expired
+    pub fn metadata(&self, now: u64) -> Option<(&str, String)> {
+        let (credential, _) = shared_codec::get::<Credential>(CREDENTIAL_KEY);
+        match credential {
+            Some(c) if now < c.expires_at => Some((
+                self.metadata_key.as_str(),
+                format!("{}{}", self.metadata_prefix, c.token),
+            )),
+            _ => {
+                warn!("[CREDENTIAL] No valid service credential, calling authz without it");
+                None
+            }
+        }
+    }
+}
+
+// (access_token, expires_in) from an OAuth2 token response
+fn parse_token_response(body: &[u8]) -> Option<(String, Option<u64>)> {
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: String,
+        #[serde(default)]
+        expires_in: Option<u64>,
+    }
+
+    let response: TokenResponse = serde_json::from_slice(body).ok()?;
+    if response.access_token.is_empty() {
+        return None;
+    }
+    Some((response.access_token, response.expires_in))
+}
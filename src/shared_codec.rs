@@ -0,0 +1,162 @@
+use proxy_wasm::hostcalls;
+use proxy_wasm::types::Status;
+
+// Compact binary encoding for values kept in Envoy shared data. Shared data
+// outlives a module swap, so during a rolling upgrade old and new filter
+// builds read each other's entries. Every value starts with a small header:
+//
+//   MAGIC | kind | version | payload
+//
+// `kind` identifies the struct, `version` the payload layout. Payloads are
+// append-only: a new version may add fields at the end, never reorder or
+// remove them, so older builds decode the prefix they know and ignore the
+// rest. Integers are LEB128 varints, byte strings are length-prefixed.
+
+const MAGIC: u8 = 0xa5;
+const HEADER_LEN: usize = 3;
+
+// Kinds in use; never reuse a retired number
+pub const KIND_COUNTER: u8 = 1;
+pub const KIND_THROUGHPUT_SNAPSHOT: u8 = 2;
+pub const KIND_JWKS: u8 = 3;
+pub const KIND_HEALTH: u8 = 4;
+pub const KIND_SERVICE_CREDENTIAL: u8 = 5;
+pub const KIND_NONCE_SLOT: u8 = 6;
+pub const KIND_RATE_LIMIT_WINDOW: u8 = 7;
+
+pub trait SharedValue: Sized {
+    // Unique per struct stored in shared data
+    const KIND: u8;
+    // Bumped whenever fields are appended
+    const VERSION: u8;
+
+    fn encode(&self, out: &mut Encoder);
+
+    // `version` is the writer's version; it may be newer than ours
+    fn decode(version: u8, input: &mut Decoder) -> Option<Self>;
+
+    // Entries written before the value had a header
+    fn decode_unversioned(_bytes: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn u64(&mut self, mut value: u64) -> &mut Self {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.buf.push(byte);
+                return self;
+            }
+            self.buf.push(byte | 0x80);
+        }
+    }
+
+    pub fn fixed_u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u64(value.len() as u64);
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.buf.split_first()?;
+        self.buf = rest;
+        Some(byte)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    pub fn fixed_u64(&mut self) -> Option<u64> {
+        let bytes = self.take(8)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        self.take(len)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < len {
+            return None;
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Some(head)
+    }
+}
+
+pub fn to_bytes<T: SharedValue>(value: &T) -> Vec<u8> {
+    let mut out = Encoder::default();
+    out.u8(MAGIC).u8(T::KIND).u8(T::VERSION);
+    value.encode(&mut out);
+    out.into_bytes()
+}
+
+pub fn from_bytes<T: SharedValue>(bytes: &[u8]) -> Option<T> {
+    decode_versioned(bytes).or_else(|| T::decode_unversioned(bytes))
+}
+
+fn decode_versioned<T: SharedValue>(bytes: &[u8]) -> Option<T> {
+    match bytes.get(..HEADER_LEN)? {
+        &[MAGIC, kind, version] if kind == T::KIND => {
+            let mut input = Decoder::new(&bytes[HEADER_LEN..]);
+            T::decode(version, &mut input)
+        }
+        _ => None,
+    }
+}
+
+// Typed shared-data access; undecodable entries read as absent
+pub fn get<T: SharedValue>(key: &str) -> (Option<T>, Option<u32>) {
+    match hostcalls::get_shared_data(key) {
+        Ok((bytes, cas)) => (bytes.as_deref().and_then(from_bytes), cas),
+        Err(_) => (None, None),
+    }
+}
+
+pub fn set<T: SharedValue>(key: &str, value: &T, cas: Option<u32>) -> Result<(), Status> {
+    hostcalls::set_shared_data(key, Some(&to_bytes(value)), cas)
+}
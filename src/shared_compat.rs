@@ -0,0 +1,169 @@
+// Rolling-upgrade compatibility of values kept in shared data. Shared data
+// survives a module swap, so for a while old and new filter builds read each
+// other's entries. Every layout that has ever been written is pinned here as
+// raw bytes: the current build must keep reading them, and entries written by
+// a newer build (fields appended, version bumped) must still decode.
+
+use crate::health::HealthState;
+use crate::jwks::KeySet;
+use crate::rate_limit::WindowCounts;
+use crate::replay::NonceSlot;
+use crate::service_credential::Credential;
+use crate::shared_codec::{self, SharedValue};
+use crate::shared_counter::Counter;
+use crate::throughput::Snapshot;
+
+fn snapshot() -> Snapshot {
+    Snapshot {
+        ts: 1_700_000_000,
+        interval_secs: 10,
+        requests: 120,
+        allow: 100,
+        deny: 15,
+        error: 5,
+        in_flight: 3,
+    }
+}
+
+// What a newer build with one extra trailing field would write
+fn from_newer_build<T: SharedValue>(value: &T) -> Vec<u8> {
+    let mut bytes = shared_codec::to_bytes(value);
+    bytes[2] = T::VERSION + 1;
+    bytes.extend_from_slice(&[0xac, 0x02]);
+    bytes
+}
+
+#[test]
+fn counter_reads_bare_little_endian_entries() {
+    // Written by builds before the versioned header
+    let legacy = 42u64.to_le_bytes();
+    assert_eq!(shared_codec::from_bytes(&legacy), Some(Counter(42)));
+}
+
+#[test]
+fn counter_reads_legacy_value_that_looks_like_a_header() {
+    // Low bytes collide with MAGIC | KIND_COUNTER | version 1
+    let value = u64::from_le_bytes([0xa5, 0x01, 0x01, 0, 0, 0, 0, 0]);
+    let legacy = value.to_le_bytes();
+    assert_eq!(shared_codec::from_bytes(&legacy), Some(Counter(value)));
+}
+
+#[test]
+fn counter_v1_layout_is_stable() {
+    let v1 = [0xa5, 0x01, 0x01, 0x2a, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(shared_codec::to_bytes(&Counter(42)), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(Counter(42)));
+}
+
+#[test]
+fn counter_tolerates_newer_versions() {
+    let bytes = from_newer_build(&Counter(7));
+    assert_eq!(shared_codec::from_bytes(&bytes), Some(Counter(7)));
+}
+
+#[test]
+fn snapshot_reads_json_entries() {
+    // Written by builds that published snapshots as JSON
+    let legacy = br#"{"ts":1700000000,"interval_secs":10,"requests":120,"allow":100,"deny":15,"error":5,"in_flight":3}"#;
+    assert_eq!(shared_codec::from_bytes(legacy), Some(snapshot()));
+}
+
+#[test]
+fn snapshot_v1_layout_is_stable() {
+    let v1 = [
+        0xa5, 0x02, 0x01, 0x80, 0xe2, 0xcf, 0xaa, 0x06, 0x0a, 0x78, 0x64, 0x0f, 0x05, 0x03,
+    ];
+    assert_eq!(shared_codec::to_bytes(&snapshot()), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(snapshot()));
+}
+
+#[test]
+fn snapshot_tolerates_newer_versions() {
+    let bytes = from_newer_build(&snapshot());
+    assert_eq!(shared_codec::from_bytes(&bytes), Some(snapshot()));
+}
+
+#[test]
+fn truncated_entries_read_as_absent() {
+    let bytes = shared_codec::to_bytes(&snapshot());
+    assert_eq!(
+        shared_codec::from_bytes::<Snapshot>(&bytes[..bytes.len() - 1]),
+        None
+    );
+}
+
+#[test]
+fn entries_of_another_kind_are_not_misread() {
+    let counter = shared_codec::to_bytes(&Counter(42));
+    assert_eq!(shared_codec::from_bytes::<Snapshot>(&counter), None);
+}
+
+#[test]
+fn jwks_v1_layout_is_stable() {
+    let set = KeySet {
+        generation: 2,
+        fetched_at: 300,
+        document: b"{}".to_vec(),
+    };
+    let v1 = [0xa5, 0x03, 0x01, 0x02, 0xac, 0x02, 0x02, b'{', b'}'];
+    assert_eq!(shared_codec::to_bytes(&set), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(set));
+}
+
+#[test]
+fn jwks_reads_bare_documents() {
+    let document = br#"{"keys":[{"kid":"a"}]}"#;
+    let set: KeySet = shared_codec::from_bytes(document).unwrap();
+    assert_eq!(set.generation, 0);
+    assert!(set.contains_kid("a"));
+}
+
+#[test]
+fn health_v1_layout_is_stable() {
+    let state = HealthState {
+        healthy: false,
+        consecutive_failures: 3,
+        checked_at_ms: 1_700_000_000_000,
+    };
+    let v1 = [
+        0xa5, 0x04, 0x01, 0x00, 0x03, 0x80, 0xd0, 0x95, 0xff, 0xbc, 0x31,
+    ];
+    assert_eq!(shared_codec::to_bytes(&state), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(state));
+}
+
+#[test]
+fn service_credential_v1_layout_is_stable() {
+    let credential = Credential {
+        token: "tok".into(),
+        fetched_at: 100,
+        expires_at: 400,
+    };
+    let v1 = [0xa5, 0x05, 0x01, 0x03, b't', b'o', b'k', 0x64, 0x90, 0x03];
+    assert_eq!(shared_codec::to_bytes(&credential), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(credential));
+}
+
+#[test]
+fn nonce_slot_v1_layout_is_stable() {
+    let slot = NonceSlot {
+        entries: vec![(0x0102_0304_0506_0708, 400)],
+    };
+    let v1 = [
+        0xa5, 0x06, 0x01, 0x01, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x90, 0x03,
+    ];
+    assert_eq!(shared_codec::to_bytes(&slot), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(slot));
+}
+
+#[test]
+fn rate_limit_window_v1_layout_is_stable() {
+    let counts = WindowCounts {
+        window: 300,
+        current: 5,
+        previous: 3,
+    };
+    let v1 = [0xa5, 0x07, 0x01, 0xac, 0x02, 0x05, 0x03];
+    assert_eq!(shared_codec::to_bytes(&counts), v1);
+    assert_eq!(shared_codec::from_bytes(&v1), Some(counts));
+}
@@ -0,0 +1,60 @@
+use log::warn;
+use proxy_wasm::types::Status;
+
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+
+// u64 counters kept in Envoy shared data, updated with CAS so every worker VM
+// sees a consistent value
+
+const CAS_RETRIES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Counter(pub u64);
+
+impl SharedValue for Counter {
+    const KIND: u8 = shared_codec::KIND_COUNTER;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.fixed_u64(self.0);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        input.fixed_u64().map(Counter)
+    }
+
+    // Earlier builds stored the bare little-endian u64
+    fn decode_unversioned(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; 8] = bytes.try_into().ok()?;
+        Some(Counter(u64::from_le_bytes(bytes)))
+    }
+}
+
+pub fn read(key: &str) -> (u64, Option<u32>) {
+    let (counter, cas) = shared_codec::get::<Counter>(key);
+    (counter.map_or(0, |counter| counter.0), cas)
+}
+
+// False when another worker updated the counter since it was read
+pub fn write(key: &str, value: u64, cas: Option<u32>) -> bool {
+    match shared_codec::set(key, &Counter(value), cas) {
+        Ok(()) => true,
+        Err(Status::CasMismatch) => false,
+        Err(e) => {
+            warn!("[SHARED] Failed to update '{}': {:?}", key, e);
+            false
+        }
+    }
+}
+
+// Returns the updated value, or None if the update kept losing the CAS race
+pub fn add(key: &str, delta: i64) -> Option<u64> {
+    for _ in 0..CAS_RETRIES {
+        let (value, cas) = read(key);
+        let updated = value.saturating_add_signed(delta);
+        if write(key, updated, cas) {
+            return Some(updated);
+        }
+    }
+    None
+}
@@ -0,0 +1,205 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::secrets;
+
+// HMAC-SHA256 request signatures. The signature header carries comma
+// separated `name=value` parameters (names configurable), e.g.
+//
+//   x-uip-signature: keyId=partner-a,ts=1700000000,sig=<base64>
+//
+// The signed string is one `name:value` line per configured component
+// (lower-cased header or pseudo-header name) followed by `@ts:<ts>`, joined
+// with '\n'. An optional nonce parameter (for replay protection) is signed as
+// a final `@nonce:<nonce>` line.
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureMode {
+    // A valid signature is required, then the request goes to the authz service
+    VerifyThenAuthorize,
+    // A valid signature authorizes the request on its own (principal = key id)
+    VerifyOnly,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SignatureConfig {
+    pub header: String,
+    pub key_id_param: String,
+    pub timestamp_param: String,
+    pub signature_param: String,
+    pub nonce_param: String,
+    pub signed_components: Vec<String>,
+    pub clock_skew_secs: u64,
+    pub mode: SignatureMode,
+    // Unsigned requests fall through to the other authenticators when false
+    pub required: bool,
+    // key id -> base64 secret, `env:NAME` or `property:<path>` (see secrets)
+    pub keys: HashMap<String, String>,
+
+    // Property keys are replaced on rotation
+    #[serde(skip)]
+    resolved_keys: RefCell<HashMap<String, Vec<u8>>>,
+}
+
+impl Default for SignatureConfig {
+    fn default() -> Self {
+        Self {
+            header: "x-uip-signature".into(),
+            key_id_param: "keyId".into(),
+            timestamp_param: "ts".into(),
+            signature_param: "sig".into(),
+            nonce_param: "nonce".into(),
+            signed_components: vec![":method".into(), ":path".into(), ":authority".into()],
+            clock_skew_secs: 300,
+            mode: SignatureMode::VerifyThenAuthorize,
+            required: true,
+            keys: HashMap::new(),
+            resolved_keys: RefCell::default(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    Missing,
+    Valid {
+        key_id: String,
+        nonce: Option<String>,
+        timestamp: u64,
+    },
+    Invalid(&'static str),
+}
+
+impl SignatureConfig {
+    // Decode inline and env-provided key material once at configure time
+    pub fn init(&mut self) -> Result<(), String> {
+        let resolved = self.resolved_keys.get_mut();
+        for (key_id, value) in &self.keys {
+            if let Some(secret) = secrets::decode_static(&key_label(key_id), value)? {
+                resolved.insert(key_id.clone(), secret);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_property_keys(&self) -> bool {
+        self.keys
+            .values()
+            .any(|value| secrets::property_path(value).is_some())
+    }
+
+    // (Re-)read the `property:` keys; keys that cannot be read keep their
+    // previous value and are reported in the error
+    pub fn refresh_keys(
+        &self,
+        property: &dyn Fn(Vec<&str>) -> Option<Vec<u8>>,
+    ) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for (key_id, value) in &self.keys {
+            if secrets::property_path(value).is_none() {
+                continue;
+            }
+            match secrets::resolve_property(&key_label(key_id), value, property) {
+                Ok(secret) => {
+                    self.resolved_keys
+                        .borrow_mut()
+                        .insert(key_id.clone(), secret);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    // `header` looks up request headers (including pseudo-headers)
+    pub fn verify(&self, header: impl Fn(&str) -> Option<String>, now: u64) -> Verification {
+        let Some(value) = header(&self.header) else {
+            return Verification::Missing;
+        };
+
+        let params: HashMap<&str, &str> = value
+            .split(',')
+            .filter_map(|param| param.trim().split_once('='))
+            .map(|(name, value)| (name.trim(), value.trim().trim_matches('"')))
+            .collect();
+
+        let (Some(key_id), Some(ts), Some(signature)) = (
+            params.get(self.key_id_param.as_str()),
+            params.get(self.timestamp_param.as_str()),
+            params.get(self.signature_param.as_str()),
+        ) else {
+            return Verification::Invalid("malformed signature header");
+        };
+
+        let Ok(timestamp) = ts.parse::<u64>() else {
+            return Verification::Invalid("malformed timestamp");
+        };
+        if now.abs_diff(timestamp) > self.clock_skew_secs {
+            return Verification::Invalid("timestamp outside clock skew window");
+        }
+
+        let keys = self.resolved_keys.borrow();
+        let Some(secret) = keys.get(*key_id) else {
+            return Verification::Invalid("unknown key id");
+        };
+        let Ok(signature) = STANDARD.decode(signature) else {
+            return Verification::Invalid("malformed signature");
+        };
+
+        let mut mac = match Hmac::<Sha256>::new_from_slice(secret) {
+            Ok(mac) => mac,
+            Err(_) => return Verification::Invalid("unusable key"),
+        };
+        let nonce = params.get(self.nonce_param.as_str()).copied();
+        mac.update(self.signing_string(&header, ts, nonce).as_bytes());
+
+        // verify_slice compares in constant time
+        match mac.verify_slice(&signature) {
+            Ok(()) => Verification::Valid {
+                key_id: key_id.to_string(),
+                nonce: nonce.map(str::to_string),
+                timestamp,
+            },
+            Err(_) => Verification::Invalid("signature mismatch"),
+        }
+    }
+
+    fn signing_string(
+        &self,
+        header: &impl Fn(&str) -> Option<String>,
+        ts: &str,
+        nonce: Option<&str>,
+    ) -> String {
+        let mut signing = String::new();
+        for component in &self.signed_components {
+            let name = component.to_ascii_lowercase();
+            let value = header(&name).unwrap_or_default();
+            signing.push_str(&name);
+            signing.push(':');
+            signing.push_str(&value);
+            signing.push('\n');
+        }
+        signing.push_str("@ts:");
+        signing.push_str(ts);
+        if let Some(nonce) = nonce {
+            signing.push_str("\n@nonce:");
+            signing.push_str(nonce);
+        }
+        signing
+    }
+}
+
+fn key_label(key_id: &str) -> String {
+    format!("request_signing key '{}'", key_id)
+}
@@ -0,0 +1,181 @@
+use prost::encoding::{encode_key, encode_varint, WireType};
+use prost::Message;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::uipbdiauthz::{FilterResponse, StreamResponse};
+
+// Authz calls over one long-lived bidirectional stream per worker instead of a
+// unary call per request, saving the per-call setup and the connection churn
+// on the authz service. Requests are tagged with an id the service echoes in
+// its response. The root context owns the stream: request contexts send on it
+// and register as waiters, and the root hands each response to its waiter.
+// While the stream is down (or full), requests use unary calls; requests
+// waiting when it closes are retried as unary calls.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub method: String,
+    // Wait before reopening a stream that closed or failed to open
+    pub reconnect_ms: u64,
+    // Requests waiting on the stream beyond this use unary calls
+    pub max_in_flight: usize,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            method: "processStream".into(),
+            reconnect_ms: 1000,
+            max_in_flight: 1000,
+        }
+    }
+}
+
+// Request context waiting on the stream
+pub struct Waiter<T> {
+    pub context_id: u32,
+    // Answered with a deadline error by the root tick after this
+    pub deadline_ms: u64,
+    pub engine: T,
+}
+
+pub struct AuthzStream<T> {
+    root_context_id: u32,
+    token: Option<u32>,
+    // Earliest time to reopen a closed stream
+    reopen_at_ms: u64,
+    next_id: u64,
+    waiters: HashMap<u64, Waiter<T>>,
+}
+
+pub type SharedAuthzStream<T> = Rc<RefCell<AuthzStream<T>>>;
+
+impl<T> Default for AuthzStream<T> {
+    fn default() -> Self {
+        Self {
+            root_context_id: 0,
+            token: None,
+            reopen_at_ms: 0,
+            next_id: 0,
+            waiters: HashMap::new(),
+        }
+    }
+}
+
+impl<T> AuthzStream<T> {
+    pub fn set_root_context(&mut self, context_id: u32) {
+        self.root_context_id = context_id;
+    }
+
+    // Context the stream belongs to; sends must be made on its behalf
+    pub fn root_context_id(&self) -> u32 {
+        self.root_context_id
+    }
+
+    pub fn is_stream(&self, token: u32) -> bool {
+        self.token == Some(token)
+    }
+
+    pub fn reopen_due(&self, now_ms: u64) -> bool {
+        self.token.is_none() && now_ms >= self.reopen_at_ms
+    }
+
+    pub fn opened(&mut self, token: u32) {
+        self.token = Some(token);
+    }
+
+    pub fn open_failed(&mut self, config: &StreamConfig, now_ms: u64) {
+        self.reopen_at_ms = now_ms + config.reconnect_ms;
+    }
+
+    // Forget the stream; returns the requests that were waiting on it
+    pub fn closed(&mut self, config: &StreamConfig, now_ms: u64) -> Vec<Waiter<T>> {
+        self.token = None;
+        self.reopen_at_ms = now_ms + config.reconnect_ms;
+        self.waiters.drain().map(|(_, waiter)| waiter).collect()
+    }
+
+    // Stream token and id for a new request; None when the stream is down or
+    // full
+    pub fn reserve(&mut self, config: &StreamConfig) -> Option<(u32, u64)> {
+        let token = self.token?;
+        if self.waiters.len() >= config.max_in_flight {
+            return None;
+        }
+        self.next_id += 1;
+        Some((token, self.next_id))
+    }
+
+    pub fn wait(&mut self, id: u64, waiter: Waiter<T>) {
+        self.waiters.insert(id, waiter);
+    }
+
+    pub fn take(&mut self, id: u64) -> Option<Waiter<T>> {
+        self.waiters.remove(&id)
+    }
+
+    pub fn remove_context(&mut self, context_id: u32) {
+        self.waiters
+            .retain(|_, waiter| waiter.context_id != context_id);
+    }
+
+    pub fn take_expired(&mut self, now_ms: u64) -> Vec<Waiter<T>> {
+        let expired: Vec<u64> = self
+            .waiters
+            .iter()
+            .filter(|(_, waiter)| waiter.deadline_ms <= now_ms)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.waiters.remove(&id))
+            .collect()
+    }
+}
+
+// StreamRequest around an already serialized FilterRequest, embedded as
+// field 2 without decoding it again
+pub fn encode_request(id: u64, filter_request: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(filter_request.len() + 24);
+    encode_key(1, WireType::Varint, &mut out);
+    encode_varint(id, &mut out);
+    encode_key(2, WireType::LengthDelimited, &mut out);
+    encode_varint(filter_request.len() as u64, &mut out);
+    out.extend_from_slice(filter_request);
+    out
+}
+
+// Request id and verdict of a StreamResponse
+pub fn decode_response(bytes: &[u8]) -> Option<(u64, Option<FilterResponse>)> {
+    let response = StreamResponse::decode(bytes).ok()?;
+    Some((response.id, response.response))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn stream_request_embeds_filter_request() {
+        use super::encode_request;
+        use crate::uipbdiauthz::{FilterRequest, StreamRequest};
+        use prost::Message;
+
+        let request = FilterRequest {
+            method: "GET".into(),
+            path: "/orders".into(),
+            ..Default::default()
+        };
+        let framed = encode_request(300, &request.encode_to_vec());
+
+        assert_eq!(
+            StreamRequest::decode(framed.as_slice()).unwrap(),
+            StreamRequest {
+                id: 300,
+                request: Some(request),
+            }
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::authority_policy;
+use crate::pipeline::RequestSource;
+
+// Per-tenant authz clusters for multi-tenant meshes: the tenant is read from
+// a header (or the request host) and looked up in `clusters`. The tenant's
+// cluster takes precedence over an authority policy's; the policy's timeout
+// and headers still apply. Tenants without an entry use the usual cluster,
+// or are rejected so they never share a backend.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TenantRoutingConfig {
+    // Header naming the tenant; the `:authority` host when unset
+    pub header: Option<String>,
+    // Tenant -> authz cluster
+    pub clusters: HashMap<String, String>,
+    // Answer requests of unmapped tenants with 403
+    pub reject_unknown: bool,
+}
+
+impl TenantRoutingConfig {
+    pub fn init(&mut self) {
+        if let Some(header) = self.header.as_mut() {
+            header.make_ascii_lowercase();
+        }
+        if self.header.is_none() {
+            // Hosts are matched case-insensitively
+            self.clusters = std::mem::take(&mut self.clusters)
+                .into_iter()
+                .map(|(host, cluster)| (host.to_ascii_lowercase(), cluster))
+                .collect();
+        }
+    }
+
+    // (tenant, its cluster) for the request
+    pub fn route(&self, source: &dyn RequestSource) -> (Option<String>, Option<&str>) {
+        let tenant = match self.header.as_deref() {
+            Some(header) => source.header(header).map(|value| value.trim().to_string()),
+            None => source
+                .header(":authority")
+                .map(|authority| authority_policy::host(&authority).to_ascii_lowercase()),
+        };
+        let tenant = tenant.filter(|tenant| !tenant.is_empty());
+        let cluster = tenant
+            .as_ref()
+            .and_then(|tenant| self.clusters.get(tenant))
+            .map(String::as_str);
+        (tenant, cluster)
+    }
+}
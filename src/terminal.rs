@@ -0,0 +1,32 @@
+use log::warn;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::metrics::Metric;
+
+// Ensures at most one terminal action (resume or local response) is taken for
+// a request. Several callbacks can race to finish the same request, e.g. the
+// root context settling it at its timeout deadline while the gRPC response is
+// already queued. The guard is shared between those parties; whoever claims
+// it first acts, later attempts are suppressed and counted.
+#[derive(Clone, Debug, Default)]
+pub struct TerminalGuard(Rc<Cell<bool>>);
+
+impl TerminalGuard {
+    // True for the first caller only
+    pub fn claim(&self, suppressed: Metric, action: &str) -> bool {
+        if self.0.replace(true) {
+            warn!(
+                "[TERMINAL] Suppressing duplicate terminal action '{}'",
+                action
+            );
+            suppressed.increment(1);
+            return false;
+        }
+        true
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.0.get()
+    }
+}
@@ -0,0 +1,245 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::metrics::Metric;
+use crate::shared_codec::{self, Decoder, Encoder, SharedValue};
+use crate::shared_counter;
+
+// Throughput self-reporting. Every worker VM counts requests, decisions and
+// in-flight authz calls, and on each report interval publishes them as
+// per-worker gauges plus a snapshot in shared data. The singleton (the same
+// module loaded as a bootstrap wasm service with `aggregate: true`) sums the
+// fresh snapshots of all workers into the aggregate gauges.
+
+// Number of worker slots handed out so far
+const WORKERS_KEY: &str = "uipbdiauthz.throughput.workers";
+const SNAPSHOT_KEY_PREFIX: &str = "uipbdiauthz.throughput.worker.";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ThroughputConfig {
+    pub report_interval_secs: u64,
+    // Set on the singleton instance only
+    pub aggregate: bool,
+    // Snapshots older than this many intervals are left out of the aggregate
+    pub stale_after_intervals: u64,
+}
+
+impl Default for ThroughputConfig {
+    fn default() -> Self {
+        Self {
+            report_interval_secs: 10,
+            aggregate: false,
+            stale_after_intervals: 3,
+        }
+    }
+}
+
+// Per-worker counters, shared by the root context and its request contexts
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    requests: u64,
+    allow: u64,
+    deny: u64,
+    error: u64,
+    in_flight: u64,
+}
+
+pub type SharedWorkerStats = Rc<RefCell<WorkerStats>>;
+
+impl WorkerStats {
+    pub fn record_request(&mut self) {
+        self.requests += 1;
+    }
+
+    pub fn record_decision(&mut self, decision: &str) {
+        match decision {
+            "allow" => self.allow += 1,
+            "deny" => self.deny += 1,
+            _ => self.error += 1,
+        }
+    }
+
+    pub fn dispatch_started(&mut self) {
+        self.in_flight += 1;
+    }
+
+    pub fn dispatch_finished(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+    }
+
+    // Interval counters reset; the dispatch depth is a level and carries over
+    fn take_snapshot(&mut self, ts: u64, interval_secs: u64) -> Snapshot {
+        let snapshot = Snapshot {
+            ts,
+            interval_secs,
+            requests: self.requests,
+            allow: self.allow,
+            deny: self.deny,
+            error: self.error,
+            in_flight: self.in_flight,
+        };
+        self.requests = 0;
+        self.allow = 0;
+        self.deny = 0;
+        self.error = 0;
+        snapshot
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub ts: u64,
+    pub interval_secs: u64,
+    pub requests: u64,
+    pub allow: u64,
+    pub deny: u64,
+    pub error: u64,
+    pub in_flight: u64,
+}
+
+impl SharedValue for Snapshot {
+    const KIND: u8 = shared_codec::KIND_THROUGHPUT_SNAPSHOT;
+    const VERSION: u8 = 1;
+
+    fn encode(&self, out: &mut Encoder) {
+        out.u64(self.ts)
+            .u64(self.interval_secs)
+            .u64(self.requests)
+            .u64(self.allow)
+            .u64(self.deny)
+            .u64(self.error)
+            .u64(self.in_flight);
+    }
+
+    fn decode(_version: u8, input: &mut Decoder) -> Option<Self> {
+        Some(Self {
+            ts: input.u64()?,
+            interval_secs: input.u64()?,
+            requests: input.u64()?,
+            allow: input.u64()?,
+            deny: input.u64()?,
+            error: input.u64()?,
+            in_flight: input.u64()?,
+        })
+    }
+
+    // Earlier builds published the snapshot as JSON
+    fn decode_unversioned(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+impl Snapshot {
+    fn request_rate(&self) -> u64 {
+        self.requests / self.interval_secs.max(1)
+    }
+}
+
+#[derive(Debug)]
+struct Gauges {
+    request_rate: Metric,
+    allow: Metric,
+    deny: Metric,
+    error: Metric,
+    dispatch_depth: Metric,
+}
+
+impl Gauges {
+    fn define(prefix: &str) -> Self {
+        Self {
+            request_rate: Metric::gauge(&format!("{}.request_rate", prefix)),
+            allow: Metric::gauge(&format!("{}.decisions.allow", prefix)),
+            deny: Metric::gauge(&format!("{}.decisions.deny", prefix)),
+            error: Metric::gauge(&format!("{}.decisions.error", prefix)),
+            dispatch_depth: Metric::gauge(&format!("{}.dispatch_depth", prefix)),
+        }
+    }
+
+    fn record(&self, snapshot: &Snapshot) {
+        self.request_rate.record(snapshot.request_rate());
+        self.allow.record(snapshot.allow);
+        self.deny.record(snapshot.deny);
+        self.error.record(snapshot.error);
+        self.dispatch_depth.record(snapshot.in_flight);
+    }
+}
+
+// Owned by the root context
+#[derive(Debug, Default)]
+pub struct Reporter {
+    // Defined on the first report, once the worker slot is known
+    gauges: Option<Gauges>,
+    snapshot_key: String,
+}
+
+impl Reporter {
+    pub fn report(&mut self, config: &ThroughputConfig, stats: &SharedWorkerStats, now: u64) {
+        if config.aggregate {
+            self.aggregate(config, now);
+        } else {
+            self.report_worker(config, stats, now);
+        }
+    }
+
+    fn report_worker(&mut self, config: &ThroughputConfig, stats: &SharedWorkerStats, now: u64) {
+        let snapshot = stats
+            .borrow_mut()
+            .take_snapshot(now, config.report_interval_secs);
+
+        if self.gauges.is_none() {
+            let Some(slots) = shared_counter::add(WORKERS_KEY, 1) else {
+                return;
+            };
+            let worker = slots - 1;
+            info!("[THROUGHPUT] Reporting as worker {}", worker);
+            self.snapshot_key = format!("{}{}", SNAPSHOT_KEY_PREFIX, worker);
+            self.gauges = Some(Gauges::define(&format!("uipbdiauthz.worker.{}", worker)));
+        }
+        if let Some(gauges) = &self.gauges {
+            gauges.record(&snapshot);
+        }
+
+        if let Err(e) = shared_codec::set(&self.snapshot_key, &snapshot, None) {
+            warn!("[THROUGHPUT] Failed to publish snapshot: {:?}", e);
+        }
+    }
+
+    fn aggregate(&mut self, config: &ThroughputConfig, now: u64) {
+        let (workers, _) = shared_counter::read(WORKERS_KEY);
+        let max_age = config.report_interval_secs * config.stale_after_intervals.max(1);
+        let mut total = Snapshot {
+            ts: now,
+            interval_secs: config.report_interval_secs,
+            ..Default::default()
+        };
+        let mut reporting = 0;
+        for worker in 0..workers {
+            let key = format!("{}{}", SNAPSHOT_KEY_PREFIX, worker);
+            let (Some(snapshot), _) = shared_codec::get::<Snapshot>(&key) else {
+                continue;
+            };
+            if now.saturating_sub(snapshot.ts) > max_age {
+                continue;
+            }
+            reporting += 1;
+            total.requests += snapshot.requests;
+            total.allow += snapshot.allow;
+            total.deny += snapshot.deny;
+            total.error += snapshot.error;
+            total.in_flight += snapshot.in_flight;
+        }
+
+        info!(
+            "[THROUGHPUT] {} of {} workers reporting, {} req/s",
+            reporting,
+            workers,
+            total.request_rate()
+        );
+        self.gauges
+            .get_or_insert_with(|| Gauges::define("uipbdiauthz.throughput"))
+            .record(&total);
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::terminal::TerminalGuard;
+
+// Requests paused on the authz call are tracked per VM so the root context can
+// settle them just before the downstream/route timeout fires. A settled
+// request has its failure mode applied by the root context; the late gRPC
+// response is then ignored instead of resuming or answering the stream twice.
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TimeoutGuardConfig {
+    // Route timeout to assume when the request carries no
+    // x-envoy-expected-rq-timeout-ms header
+    pub route_timeout_ms: Option<u64>,
+    // Settle this long before the timeout so our response wins the race
+    pub margin_ms: u64,
+    // How often the root context checks for expiring requests
+    pub check_interval_ms: u64,
+}
+
+impl Default for TimeoutGuardConfig {
+    fn default() -> Self {
+        Self {
+            route_timeout_ms: None,
+            margin_ms: 100,
+            check_interval_ms: 50,
+        }
+    }
+}
+
+impl TimeoutGuardConfig {
+    // Absolute deadline (ms) for a request that started at `now_ms`
+    pub fn deadline_ms(&self, now_ms: u64, expected_timeout: Option<&str>) -> Option<u64> {
+        let timeout = expected_timeout
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .filter(|timeout| *timeout > 0)
+            .or(self.route_timeout_ms)?;
+        Some(now_ms + timeout.saturating_sub(self.margin_ms))
+    }
+}
+
+#[derive(Debug)]
+pub struct PendingCall {
+    pub context_id: u32,
+    // None for requests waiting on the authz stream, which stays open
+    pub token: Option<u32>,
+    pub deadline_ms: u64,
+    // Downstream is gRPC: settle with a gRPC status instead of HTTP 504
+    pub grpc: bool,
+    // Resume at the deadline whatever the failure mode (monitor-only)
+    pub fail_open: bool,
+    // Shared with the request context
+    pub guard: TerminalGuard,
+}
+
+#[derive(Debug, Default)]
+pub struct PendingCalls {
+    calls: Vec<PendingCall>,
+}
+
+pub type SharedPendingCalls = Rc<RefCell<PendingCalls>>;
+
+impl PendingCalls {
+    pub fn track(&mut self, call: PendingCall) {
+        self.calls.push(call);
+    }
+
+    pub fn remove(&mut self, context_id: u32) {
+        self.calls.retain(|call| call.context_id != context_id);
+    }
+
+    // The request moved on to another call (e.g. the next chained service)
+    pub fn retarget(&mut self, context_id: u32, token: u32) {
+        for call in &mut self.calls {
+            if call.context_id == context_id {
+                call.token = Some(token);
+            }
+        }
+    }
+
+    pub fn take_expired(&mut self, now_ms: u64) -> Vec<PendingCall> {
+        let (expired, pending) = std::mem::take(&mut self.calls)
+            .into_iter()
+            .partition(|call| call.deadline_ms <= now_ms);
+        self.calls = pending;
+        expired
+    }
+}
@@ -0,0 +1,29 @@
+use crate::uipbdiauthz::TlsInfo;
+
+// Downstream TLS connection attributes for FilterRequest.tls, read from the
+// Envoy `connection.*` properties. Plaintext connections have no TLS version
+// and get no `tls` field, so policies can tell them apart. Envoy exposes no
+// cipher suite attribute; the protocol version is the closest proxy for it.
+
+// `property` reads a `connection.<name>` property
+pub fn connection_info(property: impl Fn(&str) -> Option<Vec<u8>>) -> Option<TlsInfo> {
+    let string = |name| {
+        property(name)
+            .and_then(|value| String::from_utf8(value).ok())
+            .unwrap_or_default()
+    };
+    let version = string("tls_version");
+    if version.is_empty() {
+        return None;
+    }
+    Some(TlsInfo {
+        version,
+        sni: string("requested_server_name"),
+        // Booleans are a single byte
+        mtls: property("mtls").is_some_and(|value| value.first() == Some(&1)),
+        peer_subject: string("subject_peer_certificate"),
+        peer_uri_san: string("uri_san_peer_certificate"),
+        peer_dns_san: string("dns_san_peer_certificate"),
+        peer_certificate_sha256: string("sha256_peer_certificate_digest"),
+    })
+}
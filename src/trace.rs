@@ -0,0 +1,73 @@
+use serde::Deserialize;
+
+// W3C Trace Context (https://www.w3.org/TR/trace-context/). Requests that
+// arrive without a valid `traceparent` get a fresh one before the authz call,
+// so the FilterRequest, the authz service and the upstream all see the same
+// trace id.
+//
+//   traceparent: 00-<32 hex trace-id>-<16 hex parent-id>-<2 hex flags>
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+const SAMPLED: u8 = 0x01;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TraceConfig {
+    // Set the sampled flag on generated trace contexts
+    pub sample_generated: bool,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            sample_generated: true,
+        }
+    }
+}
+
+pub fn is_valid(traceparent: &str) -> bool {
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    let [version, trace_id, parent_id, flags, ..] = parts[..] else {
+        return false;
+    };
+    // Version ff is forbidden; version 00 has exactly four fields
+    if !is_hex(version, 2) || version == "ff" || (version == "00" && parts.len() != 4) {
+        return false;
+    }
+    is_hex(trace_id, 32)
+        && !is_zero(trace_id)
+        && is_hex(parent_id, 16)
+        && !is_zero(parent_id)
+        && is_hex(flags, 2)
+}
+
+fn is_hex(field: &str, len: usize) -> bool {
+    field.len() == len
+        && field
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn is_zero(field: &str) -> bool {
+    field.bytes().all(|b| b == b'0')
+}
+
+// Fresh version-00 traceparent; None when no randomness is available
+pub fn generate(config: &TraceConfig) -> Option<String> {
+    let mut ids = [0u8; 24];
+    getrandom::getrandom(&mut ids).ok()?;
+    let (trace_id, parent_id) = ids.split_at(16);
+    let flags = if config.sample_generated { SAMPLED } else { 0 };
+    Some(format!(
+        "00-{}-{}-{:02x}",
+        hex(trace_id),
+        hex(parent_id),
+        flags
+    ))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
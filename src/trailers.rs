@@ -0,0 +1,50 @@
+use serde::Deserialize;
+
+use crate::forwarded_headers;
+use crate::pipeline::RequestSource;
+
+// Request trailers for policies that need them (gRPC status details, chunked
+// upload checksums). Trailers arrive after the body, so for the requests this
+// covers the authz call waits for them: the headers are held, the body is
+// buffered by Envoy, and the FilterRequest goes out with its `trailers` once
+// the stream ends. Only gRPC requests and requests announcing trailers or a
+// chunked body are held; the rest are authorized from their headers.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TrailersConfig {
+    // Trailer names or `prefix*` patterns to forward (every trailer when empty)
+    pub names: Vec<String>,
+    // Path prefixes whose requests wait for trailers (all paths when empty)
+    pub paths: Vec<String>,
+}
+
+impl TrailersConfig {
+    pub fn init(&mut self) -> Result<(), String> {
+        forwarded_headers::init(&mut self.names, "request_trailers.names")
+    }
+
+    // Whether the request's authz call should wait for its trailers
+    pub fn applies(&self, source: &dyn RequestSource, path: &str, grpc: bool) -> bool {
+        let has_token = |name: &str, token: &str| {
+            source.header_list(name).is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|item| item.trim().eq_ignore_ascii_case(token))
+            })
+        };
+        (grpc || has_token("te", "trailers") || has_token("transfer-encoding", "chunked"))
+            && (self.paths.is_empty() || self.paths.iter().any(|prefix| path.starts_with(prefix)))
+    }
+
+    pub fn forwards(&self, name: &str) -> bool {
+        self.names.is_empty()
+            || self
+                .names
+                .iter()
+                .any(|entry| match forwarded_headers::prefix(entry) {
+                    Some(prefix) => forwarded_headers::matches_prefix(prefix, name),
+                    None => entry.eq_ignore_ascii_case(name),
+                })
+    }
+}
@@ -1,734 +0,0 @@
-// This file is generated by rust-protobuf 2.28.0. Do not edit
-// @generated
-
-// https://github.com/rust-lang/rust-clippy/issues/702
-#![allow(unknown_lints)]
-#![allow(clippy::all)]
-
-#![allow(unused_attributes)]
-#![cfg_attr(rustfmt, rustfmt::skip)]
-
-#![allow(box_pointers)]
-#![allow(dead_code)]
-#![allow(missing_docs)]
-#![allow(non_camel_case_types)]
-#![allow(non_snake_case)]
-#![allow(non_upper_case_globals)]
-#![allow(trivial_casts)]
-#![allow(unused_imports)]
-#![allow(unused_results)]
-//! Generated file from `protos/uipbdiauthz.proto`
-
-/// Generated files are compatible only with the same version
-/// of protobuf runtime.
-// const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_2_28_0;
-
-#[derive(PartialEq,Clone,Default)]
-pub struct FilterRequest {
-    // message fields
-    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
-    pub host: ::std::string::String,
-    pub method: ::std::string::String,
-    pub path: ::std::string::String,
-    pub protocol: ::std::string::String,
-    pub scheme: ::std::string::String,
-    pub req: ::std::string::String,
-    // special fields
-    pub unknown_fields: ::protobuf::UnknownFields,
-    pub cached_size: ::protobuf::CachedSize,
-}
-
-impl<'a> ::std::default::Default for &'a FilterRequest {
-    fn default() -> &'a FilterRequest {
-        <FilterRequest as ::protobuf::Message>::default_instance()
-    }
-}
-
-impl FilterRequest {
-    pub fn new() -> FilterRequest {
-        ::std::default::Default::default()
-    }
-
-    // repeated .authengine.FilterRequest.HeadersEntry headers = 1;
-
-
-    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        &self.headers
-    }
-    pub fn clear_headers(&mut self) {
-        self.headers.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_headers(&mut self, v: ::std::collections::HashMap<::std::string::String, ::std::string::String>) {
-        self.headers = v;
-    }
-
-    // Mutable pointer to the field.
-    pub fn mut_headers(&mut self) -> &mut ::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        &mut self.headers
-    }
-
-    // Take field
-    pub fn take_headers(&mut self) -> ::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        ::std::mem::replace(&mut self.headers, ::std::collections::HashMap::new())
-    }
-
-    // string host = 2;
-
-
-    pub fn get_host(&self) -> &str {
-        &self.host
-    }
-    pub fn clear_host(&mut self) {
-        self.host.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_host(&mut self, v: ::std::string::String) {
-        self.host = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_host(&mut self) -> &mut ::std::string::String {
-        &mut self.host
-    }
-
-    // Take field
-    pub fn take_host(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.host, ::std::string::String::new())
-    }
-
-    // string method = 3;
-
-
-    pub fn get_method(&self) -> &str {
-        &self.method
-    }
-    pub fn clear_method(&mut self) {
-        self.method.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_method(&mut self, v: ::std::string::String) {
-        self.method = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_method(&mut self) -> &mut ::std::string::String {
-        &mut self.method
-    }
-
-    // Take field
-    pub fn take_method(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.method, ::std::string::String::new())
-    }
-
-    // string path = 4;
-
-
-    pub fn get_path(&self) -> &str {
-        &self.path
-    }
-    pub fn clear_path(&mut self) {
-        self.path.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_path(&mut self, v: ::std::string::String) {
-        self.path = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_path(&mut self) -> &mut ::std::string::String {
-        &mut self.path
-    }
-
-    // Take field
-    pub fn take_path(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.path, ::std::string::String::new())
-    }
-
-    // string protocol = 5;
-
-
-    pub fn get_protocol(&self) -> &str {
-        &self.protocol
-    }
-    pub fn clear_protocol(&mut self) {
-        self.protocol.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_protocol(&mut self, v: ::std::string::String) {
-        self.protocol = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_protocol(&mut self) -> &mut ::std::string::String {
-        &mut self.protocol
-    }
-
-    // Take field
-    pub fn take_protocol(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.protocol, ::std::string::String::new())
-    }
-
-    // string scheme = 6;
-
-
-    pub fn get_scheme(&self) -> &str {
-        &self.scheme
-    }
-    pub fn clear_scheme(&mut self) {
-        self.scheme.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_scheme(&mut self, v: ::std::string::String) {
-        self.scheme = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_scheme(&mut self) -> &mut ::std::string::String {
-        &mut self.scheme
-    }
-
-    // Take field
-    pub fn take_scheme(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.scheme, ::std::string::String::new())
-    }
-
-    // string req = 7;
-
-
-    pub fn get_req(&self) -> &str {
-        &self.req
-    }
-    pub fn clear_req(&mut self) {
-        self.req.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_req(&mut self, v: ::std::string::String) {
-        self.req = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_req(&mut self) -> &mut ::std::string::String {
-        &mut self.req
-    }
-
-    // Take field
-    pub fn take_req(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.req, ::std::string::String::new())
-    }
-}
-
-impl ::protobuf::Message for FilterRequest {
-    fn is_initialized(&self) -> bool {
-        true
-    }
-
-    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        while !is.eof()? {
-            let (field_number, wire_type) = is.read_tag_unpack()?;
-            match field_number {
-                1 => {
-                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
-                },
-                2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.host)?;
-                },
-                3 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.method)?;
-                },
-                4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.path)?;
-                },
-                5 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.protocol)?;
-                },
-                6 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.scheme)?;
-                },
-                7 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.req)?;
-                },
-                _ => {
-                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
-                },
-            };
-        }
-        ::std::result::Result::Ok(())
-    }
-
-    // Compute sizes of nested messages
-    #[allow(unused_variables)]
-    fn compute_size(&self) -> u32 {
-        let mut my_size = 0;
-        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(1, &self.headers);
-        if !self.host.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.host);
-        }
-        if !self.method.is_empty() {
-            my_size += ::protobuf::rt::string_size(3, &self.method);
-        }
-        if !self.path.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.path);
-        }
-        if !self.protocol.is_empty() {
-            my_size += ::protobuf::rt::string_size(5, &self.protocol);
-        }
-        if !self.scheme.is_empty() {
-            my_size += ::protobuf::rt::string_size(6, &self.scheme);
-        }
-        if !self.req.is_empty() {
-            my_size += ::protobuf::rt::string_size(7, &self.req);
-        }
-        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
-        self.cached_size.set(my_size);
-        my_size
-    }
-
-    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(1, &self.headers, os)?;
-        if !self.host.is_empty() {
-            os.write_string(2, &self.host)?;
-        }
-        if !self.method.is_empty() {
-            os.write_string(3, &self.method)?;
-        }
-        if !self.path.is_empty() {
-            os.write_string(4, &self.path)?;
-        }
-        if !self.protocol.is_empty() {
-            os.write_string(5, &self.protocol)?;
-        }
-        if !self.scheme.is_empty() {
-            os.write_string(6, &self.scheme)?;
-        }
-        if !self.req.is_empty() {
-            os.write_string(7, &self.req)?;
-        }
-        os.write_unknown_fields(self.get_unknown_fields())?;
-        ::std::result::Result::Ok(())
-    }
-
-    fn get_cached_size(&self) -> u32 {
-        self.cached_size.get()
-    }
-
-    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
-        &self.unknown_fields
-    }
-
-    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
-        &mut self.unknown_fields
-    }
-
-    fn as_any(&self) -> &dyn (::std::any::Any) {
-        self as &dyn (::std::any::Any)
-    }
-    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
-        self as &mut dyn (::std::any::Any)
-    }
-    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
-        self
-    }
-
-    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
-        Self::descriptor_static()
-    }
-
-    fn new() -> FilterRequest {
-        FilterRequest::new()
-    }
-
-    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
-        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
-        descriptor.get(|| {
-            let mut fields = ::std::vec::Vec::new();
-            fields.push(::protobuf::reflect::accessor::make_map_accessor::<_, ::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(
-                "headers",
-                |m: &FilterRequest| { &m.headers },
-                |m: &mut FilterRequest| { &mut m.headers },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "host",
-                |m: &FilterRequest| { &m.host },
-                |m: &mut FilterRequest| { &mut m.host },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "method",
-                |m: &FilterRequest| { &m.method },
-                |m: &mut FilterRequest| { &mut m.method },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "path",
-                |m: &FilterRequest| { &m.path },
-                |m: &mut FilterRequest| { &mut m.path },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "protocol",
-                |m: &FilterRequest| { &m.protocol },
-                |m: &mut FilterRequest| { &mut m.protocol },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "scheme",
-                |m: &FilterRequest| { &m.scheme },
-                |m: &mut FilterRequest| { &mut m.scheme },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "req",
-                |m: &FilterRequest| { &m.req },
-                |m: &mut FilterRequest| { &mut m.req },
-            ));
-            ::protobuf::reflect::MessageDescriptor::new_pb_name::<FilterRequest>(
-                "FilterRequest",
-                fields,
-                file_descriptor_proto()
-            )
-        })
-    }
-
-    fn default_instance() -> &'static FilterRequest {
-        static instance: ::protobuf::rt::LazyV2<FilterRequest> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(FilterRequest::new)
-    }
-}
-
-impl ::protobuf::Clear for FilterRequest {
-    fn clear(&mut self) {
-        self.headers.clear();
-        self.host.clear();
-        self.method.clear();
-        self.path.clear();
-        self.protocol.clear();
-        self.scheme.clear();
-        self.req.clear();
-        self.unknown_fields.clear();
-    }
-}
-
-impl ::std::fmt::Debug for FilterRequest {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
-        ::protobuf::text_format::fmt(self, f)
-    }
-}
-
-impl ::protobuf::reflect::ProtobufValue for FilterRequest {
-    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
-        ::protobuf::reflect::ReflectValueRef::Message(self)
-    }
-}
-
-#[derive(PartialEq,Clone,Default)]
-pub struct FilterResponse {
-    // message fields
-    pub allow: bool,
-    pub user: ::std::string::String,
-    pub headers: ::std::collections::HashMap<::std::string::String, ::std::string::String>,
-    pub message: ::std::string::String,
-    // special fields
-    pub unknown_fields: ::protobuf::UnknownFields,
-    pub cached_size: ::protobuf::CachedSize,
-}
-
-impl<'a> ::std::default::Default for &'a FilterResponse {
-    fn default() -> &'a FilterResponse {
-        <FilterResponse as ::protobuf::Message>::default_instance()
-    }
-}
-
-impl FilterResponse {
-    pub fn new() -> FilterResponse {
-        ::std::default::Default::default()
-    }
-
-    // bool allow = 1;
-
-
-    pub fn get_allow(&self) -> bool {
-        self.allow
-    }
-    pub fn clear_allow(&mut self) {
-        self.allow = false;
-    }
-
-    // Param is passed by value, moved
-    pub fn set_allow(&mut self, v: bool) {
-        self.allow = v;
-    }
-
-    // string user = 2;
-
-
-    pub fn get_user(&self) -> &str {
-        &self.user
-    }
-    pub fn clear_user(&mut self) {
-        self.user.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_user(&mut self, v: ::std::string::String) {
-        self.user = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_user(&mut self) -> &mut ::std::string::String {
-        &mut self.user
-    }
-
-    // Take field
-    pub fn take_user(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.user, ::std::string::String::new())
-    }
-
-    // repeated .authengine.FilterResponse.HeadersEntry headers = 3;
-
-
-    pub fn get_headers(&self) -> &::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        &self.headers
-    }
-    pub fn clear_headers(&mut self) {
-        self.headers.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_headers(&mut self, v: ::std::collections::HashMap<::std::string::String, ::std::string::String>) {
-        self.headers = v;
-    }
-
-    // Mutable pointer to the field.
-    pub fn mut_headers(&mut self) -> &mut ::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        &mut self.headers
-    }
-
-    // Take field
-    pub fn take_headers(&mut self) -> ::std::collections::HashMap<::std::string::String, ::std::string::String> {
-        ::std::mem::replace(&mut self.headers, ::std::collections::HashMap::new())
-    }
-
-    // string message = 4;
-
-
-    pub fn get_message(&self) -> &str {
-        &self.message
-    }
-    pub fn clear_message(&mut self) {
-        self.message.clear();
-    }
-
-    // Param is passed by value, moved
-    pub fn set_message(&mut self, v: ::std::string::String) {
-        self.message = v;
-    }
-
-    // Mutable pointer to the field.
-    // If field is not initialized, it is initialized with default value first.
-    pub fn mut_message(&mut self) -> &mut ::std::string::String {
-        &mut self.message
-    }
-
-    // Take field
-    pub fn take_message(&mut self) -> ::std::string::String {
-        ::std::mem::replace(&mut self.message, ::std::string::String::new())
-    }
-}
-
-impl ::protobuf::Message for FilterResponse {
-    fn is_initialized(&self) -> bool {
-        true
-    }
-
-    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        while !is.eof()? {
-            let (field_number, wire_type) = is.read_tag_unpack()?;
-            match field_number {
-                1 => {
-                    if wire_type != ::protobuf::wire_format::WireTypeVarint {
-                        return ::std::result::Result::Err(::protobuf::rt::unexpected_wire_type(wire_type));
-                    }
-                    let tmp = is.read_bool()?;
-                    self.allow = tmp;
-                },
-                2 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.user)?;
-                },
-                3 => {
-                    ::protobuf::rt::read_map_into::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(wire_type, is, &mut self.headers)?;
-                },
-                4 => {
-                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.message)?;
-                },
-                _ => {
-                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
-                },
-            };
-        }
-        ::std::result::Result::Ok(())
-    }
-
-    // Compute sizes of nested messages
-    #[allow(unused_variables)]
-    fn compute_size(&self) -> u32 {
-        let mut my_size = 0;
-        if self.allow != false {
-            my_size += 2;
-        }
-        if !self.user.is_empty() {
-            my_size += ::protobuf::rt::string_size(2, &self.user);
-        }
-        my_size += ::protobuf::rt::compute_map_size::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(3, &self.headers);
-        if !self.message.is_empty() {
-            my_size += ::protobuf::rt::string_size(4, &self.message);
-        }
-        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
-        self.cached_size.set(my_size);
-        my_size
-    }
-
-    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
-        if self.allow != false {
-            os.write_bool(1, self.allow)?;
-        }
-        if !self.user.is_empty() {
-            os.write_string(2, &self.user)?;
-        }
-        ::protobuf::rt::write_map_with_cached_sizes::<::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(3, &self.headers, os)?;
-        if !self.message.is_empty() {
-            os.write_string(4, &self.message)?;
-        }
-        os.write_unknown_fields(self.get_unknown_fields())?;
-        ::std::result::Result::Ok(())
-    }
-
-    fn get_cached_size(&self) -> u32 {
-        self.cached_size.get()
-    }
-
-    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
-        &self.unknown_fields
-    }
-
-    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
-        &mut self.unknown_fields
-    }
-
-    fn as_any(&self) -> &dyn (::std::any::Any) {
-        self as &dyn (::std::any::Any)
-    }
-    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
-        self as &mut dyn (::std::any::Any)
-    }
-    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
-        self
-    }
-
-    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
-        Self::descriptor_static()
-    }
-
-    fn new() -> FilterResponse {
-        FilterResponse::new()
-    }
-
-    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
-        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
-        descriptor.get(|| {
-            let mut fields = ::std::vec::Vec::new();
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeBool>(
-                "allow",
-                |m: &FilterResponse| { &m.allow },
-                |m: &mut FilterResponse| { &mut m.allow },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "user",
-                |m: &FilterResponse| { &m.user },
-                |m: &mut FilterResponse| { &mut m.user },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_map_accessor::<_, ::protobuf::types::ProtobufTypeString, ::protobuf::types::ProtobufTypeString>(
-                "headers",
-                |m: &FilterResponse| { &m.headers },
-                |m: &mut FilterResponse| { &mut m.headers },
-            ));
-            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
-                "message",
-                |m: &FilterResponse| { &m.message },
-                |m: &mut FilterResponse| { &mut m.message },
-            ));
-            ::protobuf::reflect::MessageDescriptor::new_pb_name::<FilterResponse>(
-                "FilterResponse",
-                fields,
-                file_descriptor_proto()
-            )
-        })
-    }
-
-    fn default_instance() -> &'static FilterResponse {
-        static instance: ::protobuf::rt::LazyV2<FilterResponse> = ::protobuf::rt::LazyV2::INIT;
-        instance.get(FilterResponse::new)
-    }
-}
-
-impl ::protobuf::Clear for FilterResponse {
-    fn clear(&mut self) {
-        self.allow = false;
-        self.user.clear();
-        self.headers.clear();
-        self.message.clear();
-        self.unknown_fields.clear();
-    }
-}
-
-impl ::std::fmt::Debug for FilterResponse {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
-        ::protobuf::text_format::fmt(self, f)
-    }
-}
-
-impl ::protobuf::reflect::ProtobufValue for FilterResponse {
-    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
-        ::protobuf::reflect::ReflectValueRef::Message(self)
-    }
-}
-
-static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x18protos/uipbdiauthz.proto\x12\nauthengine\"\x93\x02\n\rFilterReques\
-    t\x12@\n\x07headers\x18\x01\x20\x03(\x0b2&.authengine.FilterRequest.Head\
-    ersEntryR\x07headers\x12\x12\n\x04host\x18\x02\x20\x01(\tR\x04host\x12\
-    \x16\n\x06method\x18\x03\x20\x01(\tR\x06method\x12\x12\n\x04path\x18\x04\
-    \x20\x01(\tR\x04path\x12\x1a\n\x08protocol\x18\x05\x20\x01(\tR\x08protoc\
-    ol\x12\x16\n\x06scheme\x18\x06\x20\x01(\tR\x06scheme\x12\x10\n\x03req\
-    \x18\x07\x20\x01(\tR\x03req\x1a:\n\x0cHeadersEntry\x12\x10\n\x03key\x18\
-    \x01\x20\x01(\tR\x03key\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05value:\
-    \x028\x01\"\xd3\x01\n\x0eFilterResponse\x12\x14\n\x05allow\x18\x01\x20\
-    \x01(\x08R\x05allow\x12\x12\n\x04user\x18\x02\x20\x01(\tR\x04user\x12A\n\
-    \x07headers\x18\x03\x20\x03(\x0b2'.authengine.FilterResponse.HeadersEntr\
-    yR\x07headers\x12\x18\n\x07message\x18\x04\x20\x01(\tR\x07message\x1a:\n\
-    \x0cHeadersEntry\x12\x10\n\x03key\x18\x01\x20\x01(\tR\x03key\x12\x14\n\
-    \x05value\x18\x02\x20\x01(\tR\x05value:\x028\x012]\n\x14UIPBDIAuthZProce\
-    ssor\x12E\n\nprocessReq\x12\x19.authengine.FilterRequest\x1a\x1a.autheng\
-    ine.FilterResponse\"\0b\x06proto3\
-";
-
-static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::rt::LazyV2::INIT;
-
-fn parse_descriptor_proto() -> ::protobuf::descriptor::FileDescriptorProto {
-    ::protobuf::Message::parse_from_bytes(file_descriptor_proto_data).unwrap()
-}
-
-pub fn file_descriptor_proto() -> &'static ::protobuf::descriptor::FileDescriptorProto {
-    file_descriptor_proto_lazy.get(|| {
-        parse_descriptor_proto()
-    })
-}
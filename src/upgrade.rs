@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+use crate::pipeline::RequestSource;
+
+// Protocol upgrades (WebSocket). The handshake is authorized like any other
+// request: the filter pauses it, applies its header changes and resumes it
+// before the upstream can answer 101. Once the response switches protocols the
+// context keeps no request state, since the stream may stay open for hours and
+// carries frames the filter does not look at.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UpgradeConfig {
+    // Answer every upgrade request with 403 instead of authorizing it
+    pub deny: bool,
+}
+
+// Protocol a request asks to switch to: HTTP/1.1 `Upgrade` with
+// `Connection: upgrade`, or an HTTP/2 extended CONNECT (RFC 8441)
+pub fn protocol(source: &dyn RequestSource) -> Option<String> {
+    let method = source.header(":method").unwrap_or_default();
+    if method.eq_ignore_ascii_case("CONNECT") {
+        return source
+            .header(":protocol")
+            .map(|protocol| protocol.to_ascii_lowercase());
+    }
+    let connection = source.header_list("connection")?;
+    if !connection
+        .split(',')
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+    {
+        return None;
+    }
+    let upgrade = source.header_list("upgrade")?;
+    let protocol = upgrade.split(',').next()?.trim();
+    (!protocol.is_empty()).then(|| protocol.to_ascii_lowercase())
+}
@@ -0,0 +1,67 @@
+// Headers the filter adds to the upstream request, collected during the
+// decision and applied in one go right before resuming so their combined size
+// can be held to a budget. Upstreams answer 431 when gateway-added headers
+// push a request over their limit.
+
+use std::borrow::Cow;
+
+// Higher priority headers survive longer when the budget is exceeded
+pub const PRIORITY_IDENTITY: u8 = 100;
+pub const PRIORITY_ROUTING: u8 = 90;
+pub const PRIORITY_ATTRIBUTE: u8 = 80;
+
+#[derive(Debug)]
+pub struct HeaderAddition {
+    pub name: Cow<'static, str>,
+    pub value: String,
+    pub priority: u8,
+}
+
+impl HeaderAddition {
+    fn size(&self) -> usize {
+        // Rough HPACK/HTTP1 line cost: name, value and ": \r\n"
+        self.name.len() + self.value.len() + 4
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UpstreamHeaders {
+    additions: Vec<HeaderAddition>,
+}
+
+impl UpstreamHeaders {
+    pub fn add(&mut self, name: impl Into<Cow<'static, str>>, value: String, priority: u8) {
+        self.additions.push(HeaderAddition {
+            name: name.into(),
+            value,
+            priority,
+        });
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.additions.iter().map(HeaderAddition::size).sum()
+    }
+
+    // Drop the lowest-priority additions (latest added first among equals)
+    // until the total fits; returns the names of dropped headers
+    pub fn enforce_budget(&mut self, budget: usize) -> Vec<Cow<'static, str>> {
+        let mut dropped = Vec::new();
+        while self.total_size() > budget {
+            let Some(index) = self
+                .additions
+                .iter()
+                .enumerate()
+                .min_by_key(|(index, addition)| (addition.priority, usize::MAX - index))
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+            dropped.push(self.additions.remove(index).name);
+        }
+        dropped
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HeaderAddition> {
+        self.additions.iter()
+    }
+}
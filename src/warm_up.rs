@@ -0,0 +1,31 @@
+use serde::Deserialize;
+
+// One gRPC call per worker right after configuration, so the connection to
+// the authz cluster (and its TLS handshake) is set up before the first real
+// request instead of on its critical path. The call is an empty
+// grpc.health.v1.Health/Check: any answer, UNIMPLEMENTED included, means the
+// connection is up.
+
+// gRPC status the host reports when no connection could be established
+const UNAVAILABLE: u32 = 14;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WarmUpConfig {
+    // Delay after configuration before the call is dispatched
+    pub delay_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for WarmUpConfig {
+    fn default() -> Self {
+        Self {
+            delay_ms: 10,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+pub fn connected(status_code: u32) -> bool {
+    status_code != UNAVAILABLE
+}